@@ -3,7 +3,14 @@
 fn main() {
     println!("cargo:rustc-link-lib=dylib=evdev");
 
-    if cfg!(feature = "systemd") {
+    if cfg!(feature = "systemd") || cfg!(feature = "dbus") {
         println!("cargo:rustc-link-lib=dylib=systemd");
     }
+
+    if cfg!(feature = "lua") {
+        // The name of the library differs between distributions, e.g. Fedora calls it lua-5.4.
+        println!("cargo:rerun-if-env-changed=EVSIEVE_LUA_LIB");
+        let library = std::env::var("EVSIEVE_LUA_LIB").unwrap_or_else(|_| "lua5.4".to_owned());
+        println!("cargo:rustc-link-lib=dylib={}", library);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::event::EventType;
+use crate::key::{Key, KeyParser};
+use crate::stream::accel::{Accel, AccelProfile};
+
+/// Represents an --accel argument.
+pub(super) struct AccelArg {
+    pub keys: Vec<Key>,
+    pub profile: AccelProfile,
+    pub speed: f64,
+    pub curve: f64,
+    pub threshold: f64,
+}
+
+impl AccelArg {
+	pub fn parse(args: Vec<String>) -> Result<AccelArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["profile", "speed", "curve", "threshold"],
+            false,
+            true,
+        )?;
+
+        let key_strs = match arg_group.keys.is_empty() {
+            true => vec!["rel:x".to_string(), "rel:y".to_string()],
+            false => arg_group.keys.clone(),
+        };
+        let keys = KeyParser::pure().parse_all(&key_strs)?;
+        for (key, key_str) in keys.iter().zip(&key_strs) {
+            if key.requires_event_type() != Some(EventType::REL) {
+                return Err(ArgumentError::new(format!(
+                    "The keys of an --accel argument must be relative axes, such as rel:x. Offending key: {}", key_str
+                )));
+            }
+        }
+
+        let profile = match arg_group.get_unique_clause("profile")?.as_deref() {
+            None | Some("adaptive") => AccelProfile::Adaptive,
+            Some("flat") => AccelProfile::Flat,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid acceleration profile \"{}\". Valid profiles are \"flat\" and \"adaptive\".", other
+            ))),
+        };
+
+        let speed = parse_positive_number(&arg_group, "speed", 1.0)?;
+        let threshold = parse_positive_number(&arg_group, "threshold", 4.0)?;
+        let curve = match arg_group.get_unique_clause("curve")? {
+            None => 1.0,
+            Some(value) => match crate::utils::parse_number(&value) {
+                Some(curve) if curve >= 0.0 => curve,
+                _ => return Err(ArgumentError::new(format!(
+                    "The curve= clause requires a nonnegative number, but \"{}\" was provided.", value
+                ))),
+            },
+        };
+        if profile == AccelProfile::Flat && arg_group.get_unique_clause("curve")?.is_some() {
+            crate::utils::warn_once("Warning: the curve= clause has no effect on an --accel argument with the flat profile.");
+        }
+
+        Ok(AccelArg { keys, profile, speed, curve, threshold })
+    }
+
+    pub fn compile(self) -> Accel {
+        Accel::new(self.keys, self.profile, self.speed, self.curve, self.threshold)
+    }
+}
+
+fn parse_positive_number(arg_group: &ComplexArgGroup, clause: &str, default: f64) -> Result<f64, ArgumentError> {
+    match arg_group.get_unique_clause(clause)? {
+        None => Ok(default),
+        Some(value) => match crate::utils::parse_number(&value) {
+            Some(number) if number > 0.0 => Ok(number),
+            _ => Err(ArgumentError::new(format!(
+                "The {}= clause requires a positive number, but \"{}\" was provided.", clause, value
+            ))),
+        },
+    }
+}
@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+
+/// The amount of events that get fed through the stream if no events= clause is specified.
+const DEFAULT_EVENTS: u64 = 1_000_000;
+
+/// Represents a --benchmark argument.
+pub(super) struct BenchmarkArg {
+    /// How many events shall be fed through the stream, not counting EV_SYN events.
+    pub events: u64,
+}
+
+impl BenchmarkArg {
+	pub fn parse(args: Vec<String>) -> Result<BenchmarkArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["events"],
+            false,
+            false,
+        )?;
+
+        let events = match arg_group.get_unique_clause("events")? {
+            None => DEFAULT_EVENTS,
+            Some(events_str) => match events_str.parse::<u64>() {
+                Ok(events) if events >= 1 => events,
+                _ => return Err(ArgumentError::new(format!(
+                    "The events= clause of --benchmark must be a positive integer. Offending value: {}", events_str
+                ))),
+            },
+        };
+
+        Ok(BenchmarkArg { events })
+    }
+}
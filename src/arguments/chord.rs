@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::event::Namespace;
+use crate::key::{Key, KeyParser};
+use crate::stream::chord::Chord;
+use crate::time::Duration;
+
+/// Represents a --chord argument.
+pub(super) struct ChordArg {
+    /// The keys that must be pressed together.
+    pub keys: Vec<Key>,
+    /// Specified by the send-key clause. These keys get pressed instead of the chord.
+    pub send_keys: Vec<Key>,
+    pub window: Duration,
+}
+
+impl ChordArg {
+	pub fn parse(args: Vec<String>) -> Result<ChordArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["send-key", "window"],
+            false,
+            true,
+        )?;
+
+        let parser = KeyParser {
+            default_value: "",
+            allow_values: false,
+            allow_ranges: false,
+            allow_transitions: false,
+            allow_types: false,
+            allow_relative_values: false,
+            forbid_non_EV_KEY: true,
+            namespace: Namespace::User,
+        };
+        let keys = parser.parse_all(&arg_group.keys)?;
+        if keys.len() < 2 {
+            return Err(ArgumentError::new("A --chord argument requires at least two keys."));
+        }
+
+        let send_keys = parser.parse_all(&arg_group.get_clauses("send-key"))?;
+        if send_keys.is_empty() {
+            return Err(ArgumentError::new("A --chord argument requires at least one send-key= clause."));
+        }
+
+        let window = match arg_group.get_unique_clause("window")? {
+            Some(value) => parse_period_value(&value)?,
+            None => Duration::from_millis(50),
+        };
+
+        Ok(ChordArg { keys, send_keys, window })
+    }
+
+    pub fn compile(self) -> Chord {
+        Chord::new(self.keys, self.send_keys, self.window)
+    }
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::event::EventType;
+use crate::key::{Key, KeyParser};
+use crate::stream::combine::Combine;
+
+/// Represents a --combine argument.
+pub(super) struct CombineArg {
+    pub negative_key: Key,
+    pub positive_key: Key,
+    pub output_key: Key,
+}
+
+impl CombineArg {
+	pub fn parse(args: Vec<String>) -> Result<CombineArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            true,
+        )?;
+
+        let keys = arg_group.require_keys()?;
+        if keys.len() != 3 {
+            return Err(ArgumentError::new("A --combine argument requires exactly three keys: the negative half-axis, the positive half-axis, and the output axis."));
+        }
+
+        let input_keys = KeyParser::pure().parse_all(&keys[0..2])?;
+        for (key, key_str) in input_keys.iter().zip(&keys[0..2]) {
+            if key.requires_event_type() != Some(EventType::ABS) {
+                return Err(ArgumentError::new(format!(
+                    "The input keys of a --combine argument must be absolute axes, such as abs:z. Offending key: {}", key_str
+                )));
+            }
+        }
+
+        let mut output_parser = KeyParser::default_mask();
+        output_parser.allow_values = false;
+        let output_key = output_parser.parse(&keys[2])?;
+        if output_key.requires_event_type() != Some(EventType::ABS) {
+            return Err(ArgumentError::new(format!(
+                "The output key of a --combine argument must be an absolute axis, such as abs:y. Offending key: {}", keys[2]
+            )));
+        }
+
+        let mut input_keys = input_keys.into_iter();
+        let negative_key = input_keys.next().unwrap();
+        let positive_key = input_keys.next().unwrap();
+
+        if negative_key.intersects_with(&positive_key) {
+            return Err(ArgumentError::new("The two input keys of a --combine argument must not match the same events."));
+        }
+
+        Ok(CombineArg { negative_key, positive_key, output_key })
+    }
+
+    pub fn compile(self) -> Combine {
+        Combine::new(self.negative_key, self.positive_key, self.output_key)
+    }
+}
@@ -0,0 +1,411 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+use crate::error::{ArgumentError, Context};
+use crate::arguments::lib::ComplexArgGroup;
+
+/// Represents a --config argument.
+pub(super) struct ConfigArg {
+    /// The arguments read from the file and the files it includes, in the order they apply.
+    pub groups: Vec<ConfigGroup>,
+}
+
+/// An argument read from a configuration file.
+pub(super) struct ConfigGroup {
+    /// E.g. ["--input", "/dev/input/event0", "grab"].
+    pub args: Vec<String>,
+    /// The file this argument was read from.
+    pub path: PathBuf,
+    /// The line on which the table of this argument starts.
+    pub line: usize,
+}
+
+/// The contents of a single configuration file.
+struct Config {
+    /// The files listed by the include key, along with the line on which they are listed.
+    includes: Vec<(usize, String)>,
+    groups: Vec<(usize, Vec<String>)>,
+}
+
+impl ConfigArg {
+	pub fn parse(args: Vec<String>) -> Result<ConfigArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            true,
+            false,
+        )?;
+
+        let path = match arg_group.paths.as_slice() {
+            [path] => PathBuf::from(path),
+            _ => return Err(ArgumentError::new("The --config argument requires exactly one path.")),
+        };
+        let mut groups = Vec::new();
+        load(&path, &mut Vec::new(), &mut groups)?;
+
+        Ok(ConfigArg { groups })
+    }
+}
+
+/// Reads the configuration file at `path` and appends its arguments to `groups`, preceded by
+/// the arguments of the files it includes. Relative paths of included files are relative to the
+/// directory of the file that includes them. `chain` contains the files that (indirectly)
+/// include this file, to detect files that include themselves.
+fn load(path: &Path, chain: &mut Vec<PathBuf>, groups: &mut Vec<ConfigGroup>) -> Result<(), ArgumentError> {
+    let read_file = || -> Result<Config, ArgumentError> {
+        let contents = std::fs::read_to_string(path).map_err(|error| ArgumentError::new(format!(
+            "Failed to read the file: {}", error
+        )))?;
+        parse_config(&contents)
+    };
+    let context = || format!("While reading the configuration file \"{}\":", path.display());
+    let config = read_file().with_context_of(context)?;
+
+    let canonical_path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    if chain.contains(&canonical_path) {
+        return Err(ArgumentError::new(format!(
+            "The configuration file \"{}\" includes itself.", path.display()
+        )));
+    }
+    chain.push(canonical_path);
+    for (line, include) in config.includes {
+        let include_path = match path.parent() {
+            Some(directory) => directory.join(&include),
+            None => PathBuf::from(&include),
+        };
+        load(&include_path, chain, groups)
+            .with_context_of(|| format!("While including \"{}\" on line {}:", include, line))
+            .with_context_of(context)?;
+    }
+    chain.pop();
+
+    groups.extend(config.groups.into_iter().map(
+        |(line, args)| ConfigGroup { args, path: path.to_owned(), line }
+    ));
+    Ok(())
+}
+
+/// A value in a configuration file. Numbers are kept as the text they were written as, because
+/// they end up as part of a clause like period=0.5 anyway.
+enum Value {
+    Text(String),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// The argument that is being read from a [[NAME]] table.
+struct Table {
+    line: usize,
+    name: String,
+    /// The values of the args key, which get written right after the name of the argument.
+    positional: Vec<String>,
+    /// Flags like "grab" and clauses like "domain=foo".
+    clauses: Vec<String>,
+    keys: Vec<String>,
+}
+
+impl Table {
+    fn add(&mut self, key: String, value: Value) -> Result<(), ArgumentError> {
+        if self.keys.contains(&key) {
+            return Err(ArgumentError::new(format!("The key {} has already been defined in this table.", key)));
+        }
+        let items = match value {
+            Value::Array(items) => items,
+            value => vec![value],
+        };
+        for item in items {
+            match (key.as_str(), item) {
+                ("args", Value::Text(text)) => self.positional.push(text),
+                ("args", _) => return Err(ArgumentError::new("The values of the args key must be strings.")),
+                (_, Value::Text(text)) => self.clauses.push(format!("{}={}", key, text)),
+                (_, Value::Bool(true)) => self.clauses.push(key.clone()),
+                (_, Value::Bool(false)) => (),
+                (_, Value::Array(_)) => return Err(ArgumentError::new("Arrays cannot contain other arrays.")),
+            }
+        }
+        self.keys.push(key);
+        Ok(())
+    }
+
+    fn into_group(self) -> (usize, Vec<String>) {
+        let mut group = vec![format!("--{}", self.name)];
+        group.extend(self.positional);
+        group.extend(self.clauses);
+        (self.line, group)
+    }
+}
+
+/// Parses the contents of a configuration file written in a subset of TOML. Every [[NAME]] table
+/// becomes a --NAME argument. The strings of its args key are the paths or keys of that argument,
+/// every other key with a string or number becomes a clause, and every key that is true becomes
+/// a flag. Keys with an array of values become a clause for each of those values. Before the
+/// first table, an include key may list other files whose arguments come first.
+fn parse_config(contents: &str) -> Result<Config, ArgumentError> {
+    let mut parser = Parser { chars: contents.chars().peekable(), line: 1 };
+    let mut groups = Vec::new();
+    let mut includes: Option<Vec<(usize, String)>> = None;
+    let mut table: Option<Table> = None;
+
+    loop {
+        parser.skip_whitespace();
+        let line = parser.line;
+        let result = match parser.chars.peek() {
+            None => break,
+            Some('\n') | Some('#') => parser.end_of_line(),
+            Some('[') => parser.parse_table_header().map(|name| {
+                if let Some(table) = table.take() {
+                    groups.push(table.into_group());
+                }
+                table = Some(Table {
+                    line, name, positional: Vec::new(), clauses: Vec::new(), keys: Vec::new(),
+                });
+            }),
+            Some(_) => parser.parse_key_value().and_then(|(key, value)| match &mut table {
+                Some(table) => table.add(key, value),
+                None if key == "include" => {
+                    if includes.is_some() {
+                        return Err(ArgumentError::new("The key include has already been defined."));
+                    }
+                    includes = Some(parse_includes(line, value)?);
+                    Ok(())
+                },
+                None => Err(ArgumentError::new("Every key other than include must be part of a [[NAME]] table, such as [[input]].")),
+            }),
+        };
+        result.map_err(|error| error.with_context(format!("On line {}:", line)))?;
+    }
+    if let Some(table) = table {
+        groups.push(table.into_group());
+    }
+
+    Ok(Config { includes: includes.unwrap_or_default(), groups })
+}
+
+fn parse_includes(line: usize, value: Value) -> Result<Vec<(usize, String)>, ArgumentError> {
+    let items = match value {
+        Value::Array(items) => items,
+        value => vec![value],
+    };
+    items.into_iter().map(|item| match item {
+        Value::Text(path) => Ok((line, path)),
+        _ => Err(ArgumentError::new("The values of the include key must be strings.")),
+    }).collect()
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// The line that the next character is on.
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<char> {
+        let next = self.chars.next();
+        if next == Some('\n') {
+            self.line += 1;
+        }
+        next
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ArgumentError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some('\n') | None => Err(ArgumentError::new(format!("Expected {}, encountered the end of the line.", expected))),
+            Some(token) => Err(ArgumentError::new(format!("Expected {}, encountered {}.", expected, token))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\r') = self.chars.peek() {
+            self.next();
+        }
+    }
+
+    /// Skips whitespace, newlines and comments, for inside arrays.
+    fn skip_whitespace_and_newlines(&mut self) {
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('\n') => { self.next(); },
+                Some('#') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(&token) = self.chars.peek() {
+            if token == '\n' {
+                return;
+            }
+            self.next();
+        }
+    }
+
+    /// Makes sure that nothing but a comment follows on the current line.
+    fn end_of_line(&mut self) -> Result<(), ArgumentError> {
+        self.skip_whitespace();
+        if let Some('#') = self.chars.peek() {
+            self.skip_comment();
+        }
+        match self.next() {
+            Some('\n') | None => Ok(()),
+            Some(token) => Err(ArgumentError::new(format!("Expected the end of the line, encountered {}.", token))),
+        }
+    }
+
+    fn parse_table_header(&mut self) -> Result<String, ArgumentError> {
+        self.expect('[')?;
+        if self.chars.peek() != Some(&'[') {
+            return Err(ArgumentError::new("Only tables of the form [[NAME]] are supported, such as [[input]]."));
+        }
+        self.next();
+        self.skip_whitespace();
+        let name = self.parse_key()?;
+        self.skip_whitespace();
+        self.expect(']')?;
+        self.expect(']')?;
+        self.end_of_line()?;
+        Ok(name)
+    }
+
+    fn parse_key_value(&mut self) -> Result<(String, Value), ArgumentError> {
+        let key = self.parse_key()?;
+        self.skip_whitespace();
+        self.expect('=')?;
+        self.skip_whitespace();
+        let value = self.parse_value()?;
+        self.end_of_line()?;
+        Ok((key, value))
+    }
+
+    fn parse_key(&mut self) -> Result<String, ArgumentError> {
+        let key = self.parse_bare_word();
+        if key.is_empty() {
+            return match self.chars.peek() {
+                Some('"') | Some('\'') => Err(ArgumentError::new("Quoted keys are not supported.")),
+                Some('\n') | None => Err(ArgumentError::new("Expected a key, encountered the end of the line.")),
+                Some(token) => Err(ArgumentError::new(format!("Expected a key, encountered {}.", token))),
+            };
+        }
+        Ok(key)
+    }
+
+    /// Reads characters that may be part of a bare key or number.
+    fn parse_bare_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(&token) = self.chars.peek() {
+            if ! (token.is_ascii_alphanumeric() || "_-+.".contains(token)) {
+                break;
+            }
+            word.push(token);
+            self.next();
+        }
+        word
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ArgumentError> {
+        match self.chars.peek() {
+            Some('"') => self.parse_basic_string().map(Value::Text),
+            Some('\'') => self.parse_literal_string().map(Value::Text),
+            Some('[') => self.parse_array(),
+            _ => {
+                let word = self.parse_bare_word();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "" => match self.chars.peek() {
+                        Some('\n') | None => Err(ArgumentError::new("Expected a value, encountered the end of the line.")),
+                        Some(&token) => Err(ArgumentError::new(format!("Expected a value, encountered {}.", token))),
+                    },
+                    _ if word.starts_with(|token: char| token.is_ascii_digit() || token == '+' || token == '-') => {
+                        Ok(Value::Text(word))
+                    },
+                    _ => Err(ArgumentError::new(format!("Strings must be quoted: \"{}\".", word))),
+                }
+            },
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, ArgumentError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('\n') | None => return Err(ArgumentError::new("Unterminated string.")),
+                    Some(token) => return Err(ArgumentError::new(format!("Unsupported escape sequence: \\{}", token))),
+                },
+                Some('\n') | None => return Err(ArgumentError::new("Unterminated string.")),
+                Some(token) => text.push(token),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, ArgumentError> {
+        self.expect('\'')?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(text),
+                Some('\n') | None => return Err(ArgumentError::new("Unterminated string.")),
+                Some(token) => text.push(token),
+            }
+        }
+    }
+
+    /// Arrays may span multiple lines.
+    fn parse_array(&mut self) -> Result<Value, ArgumentError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace_and_newlines();
+            if self.chars.peek() == Some(&']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_whitespace_and_newlines();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(items)),
+                None => return Err(ArgumentError::new("Unterminated array.")),
+                Some(token) => return Err(ArgumentError::new(format!("Expected , or ], encountered {}.", token))),
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    let groups = parse_config("# Comment.\n[[input]]\nargs = \"/dev/input/event0\"\ndomain = 'kb'\ngrab = true\npersist = false\n\n[[map]] # Swap.\nargs = [\n    \"key:a\",\n    \"key:b\", # Comment.\n]\n[[hook]]\nargs = [\"key:c\"]\nexec-shell = [\"echo \\\"a\\\"\", \"echo b\"]\nperiod = 0.5\n").unwrap().groups;
+    assert_eq!(groups, vec![
+        (2, vec!["--input".to_owned(), "/dev/input/event0".to_owned(), "domain=kb".to_owned(), "grab".to_owned()]),
+        (8, vec!["--map".to_owned(), "key:a".to_owned(), "key:b".to_owned()]),
+        (13, vec!["--hook".to_owned(), "key:c".to_owned(), "exec-shell=echo \"a\"".to_owned(), "exec-shell=echo b".to_owned(), "period=0.5".to_owned()]),
+    ]);
+
+    assert!(parse_config("domain = \"kb\"").is_err());
+    assert!(parse_config("[input]").is_err());
+    assert!(parse_config("[[input]]\ndomain = kb").is_err());
+    assert!(parse_config("[[input]]\ndomain = \"kb\"\ndomain = \"foo\"").is_err());
+    assert!(parse_config("[[input]]\nargs = [\"/dev/input/event0\"").is_err());
+    assert!(parse_config("[[input]]\nargs = \"/dev/input/event0\" grab").is_err());
+    assert!(parse_config("[[input]]\nargs = [[\"/dev/input/event0\"]]").is_err());
+
+    let config = parse_config("include = [\"gamepad.toml\", \"/etc/evsieve/keyboard.toml\"]\n[[output]]\n").unwrap();
+    assert_eq!(config.includes, vec![(1, "gamepad.toml".to_owned()), (1, "/etc/evsieve/keyboard.toml".to_owned())]);
+    assert_eq!(config.groups, vec![(2, vec!["--output".to_owned()])]);
+    assert!(parse_config("include = \"a.toml\"\ninclude = \"b.toml\"").is_err());
+    assert!(parse_config("include = true").is_err());
+    assert!(parse_config("domain = \"kb\"").is_err());
+}
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+
+/// Represents a --control-socket argument.
+pub(super) struct ControlSocketArg {
+    pub paths: Vec<String>,
+}
+
+impl ControlSocketArg {
+	pub fn parse(args: Vec<String>) -> Result<ControlSocketArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            true,
+            false,
+        )?;
+
+        Ok(ControlSocketArg {
+            paths: arg_group.paths
+        })
+    }
+}
@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::event::EventType;
+use crate::key::{Key, KeyParser};
+use crate::range::Range;
+use crate::stream::counter::{Counter, CounterMode};
+use crate::time::Duration;
+
+/// Represents a --counter argument.
+pub(super) struct CounterArg {
+    pub keys: Vec<Key>,
+    /// Specified by the reset= clauses. Events matching them set the count back to zero.
+    pub reset_keys: Vec<Key>,
+    pub output_key: Key,
+    pub mode: CounterMode,
+    pub range: Range,
+    pub period: Option<Duration>,
+}
+
+impl CounterArg {
+	pub fn parse(args: Vec<String>) -> Result<CounterArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["output", "mode", "range", "reset", "period"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_keys_or_empty_key())?;
+        let reset_keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_clauses("reset"))?;
+
+        let output_str = arg_group.require_unique_clause("output")?;
+        let mut output_parser = KeyParser::default_mask();
+        output_parser.allow_values = false;
+        let output_key = output_parser.parse(&output_str)?;
+        if output_key.requires_event_type() != Some(EventType::ABS) {
+            return Err(ArgumentError::new(format!(
+                "The output= clause of a --counter argument must be an absolute axis, such as abs:misc. Offending key: {}", output_str
+            )));
+        }
+
+        let mode = match arg_group.get_unique_clause("mode")?.as_deref() {
+            None | Some("count") => CounterMode::Count,
+            Some("sum") => CounterMode::Sum,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid value for the mode= clause: \"{}\". Valid values are \"count\" and \"sum\".", other
+            ))),
+        };
+
+        let range = match arg_group.get_unique_clause("range")? {
+            Some(value) => crate::key::interpret_event_value(&value, &KeyParser::default_filter())?,
+            // i32::MIN and i32::MAX respectively.
+            None => Range::new(Some(-2147483648), Some(2147483647)),
+        };
+        if ! range.is_bounded() {
+            return Err(ArgumentError::new("The range= clause of a --counter argument must have both a lower and an upper bound."));
+        }
+
+        let period = match arg_group.get_unique_clause("period")? {
+            None => None,
+            Some(value) => Some(parse_period_value(&value)?),
+        };
+
+        Ok(CounterArg { keys, reset_keys, output_key, mode, range, period })
+    }
+
+    pub fn compile(self) -> Counter {
+        Counter::new(self.keys, self.reset_keys, self.output_key, self.mode, self.range, self.period)
+    }
+}
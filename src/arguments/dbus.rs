@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::dbus::{BusType, DEFAULT_NAME};
+
+/// Represents a --dbus argument.
+pub(super) struct DBusArg {
+    pub bus: BusType,
+    pub name: String,
+}
+
+impl DBusArg {
+	pub fn parse(args: Vec<String>) -> Result<DBusArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["bus", "name"],
+            false,
+            false,
+        )?;
+
+        if ! cfg!(feature = "dbus") {
+            return Err(ArgumentError::new("The --dbus argument requires evsieve to be compiled with the dbus feature."));
+        }
+
+        let bus = match arg_group.get_unique_clause("bus")?.as_deref() {
+            None | Some("session") => BusType::Session,
+            Some("system") => BusType::System,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid bus: {}. Valid buses are session and system.", other
+            ))),
+        };
+        let name = arg_group.get_unique_clause("name")?.unwrap_or_else(|| DEFAULT_NAME.to_owned());
+
+        Ok(DBusArg { bus, name })
+    }
+}
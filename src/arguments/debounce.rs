@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::key::{Key, KeyParser};
+use crate::stream::debounce::Debounce;
+use crate::time::Duration;
+
+/// Represents a --debounce argument.
+pub(super) struct DebounceArg {
+    pub keys: Vec<Key>,
+    pub period: Duration,
+}
+
+impl DebounceArg {
+	pub fn parse(args: Vec<String>) -> Result<DebounceArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["period"],
+            false,
+            true,
+        )?;
+
+        let parser = KeyParser {
+            default_value: "",
+            allow_values: false,
+            allow_ranges: false,
+            allow_transitions: false,
+            allow_types: true,
+            allow_relative_values: false,
+            forbid_non_EV_KEY: true,
+            namespace: crate::event::Namespace::User,
+        };
+        let keys = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let period = parse_period_value(&arg_group.require_unique_clause("period")?)?;
+
+        Ok(DebounceArg { keys, period })
+    }
+
+    pub fn compile(self) -> Debounce {
+        Debounce::new(self.keys, self.period)
+    }
+}
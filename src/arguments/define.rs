@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::error::ArgumentError;
+
+/// Represents a --define argument.
+pub(super) struct DefineArg {
+    /// The names and values of the variables in the order they were defined.
+    pub definitions: Vec<(String, String)>,
+}
+
+impl DefineArg {
+	pub fn parse(args: Vec<String>) -> Result<DefineArg, ArgumentError> {
+        let mut definitions = Vec::new();
+        for arg in args.into_iter().skip(1) {
+            let (name, value) = match arg.split_once('=') {
+                Some(pair) => pair,
+                None => return Err(ArgumentError::new(format!(
+                    "Every argument of --define must be of the form NAME=VALUE. Offending argument: {}", arg
+                ))),
+            };
+            if ! is_valid_name(name) {
+                return Err(ArgumentError::new(format!(
+                    "The name of a variable may only contain letters, digits and underscores, and may not start with a digit. Offending name: {}", name
+                )));
+            }
+            definitions.push((name.to_owned(), value.to_owned()));
+        }
+        if definitions.is_empty() {
+            return Err(ArgumentError::new("The --define argument requires at least one NAME=VALUE pair."));
+        }
+        Ok(DefineArg { definitions })
+    }
+}
+
+/// The variables defined by the --define arguments encountered so far.
+pub(super) struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new() -> Variables {
+        Variables { values: HashMap::new() }
+    }
+
+    /// Defines a variable, replacing its previous value if it was defined before.
+    pub fn define(&mut self, name: String, value: String) {
+        self.values.insert(name, value);
+    }
+
+    /// Replaces every $NAME and ${NAME} in an argument by the value of that variable, and every
+    /// ${EXPRESSION} by the result of that arithmetic expression. References to names that have
+    /// not been defined are left as they are, so shell commands in exec-shell= clauses can keep
+    /// using shell variables.
+    pub fn expand(&self, arg: &str) -> Result<String, ArgumentError> {
+        if self.values.is_empty() || ! arg.contains('$') {
+            return Ok(arg.to_owned());
+        }
+
+        let mut result = String::new();
+        let mut chars = arg.chars().peekable();
+        while let Some(character) = chars.next() {
+            if character != '$' {
+                result.push(character);
+                continue;
+            }
+            match chars.peek() {
+                Some('{') => {
+                    chars.next();
+                    let mut inner = String::new();
+                    let mut is_closed = false;
+                    for character in chars.by_ref() {
+                        if character == '}' {
+                            is_closed = true;
+                            break;
+                        }
+                        inner.push(character);
+                    }
+                    match is_closed {
+                        true => match self.expand_braces(&inner)? {
+                            Some(value) => result.push_str(&value),
+                            None => result.push_str(&format!("${{{}}}", inner)),
+                        },
+                        false => result.push_str(&format!("${{{}", inner)),
+                    }
+                },
+                Some(&next) if is_name_start(next) => {
+                    let name = take_name(&mut chars);
+                    match self.values.get(&name) {
+                        Some(value) => result.push_str(value),
+                        None => {
+                            result.push('$');
+                            result.push_str(&name);
+                        },
+                    }
+                },
+                _ => result.push('$'),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns the replacement for the text between the braces of ${...}, or None if it should
+    /// be left alone because it does not look like something that refers to our variables.
+    fn expand_braces(&self, inner: &str) -> Result<Option<String>, ArgumentError> {
+        if is_valid_name(inner) {
+            return Ok(self.values.get(inner).cloned());
+        }
+        let tokens = match tokenize(inner) {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+        let refers_to_undefined_names = tokens.iter().any(|token| match token {
+            Token::Name(name) => ! self.values.contains_key(name),
+            _ => false,
+        });
+        if refers_to_undefined_names {
+            return Ok(None);
+        }
+
+        let mut evaluator = Evaluator { tokens: &tokens, position: 0, variables: self };
+        let value = evaluator.sum()?;
+        if evaluator.position < tokens.len() {
+            return Err(ArgumentError::new(format!("Invalid arithmetic expression: ${{{}}}", inner)));
+        }
+        Ok(Some(value.to_string()))
+    }
+
+    /// Returns the value of a variable for use in arithmetic.
+    fn integer_value(&self, name: &str) -> Result<i64, ArgumentError> {
+        let value = self.values.get(name).map(String::as_str).unwrap_or("");
+        value.trim().parse::<i64>().map_err(|_| ArgumentError::new(format!(
+            "The variable {} is used in an arithmetic expression, but its value \"{}\" is not an integer.", name, value
+        )))
+    }
+}
+
+fn is_name_start(character: char) -> bool {
+    character.is_ascii_alphabetic() || character == '_'
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if is_name_start(first) => chars.all(|character| character.is_ascii_alphanumeric() || character == '_'),
+        _ => false,
+    }
+}
+
+fn take_name(chars: &mut Peekable<Chars>) -> String {
+    let mut name = String::new();
+    while let Some(&character) = chars.peek() {
+        if ! (character.is_ascii_alphanumeric() || character == '_') {
+            break;
+        }
+        name.push(character);
+        chars.next();
+    }
+    name
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(char),
+    OpenParen,
+    CloseParen,
+}
+
+/// Splits an arithmetic expression into tokens. Returns None if it contains anything that cannot
+/// be part of an expression. Names may optionally be prefixed with a $.
+fn tokenize(expression: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&character) = chars.peek() {
+        match character {
+            ' ' | '\t' => { chars.next(); },
+            '+' | '-' | '*' | '/' | '%' => {
+                chars.next();
+                tokens.push(Token::Operator(character));
+            },
+            '(' => { chars.next(); tokens.push(Token::OpenParen); },
+            ')' => { chars.next(); tokens.push(Token::CloseParen); },
+            '0' ..= '9' => {
+                let mut digits = String::new();
+                while let Some(&digit) = chars.peek() {
+                    if ! digit.is_ascii_digit() {
+                        break;
+                    }
+                    digits.push(digit);
+                    chars.next();
+                }
+                tokens.push(Token::Number(digits.parse().ok()?));
+            },
+            '$' => {
+                chars.next();
+                match chars.peek() {
+                    Some(&next) if is_name_start(next) => tokens.push(Token::Name(take_name(&mut chars))),
+                    _ => return None,
+                }
+            },
+            _ if is_name_start(character) => tokens.push(Token::Name(take_name(&mut chars))),
+            _ => return None,
+        }
+    }
+    if tokens.is_empty() {
+        return None;
+    }
+    Some(tokens)
+}
+
+/// Evaluates a list of tokens with the usual precedence of operators using recursive descent.
+struct Evaluator<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    variables: &'a Variables,
+}
+
+impl<'a> Evaluator<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn peek_operator(&self, operators: &[char]) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if operators.contains(operator) => Some(*operator),
+            _ => None,
+        }
+    }
+
+    /// sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<i64, ArgumentError> {
+        let mut value = self.product()?;
+        while let Some(operator) = self.peek_operator(&['+', '-']) {
+            self.position += 1;
+            let operand = self.product()?;
+            value = match operator {
+                '+' => value.checked_add(operand),
+                _ => value.checked_sub(operand),
+            }.ok_or_else(overflow_error)?;
+        }
+        Ok(value)
+    }
+
+    /// product := unary (('*' | '/' | '%') unary)*
+    fn product(&mut self) -> Result<i64, ArgumentError> {
+        let mut value = self.unary()?;
+        while let Some(operator) = self.peek_operator(&['*', '/', '%']) {
+            self.position += 1;
+            let operand = self.unary()?;
+            if operator != '*' && operand == 0 {
+                return Err(ArgumentError::new("Division by zero in an arithmetic expression."));
+            }
+            value = match operator {
+                '*' => value.checked_mul(operand),
+                '/' => value.checked_div(operand),
+                _ => value.checked_rem(operand),
+            }.ok_or_else(overflow_error)?;
+        }
+        Ok(value)
+    }
+
+    /// unary := '-' unary | NUMBER | NAME | '(' sum ')'
+    fn unary(&mut self) -> Result<i64, ArgumentError> {
+        match self.next() {
+            Some(Token::Operator('-')) => self.unary()?.checked_neg().ok_or_else(overflow_error),
+            Some(Token::Number(number)) => Ok(*number),
+            Some(Token::Name(name)) => self.variables.integer_value(name),
+            Some(Token::OpenParen) => {
+                let value = self.sum()?;
+                match self.next() {
+                    Some(Token::CloseParen) => Ok(value),
+                    _ => Err(ArgumentError::new("Unbalanced parentheses in an arithmetic expression.")),
+                }
+            },
+            _ => Err(ArgumentError::new("Invalid arithmetic expression: expected a number, variable or parenthesis.")),
+        }
+    }
+}
+
+fn overflow_error() -> ArgumentError {
+    ArgumentError::new("An arithmetic expression resulted in a number that is too large.")
+}
+
+#[test]
+fn unittest() {
+    let mut variables = Variables::new();
+    assert_eq!(variables.expand("abs:x:$MAX").unwrap(), "abs:x:$MAX");
+    variables.define("MAX".to_owned(), "32767".to_owned());
+    variables.define("NAME".to_owned(), "pad".to_owned());
+
+    assert_eq!(variables.expand("abs:x:$MAX").unwrap(), "abs:x:32767");
+    assert_eq!(variables.expand("abs:x:${MAX}0").unwrap(), "abs:x:327670");
+    assert_eq!(variables.expand("abs:x:-${MAX}~${MAX / 2}").unwrap(), "abs:x:-32767~16383");
+    assert_eq!(variables.expand("${-(MAX + 1) * 2 % 7}").unwrap(), ((-32768 * 2) % 7).to_string());
+    assert_eq!(variables.expand("${$MAX-1}").unwrap(), "32766");
+    assert_eq!(variables.expand("name=${NAME}").unwrap(), "name=pad");
+
+    // Things that look like they belong to a shell are left alone.
+    assert_eq!(variables.expand("echo $HOME $$ $1 ${HOME} ${MAX:-1}").unwrap(), "echo $HOME $$ $1 ${HOME} ${MAX:-1}");
+    assert_eq!(variables.expand("echo ${HOME + MAX} ${").unwrap(), "echo ${HOME + MAX} ${");
+
+    assert!(variables.expand("${MAX / 0}").is_err());
+    assert!(variables.expand("${NAME + 1}").is_err());
+    assert!(variables.expand("${(MAX + 1}").is_err());
+    assert!(variables.expand("${MAX 1}").is_err());
+    assert!(variables.expand("${MAX * MAX * MAX * MAX * MAX}").is_err());
+
+    assert!(DefineArg::parse(vec!["--define".to_owned(), "MAX=1".to_owned(), "MIN=-1".to_owned()]).is_ok());
+    assert!(DefineArg::parse(vec!["--define".to_owned()]).is_err());
+    assert!(DefineArg::parse(vec!["--define".to_owned(), "MAX".to_owned()]).is_err());
+    assert!(DefineArg::parse(vec!["--define".to_owned(), "1MAX=1".to_owned()]).is_err());
+}
@@ -8,31 +8,75 @@ use crate::time::Duration;
 
 /// Represents a --delay argument.
 pub(super) struct DelayArg {
-    pub keys: Vec<Key>,
-    pub period: Duration,
+    /// Events matching the keys of a group are delayed by the period of that group.
+    pub groups: Vec<(Vec<Key>, Duration)>,
+    /// How much the delay of each event may randomly deviate from the period.
+    pub jitter: Option<Duration>,
 }
 
 impl DelayArg {
 	pub fn parse(args: Vec<String>) -> Result<DelayArg, ArgumentError> {
+        // With multiple period= clauses, every clause applies to the keys in front of it. The
+        // ComplexArgGroup does not remember the order of the arguments, so keep a copy.
+        let raw_args: Vec<String> = args[1..].to_vec();
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &["period"],
+            &["period", "jitter"],
             false,
             true,
         )?;
 
-        let keys = KeyParser::default_filter()
-            .parse_all(&arg_group.get_keys_or_empty_key())?;
-        
-        let period = parse_period_value(
-            &arg_group.require_unique_clause("period")?
-        )?;
+        let parser = KeyParser::default_filter();
+        let period_strs = arg_group.get_clauses("period");
+        let groups = match period_strs.as_slice() {
+            [] => return Err(ArgumentError::new("The --delay argument requires a period= clause.")),
+            [period_str] => vec![(
+                parser.parse_all(&arg_group.get_keys_or_empty_key())?,
+                parse_period_value(period_str)?,
+            )],
+            _ => {
+                let mut groups = Vec::new();
+                let mut key_strs: Vec<String> = Vec::new();
+                for arg in raw_args {
+                    if let Some(period_str) = crate::utils::strip_prefix(&arg, "period=") {
+                        if key_strs.is_empty() {
+                            return Err(ArgumentError::new(format!(
+                                "When a --delay argument has multiple period= clauses, every period= clause must come after the events it applies to. Offending clause: {}", arg
+                            )));
+                        }
+                        groups.push((parser.parse_all(&key_strs)?, parse_period_value(period_str)?));
+                        key_strs.clear();
+                    } else if crate::key::resembles_key(&arg) {
+                        key_strs.push(arg);
+                    }
+                }
+                if let Some(key_str) = key_strs.first() {
+                    return Err(ArgumentError::new(format!(
+                        "The event {} of a --delay argument is not followed by any period= clause.", key_str
+                    )));
+                }
+                groups
+            },
+        };
+
+        let jitter = match arg_group.get_unique_clause("jitter")? {
+            None => None,
+            Some(jitter_str) => {
+                let jitter = parse_period_value(&jitter_str)?;
+                if groups.iter().any(|&(_, period)| jitter > period) {
+                    return Err(ArgumentError::new(format!(
+                        "The jitter of a --delay cannot exceed its period. Offending clause: jitter={}", jitter_str
+                    )));
+                }
+                Some(jitter)
+            },
+        };
 
-        Ok(DelayArg { keys, period })
+        Ok(DelayArg { groups, jitter })
     }
 
     pub fn compile(self) -> Delay {
-        Delay::new(self.keys, self.period)
+        Delay::new(self.groups, self.jitter)
     }
 }
 
@@ -85,4 +129,12 @@ fn unittest() {
     parse_period_value("0").unwrap_err();
     parse_period_value("0.0").unwrap_err();
     parse_period_value("-1").unwrap_err();
+
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    let delay = DelayArg::parse(args(&["--delay", "key:a", "period=0.01", "key:b", "key:c", "period=0.05"])).unwrap();
+    assert_eq!(delay.groups.iter().map(|(keys, period)| (keys.len(), *period)).collect::<Vec<_>>(),
+        vec![(1, Duration::from_millis(10)), (2, Duration::from_millis(50))]);
+    assert!(DelayArg::parse(args(&["--delay", "period=0.01", "key:a", "period=0.05"])).is_err());
+    assert!(DelayArg::parse(args(&["--delay", "key:a", "period=0.01", "key:b", "period=0.05", "key:c"])).is_err());
+    assert!(DelayArg::parse(args(&["--delay", "key:a", "period=0.01", "key:b", "period=0.05", "jitter=0.02"])).is_err());
 }
\ No newline at end of file
@@ -3,7 +3,7 @@
 use crate::error::ArgumentError;
 use crate::utils;
 use crate::state::{State, ToggleIndex};
-use crate::stream::hook::{Effect, Trigger, EventDispatcher};
+use crate::stream::hook::{Effect, ToggleShift, Trigger, EventDispatcher};
 use crate::key::{Key, KeyParser};
 use crate::event::Namespace;
 use crate::arguments::lib::ComplexArgGroup;
@@ -39,13 +39,16 @@ pub(super) struct HookArg {
     /// Set to true if this Hook is connected to a --withhold argument, false by default.
     /// This property is set by crate::arguments::withhold.
     pub mark_withholdable: bool,
+
+    /// Specified by the id= clause. Used to enable or disable the hook at runtime.
+    pub id: Option<String>,
 }
 
 impl HookArg {
 	pub fn parse(args: Vec<String>) -> Result<HookArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &["toggle", "sequential"],
-            &["exec-shell", "toggle", "period", "send-key"],
+            &["exec-shell", "toggle", "period", "send-key", "id"],
             false,
             true,
         )?;
@@ -80,6 +83,7 @@ impl HookArg {
                 exec_shell: arg_group.get_clauses("exec-shell"),
                 toggle_action, period, sequential, send_keys,
                 mark_withholdable: false,
+                id: arg_group.get_unique_clause("id")?,
             })
         }
     }
@@ -95,20 +99,23 @@ impl HookArg {
 }
 
 /// Represents how a single toggle clause on a hook should modify some toggle.
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 enum HookToggleShift {
     /// Move the active index to the next one, wrapping around.
     Next,
     /// Set the active index to a specific index.
     ToIndex(usize),
+    /// Set the active index to the state with a specific name. Only valid for toggles with an id.
+    ToName(String),
 }
 
 /// Represents the aggregate effect of all toggle= clauses on a single --hook.
 /// This is used to track arguments, this is not the implementation of such an effect.
 #[derive(Clone)]
 pub struct HookToggleAction {
-    /// The action based on a toggle flag or a toggle= without id.
-    global_action: Option<HookToggleShift>,
+    /// The action based on a toggle flag or a toggle= without id. Toggles without id have no
+    /// named states, so this never needs to refer to a state by name.
+    global_action: Option<ToggleShift>,
     /// The set of specific toggle=id:index specified.
     by_id_actions: HashMap<String, HookToggleShift>,
 }
@@ -124,13 +131,13 @@ impl HookToggleAction {
     pub fn parse(has_toggle_flag: bool, toggle_clauses: Vec<String>) -> Result<HookToggleAction, ArgumentError> {
         let mut toggle_action = HookToggleAction::new();
         if has_toggle_flag {
-            toggle_action.global_action = Some(HookToggleShift::Next);
+            toggle_action.global_action = Some(ToggleShift::Next);
         }
         for clause in toggle_clauses {
             let (id, index_str_opt) = utils::split_once(&clause, ":");
             let index: HookToggleShift = match index_str_opt {
                 None => HookToggleShift::Next,
-                Some(index_str) => HookToggleShift::ToIndex(
+                Some(index_str) if index_str.chars().all(|c| c.is_ascii_digit()) => HookToggleShift::ToIndex(
                     match index_str.parse::<usize>() {
                         Ok(value) => match value {
                             0 => return Err(ArgumentError::new("Cannot use toggle index 0: toggle indices start at 1.")),
@@ -139,11 +146,21 @@ impl HookToggleAction {
                         Err(error) => return Err(ArgumentError::new(format!("Cannot interpret {} as an integer: {}.", index_str, error))),
                     }
                 ),
+                Some(name) => HookToggleShift::ToName(name.to_owned()),
             };
             match id {
-                "" => match toggle_action.global_action {
-                    None => { toggle_action.global_action = Some(index); },
-                    Some(_) => return Err(ArgumentError::new("A --hook cannot have multiple unspecified toggle clauses.")),
+                "" => {
+                    let shift = match index {
+                        HookToggleShift::Next => ToggleShift::Next,
+                        HookToggleShift::ToIndex(index) => ToggleShift::ToIndex(index),
+                        HookToggleShift::ToName(_) => return Err(ArgumentError::new(
+                            "Toggle states can only be referred to by name if the id of the toggle is specified."
+                        )),
+                    };
+                    match toggle_action.global_action {
+                        None => { toggle_action.global_action = Some(shift); },
+                        Some(_) => return Err(ArgumentError::new("A --hook cannot have multiple unspecified toggle clauses.")),
+                    }
                 },
                 _ => {
                     match toggle_action.by_id_actions.get(id) {
@@ -163,12 +180,23 @@ impl HookToggleAction {
     pub fn implement(&self, state: &State, toggle_index_by_id: &HashMap<String, ToggleIndex>) -> Result<Vec<Effect>, ArgumentError> {
         let mut effects: Vec<Effect> = Vec::new();
         let mut specified_indices: Vec<ToggleIndex> = Vec::new();
-        for (toggle_id, &shift) in &self.by_id_actions {
+        for (toggle_id, shift) in &self.by_id_actions {
             let toggle_index = *toggle_index_by_id.get(toggle_id).ok_or_else(|| {
                 ArgumentError::new(format!("No toggle with the id \"{}\" exists.", toggle_id))
             })?;
 
-            if let HookToggleShift::ToIndex(target_index) = shift {
+            let shift = match shift {
+                HookToggleShift::Next => ToggleShift::Next,
+                HookToggleShift::ToIndex(index) => ToggleShift::ToIndex(*index),
+                HookToggleShift::ToName(name) => match state[toggle_index].index_of_name(name) {
+                    Some(index) => ToggleShift::ToIndex(index),
+                    None => return Err(ArgumentError::new(format!(
+                        "The toggle with id \"{}\" has no state named \"{}\".", toggle_id, name
+                    ))),
+                },
+            };
+
+            if let ToggleShift::ToIndex(target_index) = shift {
                 let toggle_size = state[toggle_index].size();
                 if target_index >= toggle_size {
                     return Err(ArgumentError::new(format!(
@@ -178,23 +206,10 @@ impl HookToggleAction {
             }
 
             specified_indices.push(toggle_index);
-            effects.push(Box::new(move |state: &mut State| {
-                match shift {
-                    HookToggleShift::Next => state[toggle_index].advance(),
-                    HookToggleShift::ToIndex(value) => state[toggle_index].set_value_wrapped(value),
-                }
-            }));
+            effects.push(Effect::ToggleSet(toggle_index, shift));
         }
         if let Some(shift) = self.global_action {
-            effects.push(Box::new(move |state: &mut State| {
-                let toggles_affected = state.get_toggles_except(&specified_indices);
-                for toggle in toggles_affected {
-                    match shift {
-                        HookToggleShift::Next => toggle.advance(),
-                        HookToggleShift::ToIndex(value) => toggle.set_value_wrapped(value),
-                    }
-                }
-            }));
+            effects.push(Effect::ToggleSetOthers(specified_indices, shift));
         }
 
         Ok(effects)
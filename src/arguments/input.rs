@@ -2,32 +2,79 @@
 
 use crate::domain;
 use crate::domain::Domain;
-use crate::predevice::{GrabMode, PersistMode};
-use crate::error::ArgumentError;
+use crate::io::hidraw::UsageMapping;
+use crate::io::network::NetworkProtocol;
+use crate::io::replay::ReplayTiming;
+use crate::predevice::{CapabilityFilter, DeviceSelector, EventClock, GrabMode, PersistMode, ReopenBackoff, SynDroppedPolicy};
+use crate::arguments::delay::parse_period_value;
+use crate::error::{ArgumentError, Context};
+use crate::event::EventCode;
+use crate::ecodes;
 use crate::arguments::lib::ComplexArgGroup;
+use crate::logging::{self, LogLevel};
 
 /// Represents an --input argument.
 pub(super) struct InputDevice {
     /// The domain of this input device.
     pub domain: Option<Domain>,
+    /// If true, each device gets its own domain derived from its identity. See domain=auto.
+    pub auto_domain: bool,
     /// All input device paths. If multiple are specified, it will read from multiple devices.
-    /// At least one path must be specified.
+    /// At least one path must be specified. Paths may contain wildcards in their last component,
+    /// in which case all devices matching that pattern are read from.
 	pub paths: Vec<String>,
+    /// If Some, the paths are not specified by the user, but the devices are found by scanning
+    /// /dev/input for devices matching this selector instead.
+    pub selector: Option<DeviceSelector>,
     pub grab_mode: GrabMode,
     pub persist_mode: PersistMode,
+    pub reopen_backoff: Option<ReopenBackoff>,
+    /// The id of a toggle and the index or name of one of its states, as specified by the
+    /// grab-while=ID:STATE clause. Resolved into a GrabCondition once the toggles are known.
+    pub grab_while: Option<(String, String)>,
+    /// If Some, events are not read from an event device, but replayed from a recording.
+    pub replay: Option<ReplaySource>,
+    /// If Some, events are received from other evsieves over the network on the given address.
+    pub network: Option<(NetworkProtocol, String)>,
+    /// If Some, events are translated from the HID reports of a hidraw device.
+    pub hidraw: Option<HidrawSource>,
+    /// If Some, events are read from a legacy joystick device at this path.
+    pub joystick: Option<String>,
+    /// If Some, only devices whose capabilities pass this filter are used.
+    pub cap_filter: Option<CapabilityFilter>,
+    pub clock: Option<EventClock>,
+    pub syn_dropped: Option<SynDroppedPolicy>,
+    pub measure_latency: bool,
+    pub sync_state: bool,
+    pub forward_leds: bool,
+}
+
+/// Represents the hidraw=, descriptor= and usage= clauses of an --input argument.
+pub(super) struct HidrawSource {
+    pub path: String,
+    pub descriptor: Option<String>,
+    pub usages: Vec<UsageMapping>,
+}
+
+/// Represents the file= and timing= clauses of an --input argument.
+pub(super) struct ReplaySource {
+    pub path: String,
+    pub timing: ReplayTiming,
 }
 
 impl InputDevice {
 	pub fn parse(args: Vec<String>) -> Result<InputDevice, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &["grab"],
-            &["domain", "grab", "persist"],
+            &["grab", "measure-latency", "sync-state", "forward-leds"],
+            &["domain", "grab", "persist", "name", "vendor", "product", "reopen-interval", "reopen-max-interval", "grab-while", "file", "mode", "timing", "tcp", "udp", "hidraw", "descriptor", "usage", "joystick", "require-caps", "exclude-caps", "clock", "syn-dropped"],
             true,
             false,
         )?;
 
+        let auto_domain = arg_group.get_unique_clause("domain")?.as_deref() == Some("auto");
         let domain = match arg_group.get_unique_clause("domain")? {
             None => None,
+            Some(_) if auto_domain => None,
             Some(domain_str) => {
                 let mut chars = domain_str.chars();
                 let first_char: Option<char> = chars.next();
@@ -41,11 +88,24 @@ impl InputDevice {
             }
         };
 
+        let grab_while = match arg_group.get_unique_clause("grab-while")? {
+            None => None,
+            Some(value) => match crate::utils::split_once(&value, ":") {
+                (id, Some(state)) if ! id.is_empty() && ! state.is_empty() => Some((id.to_owned(), state.to_owned())),
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid grab-while= clause \"{}\". The grab-while= clause requires the id of a toggle and one of its states, such as grab-while=mode:2.", value
+                ))),
+            },
+        };
+
         let grab_mode = match arg_group.get_unique_clause_or_default_if_flag("grab", "auto")? {
+            // A device with a grab-while= clause should be grabbed when its condition is met.
+            None if grab_while.is_some() => GrabMode::Auto,
             None => GrabMode::None,
             Some(value) => match value.as_str() {
                 "auto" => GrabMode::Auto,
                 "force" => GrabMode::Force,
+                "required" => GrabMode::Required,
                 _ => return Err(ArgumentError::new("Invalid grab mode specified.")),
             }
         };
@@ -54,29 +114,327 @@ impl InputDevice {
             None => PersistMode::None,
             Some(value) => match value.as_str() {
                 "reopen" => PersistMode::Reopen,
+                "full" => PersistMode::Full,
                 "none" => PersistMode::None,
                 "exit" => PersistMode::Exit,
                 _ => return Err(ArgumentError::new("Invalid persist mode specified.")),
             }
         };
 
-        let paths = arg_group.require_paths()?;
+        let reopen_interval = match arg_group.get_unique_clause("reopen-interval")? {
+            Some(value) => Some(parse_period_value(&value)?),
+            None => None,
+        };
+        let reopen_max_interval = match arg_group.get_unique_clause("reopen-max-interval")? {
+            Some(value) => Some(parse_period_value(&value)?),
+            None => None,
+        };
+        let reopen_backoff = match (reopen_interval, reopen_max_interval) {
+            (None, None) => None,
+            (None, Some(_)) => return Err(ArgumentError::new(
+                "The reopen-max-interval= clause requires a reopen-interval= clause to be specified as well."
+            )),
+            (Some(interval), max_interval) => {
+                let max_interval = max_interval.unwrap_or(interval);
+                if max_interval < interval {
+                    return Err(ArgumentError::new(
+                        "The reopen-max-interval= clause must not be shorter than the reopen-interval= clause."
+                    ));
+                }
+                Some(ReopenBackoff { interval, max_interval })
+            },
+        };
+        if reopen_backoff.is_some() && ! persist_mode.reopens() {
+            return Err(ArgumentError::new(
+                "The reopen-interval= and reopen-max-interval= clauses can only be used on --input arguments with persist=reopen or persist=full."
+            ));
+        }
+
+        let replay = match arg_group.get_unique_clause("file")? {
+            None => {
+                if ! arg_group.get_clauses("mode").is_empty() || ! arg_group.get_clauses("timing").is_empty() {
+                    return Err(ArgumentError::new("The mode= and timing= clauses of an --input argument require a file= clause to be specified as well."));
+                }
+                None
+            },
+            Some(path) => {
+                match arg_group.get_unique_clause("mode")? {
+                    None => {},
+                    Some(mode) => match mode.as_str() {
+                        "replay" => {},
+                        _ => return Err(ArgumentError::new(format!("Invalid mode \"{}\" specified. The only supported mode for files is mode=replay.", mode))),
+                    },
+                }
+                let timing = match arg_group.get_unique_clause("timing")? {
+                    None => ReplayTiming::Original,
+                    Some(value) => match value.as_str() {
+                        "original" => ReplayTiming::Original,
+                        "fast" => ReplayTiming::Fast,
+                        _ => return Err(ArgumentError::new("Invalid timing specified. The timing= clause must be either original or fast.")),
+                    },
+                };
+                Some(ReplaySource { path, timing })
+            },
+        };
+
+        let network = match (arg_group.get_unique_clause("tcp")?, arg_group.get_unique_clause("udp")?) {
+            (None, None) => None,
+            (Some(address), None) => Some((NetworkProtocol::Tcp, address)),
+            (None, Some(address)) => Some((NetworkProtocol::Udp, address)),
+            (Some(_), Some(_)) => return Err(ArgumentError::new("An --input argument cannot have both a tcp= and an udp= clause.")),
+        };
+
+        let hidraw = match arg_group.get_unique_clause("hidraw")? {
+            None => {
+                if ! arg_group.get_clauses("descriptor").is_empty() || ! arg_group.get_clauses("usage").is_empty() {
+                    return Err(ArgumentError::new("The descriptor= and usage= clauses of an --input argument require a hidraw= clause to be specified as well."));
+                }
+                None
+            },
+            Some(path) => {
+                let usages = arg_group.get_clauses("usage").iter()
+                    .map(|value| parse_usage_mapping(value))
+                    .collect::<Result<Vec<UsageMapping>, ArgumentError>>()?;
+                if usages.is_empty() {
+                    return Err(ArgumentError::new("An --input argument with a hidraw= clause requires at least one usage= clause to tell which events the HID reports should be translated to."));
+                }
+                Some(HidrawSource {
+                    path,
+                    descriptor: arg_group.get_unique_clause("descriptor")?,
+                    usages,
+                })
+            },
+        };
+
+        let joystick = arg_group.get_unique_clause("joystick")?;
+
+        let name = arg_group.get_unique_clause("name")?;
+        let vendor = match arg_group.get_unique_clause("vendor")? {
+            Some(value) => Some(parse_hex_id("vendor", &value)?),
+            None => None,
+        };
+        let product = match arg_group.get_unique_clause("product")? {
+            Some(value) => Some(parse_hex_id("product", &value)?),
+            None => None,
+        };
+        let selector = match (name, vendor, product) {
+            (None, None, None) => None,
+            (name, vendor, product) => Some(DeviceSelector { name, vendor, product }),
+        };
+
+        let required_caps = match arg_group.get_unique_clause("require-caps")? {
+            Some(value) => Some(parse_event_codes(&value).with_context("While parsing the require-caps= clause:")?),
+            None => None,
+        };
+        let excluded_caps = match arg_group.get_unique_clause("exclude-caps")? {
+            Some(value) => Some(parse_event_codes(&value).with_context("While parsing the exclude-caps= clause:")?),
+            None => None,
+        };
+        let cap_filter = match (required_caps, excluded_caps) {
+            (None, None) => None,
+            (required, excluded) => Some(CapabilityFilter {
+                required: required.unwrap_or_default(),
+                excluded: excluded.unwrap_or_default(),
+            }),
+        };
+
+        let clock = match arg_group.get_unique_clause("clock")? {
+            None => None,
+            Some(value) => match value.as_str() {
+                "realtime" => Some(EventClock::Realtime),
+                "monotonic" => Some(EventClock::Monotonic),
+                "boottime" => Some(EventClock::Boottime),
+                _ => return Err(ArgumentError::new("Invalid clock specified. The clock= clause must be realtime, monotonic or boottime.")),
+            },
+        };
+
+        let syn_dropped = match arg_group.get_unique_clause("syn-dropped")? {
+            None => None,
+            Some(value) => match value.as_str() {
+                "resync" => Some(SynDroppedPolicy::Resync),
+                "log" => Some(SynDroppedPolicy::Log),
+                "ignore" => Some(SynDroppedPolicy::Ignore),
+                _ => return Err(ArgumentError::new("Invalid syn-dropped= clause specified. The syn-dropped= clause must be resync, log or ignore.")),
+            },
+        };
+
+        let measure_latency = arg_group.has_flag("measure-latency");
+        let sync_state = arg_group.has_flag("sync-state");
+        let forward_leds = arg_group.has_flag("forward-leds");
+
+        // Recordings, network inputs and hidraw devices take the place of event devices.
+        let mut source_clauses: Vec<&str> = Vec::new();
+        if replay.is_some() {
+            source_clauses.push("file=");
+        }
+        match &network {
+            Some((NetworkProtocol::Tcp, _)) => source_clauses.push("tcp="),
+            Some((NetworkProtocol::Udp, _)) => source_clauses.push("udp="),
+            None => {},
+        }
+        if hidraw.is_some() {
+            source_clauses.push("hidraw=");
+        }
+        if joystick.is_some() {
+            source_clauses.push("joystick=");
+        }
+        if source_clauses.len() > 1 {
+            return Err(ArgumentError::new("An --input argument cannot have more than one of the file=, tcp=, udp=, hidraw= and joystick= clauses."));
+        }
+        let source_clause: Option<&str> = source_clauses.first().copied();
+        if let Some(clause) = source_clause {
+            if ! arg_group.paths.is_empty() {
+                return Err(ArgumentError::new(format!("An --input argument cannot have both paths and a {} clause.", clause)));
+            }
+            if selector.is_some() {
+                return Err(ArgumentError::new(format!("An --input argument cannot have both a {} clause and name=, vendor= or product= clauses.", clause)));
+            }
+            if grab_mode != GrabMode::None || persist_mode != PersistMode::None || reopen_backoff.is_some() || cap_filter.is_some() || clock.is_some() || syn_dropped.is_some() || measure_latency || sync_state || forward_leds {
+                return Err(ArgumentError::new(format!("The grab, persist, reopen-interval, reopen-max-interval, grab-while, require-caps, exclude-caps, clock, syn-dropped, measure-latency, sync-state and forward-leds clauses cannot be used on --input arguments with a {} clause.", clause)));
+            }
+            if auto_domain {
+                return Err(ArgumentError::new(format!("The domain=auto clause cannot be used on --input arguments with a {} clause. Specify a domain name instead.", clause)));
+            }
+            if let Some((_, address)) = &network {
+                check_network_address(address)?;
+            }
+        }
+
+        let paths = match selector {
+            None if source_clause.is_some() => Vec::new(),
+            None => arg_group.require_paths()?,
+            Some(_) => {
+                if ! arg_group.paths.is_empty() {
+                    return Err(ArgumentError::new("An --input argument cannot have both paths and name=, vendor= or product= clauses."));
+                }
+                Vec::new()
+            },
+        };
+        for path in &paths {
+            check_pattern(path)?;
+            if auto_domain && persist_mode == PersistMode::Full && crate::utils::is_glob_pattern(path) {
+                return Err(ArgumentError::new(format!(
+                    "The domain=auto clause cannot be combined with persist=full on the pattern \"{}\", because the domains of devices that show up later would not be known in advance.", path
+                )));
+            }
+        }
 
         match persist_mode {
             PersistMode::None | PersistMode::Exit => {},
-            PersistMode::Reopen => {
+            PersistMode::Reopen | PersistMode::Full => {
                 if paths.iter().any(|path| is_direct_event_device(path)) {
-                    println!("Warning: it is a bad idea to enable persistence on paths like /dev/input/event* because the kernel does not guarantee that the number of each event device remains constant. If such a device were to de disattached and reattached, it may show up under a different number. We recommend identifying event devices through their links in /dev/input/by-id/.");
+                    if logging::enabled(LogLevel::Warning) {
+                        println!("Warning: it is a bad idea to enable persistence on paths like /dev/input/event* because the kernel does not guarantee that the number of each event device remains constant. If such a device were to de disattached and reattached, it may show up under a different number. We recommend identifying event devices through their links in /dev/input/by-id/.");
+                    }
                 }
             }
         }
 
+        if selector.is_some() && persist_mode.reopens() {
+            logging::warning("Warning: devices selected by name=, vendor= or product= are reopened through their path in /dev/input/event*, but the kernel does not guarantee that the number of each event device remains constant. If such a device were to be disattached and reattached, it may show up under a different number.");
+        }
+
         Ok(InputDevice {
-            domain, grab_mode, persist_mode, paths
+            domain, auto_domain, grab_mode, persist_mode, reopen_backoff, grab_while, paths, selector, replay, network, hidraw, joystick, cap_filter, clock, syn_dropped, measure_latency, sync_state, forward_leds
         })
     }
 }
 
+/// Parses a vendor or product id in the hexadecimal form that tools like lsusb show, with or
+/// without a leading "0x".
+pub(super) fn parse_hex_id(clause: &str, value: &str) -> Result<u16, ArgumentError> {
+    let digits = crate::utils::strip_prefix(value, "0x").unwrap_or(value);
+    let is_hexadecimal = ! digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit());
+    match u16::from_str_radix(digits, 16) {
+        Ok(id) if is_hexadecimal => Ok(id),
+        _ => Err(ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as a {} id. Please provide a hexadecimal number such as {}=046d.", value, clause, clause
+        ))),
+    }
+}
+
+/// Parses a comma-separated list of event codes such as "key:a,rel:x". Ranges of codes like
+/// "btn:0..btn:9" include all codes whose numeric value lies between both ends.
+pub(super) fn parse_event_codes(value: &str) -> Result<Vec<EventCode>, ArgumentError> {
+    let mut codes: Vec<EventCode> = Vec::new();
+    for part in value.split(',') {
+        match crate::utils::split_once(part, "..") {
+            (first_str, Some(last_str)) => {
+                let first = parse_event_code(first_str)?;
+                let last = parse_event_code(last_str)?;
+                if first.ev_type() != last.ev_type() || first.code() > last.code() {
+                    return Err(ArgumentError::new(format!(
+                        "Cannot interpret \"{}\" as a range of event codes. Both ends must have the same type, and the first must not come after the last.", part
+                    )));
+                }
+                codes.extend((first.code() ..= last.code()).map(|code| EventCode::new(first.ev_type(), code)));
+            },
+            (code_str, None) => codes.push(parse_event_code(code_str)?),
+        }
+    }
+    Ok(codes)
+}
+
+fn parse_event_code(code_str: &str) -> Result<EventCode, ArgumentError> {
+    match crate::utils::split_once(code_str, ":") {
+        (type_name, Some(code_name)) => ecodes::event_code(type_name, code_name),
+        _ => Err(ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as an event code. Please specify event codes with both a type and a code, such as key:a or abs:mt_slot.", code_str
+        ))),
+    }
+}
+
+/// Parses the value of a usage=PAGE:ID=EVENT clause, where PAGE and ID are hexadecimal numbers.
+fn parse_usage_mapping(value: &str) -> Result<UsageMapping, ArgumentError> {
+    let error = || ArgumentError::new(format!(
+        "Cannot interpret \"usage={}\". Please specify usages in the format usage=PAGE:ID=EVENT, such as usage=ff00:10=key:f13, where PAGE and ID are hexadecimal numbers.", value
+    ));
+    let (usage_str, code_str) = match crate::utils::split_once(value, "=") {
+        (usage_str, Some(code_str)) => (usage_str, code_str),
+        _ => return Err(error()),
+    };
+    let (page, id) = match crate::utils::split_once(usage_str, ":") {
+        (page_str, Some(id_str)) => (
+            u16::from_str_radix(page_str, 16).map_err(|_| error())?,
+            u16::from_str_radix(id_str, 16).map_err(|_| error())?,
+        ),
+        _ => return Err(error()),
+    };
+    let code = match parse_event_codes(code_str)?.as_slice() {
+        &[code] => code,
+        _ => return Err(error()),
+    };
+    Ok(UsageMapping { page, id, code })
+}
+
+/// Returns an error if `address` is not of the form HOST:PORT.
+pub(super) fn check_network_address(address: &str) -> Result<(), ArgumentError> {
+    let is_valid = match address.rfind(':') {
+        Some(index) => index > 0 && address[index + 1 ..].parse::<u16>().is_ok(),
+        None => false,
+    };
+    match is_valid {
+        true => Ok(()),
+        false => Err(ArgumentError::new(format!(
+            "Invalid network address \"{}\". Please provide an address of the form HOST:PORT, such as tcp=0.0.0.0:4321.", address
+        ))),
+    }
+}
+
+/// Returns an error if `path` contains wildcards anywhere besides its last component.
+fn check_pattern(path: &str) -> Result<(), ArgumentError> {
+    let directory = match path.rfind('/') {
+        Some(index) => &path[.. index],
+        None => return Ok(()),
+    };
+    if crate::utils::is_glob_pattern(directory) {
+        return Err(ArgumentError::new(format!(
+            "The input path \"{}\" contains wildcards in a directory name. Wildcards are only supported in the last component of a path, such as /dev/input/by-id/usb-*-event-kbd.", path
+        )));
+    }
+    Ok(())
+}
+
 /// Returns true if `path` is of the form `^/dev/input/event[0-9]+$`.
 fn is_direct_event_device(path: &str) -> bool {
     let path = match crate::utils::strip_prefix(path, "/dev/input/event") {
@@ -93,4 +451,50 @@ fn unittest() {
     assert!(is_direct_event_device("/dev/input/event23"));
     assert!(! is_direct_event_device("/dev/input/by-id/event23"));
     assert!(! is_direct_event_device("/dev/input/event1foo"));
-}
\ No newline at end of file
+
+    assert!(check_pattern("/dev/input/by-id/usb-*-event-kbd").is_ok());
+    assert!(check_pattern("/dev/input/event?").is_ok());
+    assert!(check_pattern("/dev/input/*/usb-foo-event-kbd").is_err());
+
+    assert_eq!(parse_hex_id("vendor", "046d").unwrap(), 0x046d);
+    assert_eq!(parse_hex_id("vendor", "0x046D").unwrap(), 0x046d);
+    assert!(parse_hex_id("vendor", "").is_err());
+    assert!(parse_hex_id("vendor", "0x").is_err());
+    assert!(parse_hex_id("product", "12345").is_err());
+    assert!(parse_hex_id("product", "+12").is_err());
+
+    assert!(check_network_address("0.0.0.0:4321").is_ok());
+    assert!(check_network_address("[::1]:4321").is_ok());
+    assert!(check_network_address("localhost:4321").is_ok());
+    assert!(check_network_address("localhost").is_err());
+    assert!(check_network_address(":4321").is_err());
+    assert!(check_network_address("localhost:99999").is_err());
+
+    assert_eq!(parse_event_codes("key:a,abs:mt_slot").unwrap(), vec![EventCode::new(crate::event::EventType::KEY, 30), EventCode::ABS_MT_SLOT]);
+    assert!(parse_event_codes("key").is_err());
+    assert!(parse_event_codes("key:a,").is_err());
+    assert!(parse_event_codes("key:foo").is_err());
+    assert_eq!(parse_event_codes("key:1..key:3,rel:x").unwrap().len(), 4);
+    assert!(parse_event_codes("key:3..key:1").is_err());
+    assert!(parse_event_codes("key:a..rel:x").is_err());
+
+    assert_eq!(
+        parse_usage_mapping("ff00:10=key:f13").unwrap(),
+        UsageMapping { page: 0xff00, id: 0x10, code: crate::ecodes::event_code("key", "f13").unwrap() },
+    );
+    assert!(parse_usage_mapping("ff00:10").is_err());
+    assert!(parse_usage_mapping("ff00=key:f13").is_err());
+    assert!(parse_usage_mapping("xyz:10=key:f13").is_err());
+    assert!(parse_usage_mapping("ff00:10=key:f13,key:f14").is_err());
+
+    let parse = |args: &[&str]| InputDevice::parse(
+        std::iter::once("--input").chain(args.iter().copied()).map(str::to_owned).collect()
+    );
+    let syn_dropped = |args: &[&str]| parse(args).unwrap().syn_dropped;
+    assert!(syn_dropped(&["/dev/input/by-id/keyboard"]).is_none());
+    assert!(syn_dropped(&["/dev/input/by-id/keyboard", "syn-dropped=resync"]) == Some(SynDroppedPolicy::Resync));
+    assert!(syn_dropped(&["/dev/input/by-id/keyboard", "syn-dropped=log"]) == Some(SynDroppedPolicy::Log));
+    assert!(syn_dropped(&["/dev/input/by-id/keyboard", "syn-dropped=ignore"]) == Some(SynDroppedPolicy::Ignore));
+    assert!(parse(&["/dev/input/by-id/keyboard", "syn-dropped=quux"]).is_err());
+    assert!(parse(&["file=/tmp/recording", "syn-dropped=log"]).is_err());
+}
@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::key::{Key, KeyParser};
+use crate::stream::latch::Latch;
+
+/// Represents a --latch argument.
+pub(super) struct LatchArg {
+    pub keys: Vec<Key>,
+}
+
+impl LatchArg {
+	pub fn parse(args: Vec<String>) -> Result<LatchArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            true,
+        )?;
+
+        let parser = KeyParser {
+            default_value: "",
+            allow_values: false,
+            allow_ranges: false,
+            allow_transitions: false,
+            allow_types: true,
+            allow_relative_values: false,
+            forbid_non_EV_KEY: true,
+            namespace: crate::event::Namespace::User,
+        };
+        let keys = parser.parse_all(&arg_group.require_keys()?)?;
+
+        Ok(LatchArg { keys })
+    }
+
+    pub fn compile(self) -> Latch {
+        Latch::new(self.keys)
+    }
+}
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::logging::LogLevel;
+
+/// Represents a --log argument.
+pub(super) struct LogArg {
+    pub level: LogLevel,
+}
+
+impl LogArg {
+	pub fn parse(args: Vec<String>) -> Result<LogArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["level"],
+            false,
+            false,
+        )?;
+
+        let level = LogLevel::parse(&arg_group.require_unique_clause("level")?)?;
+        Ok(LogArg { level })
+    }
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::toggle::state_directory;
+use crate::key::{Key, KeyParser};
+use crate::stream::hook::Trigger;
+use crate::stream::macros::Macro;
+use std::path::PathBuf;
+
+/// Represents a --macro argument.
+pub(super) struct MacroArg {
+    /// The events that get recorded.
+    pub keys: Vec<Key>,
+    /// The keys that must be held simultaneously to record the macro.
+    pub record_keys: Vec<Key>,
+    /// The keys that must be pressed simultaneously to play the macro.
+    pub play_keys: Vec<Key>,
+    pub slot: u32,
+    /// The file in which the macro of this slot is stored.
+    pub path: PathBuf,
+}
+
+impl MacroArg {
+	pub fn parse(args: Vec<String>) -> Result<MacroArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["record-key", "play-key", "slot"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let record_keys = super::hook::PARSER.parse_all(&arg_group.get_clauses("record-key"))?;
+        let play_keys = super::hook::PARSER.parse_all(&arg_group.get_clauses("play-key"))?;
+        if record_keys.is_empty() || play_keys.is_empty() {
+            return Err(ArgumentError::new("A --macro argument requires at least one record-key= and one play-key= clause."));
+        }
+
+        let slot_str = arg_group.require_unique_clause("slot")?;
+        let slot = match slot_str.parse::<u32>() {
+            Ok(slot) if slot >= 1 => slot,
+            _ => return Err(ArgumentError::new(format!(
+                "The slot of a --macro must be a positive integer. Offending slot: {}", slot_str
+            ))),
+        };
+        let path = state_directory("the macros of --macro arguments")?.join(format!("macro-{}", slot));
+
+        Ok(MacroArg { keys, record_keys, play_keys, slot, path })
+    }
+
+    pub fn compile(self) -> Macro {
+        Macro::new(
+            self.keys,
+            Trigger::new(self.record_keys, None, false),
+            Trigger::new(self.play_keys, None, false),
+            self.path,
+        )
+    }
+}
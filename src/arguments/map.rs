@@ -1,9 +1,11 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::error::{ArgumentError, InternalError, RuntimeError};
+use crate::error::{ArgumentError, InternalError, RuntimeError, Context};
 use crate::arguments::lib::ComplexArgGroup;
 use crate::key::{Key, KeyParser};
+use crate::key;
 use crate::event::Namespace;
+use crate::stream::map::{Map, MapTable};
 
 /// Represents a --map or --copy argument.
 pub(super) struct MapArg {
@@ -15,7 +17,7 @@ impl MapArg {
 	pub fn parse(args: Vec<String>) -> Result<MapArg, RuntimeError> {
         let arg_group = ComplexArgGroup::parse(args,
             &["yield"],
-            &[],
+            &["slot"],
             false,
             true,
         )?;
@@ -28,7 +30,10 @@ impl MapArg {
 
         // Parse the keys.
         let keys_str = arg_group.require_keys()?;
-        let input_key = KeyParser::default_filter().parse(&keys_str[0])?;
+        let mut input_key = KeyParser::default_filter().parse(&keys_str[0])?;
+        if let Some(slot_str) = arg_group.get_unique_clause("slot")? {
+            input_key.add_slot_filter(key::interpret_slot_range(&slot_str)?);
+        }
         
         let output_namespace = match arg_group.has_flag("yield") {
             true => Namespace::Yielded,
@@ -57,13 +62,106 @@ impl BlockArg {
 	pub fn parse(args: Vec<String>) -> Result<BlockArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &[],
+            &["slot"],
             false,
             true,
         )?;
 
-        let keys = KeyParser::default_filter().parse_all(&arg_group.get_keys_or_empty_key())?;
+        let mut keys = KeyParser::default_filter().parse_all(&arg_group.get_keys_or_empty_key())?;
+        if let Some(slot_str) = arg_group.get_unique_clause("slot")? {
+            let slot_range = key::interpret_slot_range(&slot_str)?;
+            for key in &mut keys {
+                key.add_slot_filter(slot_range);
+            }
+        }
 
         Ok(BlockArg { keys })
     }
-}
\ No newline at end of file
+}
+/// Represents a --map table=FILE argument.
+pub(super) struct MapTableArg {
+    pub maps: Vec<(Key, Vec<Key>)>,
+    /// The text of the table, so the capability cache can tell whether it changed.
+    pub contents: String,
+}
+
+impl MapTableArg {
+    pub fn parse(args: Vec<String>) -> Result<MapTableArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &["yield"],
+            &["table"],
+            false,
+            true,
+        )?;
+
+        if ! arg_group.keys.is_empty() {
+            return Err(ArgumentError::new("A --map argument with a table= clause cannot have any other keys."));
+        }
+        let path = arg_group.require_unique_clause("table")?;
+        let output_namespace = match arg_group.has_flag("yield") {
+            true => Namespace::Yielded,
+            false => Namespace::User,
+        };
+
+        let contents = std::fs::read_to_string(&path).map_err(|error| ArgumentError::new(format!(
+            "Failed to read the map table \"{}\": {}", path, error
+        )))?;
+        let maps = parse_table(&contents, output_namespace)
+            .map_err(|error| error.with_context(format!("While reading the map table \"{}\":", path)))?;
+
+        Ok(MapTableArg { maps, contents })
+    }
+
+    pub fn compile(self) -> Result<MapTable, InternalError> {
+        MapTable::new(
+            self.maps.into_iter()
+                .map(|(input_key, output_keys)| Map::new(input_key, output_keys))
+                .collect()
+        )
+    }
+}
+
+/// Parses the contents of a map table. Every line contains an input key followed by zero or more
+/// output keys, separated by whitespace. Everything after a # is ignored.
+fn parse_table(contents: &str, output_namespace: Namespace) -> Result<Vec<(Key, Vec<Key>)>, ArgumentError> {
+    let input_parser = KeyParser::default_filter();
+    let mut output_parser = KeyParser::default_mask();
+    output_parser.with_namespace(output_namespace);
+
+    let mut maps = Vec::new();
+    for (line_index, line) in contents.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(index) => &line[..index],
+            None => line,
+        };
+        let key_strs: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        if key_strs.is_empty() {
+            continue;
+        }
+
+        let parse_line = || -> Result<(Key, Vec<Key>), ArgumentError> {
+            let input_key = input_parser.parse(&key_strs[0])?;
+            if input_key.requires_event_code().is_none() {
+                return Err(ArgumentError::new(format!(
+                    "Every input key in a map table must specify an event code, such as key:a. Offending key: {}", key_strs[0]
+                )));
+            }
+            let output_keys = output_parser.parse_all(&key_strs[1..])?;
+            Ok((input_key, output_keys))
+        };
+        maps.push(parse_line().map_err(|error| error.with_context(format!("On line {}:", line_index + 1)))?);
+    }
+
+    Ok(maps)
+}
+
+#[test]
+fn unittest() {
+    let maps = parse_table("key:a key:b\n\n# A comment.\nkey:b key:a # Swap.\nkey:c\n", Namespace::User).unwrap();
+    assert_eq!(maps.len(), 3);
+    assert_eq!(maps[0].1.len(), 1);
+    assert_eq!(maps[2].1.len(), 0);
+
+    assert!(parse_table("key key:a", Namespace::User).is_err());
+    assert!(parse_table("key:a key:b\nfoo", Namespace::User).is_err());
+}
@@ -2,23 +2,46 @@
 
 use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
+use crate::event::EventType;
 use crate::key::{Key, KeyParser};
-use crate::stream::merge::Merge;
+use crate::stream::merge::{Merge, MergeScope};
 
 /// Represents a --merge argument.
 pub(super) struct MergeArg {
     pub keys: Vec<Key>,
+    pub types: Vec<EventType>,
+    pub scope: MergeScope,
 }
 
 impl MergeArg {
 	pub fn parse(args: Vec<String>) -> Result<MergeArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &[],
+            &["scope", "types"],
             false,
             true,
         )?;
 
+        let scope = match arg_group.get_unique_clause("scope")?.as_deref() {
+            None | Some("domain") => MergeScope::Domain,
+            Some("device") => MergeScope::Device,
+            Some("global") => MergeScope::Global,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "The scope= clause of --merge must be one of domain, device or global. Offending value: {}", other
+            ))),
+        };
+
+        let types = match arg_group.get_unique_clause("types")? {
+            None => vec![EventType::KEY],
+            Some(types_str) => types_str.split(',').map(|type_str| match type_str {
+                "key" => Ok(EventType::KEY),
+                "sw" => Ok(EventType::SW),
+                _ => Err(ArgumentError::new(format!(
+                    "The types= clause of --merge only accepts the types key and sw. Offending type: {}", type_str
+                ))),
+            }).collect::<Result<Vec<EventType>, ArgumentError>>()?,
+        };
+
         let parser = KeyParser {
             default_value: "",
             allow_values: false,
@@ -26,17 +49,27 @@ impl MergeArg {
             allow_transitions: false,
             allow_types: true,
             allow_relative_values: false,
-            forbid_non_EV_KEY: true,
+            forbid_non_EV_KEY: false,
             namespace: crate::event::Namespace::User,
         };
 
-        let keys: Vec<Key> = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
+        let key_strs = arg_group.get_keys_or_empty_key();
+        let keys: Vec<Key> = parser.parse_all(&key_strs)?;
+        for (key, key_str) in keys.iter().zip(&key_strs) {
+            if let Some(ev_type) = key.requires_event_type() {
+                if ! types.contains(&ev_type) {
+                    return Err(ArgumentError::new(format!(
+                        "The --merge argument does not merge events of the type of {}. Use the types= clause to merge events of other types than key.", key_str
+                    )));
+                }
+            }
+        }
 
-        Ok(MergeArg { keys })
+        Ok(MergeArg { keys, types, scope })
     }
 
     pub fn compile(self) -> Merge {
-        Merge::new(self.keys)
+        Merge::new(self.keys, self.types, self.scope)
     }
 }
 
@@ -56,4 +89,10 @@ fn unittest() {
     assert!(MergeArg::parse(vec!["--merge".to_string(), "abs:x".to_string()]).is_err());
     assert!(MergeArg::parse(vec!["--merge".to_string(), "key".to_string(), "abs".to_string()]).is_err());
     assert!(MergeArg::parse(vec!["--merge".to_string(), "abs@foo".to_string()]).is_err());
+
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "scope=global".to_string()]).is_ok());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "sw".to_string(), "types=key,sw".to_string()]).is_ok());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "scope=thread".to_string()]).is_err());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "sw".to_string()]).is_err());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "types=abs".to_string()]).is_err());
 }
\ No newline at end of file
@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::path::PathBuf;
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::metrics::MetricsAddress;
+
+/// Represents a --metrics argument.
+pub(super) struct MetricsArg {
+    pub address: MetricsAddress,
+}
+
+impl MetricsArg {
+	pub fn parse(args: Vec<String>) -> Result<MetricsArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["tcp"],
+            true,
+            false,
+        )?;
+
+        let address = match (arg_group.paths.as_slice(), arg_group.get_unique_clause("tcp")?) {
+            ([path], None) => MetricsAddress::Unix(PathBuf::from(path)),
+            ([], Some(address)) => MetricsAddress::Tcp(address),
+            _ => return Err(ArgumentError::new(
+                "The --metrics argument requires either a single path for a Unix socket or a tcp=HOST:PORT clause."
+            )),
+        };
+
+        Ok(MetricsArg { address })
+    }
+}
@@ -1,36 +1,73 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::predevice::RepeatMode;
-use crate::error::ArgumentError;
+use crate::predevice::{NodePermissions, OutputIdentity, RepeatMode, SynMode};
+use crate::capability::{AbsInfo, AbsMeta, RepeatInfo};
+use crate::arguments::input::{check_network_address, parse_event_codes, parse_hex_id};
+use crate::error::{ArgumentError, Context};
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::time::Duration;
 use crate::key::{Key, KeyParser};
-use crate::event::Namespace;
+use crate::event::{EventCode, EventType, EventValue, Namespace};
+use crate::ecodes;
+use crate::utils::split_once;
+use crate::io::record::{RecordFormat, RecordTarget};
+use std::ffi::CString;
 use std::path::PathBuf;
 
 const DEFAULT_NAME: &str = "Evsieve Virtual Device";
 
 pub(super) struct OutputDevice {
-    pub create_link: Option<PathBuf>,
+    pub create_links: Vec<PathBuf>,
     pub name: String,
     pub keys: Vec<Key>,
     pub repeat_mode: RepeatMode,
+    /// Set if the user specified the delay or period with which the kernel shall generate repeat events.
+    pub repeat_info: Option<RepeatInfo>,
+    pub syn_mode: SynMode,
+    /// The path of an input device to which force feedback effects shall be forwarded.
+    pub forward_ff: Option<PathBuf>,
+    /// The force feedback effects that this device shall advertise on its own.
+    pub ff_effects: Vec<EventCode>,
+    /// The LEDs that this device shall advertise.
+    pub leds: Vec<EventCode>,
+    pub defer: bool,
+    pub idle_timeout: Option<Duration>,
+    /// Whether keyboard-, pointer- and gamepad-like events shall go to separate devices.
+    pub split_by_type: bool,
+    /// Whether events that would not change the state of the device shall be dropped.
+    pub dedup: bool,
+    pub identity: OutputIdentity,
+    pub permissions: NodePermissions,
+    /// Axis ranges that override the ones computed by capability propagation.
+    pub abs_overrides: Vec<(EventCode, AbsInfo)>,
+    /// If Some, the device shall have exactly these capabilities.
+    pub pinned_caps: Option<Vec<EventCode>>,
+    /// If Some, the events shall be recorded to this file or sent over the network instead of
+    /// written to a virtual device.
+    pub record: Option<(RecordTarget, RecordFormat)>,
 }
 
 impl OutputDevice {
 	pub fn parse(args: Vec<String>) -> Result<OutputDevice, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &["repeat"],
-            &["create-link", "name", "repeat"],
+            &["repeat", "defer", "split-by-type", "dedup", "stdout"],
+            &["create-link", "name", "repeat", "forward-ff", "vendor", "product", "version", "bus", "phys", "uniq", "absinfo", "ff", "leds", "file", "tcp", "format", "defer", "caps", "caps-file", "syn", "mode", "owner", "group"],
             false,
             true,
         )?;
 
+        let mut repeat_info = None;
         let repeat_mode = match arg_group.get_unique_clause_or_default_if_flag("repeat", "enable")? {
             None => RepeatMode::Passive,
             Some(mode) => match mode.as_str() {
                 "enable" => RepeatMode::Enable,
                 "disable" => RepeatMode::Disable,
                 "passive" => RepeatMode::Passive,
+                _ if mode.contains(':') => {
+                    repeat_info = Some(parse_repeat_info(&mode)?);
+                    RepeatMode::Enable
+                },
                 _ => return Err(ArgumentError::new(format!("Invalid repeat mode \"{}\".", mode)))
             },
         };
@@ -40,6 +77,154 @@ impl OutputDevice {
             return Err(ArgumentError::new("Output device name cannot be empty."));
         }
 
+        let parse_id = |clause: &str| -> Result<Option<u16>, ArgumentError> {
+            match arg_group.get_unique_clause(clause)? {
+                Some(value) => Ok(Some(parse_hex_id(clause, &value)?)),
+                None => Ok(None),
+            }
+        };
+        let parse_string = |clause: &str| -> Result<Option<String>, ArgumentError> {
+            match arg_group.get_unique_clause(clause)? {
+                Some(value) if value.is_empty() => Err(ArgumentError::new(format!(
+                    "The {}= clause cannot be empty.", clause
+                ))),
+                value => Ok(value),
+            }
+        };
+        let identity = OutputIdentity {
+            vendor: parse_id("vendor")?,
+            product: parse_id("product")?,
+            version: parse_id("version")?,
+            bustype: match arg_group.get_unique_clause("bus")? {
+                Some(value) => Some(parse_bus(&value)?),
+                None => None,
+            },
+            phys: parse_string("phys")?,
+            uniq: parse_string("uniq")?,
+        };
+
+        let permissions = NodePermissions {
+            mode: match arg_group.get_unique_clause("mode")? {
+                Some(value) => Some(parse_mode(&value)?),
+                None => None,
+            },
+            owner: match arg_group.get_unique_clause("owner")? {
+                Some(value) => Some(parse_owner(&value)?),
+                None => None,
+            },
+            group: match arg_group.get_unique_clause("group")? {
+                Some(value) => Some(parse_group(&value)?),
+                None => None,
+            },
+        };
+
+        let abs_overrides = arg_group.get_clauses("absinfo").iter()
+            .map(|value| parse_absinfo(value))
+            .collect::<Result<Vec<_>, ArgumentError>>()?;
+        for (index, (code, _)) in abs_overrides.iter().enumerate() {
+            if abs_overrides[.. index].iter().any(|(other_code, _)| other_code == code) {
+                return Err(ArgumentError::new(format!(
+                    "The axis {} has been given multiple absinfo= clauses.", ecodes::event_name(*code)
+                )));
+            }
+        }
+
+        let mut targets = Vec::new();
+        if let Some(path) = arg_group.get_unique_clause("file")? {
+            targets.push((RecordTarget::File(PathBuf::from(path)), "file="));
+        }
+        if let Some(address) = arg_group.get_unique_clause("tcp")? {
+            check_network_address(&address)?;
+            targets.push((RecordTarget::Tcp(address), "tcp="));
+        }
+        if arg_group.has_flag("stdout") {
+            targets.push((RecordTarget::Stdout, "stdout"));
+        }
+        if targets.len() > 1 {
+            return Err(ArgumentError::new("An --output argument can only have one of the file=, tcp= and stdout clauses."));
+        }
+        let target = targets.pop();
+        let record = match target {
+            Some((target, target_clause)) => {
+                // None of the clauses that configure a virtual device make sense for a file or socket.
+                let device_clauses = ["create-link", "name", "repeat", "forward-ff", "ff", "vendor", "product", "version", "bus", "phys", "uniq", "absinfo", "leds", "defer", "split-by-type", "dedup", "caps", "caps-file", "syn", "mode", "owner", "group"];
+                for &clause in &device_clauses {
+                    if ! arg_group.get_clauses(clause).is_empty() || arg_group.has_flag(clause) {
+                        return Err(ArgumentError::new(format!(
+                            "The {} clause cannot be used together with the {} clause.", clause, target_clause
+                        )));
+                    }
+                }
+                let format = match arg_group.get_unique_clause("format")? {
+                    None => RecordFormat::Binary,
+                    Some(format) => match format.as_str() {
+                        "binary" => RecordFormat::Binary,
+                        "json" | "json-lines" => RecordFormat::Json,
+                        _ => return Err(ArgumentError::new(format!(
+                            "Invalid recording format \"{}\". Valid formats are binary and json-lines.", format
+                        ))),
+                    },
+                };
+                Some((target, format))
+            },
+            None => {
+                if arg_group.get_unique_clause("format")?.is_some() {
+                    return Err(ArgumentError::new("The format= clause can only be used together with the file=, tcp= or stdout clause."));
+                }
+                None
+            },
+        };
+
+        let syn_mode = match arg_group.get_unique_clause("syn")?.as_deref() {
+            None | Some("passthrough") => SynMode::Passthrough,
+            Some("coalesce") => SynMode::Coalesce,
+            Some("per-event") => SynMode::PerEvent,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid value for the syn= clause: \"{}\". Valid values are passthrough, coalesce and per-event.", other
+            ))),
+        };
+
+        // A bare defer flag defers creation; defer=SECONDS additionally destroys the device after idling.
+        let idle_timeout = match arg_group.get_unique_clause("defer")? {
+            Some(value) => Some(parse_period_value(&value)?),
+            None => None,
+        };
+        let defer = arg_group.has_flag("defer") || idle_timeout.is_some();
+
+        let pinned_caps = match (arg_group.get_unique_clause("caps")?, arg_group.get_unique_clause("caps-file")?) {
+            (Some(value), None) => Some(parse_event_codes(&value).with_context("While parsing the caps= clause:")?),
+            (None, Some(path)) => {
+                let contents = std::fs::read_to_string(&path).map_err(|error| ArgumentError::new(format!(
+                    "Failed to read the capabilities file \"{}\": {}", path, error
+                )))?;
+                // The file may list its capabilities separated by commas or whitespace.
+                let value = contents.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|part| ! part.is_empty())
+                    .collect::<Vec<&str>>()
+                    .join(",");
+                Some(parse_event_codes(&value).with_context(format!("While parsing the capabilities file \"{}\":", path))?)
+            },
+            (Some(_), Some(_)) => return Err(ArgumentError::new("An --output argument cannot have both a caps= and a caps-file= clause.")),
+            (None, None) => None,
+        };
+        if let Some(pinned_caps) = &pinned_caps {
+            // Without a range, we could not guarantee the axes are the same every time.
+            for &code in pinned_caps {
+                if code.ev_type().is_abs() && ! abs_overrides.iter().any(|(other_code, _)| *other_code == code) {
+                    return Err(ArgumentError::new(format!(
+                        "The axis {} from the capabilities of an output device requires an absinfo= clause as well.", ecodes::event_name(code)
+                    )));
+                }
+            }
+            for (code, _) in &abs_overrides {
+                if ! pinned_caps.contains(code) {
+                    return Err(ArgumentError::new(format!(
+                        "The axis {} has an absinfo= clause, but is not part of the capabilities of its output device.", ecodes::event_name(*code)
+                    )));
+                }
+            }
+        }
+
         // Parse the keys that shall be sent to this output device.
         let key_strs = arg_group.get_keys_or_empty_key();
         let mut keys = Vec::new();
@@ -50,8 +235,220 @@ impl OutputDevice {
         }
 
 		Ok(OutputDevice {
-            create_link: arg_group.get_unique_clause("create-link")?.map(PathBuf::from),
-            name, keys, repeat_mode,
+            create_links: arg_group.get_clauses("create-link").into_iter().map(PathBuf::from).collect(),
+            name, keys, repeat_mode, repeat_info, syn_mode,
+            forward_ff: arg_group.get_unique_clause("forward-ff")?.map(PathBuf::from),
+            ff_effects: match arg_group.get_unique_clause("ff")? {
+                Some(value) => parse_ff_effects(&value)?,
+                None => Vec::new(),
+            },
+            leds: match arg_group.get_unique_clause("leds")? {
+                Some(value) => parse_leds(&value)?,
+                None => Vec::new(),
+            },
+            identity, permissions, abs_overrides, pinned_caps, record, defer, idle_timeout,
+            split_by_type: arg_group.has_flag("split-by-type"),
+            dedup: arg_group.has_flag("dedup"),
         })
     }
+}
+
+/// Parses the value of a bus= clause, which is either the name of a common bus or a hexadecimal
+/// number as listed in linux/input.h.
+fn parse_bus(value: &str) -> Result<u16, ArgumentError> {
+    Ok(match value {
+        "pci" => 0x01,
+        "usb" => 0x03,
+        "bluetooth" => 0x05,
+        "virtual" => 0x06,
+        "i8042" => 0x11,
+        "i2c" => 0x18,
+        _ => parse_hex_id("bus", value).map_err(|_| ArgumentError::new(format!(
+            "Invalid bus \"{}\". Please specify usb, bluetooth, pci, i8042, i2c, virtual, or a hexadecimal number.", value
+        )))?,
+    })
+}
+
+/// Parses the value of a repeat= clause of the form delay:MS,period:MS. Either of them may be
+/// omitted, in which case the kernel default is used for it.
+fn parse_repeat_info(value: &str) -> Result<RepeatInfo, ArgumentError> {
+    let mut repeat_info = RepeatInfo::kernel_default();
+    for part in value.split(',') {
+        let (name, number_str) = match split_once(part, ":") {
+            (name, Some(number_str)) => (name, number_str),
+            (_, None) => return Err(ArgumentError::new(format!(
+                "Invalid repeat mode \"{}\". Expected a value like repeat=delay:250,period:33.", value
+            ))),
+        };
+        let number: EventValue = match number_str.parse() {
+            Ok(number) if number >= 0 => number,
+            _ => return Err(ArgumentError::new(format!(
+                "The {} of the repeat= clause must be a nonnegative amount of milliseconds, but \"{}\" was provided.", name, number_str
+            ))),
+        };
+        match name {
+            "delay" => repeat_info.delay = number,
+            "period" => repeat_info.period = number,
+            _ => return Err(ArgumentError::new(format!(
+                "Invalid repeat parameter \"{}\". Only delay and period can be specified.", name
+            ))),
+        }
+    }
+    Ok(repeat_info)
+}
+
+/// Parses the value of an ff= clause, which is a comma-separated list of force feedback effects
+/// such as rumble,periodic,sine.
+fn parse_ff_effects(value: &str) -> Result<Vec<EventCode>, ArgumentError> {
+    let mut effects: Vec<EventCode> = Vec::new();
+    for name in value.split(',') {
+        let code = ecodes::event_code("ff", name).map_err(|_| ArgumentError::new(format!(
+            "Unknown force feedback effect \"{}\". Examples of valid effects are rumble, periodic, constant, sine and gain.", name
+        )))?;
+        if code.ev_type() != EventType::FF {
+            return Err(ArgumentError::new(format!("Unknown force feedback effect \"{}\".", name)));
+        }
+        if ! effects.contains(&code) {
+            effects.push(code);
+        }
+    }
+    Ok(effects)
+}
+
+/// Parses the value of a mode= clause, which is an octal number like 0660.
+fn parse_mode(value: &str) -> Result<libc::mode_t, ArgumentError> {
+    match libc::mode_t::from_str_radix(value, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(ArgumentError::new(format!(
+            "Invalid file mode \"{}\". The mode must be an octal number such as 0660.", value
+        ))),
+    }
+}
+
+/// Parses the value of an owner= clause, which is either the name or the id of a user.
+fn parse_owner(value: &str) -> Result<libc::uid_t, ArgumentError> {
+    if let Ok(uid) = value.parse() {
+        return Ok(uid);
+    }
+    let name = CString::new(value).map_err(|_| ArgumentError::new("User names cannot contain null bytes."))?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return Err(ArgumentError::new(format!("The user \"{}\" does not exist.", value)));
+    }
+    Ok(unsafe { (*passwd).pw_uid })
+}
+
+/// Parses the value of a group= clause, which is either the name or the id of a group.
+fn parse_group(value: &str) -> Result<libc::gid_t, ArgumentError> {
+    if let Ok(gid) = value.parse() {
+        return Ok(gid);
+    }
+    let name = CString::new(value).map_err(|_| ArgumentError::new("Group names cannot contain null bytes."))?;
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    if group.is_null() {
+        return Err(ArgumentError::new(format!("The group \"{}\" does not exist.", value)));
+    }
+    Ok(unsafe { (*group).gr_gid })
+}
+
+/// Parses the value of a leds= clause, which is a comma-separated list of LED names like capsl.
+fn parse_leds(value: &str) -> Result<Vec<EventCode>, ArgumentError> {
+    let mut leds: Vec<EventCode> = Vec::new();
+    for name in value.split(',') {
+        let code = ecodes::event_code("led", name).map_err(|_| ArgumentError::new(format!(
+            "Unknown LED \"{}\". Examples of valid LEDs are numl, capsl, scrolll, compose and kana.", name
+        )))?;
+        if ! leds.contains(&code) {
+            leds.push(code);
+        }
+    }
+    Ok(leds)
+}
+
+/// Parses the value of an absinfo= clause, which has the form TYPE:CODE:MIN~MAX[,FUZZ[,FLAT[,RESOLUTION]]].
+fn parse_absinfo(value: &str) -> Result<(EventCode, AbsInfo), ArgumentError> {
+    let error = || ArgumentError::new(format!(
+        "The absinfo= clause requires a value of the form abs:CODE:MIN~MAX[,FUZZ,FLAT,RESOLUTION], but \"{}\" was provided.", value
+    ));
+    let mut parts = value.splitn(3, ':');
+    let (type_name, code_name, info_str) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(type_name), Some(code_name), Some(info_str)) => (type_name, code_name, info_str),
+        _ => return Err(error()),
+    };
+    let code = ecodes::event_code(type_name, code_name)?;
+    if code.ev_type() != EventType::ABS {
+        return Err(ArgumentError::new(format!(
+            "The absinfo= clause can only be used with events of type abs, but \"{}\" was provided.", value
+        )));
+    }
+
+    let mut info_parts = info_str.split(',');
+    let (min_str, max_str) = match split_once(info_parts.next().unwrap_or(""), "~") {
+        (min_str, Some(max_str)) => (min_str, max_str),
+        (_, None) => return Err(error()),
+    };
+    let min_value: EventValue = min_str.parse().map_err(|_| error())?;
+    let max_value: EventValue = max_str.parse().map_err(|_| error())?;
+    if min_value >= max_value {
+        return Err(ArgumentError::new(format!(
+            "The upper bound of the range in \"absinfo={}\" must be larger than its lower bound.", value
+        )));
+    }
+
+    let mut meta_values: Vec<i32> = Vec::new();
+    for part in info_parts {
+        meta_values.push(part.parse().map_err(|_| error())?);
+    }
+    if meta_values.len() > 3 {
+        return Err(error());
+    }
+    let get_meta = |index: usize| meta_values.get(index).copied().unwrap_or(0);
+
+    Ok((code, AbsInfo {
+        min_value, max_value,
+        meta: AbsMeta { fuzz: get_meta(0), flat: get_meta(1), resolution: get_meta(2), value: 0 },
+    }))
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_bus("usb").unwrap(), 0x03);
+    assert_eq!(parse_bus("bluetooth").unwrap(), 0x05);
+    assert_eq!(parse_bus("19").unwrap(), 0x19);
+    assert!(parse_bus("serial port").is_err());
+
+    let repeat_info = parse_repeat_info("delay:200,period:20").unwrap();
+    assert_eq!((repeat_info.delay, repeat_info.period), (200, 20));
+    let repeat_info = parse_repeat_info("period:10").unwrap();
+    assert_eq!((repeat_info.delay, repeat_info.period), (RepeatInfo::kernel_default().delay, 10));
+    assert!(parse_repeat_info("delay:-5").is_err());
+    assert!(parse_repeat_info("speed:5").is_err());
+    assert!(parse_repeat_info("delay:5,period").is_err());
+
+    assert_eq!(parse_ff_effects("rumble,periodic,rumble").unwrap().len(), 2);
+    assert!(parse_ff_effects("rumble,wobble").is_err());
+
+    assert_eq!(parse_leds("numl,capsl,numl").unwrap(), vec![
+        EventCode::new(EventType::LED, 0), EventCode::new(EventType::LED, 1),
+    ]);
+    assert!(parse_leds("capsl,").is_err());
+
+    assert_eq!(parse_mode("0660").unwrap(), 0o660);
+    assert_eq!(parse_mode("644").unwrap(), 0o644);
+    assert!(parse_mode("0990").is_err());
+    assert!(parse_mode("17777").is_err());
+    assert_eq!(parse_owner("0").unwrap(), 0);
+    assert_eq!(parse_owner("root").unwrap(), 0);
+    assert_eq!(parse_group("1000").unwrap(), 1000);
+
+    let (code, info) = parse_absinfo("abs:x:-100~100,4,8,1").unwrap();
+    assert_eq!(code, EventCode::new(EventType::ABS, 0));
+    assert_eq!((info.min_value, info.max_value), (-100, 100));
+    assert_eq!((info.meta.fuzz, info.meta.flat, info.meta.resolution), (4, 8, 1));
+    let (_, info) = parse_absinfo("abs:y:0~255").unwrap();
+    assert_eq!((info.meta.fuzz, info.meta.flat, info.meta.resolution), (0, 0, 0));
+    assert!(parse_absinfo("abs:x:100~-100").is_err());
+    assert!(parse_absinfo("abs:x:0~10,1,2,3,4").is_err());
+    assert!(parse_absinfo("key:a:0~1").is_err());
+    assert!(parse_absinfo("abs:x").is_err());
 }
\ No newline at end of file
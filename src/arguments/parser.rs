@@ -1,44 +1,112 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::domain;
-use crate::error::{ArgumentError, RuntimeError, Context, SystemError};
+use crate::domain::{self, Domain};
+use crate::error::{ArgumentError, InternalError, RuntimeError, Context, SystemError};
 use crate::key::Key;
-use crate::event::Namespace;
+use crate::event::{EventType, Namespace};
 use crate::stream::hook::Hook;
 use crate::stream::map::{Map, Toggle};
 use crate::stream::withhold::Withhold;
 use crate::stream::{StreamEntry, Setup};
-use crate::predevice::{PreInputDevice, PreOutputDevice};
+use crate::predevice::{DeviceClass, GrabCondition, GrabMode, PersistMode, PreInputDevice, PreOutputDevice, SynDroppedPolicy};
+use crate::persist::pattern::{Pattern, find_matching_paths};
 use crate::state::{State, ToggleIndex};
 use crate::control_fifo::ControlFifo;
+use crate::control_socket::ControlSocket;
+use crate::dbus::DBusService;
+use crate::metrics::MetricsListener;
+use crate::logging::{self, LogLevel};
+use crate::capability::{Capabilities, InputCapabilites};
+use crate::capcache::{ArgumentsHasher, CapsCache};
+use crate::io::output::OutputSystem;
+use crate::io::network::{NetworkInput, NetworkListener, NetworkProtocol};
+use crate::io::record::{RecordFormat, RecordTarget};
+use crate::io::replay::ReplayDevice;
+use crate::io::hidraw::HidrawDevice;
+use crate::io::joystick::JoystickDevice;
 use crate::arguments::hook::HookArg;
 use crate::arguments::input::InputDevice;
 use crate::arguments::output::OutputDevice;
-use crate::arguments::toggle::ToggleArg;
-use crate::arguments::map::{MapArg, BlockArg};
+use crate::arguments::toggle::{ToggleArg, state_directory};
+use crate::arguments::map::{MapArg, MapTableArg, BlockArg};
 use crate::arguments::print::PrintArg;
 use crate::arguments::delay::DelayArg;
+use crate::arguments::debounce::DebounceArg;
+use crate::arguments::throttle::ThrottleArg;
+use crate::arguments::latch::LatchArg;
+use crate::arguments::sticky::StickyArg;
+use crate::arguments::chord::ChordArg;
+use crate::arguments::counter::CounterArg;
+use crate::arguments::timer::TimerArg;
 use crate::arguments::withhold::WithholdArg;
+use crate::arguments::repeat::RepeatArg;
+use crate::arguments::macros::MacroArg;
+use crate::arguments::script::ScriptArg;
+use crate::arguments::unicode::UnicodeArg;
 use crate::arguments::control_fifo::ControlFifoArg;
+use crate::arguments::control_socket::ControlSocketArg;
+use crate::arguments::dbus::DBusArg;
+use crate::arguments::metrics::MetricsArg;
+use crate::arguments::log::LogArg;
+use crate::arguments::config::ConfigArg;
+use crate::arguments::define::{DefineArg, Variables};
+use crate::arguments::profile::{ProfileArg, PROFILE_TOGGLE_ID};
+use crate::arguments::rotary::RotaryArg;
+use crate::arguments::split::SplitArg;
+use crate::arguments::touchpad::TouchpadArg;
+use crate::arguments::accel::AccelArg;
+use crate::arguments::combine::CombineArg;
+use crate::arguments::trace::TraceArg;
+use crate::arguments::benchmark::BenchmarkArg;
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use super::merge::MergeArg;
 
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 // TODO: if control-fifo does not make it to release 1.4, remove it from this USAGE_MSG.
 const USAGE_MSG: &str = 
-"Usage: evsieve [--input PATH... [domain=DOMAIN] [grab[=auto|force]] [persist=none|reopen|exit]]...
-               [--map SOURCE [DEST...] [yield]]...
-               [--copy SOURCE [DEST...] [yield]]...
-               [--block [SOURCE...]]...
+"Usage: evsieve [--input PATH...|[name=NAME] [vendor=VENDOR] [product=PRODUCT]|file=FILE [mode=replay] [timing=original|fast]|tcp=HOST:PORT|udp=HOST:PORT|hidraw=PATH [descriptor=FILE] usage=PAGE:ID=EVENT...|joystick=PATH [domain=DOMAIN|auto] [grab[=auto|force|required]] [persist=none|reopen|full|exit] [reopen-interval=SECONDS] [reopen-max-interval=SECONDS] [grab-while=ID:STATE] [require-caps=CODES] [exclude-caps=CODES] [clock=realtime|monotonic|boottime] [syn-dropped=resync|log|ignore] [measure-latency] [sync-state] [forward-leds]]...
+               [--map SOURCE [DEST...] [yield] [slot=RANGE]]...
+               [--map table=FILE [yield]]...
+               [--copy SOURCE [DEST...] [yield] [slot=RANGE]]...
+               [--block [SOURCE...] [slot=RANGE]]...
                [--control-fifo PATH...]...
-               [--toggle SOURCE DEST... [id=ID] [mode=consistent|passive]]...
-               [--hook KEY... [exec-shell=COMMAND]... [toggle[=[ID][:INDEX]]]... [sequential] [period=SECONDS]]...
-               [--merge [EVENTS...]]...
-               [--print [EVENTS...] [format=default|direct]]...
-               [--delay [EVENTS...] period=SECONDS]...
-               [--output [EVENTS...] [create-link=PATH] [name=NAME] [repeat[=MODE]]]...";
+               [--control-socket PATH...]...
+               [--dbus [bus=session|system] [name=NAME]]
+               [--metrics PATH|tcp=HOST:PORT]
+               [--log level=error|warning|info|debug]
+               [--config PATH]...
+               [--define NAME=VALUE...]...
+               [--dry-run]
+               [--parallel]
+               [--benchmark [events=COUNT]]
+               [--cache-caps]
+               [--profile NAME ... --end-profile]...
+               [--toggle SOURCE DEST... [id=ID] [mode=consistent|passive] [states=NAME,...] [persist=none|state] [timeout=SECONDS] [led=STATE:LED]... [exec-shell=COMMAND]...]...
+               [--hook KEY... [exec-shell=COMMAND]... [toggle[=[ID][:INDEX|:STATE]]]... [sequential] [period=SECONDS] [id=ID]]...
+               [--merge [EVENTS...] [scope=domain|device|global] [types=TYPES]]...
+               [--print [EVENTS...] [format=default|direct|json|TEMPLATE] [timestamp=MODE] [color[=MODE]] [delta] [mode=events|changes|summary [interval=SECONDS]] [file=PATH [max-size=BYTES] [rotate=COUNT]]]...
+               [--delay [EVENTS...] period=SECONDS [[EVENTS...] period=SECONDS]... [jitter=SECONDS]]...
+               [--repeat [EVENTS...] [delay=SECONDS] [period=SECONDS]]...
+               [--debounce [EVENTS...] period=SECONDS]...
+               [--throttle [EVENTS...] period=SECONDS [mode=drop|coalesce]]...
+               [--latch EVENTS...]...
+               [--sticky KEY... [timeout=SECONDS]]...
+               [--chord KEY... send-key=KEY... [window=SECONDS]]...
+               [--counter [EVENTS...] output=EVENT [mode=count|sum] [range=MIN~MAX] [reset=EVENT...] [period=SECONDS]]...
+               [--timer period=SECONDS send-key=KEY...]...
+               [--macro [EVENTS...] record-key=KEY... play-key=KEY... slot=N]...
+               [--script [EVENTS...] file=PATH [emit=EVENT]...]...
+               [--unicode SOURCE char=CHARACTER]...
+               [--rotary SOURCE DEST [step=UNITS] [wrap=MIN~MAX]]...
+               [--split SOURCE RANGE@DOMAIN...]...
+               [--touchpad [EVENTS...] [speed=FACTOR] [tap[=SECONDS]] [tap-distance=UNITS]]...
+               [--accel [EVENTS...] [profile=flat|adaptive] [speed=FACTOR] [curve=EXPONENT] [threshold=UNITS]]...
+               [--combine NEGATIVE POSITIVE DEST]...
+               [--trace [EVENTS...]]
+               [--output [EVENTS...] [create-link=PATH]... [name=NAME] [repeat[=MODE]] [forward-ff=PATH] [ff=EFFECTS] [leds=LEDS] [vendor=ID] [product=ID] [version=ID] [bus=BUS] [phys=PHYS] [uniq=UNIQ] [mode=MODE] [owner=USER] [group=GROUP] [absinfo=abs:CODE:MIN~MAX[,FUZZ,FLAT,RESOLUTION]]... [defer[=SECONDS]] [split-by-type] [dedup] [caps=EVENTS|caps-file=PATH] [syn=MODE]]...
+               [--output [EVENTS...] file=PATH|tcp=HOST:PORT|stdout [format=binary|json-lines]]...";
 
 enum Argument {
     InputDevice(InputDevice),
@@ -50,8 +118,33 @@ enum Argument {
     PrintArg(PrintArg),
     MergeArg(MergeArg),
     DelayArg(DelayArg),
+    DebounceArg(DebounceArg),
+    ThrottleArg(ThrottleArg),
+    LatchArg(LatchArg),
+    StickyArg(StickyArg),
+    ChordArg(ChordArg),
+    CounterArg(CounterArg),
+    TimerArg(TimerArg),
     WithholdArg(WithholdArg),
+    RepeatArg(RepeatArg),
+    MacroArg(MacroArg),
+    ScriptArg(ScriptArg),
+    UnicodeArg(UnicodeArg),
     ControlFifoArg(ControlFifoArg),
+    ControlSocketArg(ControlSocketArg),
+    DBusArg(DBusArg),
+    MetricsArg(MetricsArg),
+    RotaryArg(RotaryArg),
+    SplitArg(SplitArg),
+    TouchpadArg(TouchpadArg),
+    AccelArg(AccelArg),
+    CombineArg(CombineArg),
+    MapTableArg(MapTableArg),
+    TraceArg(TraceArg),
+    BenchmarkArg(BenchmarkArg),
+    CacheCapsFlag,
+    ProfileArg(ProfileArg),
+    EndProfileArg,
 }
 
 impl Argument {
@@ -60,7 +153,10 @@ impl Argument {
         match first_arg.as_str() {
             "--input" => Ok(Argument::InputDevice(InputDevice::parse(args)?)),
             "--output" => Ok(Argument::OutputDevice(OutputDevice::parse(args)?)),
-            "--map" => Ok(Argument::MapArg(MapArg::parse(args)?)),
+            "--map" => match args.iter().any(|arg| arg.starts_with("table=")) {
+                true => Ok(Argument::MapTableArg(MapTableArg::parse(args)?)),
+                false => Ok(Argument::MapArg(MapArg::parse(args)?)),
+            },
             "--copy" => Ok(Argument::MapArg(MapArg::parse(args)?)),
             "--hook" => Ok(Argument::HookArg(HookArg::parse(args)?)),
             "--toggle" => Ok(Argument::ToggleArg(ToggleArg::parse(args)?)),
@@ -68,13 +164,62 @@ impl Argument {
             "--print" => Ok(Argument::PrintArg(PrintArg::parse(args)?)),
             "--merge" => Ok(Argument::MergeArg(MergeArg::parse(args)?)),
             "--delay" => Ok(Argument::DelayArg(DelayArg::parse(args)?)),
+            "--debounce" => Ok(Argument::DebounceArg(DebounceArg::parse(args)?)),
+            "--throttle" => Ok(Argument::ThrottleArg(ThrottleArg::parse(args)?)),
+            "--latch" => Ok(Argument::LatchArg(LatchArg::parse(args)?)),
+            "--sticky" => Ok(Argument::StickyArg(StickyArg::parse(args)?)),
+            "--chord" => Ok(Argument::ChordArg(ChordArg::parse(args)?)),
+            "--counter" => Ok(Argument::CounterArg(CounterArg::parse(args)?)),
+            "--timer" => Ok(Argument::TimerArg(TimerArg::parse(args)?)),
             "--withhold" => Ok(Argument::WithholdArg(WithholdArg::parse(args)?)),
+            "--repeat" => Ok(Argument::RepeatArg(RepeatArg::parse(args)?)),
+            "--macro" => Ok(Argument::MacroArg(MacroArg::parse(args)?)),
+            "--script" => Ok(Argument::ScriptArg(ScriptArg::parse(args)?)),
+            "--unicode" => Ok(Argument::UnicodeArg(UnicodeArg::parse(args)?)),
             "--control-fifo" => Ok(Argument::ControlFifoArg(ControlFifoArg::parse(args)?)),
+            "--control-socket" => Ok(Argument::ControlSocketArg(ControlSocketArg::parse(args)?)),
+            "--dbus" => Ok(Argument::DBusArg(DBusArg::parse(args)?)),
+            "--metrics" => Ok(Argument::MetricsArg(MetricsArg::parse(args)?)),
+            "--rotary" => Ok(Argument::RotaryArg(RotaryArg::parse(args)?)),
+            "--split" => Ok(Argument::SplitArg(SplitArg::parse(args)?)),
+            "--touchpad" => Ok(Argument::TouchpadArg(TouchpadArg::parse(args)?)),
+            "--accel" => Ok(Argument::AccelArg(AccelArg::parse(args)?)),
+            "--combine" => Ok(Argument::CombineArg(CombineArg::parse(args)?)),
+            "--trace" => Ok(Argument::TraceArg(TraceArg::parse(args)?)),
+            "--benchmark" => Ok(Argument::BenchmarkArg(BenchmarkArg::parse(args)?)),
+            "--cache-caps" => match args.len() {
+                1 => Ok(Argument::CacheCapsFlag),
+                _ => Err(ArgumentError::new(format!(
+                    "The --cache-caps flag does not take any arguments. Offending argument: {}", args[1]
+                )).into()),
+            },
+            "--profile" => Ok(Argument::ProfileArg(ProfileArg::parse(args)?)),
+            "--end-profile" => {
+                crate::arguments::profile::parse_end_profile(args)?;
+                Ok(Argument::EndProfileArg)
+            },
             _ => Err(ArgumentError::new(format!("Encountered unknown argument: {}", first_arg)).into()),
         }
     }
 }
 
+/// Parses an argument that gets added to the running stream through the control FIFO, such as
+/// ["--map", "key:a", "key:b"]. Only arguments that do not interact with the rest of the
+/// configuration are supported.
+pub fn parse_runtime_entries(args: Vec<String>) -> Result<Vec<StreamEntry>, RuntimeError> {
+    match args[0].as_str() {
+        "--map" | "--copy" | "--block" => (),
+        other => return Err(ArgumentError::new(format!(
+            "Cannot add {} arguments at runtime. Only --map, --copy and --block arguments can be added.", other
+        )).into()),
+    }
+    match Argument::parse(args)? {
+        Argument::MapArg(map_arg) => Ok(vec![StreamEntry::Map(Map::new(map_arg.input_key, map_arg.output_keys))]),
+        Argument::BlockArg(block_arg) => Ok(block_arg.keys.into_iter().map(|key| StreamEntry::Map(Map::block(key))).collect()),
+        _ => Err(ArgumentError::new("Only --map, --copy and --block arguments can be added at runtime. Maps with a table= clause are not supported.").into()),
+    }
+}
+
 /// If a --version or --help or something is specified, prints a helpful message.
 /// Returns true if --version or --help was requested, otherwise returns false.
 pub fn check_help_and_version(args: &[String]) -> bool {
@@ -95,7 +240,26 @@ pub fn check_help_and_version(args: &[String]) -> bool {
     false
 }
 
-fn parse(args: Vec<String>) -> Result<Vec<Argument>, RuntimeError> {
+/// Returns true if the arguments contain --dry-run, in which case the arguments shall be checked
+/// with `dry_run()` instead of being run.
+pub fn is_dry_run(args: &[String]) -> bool {
+    args.contains(&"--dry-run".to_owned())
+}
+
+/// Returns true if the arguments contain --benchmark, in which case the arguments shall be
+/// implemented with `implement_benchmark()` and be fed events as fast as possible.
+pub fn is_benchmark(args: &[String]) -> bool {
+    args.contains(&"--benchmark".to_owned())
+}
+
+/// Returns true if the arguments contain --parallel, in which case the program shall be split
+/// over threads according to `plan_threads()`.
+pub fn is_parallel(args: &[String]) -> bool {
+    args.contains(&"--parallel".to_owned())
+}
+
+/// Returns the parsed arguments along with the text they were parsed from.
+fn parse(args: Vec<String>) -> Result<(Vec<Argument>, Vec<String>), RuntimeError> {
 	// Sort the arguments into groups.
     let mut groups: Vec<Vec<String>> = Vec::new();
     let mut args_iter = args.into_iter().peekable();
@@ -119,17 +283,160 @@ fn parse(args: Vec<String>) -> Result<Vec<Argument>, RuntimeError> {
 		groups.push(new_group);
     }
 
-    groups.into_iter().map(
-        |group| Argument::parse(group.clone()).with_context(format!(
-            "While parsing the arguments \"{}\":", group.join(" ")
-        )
-    )).collect::<Result<Vec<Argument>, RuntimeError>>()
+    // Replace every --config argument by the arguments in its file. Every group is accompanied
+    // by the context that errors in it get, so those errors can tell where the group came from.
+    let mut expanded_groups: Vec<(Vec<String>, String)> = Vec::new();
+    for group in groups {
+        let context = format!("While parsing the arguments \"{}\":", group.join(" "));
+        if group[0] != "--config" {
+            expanded_groups.push((group, context));
+            continue;
+        }
+        let config_arg = ConfigArg::parse(group).with_context(context)?;
+        for config_group in config_arg.groups {
+            let context = format!(
+                "While parsing the arguments \"{}\" on line {} of {}:",
+                config_group.args.join(" "), config_group.line, config_group.path.display()
+            );
+            if config_group.args[0] == "--config" {
+                return Err(ArgumentError::new(
+                    "Configuration files cannot contain [[config]] tables. Use the include key to include other files."
+                ).with_context(context).into());
+            }
+            if config_group.args[0] == "--dry-run" || config_group.args[0] == "--parallel" || config_group.args[0] == "--benchmark" {
+                return Err(ArgumentError::new(format!(
+                    "Configuration files cannot contain [[{}]] tables. Pass {} on the command line instead.",
+                    &config_group.args[0][2..], config_group.args[0]
+                )).with_context(context).into());
+            }
+            expanded_groups.push((config_group.args, context));
+        }
+    }
+
+    // The --dry-run and --parallel flags have been handled by the caller already.
+    let mut groups: Vec<(Vec<String>, String)> = Vec::new();
+    for (group, context) in expanded_groups {
+        if group[0] != "--dry-run" && group[0] != "--parallel" {
+            groups.push((group, context));
+        } else if group.len() > 1 {
+            return Err(ArgumentError::new(format!(
+                "The {} flag does not take any arguments. Offending argument: {}", group[0], group[1]
+            )).with_context(context).into());
+        }
+    }
+
+    // Replace the variables of earlier --define arguments in all arguments that come after them.
+    let mut variables = Variables::new();
+    let mut expanded_groups: Vec<(Vec<String>, String)> = Vec::new();
+    for (group, context) in groups {
+        let group = group.iter()
+            .map(|arg| variables.expand(arg))
+            .collect::<Result<Vec<String>, ArgumentError>>()
+            .with_context(context.clone())?;
+        if group[0] != "--define" {
+            expanded_groups.push((group, context));
+            continue;
+        }
+        let define_arg = DefineArg::parse(group).with_context(context)?;
+        for (name, value) in define_arg.definitions {
+            variables.define(name, value);
+        }
+    }
+
+    // The --log argument takes effect before the other arguments get parsed, because parsing
+    // them may print warnings. It does not become part of the stream.
+    let (log_groups, groups): (Vec<_>, Vec<_>) = expanded_groups.into_iter()
+        .partition(|(group, _)| group[0] == "--log");
+    let mut log_level = LogLevel::Info;
+    match log_groups.as_slice() {
+        [] => {},
+        [(group, context)] => {
+            log_level = LogArg::parse(group.clone()).with_context(context.clone())?.level;
+        },
+        _ => return Err(ArgumentError::new("Only a single --log argument can be specified.").into()),
+    }
+    logging::set_level(log_level);
+
+    let labels: Vec<String> = groups.iter().map(|(group, _)| group.join(" ")).collect();
+    let args = groups.into_iter().map(
+        |(group, context)| Argument::parse(group).with_context(context)
+    ).collect::<Result<Vec<Argument>, RuntimeError>>()?;
+    Ok((args, labels))
 }
 
 pub struct Implementation {
     pub setup: Setup,
     pub input_devices: Vec<crate::io::input::InputDevice>,
+    /// When reloading, the input devices that are already open and shall be reconfigured
+    /// according to these pre-devices instead of being opened again. Matched by their path.
+    pub reused_input_devices: Vec<PreInputDevice>,
     pub control_fifos: Vec<ControlFifo>,
+    /// Sockets that accept the same commands as control fifos and answer them.
+    pub control_sockets: Vec<ControlSocket>,
+    /// Offers the control commands over D-Bus if the --dbus argument was specified.
+    pub dbus_service: Option<DBusService>,
+    /// Serves counters to monitoring tools if the --metrics argument was specified.
+    pub metrics_listener: Option<MetricsListener>,
+    /// Input paths with wildcards whose matching devices shall be opened as they show up.
+    pub patterns: Vec<Pattern>,
+    /// Recordings that shall be replayed as if they were input devices.
+    pub replay_devices: Vec<ReplayDevice>,
+    /// Sockets on which events from other evsieves shall be received.
+    pub network_listeners: Vec<NetworkListener>,
+    pub network_inputs: Vec<NetworkInput>,
+    /// Hidraw devices whose HID reports shall be translated to events.
+    pub hidraw_devices: Vec<HidrawDevice>,
+    /// Legacy joystick devices that use the joydev protocol.
+    pub joystick_devices: Vec<JoystickDevice>,
+}
+
+/// The parts of a running evsieve that a reloaded configuration can keep using instead of
+/// opening them anew.
+pub struct Reusables<'a> {
+    /// The capabilities of the input devices that are currently open, by their canonical path.
+    pub input_devices: HashMap<PathBuf, Capabilities>,
+    /// Output devices that the new configuration would create the same way are taken from here.
+    pub output: &'a mut OutputSystem,
+}
+
+/// Which parts of the configuration `implement_with()` shall set up.
+enum Mode<'a> {
+    /// Everything needed to run the program.
+    Program,
+    /// Nothing that affects the rest of the system, see `dry_run()`.
+    DryRun,
+    /// Like DryRun, but to find out how to split the program over threads, see `plan_threads()`.
+    PlanThreads,
+    /// Everything needed to run the part of the program that a single thread is responsible for.
+    Thread(&'a ThreadPlan),
+    /// Like DryRun, but with a Setup whose outputs discard their events, see `implement_benchmark()`.
+    Benchmark,
+}
+
+/// What `implement_with()` produced, depending on the Mode.
+enum Implemented {
+    Program(Box<Implementation>),
+    DryRun(String),
+    PlanThreads(Vec<ThreadPlan>),
+    /// The program along with the amount of events the --benchmark argument asked for.
+    Benchmark(Box<Implementation>, u64),
+}
+
+impl Implemented {
+    fn into_program(self) -> Result<Implementation, RuntimeError> {
+        match self {
+            Implemented::Program(implementation) => Ok(*implementation),
+            _ => Err(InternalError::new("A program was expected to be implemented, but something else was produced.").into()),
+        }
+    }
+}
+
+/// The --input and --output arguments that a single thread is responsible for when running with
+/// --parallel, by their position among the --input and --output arguments respectively.
+#[derive(Clone)]
+pub struct ThreadPlan {
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
 }
 
 /// This function does most of the work of turning the input arguments into the components of a
@@ -137,11 +444,104 @@ pub struct Implementation {
 pub fn implement(args_str: Vec<String>)
         -> Result<Implementation, RuntimeError>
 {
-    let mut args: Vec<Argument> = parse(args_str)?;
+    implement_with(args_str, None, Mode::Program)?.into_program()
+}
+
+/// Like `implement()`, but for reloading the configuration of a program that is already running.
+/// Input devices that are already open do not get opened again, and neither do output devices
+/// that would be created the same way with the same capabilities. The control FIFOs, control
+/// sockets, D-Bus connection and metrics listener of the running program are left alone, so the
+/// returned Implementation has none of them.
+pub fn reimplement(args_str: Vec<String>, reusables: Reusables)
+        -> Result<Implementation, RuntimeError>
+{
+    implement_with(args_str, Some(reusables), Mode::Program)?.into_program()
+}
+
+/// Like `implement()`, but only implements the input and output devices of the given plan.
+pub fn implement_thread(args_str: Vec<String>, plan: &ThreadPlan)
+        -> Result<Implementation, RuntimeError>
+{
+    implement_with(args_str, None, Mode::Thread(plan))?.into_program()
+}
+
+/// Implements the arguments as far as possible without affecting the rest of the system and
+/// returns a report of which events each output device would receive and which arguments can
+/// never match anything. Input devices are opened without being grabbed, and no output devices,
+/// control interfaces or network listeners are created.
+pub fn dry_run(args_str: Vec<String>) -> Result<String, RuntimeError> {
+    match implement_with(args_str, None, Mode::DryRun)? {
+        Implemented::DryRun(report) => Ok(report),
+        _ => Err(InternalError::new("A dry run was expected to be performed, but something else was produced.").into()),
+    }
+}
+
+/// Like `dry_run()`, but returns a program that can be fed events without affecting the rest of
+/// the system: the outputs do not create virtual devices but write their events to /dev/null,
+/// so the cost of encoding and writing them is still part of the measurement. Also returns how
+/// many events the --benchmark argument asked to be fed through the program.
+pub fn implement_benchmark(args_str: Vec<String>) -> Result<(Implementation, u64), RuntimeError> {
+    match implement_with(args_str, None, Mode::Benchmark)? {
+        Implemented::Benchmark(implementation, events) => Ok((*implementation, events)),
+        _ => Err(InternalError::new("A benchmark was expected to be prepared, but something else was produced.").into()),
+    }
+}
+
+/// Splits the input devices into groups whose events never meet, so each group can be handled
+/// by a thread of its own. Two input devices end up in the same group if their events can reach
+/// the same output device or the same argument that keeps state, or if both can affect toggles.
+/// Input devices whose capabilities are not known yet are put in the same group as all others.
+/// The input devices are opened the same way as for a dry run.
+pub fn plan_threads(args_str: Vec<String>) -> Result<Vec<ThreadPlan>, RuntimeError> {
+    match implement_with(args_str, None, Mode::PlanThreads)? {
+        Implemented::PlanThreads(plans) => Ok(plans),
+        _ => Err(InternalError::new("A plan for threads was expected to be made, but something else was produced.").into()),
+    }
+}
+
+fn implement_with(args_str: Vec<String>, mut reusables: Option<Reusables>, mode: Mode)
+        -> Result<Implemented, RuntimeError>
+{
+    // Dry runs and planning must not affect the rest of the system.
+    let dry_run = match mode {
+        Mode::DryRun | Mode::PlanThreads | Mode::Benchmark => true,
+        Mode::Program | Mode::Thread(_) => false,
+    };
+    let (mut args, arg_labels) = parse(args_str)?;
+
+    // Other kinds of input do not keep their connections or position across a reload.
+    if reusables.is_some() {
+        for arg in &args {
+            if let Argument::InputDevice(device) = arg {
+                if device.replay.is_some() || device.hidraw.is_some() || device.joystick.is_some() || device.network.is_some() {
+                    return Err(ArgumentError::new(
+                        "Configurations with file=, hidraw=, joystick=, tcp= or udp= inputs cannot be reloaded. Restart evsieve instead."
+                    ).into());
+                }
+            }
+        }
+    }
+
     let mut input_devices: Vec<PreInputDevice> = Vec::new();
     let mut output_devices: Vec<PreOutputDevice> = Vec::new();
     let mut control_fifo_paths: Vec<String> = Vec::new();
+    let mut control_socket_paths: Vec<String> = Vec::new();
+    let mut dbus_arg: Option<DBusArg> = None;
+    let mut metrics_arg: Option<MetricsArg> = None;
+    let mut patterns: Vec<Pattern> = Vec::new();
+    let mut replay_devices: Vec<ReplayDevice> = Vec::new();
+    let mut network_listeners: Vec<NetworkListener> = Vec::new();
+    let mut network_inputs: Vec<NetworkInput> = Vec::new();
+    let mut hidraw_devices: Vec<HidrawDevice> = Vec::new();
+    let mut joystick_devices: Vec<JoystickDevice> = Vec::new();
     let mut stream: Vec<StreamEntry> = Vec::new();
+    // For each entry of the stream, the argument it was created by.
+    let mut stream_labels: Vec<String> = Vec::new();
+    // For each entry of the stream, the index of the profile it belongs to, if any.
+    let mut stream_profiles: Vec<Option<usize>> = Vec::new();
+    let mut tracer = None;
+    let mut benchmark_arg: Option<BenchmarkArg> = None;
+    let mut cache_caps = false;
 
     let mut state: State = State::new();
 
@@ -159,6 +559,15 @@ pub fn implement(args_str: Vec<String>)
                     },
                     None => {
                         let index = state.create_toggle_with_size(toggle_arg.size())?;
+                        if let Some(names) = toggle_arg.state_names.clone() {
+                            state[index].set_names(names)?;
+                        }
+                        // Dry runs start in the persisted state, but must not overwrite it.
+                        match toggle_arg.persist_path.clone() {
+                            Some(path) if dry_run => state[index].restore_from(&path),
+                            Some(path) => state[index].persist_to(path),
+                            None => (),
+                        }
                         toggle_indices.insert(id, index);
                     }
                 }
@@ -166,6 +575,53 @@ pub fn implement(args_str: Vec<String>)
         }
     }
 
+    // All profiles share a single toggle whose states are named after them.
+    let mut profile_names: Vec<String> = Vec::new();
+    let mut is_in_profile = false;
+    for arg in &args {
+        match arg {
+            Argument::ProfileArg(profile_arg) => {
+                if is_in_profile {
+                    return Err(ArgumentError::new("Profiles cannot be nested: every --profile argument must be closed by an --end-profile argument before the next one.").into());
+                }
+                if profile_names.contains(&profile_arg.name) {
+                    return Err(ArgumentError::new(format!("Two profiles cannot have the same name: {}", profile_arg.name)).into());
+                }
+                profile_names.push(profile_arg.name.clone());
+                is_in_profile = true;
+            },
+            Argument::EndProfileArg => {
+                if ! is_in_profile {
+                    return Err(ArgumentError::new("Encountered an --end-profile argument without a preceding --profile argument.").into());
+                }
+                is_in_profile = false;
+            },
+            Argument::InputDevice(_) | Argument::OutputDevice(_) | Argument::ControlFifoArg(_) | Argument::ControlSocketArg(_)
+                | Argument::DBusArg(_) | Argument::MetricsArg(_) | Argument::TraceArg(_) | Argument::CacheCapsFlag if is_in_profile =>
+            {
+                return Err(ArgumentError::new("Only arguments that process events can be part of a profile. The --input, --output, --control-fifo, --control-socket, --dbus, --metrics, --trace and --cache-caps arguments cannot.").into());
+            },
+            _ => (),
+        }
+    }
+    if is_in_profile {
+        return Err(ArgumentError::new("The last --profile argument is not closed by an --end-profile argument.").into());
+    }
+    let profile_toggle = match profile_names.is_empty() {
+        true => None,
+        false => {
+            if toggle_indices.contains_key(PROFILE_TOGGLE_ID) {
+                return Err(ArgumentError::new(format!(
+                    "The toggle id \"{}\" is reserved for switching between the profiles.", PROFILE_TOGGLE_ID
+                )).into());
+            }
+            let index = state.create_toggle_with_size(profile_names.len())?;
+            state[index].set_names(profile_names)?;
+            toggle_indices.insert(PROFILE_TOGGLE_ID.to_owned(), index);
+            Some(index)
+        },
+    };
+
     // Associate the --withhold argument with all --hook arguments before it.
     let mut consecutive_hooks: Vec<&mut HookArg> = Vec::new();
     for arg in &mut args {
@@ -183,40 +639,241 @@ pub fn implement(args_str: Vec<String>)
     // Keep track of the real paths for the input devices we've opened so we don't open the same
     // one twice.
     let mut input_device_real_paths: HashSet<PathBuf> = HashSet::new();
+    // The domain names that have been handed out by domain=auto clauses.
+    let mut auto_domain_names: HashSet<String> = HashSet::new();
+    // The slots used by --macro arguments, since two macros cannot share a file.
+    let mut macro_slots: HashSet<u32> = HashSet::new();
+    // The --input and --output arguments are numbered in the order they appear, so a ThreadPlan
+    // can refer to them. These map the domains of the devices to the number of their argument.
+    let mut num_input_args: usize = 0;
+    let mut num_output_args: usize = 0;
+    let mut input_arg_of_domain: HashMap<Domain, usize> = HashMap::new();
+    let mut output_arg_of_domain: HashMap<Domain, usize> = HashMap::new();
 
     // Construct the stream.
-    for arg in args {
+    let mut previous_label = String::new();
+    // The index of the profile that the current argument belongs to, and likewise for the
+    // previous argument.
+    let mut current_profile: Option<usize> = None;
+    let mut previous_profile: Option<usize> = None;
+    let mut num_profiles: usize = 0;
+    let mut args_hasher = ArgumentsHasher::new(&arg_labels);
+    for (arg, label) in args.into_iter().zip(arg_labels) {
+        // Label the entries created by the previous argument; some arguments create multiple
+        // entries or none at all.
+        stream_labels.resize(stream.len(), previous_label);
+        stream_profiles.resize(stream.len(), previous_profile);
+        previous_label = label;
+        previous_profile = current_profile;
         match arg {
             Argument::InputDevice(device) => {
-                for path_str in &device.paths {
-                    let path: PathBuf = path_str.into();
-                    let real_path = std::fs::canonicalize(path.clone()).map_err(
-                        |_| ArgumentError::new(format!("The input device \"{}\" does not exist.", path_str))
-                    )?;
-
-                    // Opening the same device multiple times could spell trouble for certain
-                    // possible future features and has little purpose, so we don't allow it.
-                    if input_device_real_paths.contains(&real_path) {
-                        return Err(ArgumentError::new(format!("The input device \"{}\" has been opened multiple times.", path_str)).into());
-                    } else {
-                        input_device_real_paths.insert(real_path);
+                let input_index = num_input_args;
+                num_input_args += 1;
+                if let Mode::Thread(plan) = &mode {
+                    if ! plan.inputs.contains(&input_index) {
+                        continue;
                     }
-                    
+                }
+
+                if let Some(replay) = &device.replay {
                     let source_domain = domain::get_unique_domain();
                     let target_domain = match &device.domain {
                         Some(value) => *value,
-                        None => domain::resolve(path_str)?,
+                        None => domain::resolve(&replay.path)?,
+                    };
+                    domain::set_origin(source_domain, target_domain);
+                    input_arg_of_domain.insert(source_domain, input_index);
+                    let replay_device = ReplayDevice::open(replay.path.clone().into(), source_domain, replay.timing)
+                        .with_context_of(|| format!("While opening the recording {}:", replay.path))?;
+                    replay_devices.push(replay_device);
+
+                    stream.push(StreamEntry::Map(
+                        Map::domain_shift(
+                            source_domain, Namespace::Input,
+                            target_domain, Namespace::User,
+                        )
+                    ));
+                    continue;
+                }
+
+                if let Some(hidraw) = &device.hidraw {
+                    let source_domain = domain::get_unique_domain();
+                    let target_domain = match &device.domain {
+                        Some(value) => *value,
+                        None => domain::resolve(&hidraw.path)?,
                     };
+                    domain::set_origin(source_domain, target_domain);
+                    input_arg_of_domain.insert(source_domain, input_index);
+                    let hidraw_device = HidrawDevice::open(
+                        hidraw.path.clone().into(), source_domain, hidraw.descriptor.clone().map(PathBuf::from), &hidraw.usages
+                    ).with_context_of(|| format!("While opening the hidraw device {}:", hidraw.path))?;
+                    hidraw_devices.push(hidraw_device);
 
-                    let input_device = PreInputDevice {
-                        path, domain: source_domain,
+                    stream.push(StreamEntry::Map(
+                        Map::domain_shift(
+                            source_domain, Namespace::Input,
+                            target_domain, Namespace::User,
+                        )
+                    ));
+                    continue;
+                }
+
+                if let Some(path) = &device.joystick {
+                    let source_domain = domain::get_unique_domain();
+                    let target_domain = match &device.domain {
+                        Some(value) => *value,
+                        None => domain::resolve(path)?,
+                    };
+                    domain::set_origin(source_domain, target_domain);
+                    input_arg_of_domain.insert(source_domain, input_index);
+                    let joystick_device = JoystickDevice::open(path.into(), source_domain)
+                        .with_context_of(|| format!("While opening the joystick {}:", path))?;
+                    joystick_devices.push(joystick_device);
+
+                    stream.push(StreamEntry::Map(
+                        Map::domain_shift(
+                            source_domain, Namespace::Input,
+                            target_domain, Namespace::User,
+                        )
+                    ));
+                    continue;
+                }
+
+                if let Some((protocol, address)) = &device.network {
+                    let source_domain = domain::get_unique_domain();
+                    let target_domain = match &device.domain {
+                        Some(value) => *value,
+                        None => domain::resolve(address)?,
+                    };
+                    domain::set_origin(source_domain, target_domain);
+                    input_arg_of_domain.insert(source_domain, input_index);
+                    let context = || format!("While trying to listen on {}:", address);
+                    match protocol {
+                        // Nothing can connect during a dry run anyway.
+                        _ if dry_run => (),
+                        NetworkProtocol::Tcp => network_listeners.push(
+                            NetworkListener::bind(address.clone(), source_domain).with_context_of(context)?
+                        ),
+                        NetworkProtocol::Udp => network_inputs.push(
+                            NetworkInput::bind_udp(address.clone(), source_domain).with_context_of(context)?
+                        ),
+                    }
+
+                    stream.push(StreamEntry::Map(
+                        Map::domain_shift(
+                            source_domain, Namespace::Input,
+                            target_domain, Namespace::User,
+                        )
+                    ));
+                    continue;
+                }
+
+                let grab_condition = match &device.grab_while {
+                    None => None,
+                    Some((id, state_str)) => Some(
+                        resolve_grab_condition(id, state_str, &state, &toggle_indices)?
+                    ),
+                };
+                let paths_strs: Vec<String> = match &device.selector {
+                    None => device.paths.clone(),
+                    Some(selector) => {
+                        let paths = crate::io::input::find_devices(selector)?;
+                        if paths.is_empty() {
+                            return Err(ArgumentError::new(
+                                "No input devices match the name=, vendor= and product= clauses of an --input argument."
+                            ).into());
+                        }
+                        paths.into_iter().map(|path| path.to_string_lossy().into_owned()).collect()
+                    },
+                };
+                for path_str in &paths_strs {
+                    // If the path contains wildcards, all devices matching it share the same domain.
+                    let is_pattern = crate::utils::is_glob_pattern(path_str);
+                    let paths: Vec<PathBuf> = match is_pattern {
+                        true => find_matching_paths(Path::new(path_str)),
+                        false => vec![path_str.into()],
+                    };
+                    if is_pattern && paths.is_empty() && device.persist_mode != PersistMode::Full {
+                        return Err(ArgumentError::new(format!(
+                            "No input devices match the pattern \"{}\". If you want evsieve to wait for matching devices to show up, specify persist=full.", path_str
+                        )).into());
+                    }
+
+                    let source_domain = domain::get_unique_domain();
+                    let target_domain = match &device.domain {
+                        Some(value) => *value,
+                        None => domain::resolve(path_str)?,
+                    };
+                    let pre_device = PreInputDevice {
+                        path: path_str.into(), domain: source_domain,
                         grab_mode: device.grab_mode,
                         persist_mode: device.persist_mode,
+                        from_pattern: is_pattern,
+                        reopen_backoff: device.reopen_backoff,
+                        grab_condition,
+                        cap_filter: device.cap_filter.clone(),
+                        clock: device.clock,
+                        syn_dropped: device.syn_dropped.unwrap_or(SynDroppedPolicy::Resync),
+                        measure_latency: device.measure_latency,
+                        sync_state: device.sync_state,
+                        // Decided once all output devices are known.
+                        forward_ff: false,
+                        forward_leds: device.forward_leds,
                     };
 
-                    // Register this device for later creation.
-                    input_devices.push(input_device);
+                    for path in &paths {
+                        let real_path = std::fs::canonicalize(path.clone()).map_err(
+                            |_| ArgumentError::new(format!("The input device \"{}\" does not exist.", path.display()))
+                        )?;
+
+                        // Opening the same device multiple times could spell trouble for certain
+                        // possible future features and has little purpose, so we don't allow it.
+                        if input_device_real_paths.contains(&real_path) {
+                            return Err(ArgumentError::new(format!("The input device \"{}\" has been opened multiple times.", path.display())).into());
+                        } else {
+                            input_device_real_paths.insert(real_path);
+                        }
+
+                        // Register this device for later creation.
+                        let mut input_device = pre_device.clone();
+                        input_device.path = path.clone();
+
+                        // With domain=auto, every device gets a domain of its own.
+                        if device.auto_domain {
+                            let base_name = crate::io::input::auto_domain_name(path);
+                            let mut name = base_name.clone();
+                            let mut suffix = 2;
+                            while ! auto_domain_names.insert(name.clone()) {
+                                name = format!("{}-{}", base_name, suffix);
+                                suffix += 1;
+                            }
+                            let device_target_domain = domain::resolve(&name)?;
+                            input_device.domain = domain::get_unique_domain();
+                            domain::set_origin(input_device.domain, device_target_domain);
+                            input_arg_of_domain.insert(input_device.domain, input_index);
+                            stream.push(StreamEntry::Map(
+                                Map::domain_shift(
+                                    input_device.domain, Namespace::Input,
+                                    device_target_domain, Namespace::User,
+                                )
+                            ));
+                        }
+
+                        input_devices.push(input_device);
+                    }
+                    if device.auto_domain {
+                        continue;
+                    }
+
+                    // Devices matching this pattern that show up later shall be opened by the
+                    // persistence subsystem.
+                    if is_pattern && device.persist_mode == PersistMode::Full {
+                        patterns.push(Pattern::new(pre_device, paths.into_iter().collect()));
+                    }
+
                     // Create a map to put those events into the stream at the right time.
+                    domain::set_origin(source_domain, target_domain);
+                    input_arg_of_domain.insert(source_domain, input_index);
                     stream.push(StreamEntry::Map(
                         Map::domain_shift(
                             source_domain, Namespace::Input,
@@ -226,16 +883,44 @@ pub fn implement(args_str: Vec<String>)
                 }
             },
             Argument::OutputDevice(device) => {
+                let output_index = num_output_args;
+                num_output_args += 1;
+
                 // Create the output device.
                 let target_domain = domain::get_unique_domain();
+                // The force feedback capabilities are filled in once the input devices are opened.
+                let forward_ff = match device.forward_ff {
+                    Some(path) => Some((
+                        std::fs::canonicalize(&path).map_err(|_| ArgumentError::new(format!(
+                            "The input device \"{}\" from a forward-ff= clause does not exist.", path.display()
+                        )))?,
+                        Vec::new(),
+                    )),
+                    None => None,
+                };
                 let output_device = PreOutputDevice {
                     domain: target_domain,
-                    create_link: device.create_link,
+                    create_links: device.create_links,
                     name: device.name,
                     repeat_mode: device.repeat_mode,
+                    repeat_info: device.repeat_info,
+                    syn_mode: device.syn_mode,
+                    forward_ff,
+                    // Decided once all input devices are known.
+                    forward_leds: false,
+                    identity: device.identity,
+                    permissions: device.permissions,
+                    abs_overrides: device.abs_overrides,
+                    pinned_caps: device.pinned_caps,
+                    record: device.record,
+                    ff_effects: device.ff_effects,
+                    leds: device.leds,
+                    defer: device.defer,
+                    idle_timeout: device.idle_timeout,
+                    split_from: None,
+                    dedup: device.dedup,
                 };
-                output_devices.push(output_device);
-                
+
                 // Map the keys to this output device.
                 for key in device.keys {
                     let map = Map::new(
@@ -244,6 +929,43 @@ pub fn implement(args_str: Vec<String>)
                     );
                     stream.push(StreamEntry::Map(map));
                 }
+
+                // If the output is split by type, its events end up on one device for each class.
+                let sub_devices: Vec<PreOutputDevice> = match device.split_by_type {
+                    true => DeviceClass::all().iter()
+                        .map(|&class| output_device.split(class))
+                        .collect(),
+                    false => vec![output_device],
+                };
+
+                // Programs playing force feedback effects or changing LEDs generate events that
+                // enter the stream right after this output, so they do not get caught by the output itself.
+                for sub_device in &sub_devices {
+                    if sub_device.ff_effects.is_empty() && sub_device.leds.is_empty() {
+                        continue;
+                    }
+                    let ff_domain = match sub_device.create_links.first() {
+                        Some(path) => domain::resolve(&path.to_string_lossy())?,
+                        None => domain::get_unique_domain(),
+                    };
+                    domain::set_origin(sub_device.domain, ff_domain);
+                    stream.push(StreamEntry::Map(
+                        Map::domain_shift(
+                            sub_device.domain, Namespace::Input,
+                            ff_domain, Namespace::User,
+                        )
+                    ));
+                }
+                for sub_device in &sub_devices {
+                    output_arg_of_domain.insert(sub_device.domain, output_index);
+                }
+                // Other threads take care of the outputs that are not part of the plan of this thread.
+                if let Mode::Thread(plan) = &mode {
+                    if ! plan.outputs.contains(&output_index) {
+                        continue;
+                    }
+                }
+                output_devices.extend(sub_devices);
             },
             Argument::MapArg(map_arg) => {
                 let map = Map::new(map_arg.input_key, map_arg.output_keys);
@@ -261,19 +983,25 @@ pub fn implement(args_str: Vec<String>)
                     hook_arg.mark_withholdable
                 );
 
-                for exec_shell in hook_arg.exec_shell {
-                    hook.add_command("/bin/sh".to_owned(), vec!["-c".to_owned(), exec_shell]);
+                // Dry runs must not run the user's scripts.
+                if ! dry_run {
+                    for exec_shell in hook_arg.exec_shell {
+                        hook.add_command("/bin/sh".to_owned(), vec!["-c".to_owned(), exec_shell]);
+                    }
                 }
 
                 for effect in hook_arg.toggle_action.implement(&state, &toggle_indices)? {
                     hook.add_effect(effect);
                 }
+                if let Some(id) = hook_arg.id {
+                    hook.set_id(id);
+                }
                 
                 stream.push(StreamEntry::Hook(hook));
             },
             Argument::WithholdArg(withhold_arg) => {
                 stream.push(StreamEntry::Withhold(
-                    Withhold::new(withhold_arg.keys, withhold_arg.associated_triggers)
+                    Withhold::new(withhold_arg.keys, withhold_arg.associated_triggers, withhold_arg.timeout, withhold_arg.order)
                 ));
             },
             Argument::ToggleArg(toggle_arg) => {
@@ -281,11 +1009,29 @@ pub fn implement(args_str: Vec<String>)
                     Some(id) => toggle_indices.get(id).cloned(),
                     None => None,
                 };
-                let toggle = Toggle::new(toggle_arg.input_key, toggle_arg.output_keys, toggle_arg.mode, &mut state, index)?;
+                let mut toggle = Toggle::new(toggle_arg.input_key, toggle_arg.output_keys, toggle_arg.mode, &mut state, index)?;
+                if let Some(timeout) = toggle_arg.timeout {
+                    toggle.set_timeout(timeout);
+                }
+                toggle.set_leds(toggle_arg.leds);
+                if ! dry_run {
+                    for exec_shell in toggle_arg.exec_shell {
+                        state[toggle.state_index].add_exec_shell(exec_shell);
+                    }
+                }
+                if let Some(names) = toggle_arg.state_names {
+                    state[toggle.state_index].set_names(names)?;
+                }
                 stream.push(StreamEntry::Toggle(toggle));
             },
-            Argument::PrintArg(print_arg) => {
-                stream.push(StreamEntry::Print(print_arg.compile()));
+            Argument::PrintArg(mut print_arg) => {
+                // Dry runs must not append to or rotate the file, but still pay for formatting
+                // and writing the lines.
+                if dry_run && print_arg.file.is_some() {
+                    print_arg.file = Some("/dev/null".into());
+                    print_arg.max_size = None;
+                }
+                stream.push(StreamEntry::Print(print_arg.compile()?));
             },
             Argument::MergeArg(merge_arg) => {
                 stream.push(StreamEntry::Merge(merge_arg.compile()));
@@ -293,29 +1039,413 @@ pub fn implement(args_str: Vec<String>)
             Argument::DelayArg(delay_arg) => {
                 stream.push(StreamEntry::Delay(delay_arg.compile()));
             },
+            Argument::DebounceArg(debounce_arg) => {
+                stream.push(StreamEntry::Debounce(debounce_arg.compile()));
+            },
+            Argument::ThrottleArg(throttle_arg) => {
+                stream.push(StreamEntry::Throttle(throttle_arg.compile()));
+            },
+            Argument::LatchArg(latch_arg) => {
+                stream.push(StreamEntry::Latch(latch_arg.compile()));
+            },
+            Argument::StickyArg(sticky_arg) => {
+                stream.push(StreamEntry::Sticky(sticky_arg.compile()));
+            },
+            Argument::ChordArg(chord_arg) => {
+                stream.push(StreamEntry::Chord(chord_arg.compile()));
+            },
+            Argument::CounterArg(counter_arg) => {
+                stream.push(StreamEntry::Counter(counter_arg.compile()));
+            },
+            Argument::TimerArg(timer_arg) => {
+                stream.push(StreamEntry::Timer(timer_arg.compile()));
+            },
+            Argument::RepeatArg(repeat_arg) => {
+                stream.push(StreamEntry::Repeat(repeat_arg.compile()));
+            },
+            Argument::MacroArg(macro_arg) => {
+                if ! macro_slots.insert(macro_arg.slot) {
+                    return Err(ArgumentError::new(format!(
+                        "Two --macro arguments cannot use the same slot: {}", macro_arg.slot
+                    )).into());
+                }
+                let mut macro_entry = macro_arg.compile();
+                if dry_run {
+                    macro_entry.disable_saving();
+                }
+                stream.push(StreamEntry::Macro(macro_entry));
+            },
+            Argument::ScriptArg(script_arg) => {
+                stream.push(StreamEntry::Script(script_arg.compile()?));
+            },
+            Argument::UnicodeArg(unicode_arg) => {
+                stream.push(StreamEntry::Unicode(unicode_arg.compile()?));
+            },
+            Argument::RotaryArg(rotary_arg) => {
+                stream.push(StreamEntry::Rotary(rotary_arg.compile()));
+            },
+            Argument::SplitArg(split_arg) => {
+                stream.push(StreamEntry::Split(split_arg.compile()));
+            },
             Argument::ControlFifoArg(control_fifo) => {
                 control_fifo_paths.extend(control_fifo.paths);
             },
+            Argument::ControlSocketArg(control_socket) => {
+                control_socket_paths.extend(control_socket.paths);
+            },
+            Argument::DBusArg(arg) => {
+                if dbus_arg.is_some() {
+                    return Err(ArgumentError::new("Only a single --dbus argument can be specified.").into());
+                }
+                dbus_arg = Some(arg);
+            },
+            Argument::MetricsArg(arg) => {
+                if metrics_arg.is_some() {
+                    return Err(ArgumentError::new("Only a single --metrics argument can be specified.").into());
+                }
+                metrics_arg = Some(arg);
+            },
+            Argument::TouchpadArg(touchpad_arg) => {
+                stream.push(StreamEntry::Touchpad(touchpad_arg.compile()?));
+            },
+            Argument::AccelArg(accel_arg) => {
+                stream.push(StreamEntry::Accel(accel_arg.compile()));
+            },
+            Argument::CombineArg(combine_arg) => {
+                stream.push(StreamEntry::Combine(combine_arg.compile()));
+            },
+            Argument::MapTableArg(map_table_arg) => {
+                args_hasher.add_file_contents(&map_table_arg.contents);
+                stream.push(StreamEntry::MapTable(map_table_arg.compile()?));
+            },
+            Argument::TraceArg(trace_arg) => {
+                if tracer.is_some() {
+                    return Err(ArgumentError::new("Only a single --trace argument can be specified.").into());
+                }
+                tracer = Some(trace_arg.compile());
+            },
+            Argument::BenchmarkArg(arg) => {
+                if benchmark_arg.is_some() {
+                    return Err(ArgumentError::new("Only a single --benchmark argument can be specified.").into());
+                }
+                benchmark_arg = Some(arg);
+            },
+            Argument::CacheCapsFlag => {
+                cache_caps = true;
+            },
+            Argument::ProfileArg(_) => {
+                current_profile = Some(num_profiles);
+                num_profiles += 1;
+            },
+            Argument::EndProfileArg => {
+                current_profile = None;
+            },
         }
     }
+    stream_labels.resize(stream.len(), previous_label);
+    stream_profiles.resize(stream.len(), previous_profile);
 
     // Do sanity checks.
-    if ! are_unique(output_devices.iter().filter_map(|device| device.create_link.as_ref())) {
+    if ! are_unique(output_devices.iter().flat_map(|device| device.create_links.iter())) {
         return Err(ArgumentError::new("Multiple output devices cannot create a link at the same location.".to_owned()).into());
     }
     if ! are_unique(control_fifo_paths.iter()) {
         return Err(ArgumentError::new("A control fifo was specified twice at the same location.".to_owned()).into());
     }
+    if ! are_unique(control_fifo_paths.iter().chain(&control_socket_paths)) {
+        return Err(ArgumentError::new("A control socket was specified twice at the same location, or at the same location as a control fifo.".to_owned()).into());
+    }
 
+    // Threads cannot share the toggles, so nothing that affects the toggles of other threads or
+    // needs to know about the devices of other threads can be used with --parallel.
+    if let Mode::PlanThreads = mode {
+        let is_unsupported = ! control_fifo_paths.is_empty() || ! control_socket_paths.is_empty()
+            || dbus_arg.is_some() || metrics_arg.is_some() || profile_toggle.is_some() || tracer.is_some()
+            || input_devices.iter().chain(patterns.iter().map(|pattern| &pattern.pre_device))
+                .any(|device| device.grab_condition.is_some() || device.forward_leds)
+            || output_devices.iter().any(|device| device.forward_ff.is_some() || ! device.ff_effects.is_empty() || ! device.leds.is_empty());
+        if is_unsupported {
+            return Err(ArgumentError::new(
+                "The --parallel flag cannot be combined with --control-fifo, --control-socket, --dbus, --metrics, --profile or --trace arguments, nor with grab-while=, forward-leds, forward-ff=, ff= or leds= clauses."
+            ).into());
+        }
+    }
+
+    // The running program keeps its own control interfaces and metrics listener when reloading.
+    if reusables.is_some() || dry_run {
+        control_fifo_paths.clear();
+        control_socket_paths.clear();
+        dbus_arg = None;
+        metrics_arg = None;
+    }
     let control_fifos: Vec<ControlFifo> = control_fifo_paths.into_iter()
         .map(ControlFifo::create)
         .collect::<Result<Vec<ControlFifo>, SystemError>>()?;
+    let control_sockets: Vec<ControlSocket> = control_socket_paths.into_iter()
+        .map(|path| ControlSocket::bind(PathBuf::from(path)))
+        .collect::<Result<Vec<ControlSocket>, SystemError>>()?;
+    let dbus_service = match dbus_arg {
+        Some(arg) => Some(DBusService::connect(arg.bus, &arg.name)?),
+        None => None,
+    };
+    let metrics_listener = match metrics_arg {
+        Some(arg) => Some(MetricsListener::bind(arg.address)?),
+        None => None,
+    };
+
+    // Only the first output writing to stdout would get the real stdout; see io::record::take_stdout().
+    let stdout_outputs = output_devices.iter()
+        .filter(|device| match device.record {
+            Some((RecordTarget::Stdout, _)) => true,
+            _ => false,
+        })
+        .count();
+    if stdout_outputs > 1 {
+        return Err(ArgumentError::new("Only a single --output argument can write to stdout.").into());
+    }
+
+    // If some input device wants to mirror the LEDs of the output devices, all output devices
+    // need to tell us when their LEDs change.
+    let forward_leds = input_devices.iter().any(|device| device.forward_leds)
+        || patterns.iter().any(|pattern| pattern.pre_device.forward_leds);
+    for output_device in &mut output_devices {
+        output_device.forward_leds = forward_leds && output_device.record.is_none();
+    }
+
+    // Input devices that receive force feedback need to be opened with write access.
+    for input_device in &mut input_devices {
+        let real_path = std::fs::canonicalize(&input_device.path).ok();
+        input_device.forward_ff = output_devices.iter().any(|output_device| match (&output_device.forward_ff, &real_path) {
+            (Some((ff_path, _)), Some(real_path)) => ff_path == real_path,
+            _ => false,
+        });
+    }
+
+    // When reloading, the devices that are already open keep being used.
+    let mut reused_input_devices: Vec<PreInputDevice> = Vec::new();
+    let mut reused_capabilities: Vec<Capabilities> = Vec::new();
+    if let Some(reusables) = &reusables {
+        let mut new_input_devices: Vec<PreInputDevice> = Vec::new();
+        for device in input_devices {
+            let capabilities = match std::fs::canonicalize(&device.path).ok()
+                .and_then(|real_path| reusables.input_devices.get(&real_path))
+            {
+                Some(capabilities) => capabilities.clone(),
+                None => {
+                    new_input_devices.push(device);
+                    continue;
+                },
+            };
+            let is_accepted = match &device.cap_filter {
+                Some(filter) => filter.accepts(&capabilities),
+                None => true,
+            };
+            if ! is_accepted {
+                logging::info(format!("The device {} is no longer used because its capabilities do not satisfy the require-caps= or exclude-caps= clauses.", device.path.display()));
+                continue;
+            }
+            reused_input_devices.push(device);
+            reused_capabilities.push(capabilities);
+        }
+        input_devices = new_input_devices;
+    }
+
+    // A dry run must not take the input devices away from the rest of the system.
+    if dry_run {
+        for input_device in &mut input_devices {
+            input_device.grab_mode = GrabMode::None;
+            input_device.forward_ff = false;
+            input_device.forward_leds = false;
+        }
+    }
 
     // Compute the capabilities of the output devices.
-    let (input_devices, input_capabilities) = crate::io::input::open_and_query_capabilities(input_devices)?;
-    let setup = Setup::create(stream, output_devices, state, toggle_indices, input_capabilities)?;
+    let (input_devices, mut input_capabilities) = crate::io::input::open_and_query_capabilities(input_devices)?;
+    for (device, capabilities) in reused_input_devices.iter().zip(&reused_capabilities) {
+        // Devices that were found through the same pattern share their domain.
+        input_capabilities.entry(device.domain).or_insert_with(Capabilities::new).merge(capabilities);
+    }
+    for output_device in &mut output_devices {
+        if let Some((ff_path, ff_codes)) = &mut output_device.forward_ff {
+            let ff_source = input_devices.iter()
+                .map(|input_device| (input_device.path(), input_device.capabilities()))
+                .chain(reused_input_devices.iter().zip(&reused_capabilities).map(|(device, capabilities)| (device.path.as_path(), capabilities)))
+                .find(|(path, _)| std::fs::canonicalize(path).ok().as_ref() == Some(ff_path));
+            let (ff_source_path, ff_source_caps) = match ff_source {
+                Some(source) => source,
+                None => return Err(ArgumentError::new(format!(
+                    "The forward-ff= clause refers to the device \"{}\", which is not opened by any --input argument.", ff_path.display()
+                )).into()),
+            };
+            // Forward the requests to the path through which the input device was opened.
+            *ff_path = ff_source_path.to_owned();
+            *ff_codes = ff_source_caps.codes.iter()
+                .copied()
+                .filter(|code| code.ev_type() == EventType::FF)
+                .collect();
+            if ff_codes.is_empty() {
+                logging::warning(format!("Warning: the device {} does not support force feedback.", ff_path.display()));
+            }
+        }
+    }
+    for replay_device in &replay_devices {
+        input_capabilities.insert(replay_device.domain(), replay_device.capabilities().clone());
+    }
+    for hidraw_device in &hidraw_devices {
+        input_capabilities.insert(hidraw_device.domain(), hidraw_device.capabilities().clone());
+    }
+    for joystick_device in &joystick_devices {
+        input_capabilities.insert(joystick_device.domain(), joystick_device.capabilities().clone());
+    }
+    if let Mode::DryRun = mode {
+        return Ok(Implemented::DryRun(crate::stream::describe_dry_run(
+            &stream, stream_labels, stream_profiles, &output_devices, &input_capabilities
+        )));
+    }
+    if let Mode::PlanThreads = mode {
+        let plans = plan_threads_for(
+            &stream, &input_capabilities, &input_arg_of_domain, &output_arg_of_domain, num_input_args, num_output_args
+        );
+        return Ok(Implemented::PlanThreads(plans));
+    }
+    // The events of a benchmark must not end up anywhere.
+    if let Mode::Benchmark = mode {
+        for output_device in &mut output_devices {
+            output_device.record = Some((RecordTarget::File("/dev/null".into()), RecordFormat::Binary));
+        }
+    }
+    // Reloads and threads start out with devices that were opened before, so only a fresh start
+    // of the whole program uses the capability cache.
+    let caps_cache = match (&mode, &reusables) {
+        (Mode::Program, None) if cache_caps => state_directory("the capability cache").ok()
+            .map(|dir| CapsCache::new(&dir, args_hasher, &input_capabilities)),
+        _ => None,
+    };
+    let previous_output = reusables.as_mut().map(|reusables| &mut *reusables.output);
+    let setup = Setup::create(stream, stream_labels, stream_profiles, profile_toggle, output_devices, state, toggle_indices, input_capabilities, tracer, previous_output, caps_cache)?;
 
-    Ok(Implementation { setup, input_devices, control_fifos })
+    let implementation = Box::new(Implementation { setup, input_devices, reused_input_devices, control_fifos, control_sockets, dbus_service, metrics_listener, patterns, replay_devices, network_listeners, network_inputs, hidraw_devices, joystick_devices });
+    match (mode, benchmark_arg) {
+        (Mode::Benchmark, Some(benchmark_arg)) => Ok(Implemented::Benchmark(implementation, benchmark_arg.events)),
+        (Mode::Benchmark, None) => Err(InternalError::new("A benchmark was requested without a --benchmark argument.").into()),
+        _ => Ok(Implemented::Program(implementation)),
+    }
+}
+
+/// The second half of `plan_threads()`, see there. Input arguments that do not end up in any
+/// plan because none of their devices could be opened are left out. Output arguments that no
+/// events can reach are handled by the first thread.
+fn plan_threads_for(
+    stream: &[StreamEntry],
+    input_capabilities: &InputCapabilites,
+    input_arg_of_domain: &HashMap<Domain, usize>,
+    output_arg_of_domain: &HashMap<Domain, usize>,
+    num_input_args: usize,
+    num_output_args: usize,
+) -> Vec<ThreadPlan> {
+    // For each input argument, the output arguments and stateful entries its events can reach.
+    let mut outputs_of_input: Vec<HashSet<usize>> = vec![HashSet::new(); num_input_args];
+    let mut entries_of_input: Vec<HashSet<usize>> = vec![HashSet::new(); num_input_args];
+    let mut uses_toggles: Vec<bool> = vec![false; num_input_args];
+    let mut is_unknown: Vec<bool> = vec![false; num_input_args];
+    let mut is_present: Vec<bool> = vec![false; num_input_args];
+    for (&domain, &input_index) in input_arg_of_domain {
+        is_present[input_index] = true;
+        let caps = match input_capabilities.get(&domain) {
+            Some(caps) => caps,
+            None => {
+                is_unknown[input_index] = true;
+                continue;
+            },
+        };
+        let reach = crate::stream::trace_input_domain(stream, domain, caps);
+        outputs_of_input[input_index].extend(
+            reach.outputs.iter().filter_map(|output_domain| output_arg_of_domain.get(output_domain))
+        );
+        entries_of_input[input_index].extend(reach.stateful_entries);
+        uses_toggles[input_index] |= reach.uses_toggles;
+    }
+
+    // Each input argument starts out with a group of its own, and groups get merged as long as
+    // some of their input arguments can affect each other.
+    let mut group_of_input: Vec<usize> = (0 .. num_input_args).collect();
+    for first in 0 .. num_input_args {
+        for second in first + 1 .. num_input_args {
+            let interacts = is_unknown[first] || is_unknown[second]
+                || (uses_toggles[first] && uses_toggles[second])
+                || ! outputs_of_input[first].is_disjoint(&outputs_of_input[second])
+                || ! entries_of_input[first].is_disjoint(&entries_of_input[second]);
+            if interacts {
+                let (old_group, new_group) = (group_of_input[second], group_of_input[first]);
+                for group in &mut group_of_input {
+                    if *group == old_group {
+                        *group = new_group;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut plans: Vec<ThreadPlan> = Vec::new();
+    let mut plan_of_group: HashMap<usize, usize> = HashMap::new();
+    for input_index in 0 .. num_input_args {
+        if ! is_present[input_index] {
+            continue;
+        }
+        let plan_index = *plan_of_group.entry(group_of_input[input_index]).or_insert_with(|| {
+            plans.push(ThreadPlan { inputs: Vec::new(), outputs: Vec::new() });
+            plans.len() - 1
+        });
+        let plan = &mut plans[plan_index];
+        plan.inputs.push(input_index);
+        for &output_index in &outputs_of_input[input_index] {
+            if ! plan.outputs.contains(&output_index) {
+                plan.outputs.push(output_index);
+            }
+        }
+    }
+
+    if plans.is_empty() {
+        plans.push(ThreadPlan { inputs: Vec::new(), outputs: Vec::new() });
+    }
+    for output_index in 0 .. num_output_args {
+        if ! plans.iter().any(|plan| plan.outputs.contains(&output_index)) {
+            plans[0].outputs.push(output_index);
+        }
+    }
+    for plan in &mut plans {
+        plan.outputs.sort_unstable();
+    }
+    plans
+}
+
+/// Turns the toggle id and state of a grab-while=ID:STATE clause into a GrabCondition. The state
+/// may be either an index starting at 1 or the name of a state.
+fn resolve_grab_condition(id: &str, state_str: &str, state: &State, toggle_indices: &HashMap<String, ToggleIndex>)
+        -> Result<GrabCondition, ArgumentError>
+{
+    let toggle = match toggle_indices.get(id) {
+        Some(&index) => index,
+        None => return Err(ArgumentError::new(format!(
+            "The grab-while= clause refers to a toggle with id \"{}\", but no toggle with that id exists.", id
+        ))),
+    };
+    let state_index = match state_str.parse::<usize>() {
+        Ok(0) => return Err(ArgumentError::new("Cannot use toggle index 0: toggle indices start at 1.")),
+        Ok(index) => index - 1,
+        Err(_) => match state[toggle].index_of_name(state_str) {
+            Some(index) => index,
+            None => return Err(ArgumentError::new(format!(
+                "The toggle with id \"{}\" has no state named \"{}\".", id, state_str
+            ))),
+        },
+    };
+    if state_index >= state[toggle].size() {
+        return Err(ArgumentError::new(format!(
+            "The toggle with id \"{}\" does not have {} states.", id, state_index + 1
+        )));
+    }
+    Ok(GrabCondition { toggle, state_index })
 }
 
 /// Returns true if all items in the iterator are unique, otherwise returns false.
@@ -328,4 +1458,33 @@ fn are_unique<T: Eq>(items: impl Iterator<Item=T>) -> bool {
         seen_items.push(item)
     }
     true
-}
\ No newline at end of file
+}
+
+#[test]
+fn unittest() {
+    use crate::state::ToggleState;
+
+    let mut state = State::new();
+    let mut toggle_state = ToggleState::new(3).unwrap();
+    toggle_state.set_names(vec!["off".to_owned(), "on".to_owned(), "auto".to_owned()]).unwrap();
+    let toggle = state.push_toggle(toggle_state);
+    let mut toggle_indices = HashMap::new();
+    toggle_indices.insert("mode".to_owned(), toggle);
+
+    // The state of a grab-while= clause can be given either as an index or as a name.
+    let condition = resolve_grab_condition("mode", "2", &state, &toggle_indices).unwrap();
+    assert_eq!(condition.state_index, 1);
+    let condition = resolve_grab_condition("mode", "auto", &state, &toggle_indices).unwrap();
+    assert_eq!(condition.state_index, 2);
+    assert!(resolve_grab_condition("mode", "0", &state, &toggle_indices).is_err());
+    assert!(resolve_grab_condition("mode", "4", &state, &toggle_indices).is_err());
+    assert!(resolve_grab_condition("mode", "other", &state, &toggle_indices).is_err());
+    assert!(resolve_grab_condition("other", "1", &state, &toggle_indices).is_err());
+
+    // The device is handed back to the system whenever the toggle leaves the given state.
+    assert!(! condition.is_met(&state));
+    state[toggle].set_value_wrapped(2);
+    assert!(condition.is_met(&state));
+    state[toggle].advance();
+    assert!(! condition.is_met(&state));
+}
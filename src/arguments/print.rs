@@ -1,21 +1,33 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::error::ArgumentError;
+use crate::error::{ArgumentError, SystemError};
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
 use crate::key::{Key, KeyParser};
-use crate::stream::print::{EventPrinter, EventPrintMode};
+use crate::io::logfile::LogFile;
+use crate::stream::print::{ColorMode, EventPrinter, EventPrintMode, PrintSelection, TemplateField, TemplatePart, TimestampMode};
+use crate::time::Duration;
+use std::path::PathBuf;
 
 /// Represents a --print argument.
 pub(super) struct PrintArg {
     pub keys: Vec<Key>,
     pub mode: EventPrintMode,
+    /// If Some, the events shall be appended to this file instead of printed to stdout.
+    pub file: Option<PathBuf>,
+    pub max_size: Option<u64>,
+    pub rotate: u32,
+    pub selection: PrintSelection,
+    pub timestamp: TimestampMode,
+    pub color: ColorMode,
+    pub delta: bool,
 }
 
 impl PrintArg {
 	pub fn parse(args: Vec<String>) -> Result<PrintArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &[],
-            &["format"],
+            &["color", "delta"],
+            &["format", "file", "max-size", "rotate", "mode", "interval", "timestamp", "color"],
             false,
             true,
         )?;
@@ -26,15 +38,195 @@ impl PrintArg {
             Some(value) => match value.as_str() {
                 "direct" => EventPrintMode::Direct,
                 "default" => EventPrintMode::Detailed,
+                "json" => EventPrintMode::Json,
+                other if other.contains('{') => EventPrintMode::Template(parse_template(other)?),
                 other => return Err(ArgumentError::new(format!("Invalid --print format: {}", other))),
             } ,
             None => EventPrintMode::Detailed,
         };
 
-        Ok(PrintArg { keys, mode })
+        let file = arg_group.get_unique_clause("file")?.map(PathBuf::from);
+        let max_size = match arg_group.get_unique_clause("max-size")? {
+            Some(value) => Some(parse_size(&value)?),
+            None => None,
+        };
+        let rotate = match arg_group.get_unique_clause("rotate")? {
+            Some(value) => {
+                if max_size.is_none() {
+                    return Err(ArgumentError::new("The rotate= clause can only be used together with the max-size= clause."));
+                }
+                value.parse().map_err(|_| ArgumentError::new(format!(
+                    "The rotate= clause requires a nonnegative integer, but \"{}\" was provided.", value
+                )))?
+            },
+            None => 1,
+        };
+        if file.is_none() && max_size.is_some() {
+            return Err(ArgumentError::new("The max-size= clause can only be used together with the file= clause."));
+        }
+
+        let interval = match arg_group.get_unique_clause("interval")? {
+            Some(value) => Some(parse_period_value(&value)?),
+            None => None,
+        };
+        let selection = match arg_group.get_unique_clause("mode")?.as_deref() {
+            None | Some("events") => PrintSelection::All,
+            Some("changes") => PrintSelection::Changes,
+            Some("summary") => PrintSelection::Summary(interval.unwrap_or_else(|| Duration::from_secs(1))),
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid --print mode: {}. Valid modes are events, changes and summary.", other
+            ))),
+        };
+        match selection {
+            PrintSelection::Summary(interval) => if interval.as_millis() == 0 {
+                return Err(ArgumentError::new("The interval of a --print argument with mode=summary must be positive."));
+            },
+            _ => if interval.is_some() {
+                return Err(ArgumentError::new("The interval= clause can only be used together with mode=summary."));
+            },
+        }
+
+        let timestamp = match arg_group.get_unique_clause("timestamp")?.as_deref() {
+            Some("none") => TimestampMode::None,
+            Some("relative") => TimestampMode::Relative,
+            Some("absolute") => TimestampMode::Absolute,
+            Some("iso8601") => TimestampMode::Iso8601,
+            // JSON output has always included the absolute time.
+            None => match mode {
+                EventPrintMode::Json | EventPrintMode::Template(_) => TimestampMode::Absolute,
+                _ => TimestampMode::None,
+            },
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid timestamp mode: {}. Valid modes are none, relative, absolute and iso8601.", other
+            ))),
+        };
+
+        let color = match arg_group.get_unique_clause_or_default_if_flag("color", "auto")?.as_deref() {
+            None | Some("never") => ColorMode::Never,
+            Some("always") => ColorMode::Always,
+            Some("auto") => ColorMode::Auto,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid color mode: {}. Valid modes are auto, always and never.", other
+            ))),
+        };
+
+        let delta = arg_group.has_flag("delta");
+        match mode {
+            EventPrintMode::Detailed | EventPrintMode::Json => (),
+            EventPrintMode::Direct | EventPrintMode::Template(_) => if delta {
+                return Err(ArgumentError::new("The delta flag can only be used with format=default or format=json. Use a format string with a {delta} placeholder instead."));
+            },
+        }
+
+        Ok(PrintArg { keys, mode, file, max_size, rotate, selection, timestamp, color, delta })
     }
 
-    pub fn compile(self) -> EventPrinter {
-        EventPrinter::new(self.keys, self.mode)
+    pub fn compile(self) -> Result<EventPrinter, SystemError> {
+        let log_file = match self.file {
+            Some(path) => Some(LogFile::open(path, self.max_size, self.rotate)?),
+            None => None,
+        };
+        Ok(EventPrinter::new(self.keys, self.mode, log_file, self.selection, self.timestamp, self.color, self.delta))
     }
-}
\ No newline at end of file
+}
+
+/// Parses a format string like "{domain}\\t{name}={value}". Literal braces can be written as {{ and }},
+/// and the escape sequences \\t, \\n and \\\\ stand for a tab, a newline and a backslash.
+fn parse_template(format: &str) -> Result<Vec<TemplatePart>, ArgumentError> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            },
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(ArgumentError::new(format!(
+                            "The format string \"{}\" contains a {{ without matching }}.", format
+                        ))),
+                    }
+                }
+                let field = TemplateField::from_name(&name).ok_or_else(|| ArgumentError::new(format!(
+                    "Unknown placeholder {{{}}} in the format string \"{}\". Valid placeholders are {{name}}, {{type}}, {{code}}, {{code_num}}, {{value}}, {{previous_value}}, {{delta}}, {{domain}}, {{origin}} and {{time}}.", name, format
+                )))?;
+                if ! literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(TemplatePart::Field(field));
+            },
+            '}' => return Err(ArgumentError::new(format!(
+                "The format string \"{}\" contains a }} without matching {{. Use }}}} for a literal }}.", format
+            ))),
+            '\\' => match chars.next() {
+                Some('t') => literal.push('\t'),
+                Some('n') => literal.push('\n'),
+                Some('\\') => literal.push('\\'),
+                Some(other) => {
+                    literal.push('\\');
+                    literal.push(other);
+                },
+                None => literal.push('\\'),
+            },
+            c => literal.push(c),
+        }
+    }
+    if ! literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+/// Parses an amount of bytes like 512, 64k, 10M or 1G.
+fn parse_size(value: &str) -> Result<u64, ArgumentError> {
+    let (number_str, multiplier) = match value.chars().last() {
+        Some('k') | Some('K') => (&value[.. value.len() - 1], 1 << 10),
+        Some('M') => (&value[.. value.len() - 1], 1 << 20),
+        Some('G') => (&value[.. value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    match number_str.parse::<u64>() {
+        Ok(number) if number > 0 => number.checked_mul(multiplier).ok_or_else(|| ArgumentError::new(format!(
+            "The size \"{}\" is too large.", value
+        ))),
+        _ => Err(ArgumentError::new(format!(
+            "Invalid size \"{}\". Sizes must be a positive amount of bytes, optionally followed by k, M or G.", value
+        ))),
+    }
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_size("512").unwrap(), 512);
+    assert_eq!(parse_size("64k").unwrap(), 64 * 1024);
+    assert_eq!(parse_size("10M").unwrap(), 10 * 1024 * 1024);
+    assert!(parse_size("0").is_err());
+    assert!(parse_size("M").is_err());
+    assert!(parse_size("-5k").is_err());
+
+    assert_eq!(parse_template("{domain}\\t{name}={value}").unwrap(), vec![
+        TemplatePart::Field(TemplateField::Domain),
+        TemplatePart::Literal("\t".to_owned()),
+        TemplatePart::Field(TemplateField::Name),
+        TemplatePart::Literal("=".to_owned()),
+        TemplatePart::Field(TemplateField::Value),
+    ]);
+    assert_eq!(parse_template("{{{code}}}").unwrap(), vec![
+        TemplatePart::Literal("{".to_owned()),
+        TemplatePart::Field(TemplateField::Code),
+        TemplatePart::Literal("}".to_owned()),
+    ]);
+    assert!(parse_template("{nmae}").is_err());
+    assert!(parse_template("{name").is_err());
+    assert!(parse_template("name}").is_err());
+}
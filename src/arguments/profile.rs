@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+
+/// The id of the toggle whose states are named after the profiles. Hooks and control commands
+/// switch between profiles by switching this toggle, e.g. toggle=profile:gaming.
+pub const PROFILE_TOGGLE_ID: &str = "profile";
+
+/// Represents a --profile argument. The arguments between it and the next --end-profile argument
+/// only apply while this profile is active.
+pub(super) struct ProfileArg {
+    pub name: String,
+}
+
+impl ProfileArg {
+	pub fn parse(args: Vec<String>) -> Result<ProfileArg, ArgumentError> {
+        let name = match args.as_slice() {
+            [_, name] => name.clone(),
+            _ => return Err(ArgumentError::new("The --profile argument requires exactly one name, like --profile gaming.")),
+        };
+        // The name becomes the name of a toggle state, so it is subject to the same restrictions.
+        if name.contains(':') || name.contains('=') {
+            return Err(ArgumentError::new(format!("The names of profiles cannot contain colons or equals signs. Offending name: {}", name)));
+        }
+        if name.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ArgumentError::new(format!("The names of profiles cannot be numbers. Offending name: {}", name)));
+        }
+        Ok(ProfileArg { name })
+    }
+}
+
+pub(super) fn parse_end_profile(args: Vec<String>) -> Result<(), ArgumentError> {
+    match args.len() {
+        1 => Ok(()),
+        _ => Err(ArgumentError::new("The --end-profile argument does not take any parameters.")),
+    }
+}
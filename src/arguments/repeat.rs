@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::key::{Key, KeyParser};
+use crate::stream::repeat::Repeat;
+use crate::time::Duration;
+
+/// Represents a --repeat argument.
+pub(super) struct RepeatArg {
+    pub keys: Vec<Key>,
+    pub delay: Duration,
+    pub period: Duration,
+}
+
+impl RepeatArg {
+	pub fn parse(args: Vec<String>) -> Result<RepeatArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["delay", "period"],
+            false,
+            true,
+        )?;
+
+        let mut parser = KeyParser::pure();
+        parser.forbid_non_EV_KEY = true;
+        let keys = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        // The defaults are the same as the kernel's defaults for REP_DELAY and REP_PERIOD.
+        let delay = match arg_group.get_unique_clause("delay")? {
+            Some(value) => parse_period_value(&value)?,
+            None => Duration::from_millis(250),
+        };
+        let period = match arg_group.get_unique_clause("period")? {
+            Some(value) => parse_period_value(&value)?,
+            None => Duration::from_millis(33),
+        };
+
+        Ok(RepeatArg { keys, delay, period })
+    }
+
+    pub fn compile(self) -> Repeat {
+        Repeat::new(self.keys, self.delay, self.period)
+    }
+}
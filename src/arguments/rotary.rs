@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::event::{EventType, EventValue};
+use crate::key::{Key, KeyParser};
+use crate::stream::rotary::Rotary;
+use crate::utils::split_once;
+
+/// Represents a --rotary argument.
+pub(super) struct RotaryArg {
+    pub input_key: Key,
+    pub output_key: Key,
+    pub step: EventValue,
+    pub wrap: Option<(EventValue, EventValue)>,
+}
+
+impl RotaryArg {
+	pub fn parse(args: Vec<String>) -> Result<RotaryArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["step", "wrap"],
+            false,
+            true,
+        )?;
+
+        let keys = arg_group.require_keys()?;
+        if keys.len() != 2 {
+            return Err(ArgumentError::new("A --rotary argument requires exactly one input key and one output key."));
+        }
+
+        let input_key = KeyParser::pure().parse(&keys[0])?;
+        if input_key.requires_event_type() != Some(EventType::ABS) {
+            return Err(ArgumentError::new(format!(
+                "The input key of a --rotary argument must be an absolute axis, such as abs:wheel. Offending key: {}", keys[0]
+            )));
+        }
+
+        let mut output_parser = KeyParser::default_mask();
+        output_parser.allow_values = false;
+        let output_key = output_parser.parse(&keys[1])?;
+        if output_key.requires_event_type() != Some(EventType::REL) {
+            return Err(ArgumentError::new(format!(
+                "The output key of a --rotary argument must be a relative axis, such as rel:wheel. Offending key: {}", keys[1]
+            )));
+        }
+
+        let step = match arg_group.get_unique_clause("step")? {
+            None => 1,
+            Some(value) => match value.parse::<EventValue>() {
+                Ok(step) if step > 0 => step,
+                _ => return Err(ArgumentError::new(format!(
+                    "The step= clause requires a positive integer, but \"{}\" was provided.", value
+                ))),
+            },
+        };
+
+        let wrap = match arg_group.get_unique_clause("wrap")? {
+            None => None,
+            Some(value) => Some(parse_wrap_range(&value)?),
+        };
+
+        Ok(RotaryArg { input_key, output_key, step, wrap })
+    }
+
+    pub fn compile(self) -> Rotary {
+        Rotary::new(self.input_key, self.output_key, self.step, self.wrap)
+    }
+}
+
+/// Parses a range like "0~255".
+fn parse_wrap_range(value: &str) -> Result<(EventValue, EventValue), ArgumentError> {
+    let error = || ArgumentError::new(format!(
+        "The wrap= clause requires a range of the form MIN~MAX, but \"{}\" was provided.", value
+    ));
+    let (min_str, max_str) = match split_once(value, "~") {
+        (min_str, Some(max_str)) => (min_str, max_str),
+        (_, None) => return Err(error()),
+    };
+    let min: EventValue = min_str.parse().map_err(|_| error())?;
+    let max: EventValue = max_str.parse().map_err(|_| error())?;
+    if min >= max {
+        return Err(ArgumentError::new("The upper bound of the wrap= range must be larger than its lower bound."));
+    }
+    Ok((min, max))
+}
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::{ArgumentError, Context, SystemError};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::key::{Key, KeyParser};
+use crate::stream::script::Script;
+use std::path::PathBuf;
+
+/// Represents a --script argument.
+pub(super) struct ScriptArg {
+    pub keys: Vec<Key>,
+    pub path: PathBuf,
+    /// The events that the script may generate, as specified by emit= clauses.
+    pub emit_keys: Vec<Key>,
+}
+
+impl ScriptArg {
+	pub fn parse(args: Vec<String>) -> Result<ScriptArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["file", "emit"],
+            false,
+            true,
+        )?;
+
+        if ! cfg!(feature = "lua") {
+            return Err(ArgumentError::new("The --script argument requires evsieve to be compiled with the lua feature."));
+        }
+
+        let keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_keys_or_empty_key())?;
+        let path = PathBuf::from(arg_group.require_unique_clause("file")?);
+
+        let mut emit_parser = KeyParser::default_mask();
+        emit_parser.allow_values = false;
+        let emit_keys = emit_parser.parse_all(&arg_group.get_clauses("emit"))?;
+        for (key, key_str) in emit_keys.iter().zip(arg_group.get_clauses("emit")) {
+            if key.requires_event_code().is_none() {
+                return Err(ArgumentError::new(format!(
+                    "The emit= clause requires events with a type and code, such as key:a. Offending clause: emit={}", key_str
+                )));
+            }
+        }
+
+        Ok(ScriptArg { keys, path, emit_keys })
+    }
+
+    pub fn compile(self) -> Result<Script, SystemError> {
+        let path_str = self.path.display().to_string();
+        Script::load(self.path, self.keys, self.emit_keys)
+            .with_context_of(|| format!("While loading the script {}:", path_str))
+    }
+}
@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::domain::Domain;
+use crate::key::{Key, KeyParser};
+use crate::range::Range;
+use crate::stream::split::Split;
+use crate::utils::split_once;
+
+/// Represents a --split argument.
+pub(super) struct SplitArg {
+    pub input_key: Key,
+    pub targets: Vec<(Range, Domain)>,
+}
+
+impl SplitArg {
+	pub fn parse(args: Vec<String>) -> Result<SplitArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            true,
+        )?;
+
+        let keys = arg_group.require_keys()?;
+        if keys.len() < 2 {
+            return Err(ArgumentError::new("A --split argument requires a source key followed by at least one RANGE@DOMAIN target, such as abs:z 0~127@low."));
+        }
+        let input_key = KeyParser::pure().parse(&keys[0])?;
+
+        let targets = keys[1..].iter()
+            .map(|target| parse_target(target))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (index, (range, _)) in targets.iter().enumerate() {
+            if targets[.. index].iter().any(|(other_range, _)| range.intersects_with(other_range)) {
+                return Err(ArgumentError::new(format!(
+                    "The ranges of a --split argument must not overlap. Offending target: {}", keys[index + 1]
+                )));
+            }
+        }
+
+        Ok(SplitArg { input_key, targets })
+    }
+
+    pub fn compile(self) -> Split {
+        Split::new(self.input_key, self.targets)
+    }
+}
+
+/// Parses a target like "0~127@low".
+fn parse_target(target: &str) -> Result<(Range, Domain), ArgumentError> {
+    let (range_str, domain_str) = match split_once(target, "@") {
+        (range_str, Some(domain_str)) if ! range_str.is_empty() && ! domain_str.is_empty() => (range_str, domain_str),
+        _ => return Err(ArgumentError::new(format!(
+            "The targets of a --split argument must be of the form RANGE@DOMAIN, such as 0~127@low, but \"{}\" was provided.", target
+        ))),
+    };
+    let range = crate::key::interpret_event_value(range_str, &KeyParser::default_filter())?;
+    let domain = crate::domain::resolve(domain_str)?;
+    Ok((range, domain))
+}
+
+#[test]
+fn unittest() {
+    let parse = |args: &[&str]| SplitArg::parse(
+        std::iter::once("--split").chain(args.iter().copied()).map(str::to_owned).collect()
+    );
+    let split = parse(&["abs:z", "0~127@low", "128~@high"]).unwrap();
+    assert_eq!(split.targets.len(), 2);
+    assert_eq!(split.targets[1].0, Range::new(Some(128), None));
+    assert!(parse(&["abs:z", "0~127@low", "127~255@high"]).is_err());
+    assert!(parse(&["abs:z", "0~127"]).is_err());
+    assert!(parse(&["abs:z"]).is_err());
+}
@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::key::{Key, KeyParser};
+use crate::stream::sticky::Sticky;
+use crate::time::Duration;
+
+/// Represents a --sticky argument.
+pub(super) struct StickyArg {
+    /// The modifiers that become sticky.
+    pub keys: Vec<Key>,
+    pub timeout: Option<Duration>,
+}
+
+impl StickyArg {
+	pub fn parse(args: Vec<String>) -> Result<StickyArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["timeout"],
+            false,
+            true,
+        )?;
+
+        let parser = KeyParser {
+            default_value: "",
+            allow_values: false,
+            allow_ranges: false,
+            allow_transitions: false,
+            allow_types: false,
+            allow_relative_values: false,
+            forbid_non_EV_KEY: true,
+            namespace: crate::event::Namespace::User,
+        };
+        let keys = parser.parse_all(&arg_group.require_keys()?)?;
+
+        let timeout = match arg_group.get_unique_clause("timeout")? {
+            None => None,
+            Some(value) => Some(parse_period_value(&value)?),
+        };
+
+        Ok(StickyArg { keys, timeout })
+    }
+
+    pub fn compile(self) -> Sticky {
+        Sticky::new(self.keys, self.timeout)
+    }
+}
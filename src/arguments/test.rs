@@ -57,6 +57,18 @@ fn test_argument_validity() {
     require_err(["--hook", "key:a", "key:b:1", "--withhold", "key"]);
     require_ok( ["--hook", "key:a", "key:b:1", "--withhold", "key:a"]);
     require_ok( ["--hook", "key:a", "key:b:1", "--withhold", "btn"]);
+    require_ok( ["--hook", "abs:x:200~", "--withhold"]);
+    require_ok( ["--hook", "rel:y:10~", "key:a", "--withhold"]);
+    require_ok( ["--hook", "abs:x:200~", "--withhold", "abs"]);
+    require_err(["--hook", "rel:x", "--withhold"]);
+    require_err(["--hook", "key:a", "--withhold", "msc"]);
+
+    // Patterns only have to match a device at startup if evsieve does not watch for new ones.
+    require_ok( ["--input", "/nonexistent-evsieve-test/usb-*-event-kbd", "persist=full"]);
+    require_err(["--input", "/nonexistent-evsieve-test/usb-*-event-kbd", "persist=reopen"]);
+    require_err(["--input", "/nonexistent-evsieve-test/usb-*-event-kbd", "persist=full", "domain=auto"]);
+    require_ok( ["--input", "/nonexistent-evsieve-test/usb-*-event-kbd", "persist=full", "reopen-interval=1"]);
+    require_err(["--input", "/nonexistent-evsieve-test/usb-*-event-kbd", "persist=none", "reopen-interval=1"]);
 }
 
 fn require_ok(args: impl IntoIterator<Item=impl Into<String>>) {
@@ -74,4 +86,45 @@ fn try_implement(args: impl IntoIterator<Item=impl Into<String>>) -> Result<crat
         .collect();
 
     crate::arguments::parser::implement(args)
-}
\ No newline at end of file
+}
+#[test]
+fn test_benchmark_side_effects() {
+    use crate::event::{EventCode, EventType};
+    use crate::io::serialize::{encode_event, TimedEvent};
+
+    let directory = std::env::temp_dir().join(format!("evsieve-benchmark-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let key_a = EventCode::new(EventType::KEY, 30);
+    let recording = directory.join("recording");
+    let bytes: Vec<u8> = [(key_a, 1), (EventCode::SYN_REPORT, 0), (key_a, 0), (EventCode::SYN_REPORT, 0)].iter()
+        .flat_map(|&(code, value)| encode_event(TimedEvent { time_us: 0, code, value }).to_vec())
+        .collect();
+    std::fs::write(&recording, bytes).unwrap();
+
+    // The benchmark toggles the state many times, but must leave the state file alone, must not
+    // run the exec-shell commands and must not write to the files of --print.
+    let state_file = directory.join("toggle-mode");
+    std::fs::write(&state_file, "2\n").unwrap();
+    std::env::set_var("STATE_DIRECTORY", &directory);
+    let touch = |name: &str| format!("exec-shell=touch {}", directory.join(name).display());
+    let args: Vec<String> = vec![
+        std::env::args().next().unwrap(),
+        "--input".to_owned(), format!("file={}", recording.display()), "timing=fast".to_owned(),
+        "--toggle".to_owned(), "key:a".to_owned(), "key:b".to_owned(), "key:c".to_owned(),
+            "id=mode".to_owned(), "persist=state".to_owned(), touch("toggle-ran"),
+        "--hook".to_owned(), "key:a".to_owned(), "toggle=mode".to_owned(), touch("hook-ran"),
+        "--print".to_owned(), format!("file={}", directory.join("log").display()),
+        "--output".to_owned(),
+        "--benchmark".to_owned(), "events=100".to_owned(),
+    ];
+    let (implementation, num_events) = crate::arguments::parser::implement_benchmark(args).unwrap();
+    crate::benchmark::run(implementation, num_events);
+
+    assert_eq!(std::fs::read_to_string(&state_file).unwrap(), "2\n");
+    assert!(! directory.join("toggle-ran").exists());
+    assert!(! directory.join("hook-ran").exists());
+    assert!(! directory.join("log").exists());
+    let _ = std::fs::remove_dir_all(&directory);
+}
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::key::{Key, KeyParser};
+use crate::stream::throttle::{Throttle, ThrottleMode};
+use crate::time::Duration;
+
+/// Represents a --throttle argument.
+pub(super) struct ThrottleArg {
+    pub keys: Vec<Key>,
+    pub period: Duration,
+    pub mode: ThrottleMode,
+}
+
+impl ThrottleArg {
+	pub fn parse(args: Vec<String>) -> Result<ThrottleArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["period", "mode"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_keys_or_empty_key())?;
+        let period = parse_period_value(&arg_group.require_unique_clause("period")?)?;
+
+        let mode = match arg_group.get_unique_clause("mode")?.as_deref() {
+            None | Some("drop") => ThrottleMode::Drop,
+            Some("coalesce") => ThrottleMode::Coalesce,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid value for the mode= clause: \"{}\". Valid values are \"drop\" and \"coalesce\".", other
+            ))),
+        };
+
+        Ok(ThrottleArg { keys, period, mode })
+    }
+
+    pub fn compile(self) -> Throttle {
+        Throttle::new(self.keys, self.period, self.mode)
+    }
+}
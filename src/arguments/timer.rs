@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::event::{EventCode, Namespace};
+use crate::key::{Key, KeyParser};
+use crate::stream::timer::Timer;
+use crate::time::Duration;
+
+/// Represents a --timer argument.
+pub(super) struct TimerArg {
+    /// Specified by the send-key clauses, along with their event codes.
+    pub send_keys: Vec<(Key, EventCode)>,
+    pub period: Duration,
+}
+
+impl TimerArg {
+	pub fn parse(args: Vec<String>) -> Result<TimerArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["period", "send-key"],
+            false,
+            false,
+        )?;
+
+        let period = parse_period_value(&arg_group.require_unique_clause("period")?)?;
+        if period == Duration::from_millis(0) {
+            return Err(ArgumentError::new("The period of a --timer argument must be positive."));
+        }
+
+        let parser = KeyParser {
+            allow_transitions: false,
+            allow_values: false,
+            allow_ranges: false,
+            allow_types: false,
+            default_value: "",
+            allow_relative_values: false,
+            forbid_non_EV_KEY: true,
+            namespace: Namespace::User,
+        };
+        let send_key_strs = arg_group.get_clauses("send-key");
+        if send_key_strs.is_empty() {
+            return Err(ArgumentError::new("A --timer argument requires at least one send-key= clause."));
+        }
+        let mut send_keys = Vec::new();
+        for key_str in send_key_strs {
+            let key = parser.parse(&key_str)?;
+            let code = match key.requires_event_code() {
+                Some(code) => code,
+                None => return Err(ArgumentError::new(format!(
+                    "The send-key= clause of a --timer argument must specify an event code, such as key:f24. Offending key: {}", key_str
+                ))),
+            };
+            send_keys.push((key, code));
+        }
+
+        Ok(TimerArg { send_keys, period })
+    }
+
+    pub fn compile(self) -> Timer {
+        Timer::new(self.send_keys, self.period)
+    }
+}
@@ -4,6 +4,11 @@ use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
 use crate::key::{Key, KeyParser};
 use crate::stream::map::ToggleMode;
+use crate::arguments::delay::parse_period_value;
+use crate::time::Duration;
+use crate::event::EventType;
+use crate::utils;
+use std::path::PathBuf;
 
 /// Represents a --toggle argument.
 pub(super) struct ToggleArg {
@@ -11,13 +16,23 @@ pub(super) struct ToggleArg {
     pub output_keys: Vec<Key>,
     pub id: Option<String>,
     pub mode: ToggleMode,
+    /// The names of the states as provided by a states= clause.
+    pub state_names: Option<Vec<String>>,
+    /// If persist=state was specified, the file in which the active state is stored.
+    pub persist_path: Option<PathBuf>,
+    /// How long the toggle may remain inactive before it returns to its first state.
+    pub timeout: Option<Duration>,
+    /// The LEDs that shall be on while a certain state (zero-indexed) is active.
+    pub leds: Vec<(usize, Key)>,
+    /// Shell commands to run whenever the active state changes.
+    pub exec_shell: Vec<String>,
 }
 
 impl ToggleArg {
 	pub fn parse(args: Vec<String>) -> Result<ToggleArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &["id", "mode"],
+            &["id", "mode", "states", "persist", "timeout", "led", "exec-shell"],
             false,
             true,
         )?;
@@ -47,9 +62,35 @@ impl ToggleArg {
                 return Err(ArgumentError::new(format!("A toggle's id cannot contain any colons. Offending id: {}", id)));
             }
         }
+
+        let state_names = match arg_group.get_unique_clause("states")? {
+            None => None,
+            Some(states_str) => Some(parse_state_names(&states_str, output_keys.len())?),
+        };
+
+        let persist_path = match arg_group.get_unique_clause("persist")?.as_deref() {
+            None | Some("none") => None,
+            Some("state") => match &id {
+                Some(id) => Some(state_file_path(id)?),
+                None => return Err(ArgumentError::new("A --toggle with persist=state must have an id.")),
+            },
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid persist mode specified: {}", other
+            ))),
+        };
+
+        let timeout = match arg_group.get_unique_clause("timeout")? {
+            None => None,
+            Some(value) => Some(parse_period_value(&value)?),
+        };
+
+        let leds = arg_group.get_clauses("led").iter()
+            .map(|clause| parse_led(clause, output_keys.len(), state_names.as_deref()))
+            .collect::<Result<Vec<_>, _>>()?;
         
         Ok(ToggleArg {
-            input_key, output_keys, mode, id
+            input_key, output_keys, mode, id, state_names, persist_path, timeout, leds,
+            exec_shell: arg_group.get_clauses("exec-shell"),
         })
     }
 
@@ -57,4 +98,97 @@ impl ToggleArg {
     pub fn size(&self) -> usize {
         self.output_keys.len()
     }
-}
\ No newline at end of file
+}
+
+/// Returns the file in which the state of a toggle with persist=state is stored.
+fn state_file_path(id: &str) -> Result<PathBuf, ArgumentError> {
+    if id.contains('/') {
+        return Err(ArgumentError::new(format!("The id of a toggle with persist=state cannot contain any slashes. Offending id: {}", id)));
+    }
+    Ok(state_directory("the state of a toggle with persist=state")?.join(format!("toggle-{}", id)))
+}
+
+/// Returns the directory in which evsieve stores state that must survive a restart. This is the
+/// directory provided by systemd's StateDirectory= if available, otherwise the XDG state directory.
+/// The `purpose` is used in the error message in case no directory can be found.
+pub(super) fn state_directory(purpose: &str) -> Result<PathBuf, ArgumentError> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| ! value.is_empty()).map(PathBuf::from);
+    if let Some(dir) = var("STATE_DIRECTORY") {
+        Ok(dir)
+    } else if let Some(dir) = var("XDG_STATE_HOME") {
+        Ok(dir.join("evsieve"))
+    } else if let Some(dir) = var("HOME") {
+        Ok(dir.join(".local/state/evsieve"))
+    } else {
+        Err(ArgumentError::new(format!("Cannot determine where to store {}: none of the STATE_DIRECTORY, XDG_STATE_HOME or HOME environment variables are set.", purpose)))
+    }
+}
+
+/// Parses a clause like led=2:led:scrolll or led=gaming:led:capsl@kbd.
+fn parse_led(clause: &str, num_states: usize, state_names: Option<&[String]>) -> Result<(usize, Key), ArgumentError> {
+    let (state_str, key_str) = match utils::split_once(clause, ":") {
+        (state_str, Some(key_str)) => (state_str, key_str),
+        (_, None) => return Err(ArgumentError::new(format!(
+            "The led= clause requires a state and an LED, like led=2:led:scrolll. Offending clause: led={}", clause
+        ))),
+    };
+
+    let state = match state_str.parse::<usize>() {
+        Ok(index) if index >= 1 && index <= num_states => index - 1,
+        Ok(index) => return Err(ArgumentError::new(format!(
+            "The index {} is out of range for this toggle.", index
+        ))),
+        Err(_) => match state_names.and_then(|names| names.iter().position(|name| name == state_str)) {
+            Some(index) => index,
+            None => return Err(ArgumentError::new(format!(
+                "This toggle has no state named \"{}\".", state_str
+            ))),
+        },
+    };
+
+    let mut parser = KeyParser::default_mask();
+    parser.allow_values = false;
+    let key = parser.parse(key_str)?;
+    if key.requires_event_type() != Some(EventType::LED) || key.requires_event_code().is_none() {
+        return Err(ArgumentError::new(format!(
+            "The led= clause requires an LED such as led:scrolll. Offending key: {}", key_str
+        )));
+    }
+
+    Ok((state, key))
+}
+
+/// Parses a comma-separated list of state names like "normal,gaming,media".
+fn parse_state_names(states_str: &str, num_states: usize) -> Result<Vec<String>, ArgumentError> {
+    let names: Vec<String> = states_str.split(',').map(str::to_owned).collect();
+    if names.len() != num_states {
+        return Err(ArgumentError::new(format!(
+            "The states= clause names {} states, but the toggle has {} output keys.", names.len(), num_states
+        )));
+    }
+    for (index, name) in names.iter().enumerate() {
+        if name.is_empty() {
+            return Err(ArgumentError::new("The names of toggle states cannot be empty."));
+        }
+        if name.contains(':') {
+            return Err(ArgumentError::new(format!("The names of toggle states cannot contain any colons. Offending name: {}", name)));
+        }
+        // Names that look like indices would be ambiguous in toggle=ID:INDEX clauses.
+        if name.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ArgumentError::new(format!("The names of toggle states cannot be numbers. Offending name: {}", name)));
+        }
+        if names[..index].contains(name) {
+            return Err(ArgumentError::new(format!("Two states of the same toggle cannot have the same name: {}", name)));
+        }
+    }
+    Ok(names)
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_state_names("normal,gaming", 2).unwrap(), vec!["normal", "gaming"]);
+    assert!(parse_state_names("normal,gaming", 3).is_err());
+    assert!(parse_state_names("normal,,gaming", 3).is_err());
+    assert!(parse_state_names("normal,2", 2).is_err());
+    assert!(parse_state_names("normal,normal", 2).is_err());
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::event::EventValue;
+use crate::key::{Key, KeyParser};
+use crate::stream::touchpad::{Touchpad, TouchpadCodes};
+use crate::time::Duration;
+use crate::ecodes;
+
+/// Represents a --touchpad argument.
+pub(super) struct TouchpadArg {
+    pub keys: Vec<Key>,
+    pub speed: f64,
+    pub tap_period: Option<Duration>,
+    pub tap_distance: EventValue,
+}
+
+impl TouchpadArg {
+	pub fn parse(args: Vec<String>) -> Result<TouchpadArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &["tap"],
+            &["speed", "tap", "tap-distance"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::pure().parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let speed = match arg_group.get_unique_clause("speed")? {
+            None => 1.0,
+            Some(value) => match crate::utils::parse_number(&value) {
+                Some(speed) if speed > 0.0 => speed,
+                _ => return Err(ArgumentError::new(format!(
+                    "The speed= clause requires a positive number, but \"{}\" was provided.", value
+                ))),
+            },
+        };
+
+        let tap_period = match arg_group.get_unique_clause_or_default_if_flag("tap", "0.2")? {
+            None => None,
+            Some(value) => Some(parse_period_value(&value)?),
+        };
+
+        let tap_distance = match arg_group.get_unique_clause("tap-distance")? {
+            None => 10,
+            Some(value) => match value.parse::<EventValue>() {
+                Ok(distance) if distance >= 0 => distance,
+                _ => return Err(ArgumentError::new(format!(
+                    "The tap-distance= clause requires a nonnegative integer, but \"{}\" was provided.", value
+                ))),
+            },
+        };
+
+        Ok(TouchpadArg { keys, speed, tap_period, tap_distance })
+    }
+
+    pub fn compile(self) -> Result<Touchpad, ArgumentError> {
+        let codes = TouchpadCodes {
+            abs_x: ecodes::event_code("abs", "x")?,
+            abs_y: ecodes::event_code("abs", "y")?,
+            btn_touch: ecodes::event_code("btn", "touch")?,
+            rel_x: ecodes::event_code("rel", "x")?,
+            rel_y: ecodes::event_code("rel", "y")?,
+            btn_left: ecodes::event_code("btn", "left")?,
+        };
+        Ok(Touchpad::new(self.keys, codes, self.speed, self.tap_period, self.tap_distance))
+    }
+}
@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::key::{Key, KeyParser};
+use crate::stream::trace::Tracer;
+
+/// Represents a --trace argument.
+pub(super) struct TraceArg {
+    pub keys: Vec<Key>,
+}
+
+impl TraceArg {
+	pub fn parse(args: Vec<String>) -> Result<TraceArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter().parse_all(&arg_group.get_keys_or_empty_key())?;
+        Ok(TraceArg { keys })
+    }
+
+    pub fn compile(self) -> Tracer {
+        Tracer::new(self.keys)
+    }
+}
@@ -0,0 +1,100 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::event::{EventCode, EventValue};
+use crate::key::{Key, KeyParser};
+use crate::stream::unicode::UnicodeMap;
+use crate::ecodes;
+
+/// Represents a --unicode argument.
+pub(super) struct UnicodeArg {
+    pub input_key: Key,
+    pub character: char,
+}
+
+impl UnicodeArg {
+	pub fn parse(args: Vec<String>) -> Result<UnicodeArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["char"],
+            false,
+            true,
+        )?;
+
+        let keys = arg_group.require_keys()?;
+        if keys.len() != 1 {
+            return Err(ArgumentError::new("A --unicode argument requires exactly one input key."));
+        }
+        let mut parser = KeyParser::default_filter();
+        parser.forbid_non_EV_KEY = true;
+        let input_key = parser.parse(&keys[0])?;
+
+        let character = parse_character(&arg_group.require_unique_clause("char")?)?;
+
+        Ok(UnicodeArg { input_key, character })
+    }
+
+    pub fn compile(self) -> Result<UnicodeMap, ArgumentError> {
+        let sequence = ctrl_shift_u_sequence(self.character)?;
+        Ok(UnicodeMap::new(self.input_key, sequence))
+    }
+}
+
+/// Interprets either a single literal character like "é" or a codepoint like "U+00E9".
+fn parse_character(value: &str) -> Result<char, ArgumentError> {
+    let hex_str = value.strip_prefix("U+").or_else(|| value.strip_prefix("u+"));
+    if let Some(hex_str) = hex_str {
+        let codepoint = u32::from_str_radix(hex_str, 16).map_err(|_| ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as a hexadecimal codepoint.", hex_str
+        )))?;
+        return std::char::from_u32(codepoint).ok_or_else(|| ArgumentError::new(format!(
+            "U+{:04X} is not a valid Unicode codepoint.", codepoint
+        )));
+    }
+
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(character), None) => Ok(character),
+        (None, _) => Err(ArgumentError::new("The char= clause cannot be empty.")),
+        (Some(_), Some(_)) => Err(ArgumentError::new(format!(
+            "The char= clause accepts only a single character or a codepoint of the form U+XXXX, but \"{}\" was provided.", value
+        ))),
+    }
+}
+
+/// Computes the sequence of key events required to type a character using the Ctrl+Shift+U
+/// input method supported by IBus and GTK: Ctrl+Shift+U, followed by the hexadecimal digits
+/// of the codepoint, followed by a space.
+fn ctrl_shift_u_sequence(character: char) -> Result<Vec<(EventCode, EventValue)>, ArgumentError> {
+    let key = |name: &str| ecodes::event_code("key", name);
+    let leftctrl = key("leftctrl")?;
+    let leftshift = key("leftshift")?;
+
+    let mut sequence: Vec<(EventCode, EventValue)> = vec![
+        (leftctrl, 1), (leftshift, 1),
+        (key("u")?, 1), (key("u")?, 0),
+        (leftshift, 0), (leftctrl, 0),
+    ];
+    for digit in format!("{:x}", character as u32).chars() {
+        let digit_code = key(&digit.to_string())?;
+        sequence.push((digit_code, 1));
+        sequence.push((digit_code, 0));
+    }
+    sequence.push((key("space")?, 1));
+    sequence.push((key("space")?, 0));
+
+    Ok(sequence)
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_character("a").unwrap(), 'a');
+    assert_eq!(parse_character("é").unwrap(), 'é');
+    assert_eq!(parse_character("U+00E9").unwrap(), 'é');
+    assert_eq!(parse_character("u+1f600").unwrap(), '\u{1F600}');
+    parse_character("").unwrap_err();
+    parse_character("ab").unwrap_err();
+    parse_character("U+D800").unwrap_err();
+    parse_character("U+quux").unwrap_err();
+}
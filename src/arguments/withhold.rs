@@ -5,29 +5,54 @@ use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
 use crate::arguments::hook::HookArg;
 use crate::stream::hook::Trigger;
+use crate::stream::withhold::ReleaseOrder;
 use crate::key::{Key, KeyParser};
+use crate::arguments::delay::parse_period_value;
+use crate::time::Duration;
 
 /// Represents a --withhold argument.
 pub(super) struct WithholdArg {
     pub keys: Vec<Key>,
     /// All the triggers of all --hook arguments that come before a --withhold argument.
     pub associated_triggers: Vec<Trigger>,
+    pub timeout: Option<Duration>,
+    pub order: ReleaseOrder,
 }
 
 impl WithholdArg {
 	pub fn parse(args: Vec<String>) -> Result<WithholdArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &[],
+            &["timeout", "order"],
             false,
             true,
         )?;
 
-        let mut parser = KeyParser::pure();
-        parser.forbid_non_EV_KEY = true;
-        let keys = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
+        let key_strs = arg_group.get_keys_or_empty_key();
+        let keys = KeyParser::pure().parse_all(&key_strs)?;
+        for (key, key_str) in keys.iter().zip(&key_strs) {
+            match key.requires_event_type() {
+                None | Some(EventType::KEY) | Some(EventType::ABS) | Some(EventType::REL) => (),
+                Some(_) => return Err(ArgumentError::new(format!(
+                    "Only events of type \"key\", \"btn\", \"abs\" or \"rel\" can be withheld. Offending key: {}", key_str
+                ))),
+            }
+        }
+
+        let timeout = match arg_group.get_unique_clause("timeout")? {
+            None => None,
+            Some(value) => Some(parse_period_value(&value)?),
+        };
 
-        Ok(WithholdArg { keys, associated_triggers: Vec::new() })
+        let order = match arg_group.get_unique_clause("order")?.as_deref() {
+            None | Some("decision") => ReleaseOrder::Decision,
+            Some("original") => ReleaseOrder::Original,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid value for the order= clause: \"{}\". Valid values are \"decision\" and \"original\".", other
+            ))),
+        };
+
+        Ok(WithholdArg { keys, associated_triggers: Vec::new(), timeout, order })
     }
 
     pub fn associate_hooks(&mut self, hooks: &mut [&mut HookArg]) -> Result<(), ArgumentError> {
@@ -55,31 +80,53 @@ impl WithholdArg {
                 }
 
                 // Make sure that all triggers whose associated may possibly be withheld can
-                // only trigger on events of type EV_KEY.
+                // only trigger on events of type EV_KEY, EV_ABS or EV_REL.
+                let mut pedantic_parser = super::hook::PARSER;
+                pedantic_parser.allow_values = false;
+                let has_default_value = pedantic_parser.parse(key_str).is_ok();
                 match key.requires_event_type() {
-                    Some(EventType::KEY) => (),
+                    Some(EventType::KEY) => {
+                        // Only permit matching with default (unspecified) values.
+                        if ! has_default_value {
+                            return Err(ArgumentError::new(format!(
+                                "Cannot use --withhold after a --hook that activates on events of type \"key\" or \"btn\" with a specific value such as \"{}\".",
+                                key_str
+                            )));
+                        }
+                    },
+                    Some(EventType::ABS) | Some(EventType::REL) => {
+                        // The default value of 1~ is hardly ever meaningful for axes, so require
+                        // the user to specify the range in which the hook activates.
+                        if has_default_value {
+                            return Err(ArgumentError::new(format!(
+                                "Cannot use --withhold after a hook that triggers on the key \"{}\". Hooks on events of type \"abs\" or \"rel\" must specify the range of values on which they activate, such as \"{}:200~\".",
+                                key_str, key_str,
+                            )));
+                        }
+                    },
                     None => return Err(ArgumentError::new(format!(
                         "Cannot use --withhold after a hook that triggers on the key \"{}\", because this key can be triggered by events of any event type.",
                         key_str,
                     ))),
                     Some(_) => return Err(ArgumentError::new(format!(
-                        "Cannot use --withhold after a hook that triggers on the key \"{}\". Only events of type \"key\" or \"btn\" can be withheld. If you wish for this --withhold to ignore non-EV_KEY-type events, then you can get rid of this error by explicitly specifying \"--withhold key btn\".",
+                        "Cannot use --withhold after a hook that triggers on the key \"{}\". Only events of type \"key\", \"btn\", \"abs\" or \"rel\" can be withheld. If you wish for this --withhold to ignore other events, then you can get rid of this error by explicitly specifying \"--withhold key btn abs rel\".",
                         key_str,
                     ))),
                 }
-
-                // Only permit matching with default (unspecified) values.
-                let mut pedantic_parser = super::hook::PARSER;
-                pedantic_parser.allow_values = false;
-                if pedantic_parser.parse(key_str).is_err() {
-                    return Err(ArgumentError::new(format!(
-                        "Cannot use --withhold after a --hook that activates on events with a specific value such as \"{}\".",
-                        key_str
-                    )));
-                }
             }
         }
 
+        // If a list of keys was specified, only events matching those keys get withheld while
+        // the other keys of the hooks pass immediately. Warn if that means nothing gets withheld.
+        let can_withhold_anything = hooks.iter().any(|hook_arg|
+            hook_arg.keys_and_str.iter().any(|(key, _)|
+                self.keys.iter().any(|self_key| self_key.intersects_with(key))
+            )
+        );
+        if ! can_withhold_anything {
+            crate::utils::warn_once("Warning: none of the keys of a --withhold argument match any key of its preceding hooks, so that --withhold argument will never withhold any events.");
+        }
+
         // Inform all associated hooks to mark events as withholdable.
         for hook in hooks.iter_mut() {
             hook.mark_withholdable = true;
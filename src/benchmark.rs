@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements --benchmark: feeds events through the stream as fast as possible and reports how
+//! long that took, in total and for each argument. The events come from the recordings of
+//! `--input file=` arguments, or are made up based on the capabilities of the other inputs.
+
+use crate::arguments::parser::Implementation;
+use crate::capability::Capabilities;
+use crate::domain::Domain;
+use crate::event::{Event, EventCode, EventValue, Namespace};
+use crate::stream::{self, Setup};
+use crate::time::{Duration, Instant};
+use std::collections::HashMap;
+
+/// Events that get fed through the stream over and over, as if they came from a single device.
+struct Source {
+    domain: Domain,
+    /// Every report ends with an EV_SYN event.
+    events: Vec<(EventCode, EventValue)>,
+    /// The index of the next event in `events`.
+    position: usize,
+    /// The value each event code had the last time it was fed through the stream.
+    state: HashMap<EventCode, EventValue>,
+}
+
+impl Source {
+    /// Returns None if there are no events other than EV_SYN events to feed.
+    fn new(domain: Domain, mut events: Vec<(EventCode, EventValue)>) -> Option<Source> {
+        if events.iter().all(|(code, _)| code.ev_type().is_syn()) {
+            return None;
+        }
+        // A recording may not end with a complete report.
+        match events.last() {
+            Some((code, _)) if code.ev_type().is_syn() => (),
+            _ => events.push((EventCode::SYN_REPORT, 0)),
+        }
+        Some(Source { domain, events, position: 0, state: HashMap::new() })
+    }
+
+    /// Feeds the next report through the stream and returns how many events other than EV_SYN
+    /// events it contained.
+    fn feed_report(&mut self, setup: &mut Setup) -> u64 {
+        let mut count = 0;
+        loop {
+            let (code, value) = self.events[self.position];
+            self.position = (self.position + 1) % self.events.len();
+            let previous_value = self.state.insert(code, value).unwrap_or(0);
+            stream::run(setup, Event::new(code, value, previous_value, self.domain, Namespace::Input));
+            if code.ev_type().is_syn() {
+                return count;
+            }
+            count += 1;
+        }
+    }
+}
+
+/// Makes up reports that exercise all keys, absolute axes and relative axes a device is capable of:
+/// every key gets pressed and released, every absolute axis goes to its minimum, maximum and
+/// center, and every relative axis moves in both directions.
+fn synthesize_events(capabilities: &Capabilities) -> Vec<(EventCode, EventValue)> {
+    let mut codes: Vec<EventCode> = capabilities.codes.iter().copied().collect();
+    codes.sort_by_key(|code| (u16::from(code.ev_type()), code.code()));
+
+    let mut events = Vec::new();
+    for code in codes {
+        let values: Vec<EventValue> = if code.ev_type().is_key() {
+            vec![1, 0]
+        } else if code.ev_type().is_abs() {
+            match capabilities.abs_info.get(&code) {
+                Some(info) => vec![info.min_value, info.max_value, info.min_value / 2 + info.max_value / 2],
+                None => continue,
+            }
+        } else if code.ev_type().is_rel() {
+            vec![1, -1]
+        } else {
+            continue;
+        };
+        for value in values {
+            events.push((code, value));
+            events.push((EventCode::SYN_REPORT, 0));
+        }
+    }
+    events
+}
+
+/// Feeds `num_events` events through the program, not counting EV_SYN events, and returns a
+/// report of how long that took. Wakeups of --delay, --repeat and similar arguments get handled
+/// whenever they are due, so they only show up in the report if the benchmark runs long enough.
+pub fn run(implementation: Implementation, num_events: u64) -> String {
+    let Implementation { mut setup, input_devices, replay_devices, hidraw_devices, joystick_devices, .. } = implementation;
+
+    let mut sources: Vec<Source> = Vec::new();
+    for device in &replay_devices {
+        sources.extend(Source::new(device.domain(), device.remaining_events().collect()));
+    }
+    let capabilities = input_devices.iter().map(|device| (device.domain(), device.capabilities()))
+        .chain(hidraw_devices.iter().map(|device| (device.domain(), device.capabilities())))
+        .chain(joystick_devices.iter().map(|device| (device.domain(), device.capabilities())));
+    for (domain, capabilities) in capabilities {
+        sources.extend(Source::new(domain, synthesize_events(capabilities)));
+    }
+    if sources.is_empty() {
+        return "No events can be fed through the arguments, because none of the input devices can generate any keys, absolute axes or relative axes.\n".to_owned();
+    }
+
+    setup.measure_time_spent();
+    let start_time = Instant::now();
+    let mut events_fed: u64 = 0;
+    // The sources take turns, one report at a time, like devices used simultaneously would.
+    'feed: loop {
+        for source in &mut sources {
+            if events_fed >= num_events {
+                break 'feed;
+            }
+            events_fed += source.feed_report(&mut setup);
+            if let Some(next_wakeup) = setup.next_wakeup() {
+                if next_wakeup <= Instant::now() {
+                    stream::wakeup(&mut setup);
+                }
+            }
+        }
+    }
+    let elapsed = Instant::now().checked_duration_since(start_time)
+        .unwrap_or_else(|| Duration::from_nanos(0));
+
+    let events_per_second = match elapsed.as_nanos() {
+        0 => 0,
+        nanos => u128::from(events_fed) * 1_000_000_000 / nanos,
+    };
+    let mut report = format!(
+        "Processed {} events in {} ms, which is {} events per second.\n",
+        events_fed, elapsed.as_millis(), events_per_second
+    );
+    report.push_str(&setup.format_time_spent());
+    report.push_str(&setup.output().format_stats());
+    report
+}
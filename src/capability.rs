@@ -5,6 +5,7 @@ use crate::event::{EventType, EventCode, EventValue, Namespace};
 use crate::domain::Domain;
 use crate::range::Range;
 use crate::ecodes;
+use crate::logging;
 use crate::bindings::libevdev;
 
 const EV_REP_CODES: &[EventCode] = &[
@@ -207,7 +208,7 @@ impl Capabilities {
             // We might get an unbounded range in case we mapped some non-abs non-key event with
             // an unknown value range.
             if ! new_range.is_bounded() {
-                eprintln!("Warning: could not automatically derive the possible range of the absolute axis {}.", ecodes::event_name(cap.code));
+                logging::warning(format!("Warning: could not automatically derive the possible range of the absolute axis {}.", ecodes::event_name(cap.code)));
             };
             // i32::MIN and i32::MAX respectively. We use literals instead of constant names to be
             // compatible with rustc version 1.41.1, which is shipped by Debian.
@@ -221,6 +222,26 @@ impl Capabilities {
         }
     }
 
+    /// For sources that do not report their capabilities in advance: extends self so it includes
+    /// an event with the given code and value that was observed from such a source. Absolute axes
+    /// get a range that spans all values observed so far. Returns true if self changed.
+    pub fn add_observed_event(&mut self, code: EventCode, value: EventValue) -> bool {
+        let mut changed = self.codes.insert(code);
+        if code.ev_type().is_abs() {
+            let abs_info = self.abs_info.entry(code).or_insert(AbsInfo {
+                min_value: value,
+                max_value: value,
+                meta: AbsMeta { fuzz: 0, flat: 0, resolution: 0, value },
+            });
+            if value < abs_info.min_value || value > abs_info.max_value {
+                abs_info.min_value = std::cmp::min(abs_info.min_value, value);
+                abs_info.max_value = std::cmp::max(abs_info.max_value, value);
+                changed = true;
+            }
+        }
+        changed
+    }
+
     /// Adds EV_REP capabilities to self with arbitrary delay and period.
     /// The kernel is going to ignore the delay and period we give it anyway.
     pub fn require_ev_rep(&mut self) {
@@ -239,7 +260,7 @@ impl Capabilities {
 
     /// Sets the rep_info variable of self and makes sure that the correct capabilities
     /// are inserted to self.codes.
-    fn set_ev_rep(&mut self, repeat_info: RepeatInfo) {
+    pub fn set_ev_rep(&mut self, repeat_info: RepeatInfo) {
         self.rep_info = Some(repeat_info);
         for &code in EV_REP_CODES {
             self.codes.insert(code);
@@ -252,6 +273,26 @@ impl Capabilities {
             .collect()
     }
 
+    /// Adds all capabilities of `other` to self, e.g. because the events of both devices end up
+    /// in the same domain. The ranges of absolute axes that both have in common get merged.
+    pub fn merge(&mut self, other: &Capabilities) {
+        self.codes.extend(other.codes.iter().copied());
+        for (&code, &info) in &other.abs_info {
+            let merged_info = match self.abs_info.get(&code) {
+                Some(existing_info) => AbsInfo {
+                    min_value: std::cmp::min(existing_info.min_value, info.min_value),
+                    max_value: std::cmp::max(existing_info.max_value, info.max_value),
+                    meta: existing_info.meta,
+                },
+                None => info,
+            };
+            self.abs_info.insert(code, merged_info);
+        }
+        if self.rep_info.is_none() {
+            self.rep_info = other.rep_info;
+        }
+    }
+
     /// Given a device that has output capabilities `other`, can we properly write all events corrosponding
     /// to the capabilities of `self` to that device? Returns true if we can, false if there may be issues.
     ///
@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! With many input devices and arguments, figuring out which capabilities the output devices need
+//! can take a noticeable amount of time at startup. The result only depends on the arguments and
+//! the capabilities of the input devices, so it gets stored in the state directory and reused the
+//! next time evsieve starts with the same arguments and the same devices.
+//!
+//! The cache is only used if the --cache-caps flag is specified. Every configuration gets its own
+//! file, named after a hash of its arguments, and only the files of the most recently used
+//! configurations are kept. The first line of
+//! that file holds a hash of both the arguments and the input capabilities the output capabilities
+//! were computed from, followed by the amount of capabilities. Every other line holds a capability:
+//!
+//!     type code domain namespace min max [fuzz flat resolution value]
+//!
+//! The bounds of the range are either integers or one of -inf and inf. The last four numbers are
+//! only present for capabilities that carry absolute axis information.
+
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::capability::{AbsMeta, Capability, InputCapabilites};
+use crate::domain::Domain;
+use crate::event::{EventCode, EventType, Namespace};
+use crate::range::{ExtendedInteger, Range};
+
+const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+const FILE_PREFIX: &str = "capabilities-";
+/// The amount of configurations whose capabilities are kept in the state directory. Whenever
+/// another configuration gets cached, the least recently used ones are removed.
+const MAX_CACHED_CONFIGURATIONS: usize = 8;
+
+/// Hashes everything that the output capabilities of a configuration depend on, other than the
+/// capabilities of the input devices.
+pub struct ArgumentsHasher {
+    hasher: DefaultHasher,
+}
+
+impl ArgumentsHasher {
+    pub fn new(args: &[String]) -> ArgumentsHasher {
+        let mut hasher = DefaultHasher::new();
+        // The way capabilities propagate may change between versions.
+        VERSION.hash(&mut hasher);
+        args.hash(&mut hasher);
+        ArgumentsHasher { hasher }
+    }
+
+    /// For files whose contents affect the capabilities, such as map tables.
+    pub fn add_file_contents(&mut self, contents: &str) {
+        contents.hash(&mut self.hasher);
+    }
+}
+
+/// Where the output capabilities of a configuration are cached, and what they must have been
+/// computed from to be used.
+pub struct CapsCache {
+    path: PathBuf,
+    key: u64,
+}
+
+impl CapsCache {
+    pub fn new(directory: &Path, args: ArgumentsHasher, input_caps: &InputCapabilites) -> CapsCache {
+        let args_hash = args.hasher.finish();
+
+        // The capabilities are not stored in any particular order, so they are hashed one by one
+        // and the hashes get sorted.
+        let mut cap_hashes: Vec<u64> = crate::capability::input_caps_to_vec(input_caps).iter()
+            .map(|cap| {
+                let mut hasher = DefaultHasher::new();
+                cap.hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect();
+        cap_hashes.sort_unstable();
+        let mut hasher = DefaultHasher::new();
+        args_hash.hash(&mut hasher);
+        cap_hashes.hash(&mut hasher);
+
+        CapsCache {
+            path: directory.join(format!("{}{:016x}", FILE_PREFIX, args_hash)),
+            key: hasher.finish(),
+        }
+    }
+
+    /// Returns the cached output capabilities, if they were computed from the same arguments and
+    /// input capabilities.
+    pub fn load(&self) -> Option<Vec<Capability>> {
+        let contents = std::fs::read_to_string(&self.path).ok()?;
+        let mut lines = contents.lines();
+        let mut header = lines.next()?.split_whitespace();
+        let key = u64::from_str_radix(header.next()?, 16).ok()?;
+        let count: usize = header.next()?.parse().ok()?;
+        if key != self.key {
+            return None;
+        }
+
+        // A file that was only partially written has fewer lines than promised.
+        let caps = lines.map(decode_capability).collect::<Option<Vec<Capability>>>()?;
+        match caps.len() == count {
+            true => {
+                self.touch();
+                Some(caps)
+            },
+            false => None,
+        }
+    }
+
+    /// Stores the output capabilities. Failing to do so only makes the next startup slower, so
+    /// errors get ignored.
+    pub fn save(&self, caps: &[Capability]) {
+        let mut contents = format!("{:016x} {}\n", self.key, caps.len());
+        for cap in caps {
+            contents.push_str(&encode_capability(cap));
+            contents.push('\n');
+        }
+        let directory = match self.path.parent() {
+            Some(directory) => directory,
+            None => return,
+        };
+        if std::fs::create_dir_all(directory).and_then(|()| std::fs::write(&self.path, contents)).is_ok() {
+            prune(directory, MAX_CACHED_CONFIGURATIONS);
+        }
+    }
+
+    /// Marks the cached capabilities as recently used, so they do not get pruned in favour of
+    /// configurations that were used less recently.
+    fn touch(&self) {
+        let _ = set_modified(&self.path, None);
+    }
+}
+
+/// Removes the cached capabilities of all but the `keep` most recently used configurations.
+fn prune(directory: &Path, keep: usize) {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(FILE_PREFIX))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    files.sort_by(|(time_a, _), (time_b, _)| time_b.cmp(time_a));
+    for (_, path) in files.into_iter().skip(keep) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Sets the modification time of a file to a certain amount of seconds since the epoch, or to
+/// the current time if None is given.
+fn set_modified(path: &Path, seconds: Option<i64>) -> std::io::Result<()> {
+    let path_cstr = CString::new(path.as_os_str().as_bytes())?;
+    let times = seconds.map(|seconds| {
+        [libc::timespec { tv_sec: seconds as libc::time_t, tv_nsec: 0 }; 2]
+    });
+    let times_ptr = match &times {
+        Some(times) => times.as_ptr(),
+        None => std::ptr::null(),
+    };
+    match unsafe { libc::utimensat(libc::AT_FDCWD, path_cstr.as_ptr(), times_ptr, 0) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+fn encode_capability(cap: &Capability) -> String {
+    let namespace = match cap.namespace {
+        Namespace::Input => "input",
+        Namespace::User => "user",
+        Namespace::Yielded => "yielded",
+        Namespace::Output => "output",
+    };
+    let mut result = format!(
+        "{} {} {} {} {} {}",
+        u16::from(cap.code.ev_type()), cap.code.code(), cap.domain.index(), namespace,
+        encode_bound(cap.value_range.min), encode_bound(cap.value_range.max),
+    );
+    if let Some(meta) = cap.abs_meta {
+        result.push_str(&format!(" {} {} {} {}", meta.fuzz, meta.flat, meta.resolution, meta.value));
+    }
+    result
+}
+
+fn decode_capability(line: &str) -> Option<Capability> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let (ev_type, code, domain, namespace, min, max, meta) = match parts.as_slice() {
+        [ev_type, code, domain, namespace, min, max, meta @ ..] => (ev_type, code, domain, namespace, min, max, meta),
+        _ => return None,
+    };
+    let namespace = match *namespace {
+        "input" => Namespace::Input,
+        "user" => Namespace::User,
+        "yielded" => Namespace::Yielded,
+        "output" => Namespace::Output,
+        _ => return None,
+    };
+    let abs_meta = match meta {
+        [] => None,
+        [fuzz, flat, resolution, value] => Some(AbsMeta {
+            fuzz: fuzz.parse().ok()?,
+            flat: flat.parse().ok()?,
+            resolution: resolution.parse().ok()?,
+            value: value.parse().ok()?,
+        }),
+        _ => return None,
+    };
+    Some(Capability {
+        code: EventCode::new(EventType::new(ev_type.parse().ok()?), code.parse().ok()?),
+        domain: Domain::from_index(domain.parse().ok()?),
+        namespace,
+        value_range: Range { min: decode_bound(min)?, max: decode_bound(max)? },
+        abs_meta,
+    })
+}
+
+fn encode_bound(bound: ExtendedInteger) -> String {
+    match bound {
+        ExtendedInteger::NegativeInfinity => "-inf".to_owned(),
+        ExtendedInteger::PositiveInfinity => "inf".to_owned(),
+        ExtendedInteger::Discrete(value) => format!("{}", value),
+    }
+}
+
+fn decode_bound(bound: &str) -> Option<ExtendedInteger> {
+    match bound {
+        "-inf" => Some(ExtendedInteger::NegativeInfinity),
+        "inf" => Some(ExtendedInteger::PositiveInfinity),
+        _ => bound.parse().ok().map(ExtendedInteger::Discrete),
+    }
+}
+
+#[test]
+fn unittest() {
+    let domain = crate::domain::get_unique_domain();
+    let caps = vec![
+        Capability {
+            code: EventCode::new(EventType::KEY, 30), domain, namespace: Namespace::Output,
+            value_range: Range::new(Some(0), Some(2)), abs_meta: None,
+        },
+        Capability {
+            code: EventCode::new(EventType::ABS, 0), domain, namespace: Namespace::Output,
+            value_range: Range::new(None, Some(-5)),
+            abs_meta: Some(AbsMeta { fuzz: 1, flat: 2, resolution: 3, value: -4 }),
+        },
+    ];
+    for cap in &caps {
+        assert!(decode_capability(&encode_capability(cap)) == Some(*cap));
+    }
+    assert!(decode_capability("1 30 0 output 0").is_none());
+    assert!(decode_capability("1 30 0 elsewhere 0 1").is_none());
+
+    let directory = std::env::temp_dir().join(format!("evsieve-capcache-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let unrelated_file = directory.join("toggle-mode");
+    std::fs::write(&unrelated_file, "1").unwrap();
+    for index in 0 .. 4 {
+        let path = directory.join(format!("{}{:016x}", FILE_PREFIX, index));
+        std::fs::write(&path, "").unwrap();
+        set_modified(&path, Some(1_000_000 + 60 * index as i64)).unwrap();
+    }
+    prune(&directory, 2);
+    let mut remaining: Vec<String> = std::fs::read_dir(&directory).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    remaining.sort();
+    assert_eq!(remaining, vec![
+        format!("{}{:016x}", FILE_PREFIX, 2), format!("{}{:016x}", FILE_PREFIX, 3), "toggle-mode".to_owned(),
+    ]);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
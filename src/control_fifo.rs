@@ -1,13 +1,20 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use std::collections::HashMap;
 use std::os::unix::io::{RawFd, AsRawFd};
 
-use crate::error::{SystemError, ArgumentError, Context};
+use crate::error::{SystemError, ArgumentError, InternalError, RuntimeError, Context};
 use crate::io::fd::HasFixedFd;
 use crate::io::fifo::Fifo;
 use crate::arguments::hook::HookToggleAction;
 use crate::stream::Setup;
+use crate::arguments::parser::parse_runtime_entries;
+use crate::state::{State, ToggleIndex};
 use crate::io::fifo::LineRead;
+use crate::event::{Event, EventType, Namespace};
+use crate::key::KeyParser;
+use crate::logging::LogLevel;
+use crate::time::Duration;
 
 pub struct ControlFifo {
     source: Box<dyn LineRead>,
@@ -46,9 +53,41 @@ impl ControlFifo {
 
 pub enum Command {
     Toggle(HookToggleAction),
+    /// Reports the state of all toggles with an id as the output of the command.
+    Query,
+    /// Prints the latency statistics of all input devices with the measure-latency flag. Since
+    /// those are not part of the Setup, this command is executed by the main loop instead.
+    Stats,
+    /// Adds a --map, --copy or --block argument to the stream, e.g. "add map key:a key:b id=NAME".
+    /// Contains the argument as it would be written on the command line and the optional id.
+    Add(Vec<String>, Option<String>),
+    /// Removes all arguments that were added with a certain id.
+    RemoveId(String),
+    /// Reports the state of toggles, hooks, withheld events and the keys held on each input
+    /// device as the output of the command. Like Stats, this command is executed by the main loop
+    /// because the input devices are not part of the Setup.
+    DumpState,
+    /// Enables or disables all hooks with a certain id.
+    SetHookEnabled(String, bool),
+    /// Lists the input and output devices, as JSON if the bool is true. Executed by the main loop,
+    /// like Stats.
+    ListDevices(bool),
+    /// Gives the output device with a certain link or name a new name, e.g.
+    /// "rename-output /dev/input/by-id/virtual-pad Xbox 360 pad".
+    RenameOutput(String, String),
+    /// Ungrabs the input devices and ignores their events until the Resume command. Executed by
+    /// the main loop, like Stats.
+    Pause,
+    Resume,
+    /// Runs events through the stream as if an input device generated them, e.g.
+    /// "send key:a:1 key:a:0 @keyboard".
+    Send(Vec<Event>),
+    /// Changes the log level, or reports it if no level is given. If a duration is given, the
+    /// previous level gets restored after that much time. Executed by the main loop, like Stats.
+    LogLevel(Option<LogLevel>, Option<Duration>),
 }
 
-fn parse_command(line: &str) -> Result<Command, ArgumentError> {
+pub fn parse_command(line: &str) -> Result<Command, ArgumentError> {
     let mut parts = line.split_whitespace();
     let command = match parts.next() {
         Some(command) => command,
@@ -64,24 +103,213 @@ fn parse_command(line: &str) -> Result<Command, ArgumentError> {
                 HookToggleAction::parse(has_toggle_flag, toggle_clauses)?
             ))
         },
+        "query" => match args.as_slice() {
+            [] => Ok(Command::Query),
+            _ => Err(ArgumentError::new("The query command does not take any arguments.")),
+        },
+        "stats" => match args.as_slice() {
+            [] => Ok(Command::Stats),
+            _ => Err(ArgumentError::new("The stats command does not take any arguments.")),
+        },
+        "add" => {
+            let (kind, args) = match args.split_first() {
+                Some((kind, args)) => (kind, args),
+                None => return Err(ArgumentError::new("The add command requires an argument, like \"add map key:a key:b\".")),
+            };
+            let mut arg_strs = vec![format!("--{}", kind)];
+            let mut id = None;
+            for arg in args {
+                match crate::utils::split_once(arg, "=") {
+                    ("id", Some(value)) => {
+                        if id.is_some() {
+                            return Err(ArgumentError::new("The add command accepts at most one id= clause."));
+                        }
+                        id = Some(value.to_owned());
+                    },
+                    _ => arg_strs.push(arg.to_string()),
+                }
+            }
+            Ok(Command::Add(arg_strs, id))
+        },
+        "dump-state" => match args.as_slice() {
+            [] => Ok(Command::DumpState),
+            _ => Err(ArgumentError::new("The dump-state command does not take any arguments.")),
+        },
+        "enable-hook" | "disable-hook" => match args.as_slice() {
+            [id] => Ok(Command::SetHookEnabled(id.to_string(), command == "enable-hook")),
+            _ => Err(ArgumentError::new(format!("The {} command requires exactly one argument: the id of the hook.", command))),
+        },
+        "list-devices" => match args.as_slice() {
+            [] | ["format=text"] => Ok(Command::ListDevices(false)),
+            ["format=json"] => Ok(Command::ListDevices(true)),
+            _ => Err(ArgumentError::new("The list-devices command takes at most one argument: format=text or format=json.")),
+        },
+        "pause" | "resume" => match args.as_slice() {
+            [] if command == "pause" => Ok(Command::Pause),
+            [] => Ok(Command::Resume),
+            _ => Err(ArgumentError::new(format!("The {} command does not take any arguments.", command))),
+        },
+        "send" => Ok(Command::Send(parse_send_events(&args)?)),
+        "log-level" => match args.as_slice() {
+            [] => Ok(Command::LogLevel(None, None)),
+            [level] => Ok(Command::LogLevel(Some(LogLevel::parse(level)?), None)),
+            [level, duration] => match crate::utils::split_once(duration, "=") {
+                ("duration", Some(value)) => Ok(Command::LogLevel(
+                    Some(LogLevel::parse(level)?),
+                    Some(crate::arguments::delay::parse_period_value(value)?),
+                )),
+                _ => Err(ArgumentError::new(format!("Invalid argument for the log-level command: {}", duration))),
+            },
+            _ => Err(ArgumentError::new("The log-level command takes at most two arguments: a level and a duration=SECONDS clause.")),
+        },
+        "rename-output" => match args.split_first() {
+            Some((target, name)) if ! name.is_empty() => Ok(Command::RenameOutput(target.to_string(), name.join(" "))),
+            _ => Err(ArgumentError::new("The rename-output command requires a create-link= path or name of an output device, followed by its new name.")),
+        },
+        "remove-id" => match args.as_slice() {
+            [id] => Ok(Command::RemoveId(id.to_string())),
+            _ => Err(ArgumentError::new("The remove-id command requires exactly one argument: the id of the arguments to remove.")),
+        },
         _ => Err(ArgumentError::new(format!("Unknown command name: {}", command))),
     }
 }
 
 impl Command {
-    pub fn execute(self, setup: &mut Setup) -> Result<(), ArgumentError> {
+    /// Returns the output of the command, which the caller should show to the user.
+    pub fn execute(self, setup: &mut Setup) -> Result<String, RuntimeError> {
         match self {
             Command::Toggle(action) => {
                 // TODO: More helpful error.
                 let effects = action.implement(setup.state(), setup.toggle_indices())?;
                 for effect in effects {
-                    effect(setup.state_mut());
+                    effect.apply(setup.state_mut());
                 }
-            }
+            },
+            Command::Query => {
+                return Ok(format_toggle_report(setup.state(), setup.toggle_indices()));
+            },
+            Command::Stats => {
+                return Err(InternalError::new("The stats command cannot be executed on a Setup.").into());
+            },
+            Command::DumpState => {
+                return Err(InternalError::new("The dump-state command cannot be executed on a Setup.").into());
+            },
+            Command::ListDevices(_) => {
+                return Err(InternalError::new("The list-devices command cannot be executed on a Setup.").into());
+            },
+            Command::Pause | Command::Resume => {
+                return Err(InternalError::new("The pause and resume commands cannot be executed on a Setup.").into());
+            },
+            Command::LogLevel(..) => {
+                return Err(InternalError::new("The log-level command cannot be executed on a Setup.").into());
+            },
+            Command::SetHookEnabled(id, enabled) => {
+                if ! setup.set_hooks_enabled(&id, enabled) {
+                    return Err(ArgumentError::new(format!(
+                        "No --hook has the id \"{}\".", id
+                    )).into());
+                }
+            },
+            Command::Add(args, id) => {
+                let mut label = args.join(" ");
+                if let Some(id) = &id {
+                    label = format!("{} (id={})", label, id);
+                }
+                let entries = parse_runtime_entries(args)?;
+                setup.add_entries(entries, label, id);
+            },
+            Command::RenameOutput(target, name) => {
+                setup.output_mut().rename(&target, name)?;
+            },
+            Command::Send(events) => {
+                for event in events {
+                    crate::stream::run(setup, event);
+                }
+                crate::stream::syn(setup);
+            },
+            Command::RemoveId(id) => {
+                if ! setup.remove_entries(&id) {
+                    return Err(ArgumentError::new(format!(
+                        "No arguments have been added with the id \"{}\".", id
+                    )).into());
+                }
+            },
+        }
+
+        Ok(String::new())
+    }
+}
+
+/// Parses the arguments of the send command: keys with an event code and value, like key:a:1,
+/// optionally followed by a single @DOMAIN that applies to all keys without a domain of their own.
+fn parse_send_events(args: &[&str]) -> Result<Vec<Event>, ArgumentError> {
+    let mut domain = None;
+    let mut key_strs: Vec<&str> = Vec::new();
+    for &arg in args {
+        match arg.strip_prefix('@') {
+            Some(name) => match domain {
+                None => domain = Some(crate::domain::resolve(name)?),
+                Some(_) => return Err(ArgumentError::new("The send command accepts at most one @DOMAIN.")),
+            },
+            None => key_strs.push(arg),
         }
+    }
+    if key_strs.is_empty() {
+        return Err(ArgumentError::new("The send command requires at least one event, like \"send key:a:1 key:a:0\"."));
+    }
+    // Without a domain, the events can only be matched by keys that do not specify a domain.
+    let domain = domain.unwrap_or_else(crate::domain::get_unique_domain);
 
-        Ok(())
+    let parser = KeyParser {
+        default_value: "",
+        allow_values: true,
+        allow_ranges: false,
+        allow_transitions: false,
+        allow_types: false,
+        allow_relative_values: false,
+        forbid_non_EV_KEY: false,
+        namespace: Namespace::User,
+    };
+    let mut events = Vec::new();
+    for key_str in key_strs {
+        let key = parser.parse(key_str)?;
+        let code = match key.requires_event_code() {
+            Some(code) => code,
+            None => return Err(ArgumentError::new(format!("The key \"{}\" does not specify an event code.", key_str))),
+        };
+        if key.clone().pop_value().is_none() {
+            return Err(ArgumentError::new(format!("The key \"{}\" does not specify a value, like :1 for pressing or :0 for releasing.", key_str)));
+        }
+        let mut event = key.merge(Event::new(code, 0, 0, domain, Namespace::User));
+        // There is no device to remember the previous value, so assume the key changed state.
+        if code.ev_type() == EventType::KEY {
+            event.previous_value = match event.value {
+                0 => 1,
+                2 => 1,
+                _ => 0,
+            };
+        }
+        events.push(event);
     }
+    Ok(events)
+}
+
+/// Returns one line for each toggle with an id, formatted like "id=ID index=INDEX state=NAME",
+/// where the state= part is omitted if the toggle's states are unnamed. Sorted by id.
+fn format_toggle_report(state: &State, toggle_indices: &HashMap<String, ToggleIndex>) -> String {
+    let mut toggles: Vec<(&String, &ToggleIndex)> = toggle_indices.iter().collect();
+    toggles.sort_by_key(|(id, _)| id.as_str());
+
+    let mut report = String::new();
+    for (id, &index) in toggles {
+        let toggle_state = &state[index];
+        report.push_str(&format!("id={} index={}", id, toggle_state.value() + 1));
+        if let Some(name) = toggle_state.name() {
+            report.push_str(&format!(" state={}", name));
+        }
+        report.push('\n');
+    }
+    report
 }
 
 impl AsRawFd for ControlFifo {
@@ -89,4 +317,64 @@ impl AsRawFd for ControlFifo {
         self.source.as_raw_fd()
     }
 }
-unsafe impl HasFixedFd for ControlFifo {}
\ No newline at end of file
+unsafe impl HasFixedFd for ControlFifo {}
+
+#[test]
+fn unittest() {
+    match parse_command("add map key:a key:b id=swap") {
+        Ok(Command::Add(args, id)) => {
+            assert_eq!(args, vec!["--map", "key:a", "key:b"]);
+            assert_eq!(id.as_deref(), Some("swap"));
+        },
+        _ => panic!(),
+    }
+    match parse_command("add block key:c") {
+        Ok(Command::Add(args, None)) => assert_eq!(args, vec!["--block", "key:c"]),
+        _ => panic!(),
+    }
+    match parse_command("remove-id swap") {
+        Ok(Command::RemoveId(id)) => assert_eq!(id, "swap"),
+        _ => panic!(),
+    }
+    assert!(parse_command("add").is_err());
+    assert!(parse_command("add map key:a id=x id=y").is_err());
+    assert!(parse_command("remove-id").is_err());
+    match parse_command("disable-hook copy") {
+        Ok(Command::SetHookEnabled(id, false)) => assert_eq!(id, "copy"),
+        _ => panic!(),
+    }
+    assert!(parse_command("enable-hook").is_err());
+    match parse_command("dump-state") {
+        Ok(Command::DumpState) => (),
+        _ => panic!(),
+    }
+    assert!(parse_command("dump-state /tmp/state").is_err());
+    match parse_command("log-level debug duration=30") {
+        Ok(Command::LogLevel(Some(LogLevel::Debug), Some(_))) => (),
+        _ => panic!(),
+    }
+    match parse_command("log-level") {
+        Ok(Command::LogLevel(None, None)) => (),
+        _ => panic!(),
+    }
+    assert!(parse_command("log-level loud").is_err());
+    assert!(parse_command("log-level debug 30").is_err());
+
+    match parse_command("query") {
+        Ok(Command::Query) => (),
+        _ => panic!(),
+    }
+    assert!(parse_command("query /tmp/toggles").is_err());
+    let mut state = State::new();
+    let mut toggle_indices = HashMap::new();
+    let mut layer_state = crate::state::ToggleState::new(2).unwrap();
+    layer_state.set_names(vec!["base".to_owned(), "gaming".to_owned()]).unwrap();
+    toggle_indices.insert("layer".to_owned(), state.push_toggle(layer_state));
+    let mode = state.push_toggle(crate::state::ToggleState::new(3).unwrap());
+    toggle_indices.insert("mode".to_owned(), mode);
+    state[mode].advance();
+    assert_eq!(
+        format_toggle_report(&state, &toggle_indices),
+        "id=layer index=1 state=base\nid=mode index=2\n",
+    );
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements --control-socket, which accepts the same commands as --control-fifo over a Unix
+//! domain socket, but answers each command. The reply to a command consists of the output of
+//! that command, if any, followed by a line that is either "ok" or "error: MESSAGE".
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::{RawFd, AsRawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+use crate::error::{Context, SystemError};
+use crate::io::fd::HasFixedFd;
+
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    pub fn bind(path: PathBuf) -> Result<ControlSocket, SystemError> {
+        // A socket left behind by a previous instance of evsieve would make the bind fail.
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if metadata.file_type().is_socket() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        let listener = UnixListener::bind(&path)
+            .map_err(SystemError::from)
+            .with_context_of(|| format!("While creating a control socket at {}:", path.display()))?;
+        listener.set_nonblocking(true)?;
+        Ok(ControlSocket { listener, path })
+    }
+
+    /// Returns None if no connection was waiting to be accepted after all.
+    pub fn accept(&mut self) -> Result<Option<ControlConnection>, SystemError> {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) => match error.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted => return Ok(None),
+                _ => return Err(error.into()),
+            },
+        };
+        stream.set_nonblocking(true)?;
+        Ok(Some(ControlConnection { stream, buffer: Vec::new(), is_closed: false }))
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl AsRawFd for ControlSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for ControlSocket {}
+
+/// A client that connected to a control socket.
+pub struct ControlConnection {
+    stream: UnixStream,
+    /// Bytes that have been received, but do not form a whole line yet.
+    buffer: Vec<u8>,
+    /// Set once the client has stopped sending commands.
+    is_closed: bool,
+}
+
+impl ControlConnection {
+    /// Returns the commands that have been received. If the client stopped sending commands,
+    /// is_closed() returns true afterwards and the connection should be dropped once the
+    /// returned commands have been answered.
+    pub fn poll(&mut self) -> Result<Vec<String>, SystemError> {
+        let mut buf = [0u8; 4096];
+        let num_bytes = match self.stream.read(&mut buf) {
+            Ok(num_bytes) => num_bytes,
+            Err(error) => match error.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted => return Ok(Vec::new()),
+                _ => return Err(error.into()),
+            },
+        };
+        if num_bytes == 0 {
+            self.is_closed = true;
+            // The last command need not be followed by a newline.
+            let remainder = std::mem::take(&mut self.buffer);
+            return Ok(decode_lines(vec![remainder]));
+        }
+
+        self.buffer.extend_from_slice(&buf[.. num_bytes]);
+        let mut lines = Vec::new();
+        while let Some(newline_index) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..= newline_index).collect();
+            lines.push(line[.. newline_index].to_vec());
+        }
+        Ok(decode_lines(lines))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+
+    pub fn send(&mut self, reply: &str) -> Result<(), SystemError> {
+        self.stream.write_all(reply.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Turns the received lines into strings, skipping empty lines. Lines that are not valid UTF-8
+/// are kept as lossy strings so they still get an error as reply.
+fn decode_lines(lines: Vec<Vec<u8>>) -> Vec<String> {
+    lines.into_iter()
+        .map(|line| String::from_utf8_lossy(&line).trim().to_owned())
+        .filter(|line| ! line.is_empty())
+        .collect()
+}
+
+/// Formats the reply to a command given its output or the error it caused.
+pub fn format_reply(result: Result<String, String>) -> String {
+    match result {
+        Ok(output) => format!("{}ok\n", output),
+        // Error messages may span multiple lines, but the reply must end with a single error line.
+        Err(message) => format!("error: {}\n", message.lines().map(str::trim).collect::<Vec<_>>().join(" ")),
+    }
+}
+
+impl AsRawFd for ControlConnection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for ControlConnection {}
+
+#[test]
+fn unittest() {
+    assert_eq!(format_reply(Ok(String::new())), "ok\n");
+    assert_eq!(format_reply(Ok("id=foo index=1\n".to_owned())), "id=foo index=1\nok\n");
+    assert_eq!(format_reply(Err("While executing a command:\n    No such toggle.".to_owned())), "error: While executing a command: No such toggle.\n");
+}
@@ -2,6 +2,9 @@
 
 mod systemd;
 
+use crate::time::{Duration, Instant};
+use crate::logging;
+
 pub fn notify_ready_async() {
     if systemd::is_available() {
         systemd::notify_ready();
@@ -12,4 +15,40 @@ pub fn await_completion() {
     if systemd::is_available() {
         systemd::await_completion();
     }
-}
\ No newline at end of file
+}
+
+/// Periodically lets systemd know that evsieve is still alive if the service has a `WatchdogSec=`
+/// property, so systemd can restart evsieve if its main loop ever gets stuck.
+pub struct Watchdog {
+    interval: Duration,
+    next_ping: Instant,
+}
+
+impl Watchdog {
+    /// Returns None if systemd does not expect any pings from evsieve.
+    pub fn from_env() -> Option<Watchdog> {
+        if ! systemd::is_available() {
+            return None;
+        }
+        // Ping twice as often as required, as systemd recommends.
+        let interval = Duration::from_micros(systemd::watchdog_usec()? / 2);
+        Some(Watchdog { interval, next_ping: Instant::now() })
+    }
+
+    /// The moment at which the next ping is due.
+    pub fn next_ping(&self) -> Instant {
+        self.next_ping
+    }
+
+    /// Pings systemd if the next ping is due.
+    pub fn ping_if_due(&mut self) {
+        let now = Instant::now();
+        if now < self.next_ping {
+            return;
+        }
+        if let Err(error) = systemd::notify_watchdog() {
+            logging::warning(format!("Warning: failed to notify the systemd watchdog that evsieve is still running: {}", error));
+        }
+        self.next_ping = now + self.interval;
+    }
+}
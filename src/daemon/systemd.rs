@@ -5,6 +5,7 @@ use std::os::raw::{c_int, c_char};
 use std::process::{Command, Stdio};
 use std::io::ErrorKind;
 use std::sync::{Mutex, Barrier, Arc};
+use crate::logging;
 
 /// The systemd feature links against libsystemd instead of falling back on the slower systemd-notify
 /// tool. It is currently unused because it complicates the build process.
@@ -41,8 +42,8 @@ fn notify(state: &str) {
     match child_res {
         Err(error) => {
             match error.kind() {
-                ErrorKind::NotFound => { eprintln!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, the systemd-notify tool was not found. Evsieve is unable to notify the hypervisor that evsieve is ready."); },
-                _ => { eprintln!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, we failed to notify the systemd supervisor that evsieve is ready.\nError encountered: {}", error); },
+                ErrorKind::NotFound => { logging::warning("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, the systemd-notify tool was not found. Evsieve is unable to notify the hypervisor that evsieve is ready."); },
+                _ => { logging::warning(format!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, we failed to notify the systemd supervisor that evsieve is ready.\nError encountered: {}", error)); },
             }
         },
         Ok(mut child) => {
@@ -61,13 +62,13 @@ fn notify(state: &str) {
                     Ok(return_code) => {
                         if ! return_code.success() {
                             match return_code.code() {
-                                Some(code) => eprintln!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, the systemd-notify tool returned the following error code when we tried to inform the hypervisor that evsieve is ready: {}", code),
-                                None => eprintln!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, the systemd-notify tool failed to notify the hypervisor for an unknown reason."),
+                                Some(code) => logging::warning(format!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, the systemd-notify tool returned the following error code when we tried to inform the hypervisor that evsieve is ready: {}", code)),
+                                None => logging::warning("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, the systemd-notify tool failed to notify the hypervisor for an unknown reason."),
                             }
                         }
                     },
                     Err(error) => {
-                        eprintln!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, we failed to notify the systemd supervisor that evsieve is ready.\nError encountered: {}", error);
+                        logging::warning(format!("Warning: the environment variable NOTIFY_SOCKET was set, suggesting that evsieve is being ran as a systemd service. However, we failed to notify the systemd supervisor that evsieve is ready.\nError encountered: {}", error));
                     }
                 }
             });
@@ -83,6 +84,55 @@ pub fn notify_ready() {
     notify("READY=1")
 }
 
+/// Tells the service manager that evsieve is still alive. Unlike `notify_ready()`, this does not
+/// spawn systemd-notify, because doing so every few seconds would be wasteful, and systemd rejects
+/// the notifications of child processes unless `NotifyAccess=all` is set.
+pub fn notify_watchdog() -> std::io::Result<()> {
+    #[cfg(feature = "systemd")]
+    {
+        notify("WATCHDOG=1");
+        Ok(())
+    }
+    #[cfg(not(feature = "systemd"))]
+    send_to_socket("WATCHDOG=1")
+}
+
+/// Writes a state directly to the socket that the service manager listens on.
+#[cfg(not(feature = "systemd"))]
+fn send_to_socket(state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+    use std::os::linux::net::SocketAddrExt;
+
+    let path = match std::env::var("NOTIFY_SOCKET") {
+        Ok(path) => path,
+        Err(_) => return Ok(()),
+    };
+    // Socket names starting with an @ live in the abstract namespace.
+    let address = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// Returns within how many microseconds systemd expects to hear from evsieve if `WatchdogSec=` has
+/// been set for this service, or None if systemd does not watch over evsieve.
+pub fn watchdog_usec() -> Option<u64> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // If WATCHDOG_PID is set, the watchdog is meant for some other process.
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    match usec {
+        0 => None,
+        _ => Some(usec),
+    }
+}
+
 /// If notification is in progress, this function will wait until after it is completed.
 pub fn await_completion() {
     drop(DAEMON_NOTIFICATION_IN_PROGRESS.lock());
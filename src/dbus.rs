@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements --dbus, which offers the commands of the control FIFO as methods of a D-Bus
+//! service. Requires the dbus feature, which links against libsystemd for its sd-bus API.
+//!
+//! All methods live on the interface org.evsieve.Evsieve1 of the object /org/evsieve/Evsieve.
+//! Methods that return something return a string, which is formatted like the output of the
+//! corresponding control command. Failing commands result in a D-Bus error.
+
+#![allow(unused_imports)]
+use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ffi::{CStr, CString};
+
+use crate::error::{ArgumentError, Context, SystemError};
+use crate::io::fd::HasFixedFd;
+
+pub const DEFAULT_NAME: &str = "org.evsieve.Evsieve";
+#[cfg(feature = "dbus")]
+const OBJECT_PATH: &str = "/org/evsieve/Evsieve";
+#[cfg(feature = "dbus")]
+const INTERFACE: &str = "org.evsieve.Evsieve1";
+#[cfg(feature = "dbus")]
+const ERROR_NAME: &str = "org.evsieve.Evsieve1.Error.Failed";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BusType {
+    Session,
+    System,
+}
+
+/// Translates a call to a D-Bus method into the equivalent control command. The argument must
+/// be Some for methods that take a string and None for methods without arguments.
+pub fn method_to_command(member: &str, arg: Option<&str>) -> Result<String, String> {
+    let command = match (member, arg) {
+        ("Command", Some(command)) => command.to_owned(),
+        ("Toggle", Some(clause)) => format!("toggle {}", clause),
+        ("EnableHook", Some(id)) => format!("enable-hook {}", id),
+        ("DisableHook", Some(id)) => format!("disable-hook {}", id),
+        ("QueryToggles", None) => "query".to_owned(),
+        ("DumpState", None) => "dump-state".to_owned(),
+        ("ListDevices", None) => "list-devices".to_owned(),
+        ("Stats", None) => "stats".to_owned(),
+        ("Command", None) | ("Toggle", None) | ("EnableHook", None) | ("DisableHook", None)
+            => return Err(format!("The method {} takes a single string as argument.", member)),
+        ("QueryToggles", Some(_)) | ("DumpState", Some(_)) | ("ListDevices", Some(_)) | ("Stats", Some(_))
+            => return Err(format!("The method {} does not take any arguments.", member)),
+        _ => return Err(format!("Unknown method: {}", member)),
+    };
+    // The arguments of a method must not smuggle in additional commands.
+    if command.contains('\n') {
+        return Err("Commands cannot contain newlines.".to_owned());
+    }
+    Ok(command)
+}
+
+#[cfg(feature = "dbus")]
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub type sd_bus = c_void;
+    pub type sd_bus_message = c_void;
+    pub type sd_bus_slot = c_void;
+    pub type sd_bus_error = c_void;
+    pub type sd_bus_message_handler_t = extern "C" fn(*mut sd_bus_message, *mut c_void, *mut sd_bus_error) -> c_int;
+
+    extern "C" {
+        pub fn sd_bus_open_user(bus: *mut *mut sd_bus) -> c_int;
+        pub fn sd_bus_open_system(bus: *mut *mut sd_bus) -> c_int;
+        pub fn sd_bus_request_name(bus: *mut sd_bus, name: *const c_char, flags: u64) -> c_int;
+        pub fn sd_bus_add_object(bus: *mut sd_bus, slot: *mut *mut sd_bus_slot, path: *const c_char, callback: sd_bus_message_handler_t, userdata: *mut c_void) -> c_int;
+        pub fn sd_bus_get_fd(bus: *mut sd_bus) -> c_int;
+        pub fn sd_bus_process(bus: *mut sd_bus, ret: *mut *mut sd_bus_message) -> c_int;
+        pub fn sd_bus_flush(bus: *mut sd_bus) -> c_int;
+        pub fn sd_bus_flush_close_unref(bus: *mut sd_bus) -> *mut sd_bus;
+        pub fn sd_bus_message_ref(message: *mut sd_bus_message) -> *mut sd_bus_message;
+        pub fn sd_bus_message_unref(message: *mut sd_bus_message) -> *mut sd_bus_message;
+        pub fn sd_bus_message_is_method_call(message: *mut sd_bus_message, interface: *const c_char, member: *const c_char) -> c_int;
+        pub fn sd_bus_message_get_member(message: *mut sd_bus_message) -> *const c_char;
+        pub fn sd_bus_message_has_signature(message: *mut sd_bus_message, signature: *const c_char) -> c_int;
+        pub fn sd_bus_message_read_basic(message: *mut sd_bus_message, type_: c_char, p: *mut c_void) -> c_int;
+        pub fn sd_bus_reply_method_return(call: *mut sd_bus_message, types: *const c_char, ...) -> c_int;
+        pub fn sd_bus_reply_method_errorf(call: *mut sd_bus_message, name: *const c_char, format: *const c_char, ...) -> c_int;
+    }
+}
+
+/// A method call that has been received but not answered yet.
+pub struct MethodCall {
+    #[cfg(feature = "dbus")]
+    message: *mut ffi::sd_bus_message,
+    /// The control command this call translates to, or an error message if it is invalid.
+    pub command: Result<String, String>,
+}
+
+pub struct DBusService {
+    #[cfg(feature = "dbus")]
+    bus: *mut ffi::sd_bus,
+    /// The method calls that sd-bus has handed to us while processing. Boxed so its address stays
+    /// the same, because sd-bus holds a pointer to it.
+    #[cfg(feature = "dbus")]
+    #[allow(clippy::box_collection)]
+    queue: Box<Vec<*mut ffi::sd_bus_message>>,
+    fd: RawFd,
+}
+
+#[cfg(feature = "dbus")]
+fn check(result: c_int) -> Result<c_int, SystemError> {
+    match result {
+        _ if result < 0 => Err(std::io::Error::from_raw_os_error(-result).into()),
+        _ => Ok(result),
+    }
+}
+
+#[cfg(feature = "dbus")]
+extern "C" fn enqueue_message(message: *mut ffi::sd_bus_message, userdata: *mut c_void, _error: *mut ffi::sd_bus_error) -> c_int {
+    let interface = CString::new(INTERFACE).unwrap();
+    if unsafe { ffi::sd_bus_message_is_method_call(message, interface.as_ptr(), std::ptr::null()) } <= 0 {
+        // Let sd-bus tell the caller that the method does not exist.
+        return 0;
+    }
+    let queue = unsafe { &mut *(userdata as *mut Vec<*mut ffi::sd_bus_message>) };
+    queue.push(unsafe { ffi::sd_bus_message_ref(message) });
+    1
+}
+
+#[cfg(feature = "dbus")]
+impl DBusService {
+    pub fn connect(bus_type: BusType, name: &str) -> Result<DBusService, SystemError> {
+        let name_cstr = CString::new(name).map_err(|_| SystemError::new("Bus names may not contain nul bytes."))?;
+        let path_cstr = CString::new(OBJECT_PATH).unwrap();
+        let mut bus: *mut ffi::sd_bus = std::ptr::null_mut();
+        unsafe {
+            check(match bus_type {
+                BusType::Session => ffi::sd_bus_open_user(&mut bus),
+                BusType::System => ffi::sd_bus_open_system(&mut bus),
+            }).with_context("While connecting to D-Bus:")?;
+        }
+        let mut service = DBusService {
+            bus, fd: -1, queue: Box::new(Vec::new()),
+        };
+        unsafe {
+            let queue_ptr = &mut *service.queue as *mut Vec<*mut ffi::sd_bus_message> as *mut c_void;
+            check(ffi::sd_bus_add_object(bus, std::ptr::null_mut(), path_cstr.as_ptr(), enqueue_message, queue_ptr))
+                .with_context("While registering the evsieve object on D-Bus:")?;
+            check(ffi::sd_bus_request_name(bus, name_cstr.as_ptr(), 0))
+                .with_context_of(|| format!("While acquiring the name {} on D-Bus:", name))?;
+            service.fd = check(ffi::sd_bus_get_fd(bus))?;
+        }
+        Ok(service)
+    }
+
+    /// Processes all messages that D-Bus sent to us and returns the method calls among them.
+    pub fn poll(&mut self) -> Result<Vec<MethodCall>, SystemError> {
+        while unsafe { check(ffi::sd_bus_process(self.bus, std::ptr::null_mut()))? } > 0 {}
+
+        let string_signature = CString::new("s").unwrap();
+        let empty_signature = CString::new("").unwrap();
+        let mut calls = Vec::new();
+        for message in self.queue.drain(..) {
+            let member = unsafe {
+                CStr::from_ptr(ffi::sd_bus_message_get_member(message)).to_string_lossy().into_owned()
+            };
+            let arg = unsafe {
+                if ffi::sd_bus_message_has_signature(message, string_signature.as_ptr()) > 0 {
+                    let mut value: *const c_char = std::ptr::null();
+                    match ffi::sd_bus_message_read_basic(message, b's' as c_char, &mut value as *mut *const c_char as *mut c_void) {
+                        result if result > 0 => Ok(Some(CStr::from_ptr(value).to_string_lossy().into_owned())),
+                        _ => Err("Failed to read the argument.".to_owned()),
+                    }
+                } else if ffi::sd_bus_message_has_signature(message, empty_signature.as_ptr()) > 0 {
+                    Ok(None)
+                } else {
+                    Err("Methods of evsieve take either no arguments or a single string.".to_owned())
+                }
+            };
+            let command = arg.and_then(|arg| method_to_command(&member, arg.as_deref()));
+            calls.push(MethodCall { message, command });
+        }
+        Ok(calls)
+    }
+
+    /// Answers a method call with the output of its command or an error message.
+    pub fn reply(&mut self, call: MethodCall, result: Result<String, String>) -> Result<(), SystemError> {
+        let to_cstring = |text: String| CString::new(text.replace('\0', "")).unwrap();
+        let retval = match result {
+            Ok(output) => {
+                let signature = CString::new("s").unwrap();
+                let output = to_cstring(output);
+                unsafe { ffi::sd_bus_reply_method_return(call.message, signature.as_ptr(), output.as_ptr()) }
+            },
+            Err(message) => {
+                let name = CString::new(ERROR_NAME).unwrap();
+                let format = CString::new("%s").unwrap();
+                let message = to_cstring(message);
+                unsafe { ffi::sd_bus_reply_method_errorf(call.message, name.as_ptr(), format.as_ptr(), message.as_ptr()) }
+            },
+        };
+        check(retval).with_context("While replying to a D-Bus method call:")?;
+        unsafe { check(ffi::sd_bus_flush(self.bus))? };
+        Ok(())
+    }
+}
+
+#[cfg(feature = "dbus")]
+impl Drop for MethodCall {
+    fn drop(&mut self) {
+        unsafe { ffi::sd_bus_message_unref(self.message) };
+    }
+}
+
+#[cfg(feature = "dbus")]
+impl Drop for DBusService {
+    fn drop(&mut self) {
+        for &message in self.queue.iter() {
+            unsafe { ffi::sd_bus_message_unref(message) };
+        }
+        unsafe { ffi::sd_bus_flush_close_unref(self.bus) };
+    }
+}
+
+#[cfg(not(feature = "dbus"))]
+impl DBusService {
+    pub fn connect(_bus_type: BusType, _name: &str) -> Result<DBusService, SystemError> {
+        Err(SystemError::new("This version of evsieve was compiled without the dbus feature."))
+    }
+
+    pub fn poll(&mut self) -> Result<Vec<MethodCall>, SystemError> {
+        Ok(Vec::new())
+    }
+
+    pub fn reply(&mut self, _call: MethodCall, _result: Result<String, String>) -> Result<(), SystemError> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for DBusService {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+unsafe impl HasFixedFd for DBusService {}
+
+#[test]
+fn unittest() {
+    assert_eq!(method_to_command("Toggle", Some("mode:2")), Ok("toggle mode:2".to_owned()));
+    assert_eq!(method_to_command("DisableHook", Some("launcher")), Ok("disable-hook launcher".to_owned()));
+    assert_eq!(method_to_command("ListDevices", None), Ok("list-devices".to_owned()));
+    assert!(method_to_command("ListDevices", Some("x")).is_err());
+    assert!(method_to_command("Toggle", None).is_err());
+    assert!(method_to_command("Command", Some("query\nstats")).is_err());
+    assert!(method_to_command("Frobnicate", None).is_err());
+}
@@ -7,6 +7,19 @@ use crate::error::ArgumentError;
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Domain(usize);
 
+impl Domain {
+    /// The number behind this domain. Only meaningful within a single run of evsieve, or in runs
+    /// that create their domains in the same order, such as runs with the same arguments.
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    /// The inverse of `index()`.
+    pub fn from_index(index: usize) -> Domain {
+        Domain(index)
+    }
+}
+
 pub fn get_unique_domain() -> Domain {
     TRACKER.lock()
         .expect("Fatal error: internal mutex poisoned.")
@@ -24,6 +37,22 @@ pub fn resolve(name: &str) -> Result<Domain, ArgumentError> {
         .resolve(name)
 }
 
+/// Records that events with the source domain were generated by an input device whose events
+/// enter the stream with the target domain.
+pub fn set_origin(source: Domain, target: Domain) {
+    TRACKER.lock()
+        .expect("Fatal error: internal mutex poisoned.")
+        .origins.insert(source, target);
+}
+
+/// Returns the name of the domain that the input device which generated events with the given
+/// source domain puts its events into the stream with, if known.
+pub fn try_reverse_resolve_origin(source: Domain) -> Option<String> {
+    let mut tracker = TRACKER.lock().expect("Fatal error: internal mutex poisoned.");
+    let target = *tracker.origins.get(&source)?;
+    tracker.try_reverse_resolve(target)
+}
+
 /// Returns a String that resolves to this Domain, if it exists. Otherwise, returns None.
 pub fn try_reverse_resolve(domain: Domain) -> Option<String> {
     TRACKER.lock()
@@ -31,6 +60,22 @@ pub fn try_reverse_resolve(domain: Domain) -> Option<String> {
         .try_reverse_resolve(domain)
 }
 
+/// Turns an arbitrary string into something that can be used as a domain name in filters like
+/// key:a@name, by replacing all characters other than letters, digits, dashes, underscores and
+/// periods with underscores.
+pub fn sanitize(name: &str) -> String {
+    let result: String = name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    match result.is_empty() {
+        true => "_".to_owned(),
+        false => result,
+    }
+}
+
 lazy_static!{
     static ref TRACKER: Mutex<DomainTracker> = Mutex::new(DomainTracker::new());
 }
@@ -43,6 +88,9 @@ lazy_static!{
 struct DomainTracker {
     name_map: HashMap<String, Domain>,
     reveres_name_map: HashMap<Domain, String>,
+    /// Maps the domains of events generated by input devices to the domains those events get
+    /// when they enter the stream.
+    origins: HashMap<Domain, Domain>,
     /// A counter for how many domains have been allocated. Used to allocate new unique domains.
     counter: usize,
 }
@@ -83,7 +131,16 @@ impl DomainTracker {
         DomainTracker {
             name_map: HashMap::new(),
             reveres_name_map: HashMap::new(),
+            origins: HashMap::new(),
             counter: 0,
         }
     }
 }
+
+#[test]
+fn unittest() {
+    assert_eq!(sanitize("usb-Logitech_USB_Receiver-event-kbd"), "usb-Logitech_USB_Receiver-event-kbd");
+    assert_eq!(sanitize("AT Translated Set 2 keyboard"), "AT_Translated_Set_2_keyboard");
+    assert_eq!(sanitize("pci-0000:00:14.0-usb@1"), "pci-0000_00_14.0-usb_1");
+    assert_eq!(sanitize(""), "_");
+}
@@ -257,15 +257,23 @@ pub fn event_code(type_name: &str, code_name: &str) -> Result<EventCode, Argumen
 }
 
 pub const EV_ABS: u16 = libevdev::EV_ABS as u16;
+pub const EV_REL: u16 = libevdev::EV_REL as u16;
 pub const EV_SYN: u16 = libevdev::EV_SYN as u16;
 pub const EV_REP: u16 = libevdev::EV_REP as u16;
 pub const EV_KEY: u16 = libevdev::EV_KEY as u16;
 pub const EV_MSC: u16 = libevdev::EV_MSC as u16;
+pub const EV_LED: u16 = libevdev::EV_LED as u16;
+pub const EV_SW: u16 = libevdev::EV_SW as u16;
+pub const EV_FF: u16 = libevdev::EV_FF as u16;
 pub const EV_MAX: u16 = libevdev::EV_MAX as u16;
 
 pub const REP_DELAY: u16 = libevdev::REP_DELAY as u16;
 pub const REP_PERIOD: u16 = libevdev::REP_PERIOD as u16;
 pub const MSC_SCAN: u16 = libevdev::MSC_SCAN as u16;
+// Like ABS_MT_SLOT below, SYN_REPORT is missing from the bindings.
+pub const SYN_REPORT: u16 = 0;
+// The ABS_MT_SLOT constant is part of the kernel headers, not libevdev, so it is missing from the bindings.
+pub const ABS_MT_SLOT: u16 = 0x2f;
 
 /// Returns an iterator over all event types that fall within EV_MAX,
 /// whether those types are named or not.
@@ -13,9 +13,13 @@ pub struct EventType(u16);
 impl EventType {
     pub const KEY: EventType = EventType(ecodes::EV_KEY);
     pub const ABS: EventType = EventType(ecodes::EV_ABS);
+    pub const REL: EventType = EventType(ecodes::EV_REL);
     pub const REP: EventType = EventType(ecodes::EV_REP);
     pub const SYN: EventType = EventType(ecodes::EV_SYN);
     pub const MSC: EventType = EventType(ecodes::EV_MSC);
+    pub const LED: EventType = EventType(ecodes::EV_LED);
+    pub const SW: EventType = EventType(ecodes::EV_SW);
+    pub const FF: EventType = EventType(ecodes::EV_FF);
 
     pub fn is_key(self) -> bool {
         self == EventType::KEY
@@ -23,12 +27,18 @@ impl EventType {
     pub fn is_abs(self) -> bool {
         self == EventType::ABS
     }
+    pub fn is_rel(self) -> bool {
+        self == EventType::REL
+    }
     pub fn is_rep(self) -> bool {
         self == EventType::REP
     }
     pub fn is_syn(self) -> bool {
         self == EventType::SYN
     }
+    pub fn is_sw(self) -> bool {
+        self == EventType::SW
+    }
 }
 
 impl EventType {
@@ -57,6 +67,16 @@ impl EventCode {
         code: ecodes::MSC_SCAN,
     };
 
+    pub const SYN_REPORT: EventCode = EventCode {
+        ev_type: EventType::SYN,
+        code: ecodes::SYN_REPORT,
+    };
+
+    pub const ABS_MT_SLOT: EventCode = EventCode {
+        ev_type: EventType::ABS,
+        code: ecodes::ABS_MT_SLOT,
+    };
+
     pub const fn new(ev_type: EventType, code: u16) -> EventCode {
         EventCode { ev_type, code }
     }
@@ -167,6 +187,15 @@ pub struct Event {
     pub domain: Domain,
     pub namespace: Namespace,
     pub flags: EventFlags,
+
+    /// The domain this event had when it was created. For events generated by input devices,
+    /// this identifies the device, even if maps changed the domain of the event afterwards.
+    pub origin: Domain,
+
+    /// For ABS_MT_* events, the multitouch slot this event applies to. The ABS_MT_SLOT events
+    /// themselves do not enter the stream; the output devices generate them based on this
+    /// property instead.
+    pub slot: Option<EventValue>,
 }
 
 impl Event {
@@ -177,7 +206,7 @@ impl Event {
                namespace: Namespace
     ) -> Event {
         let flags = EventFlags::empty();
-        Event { code, value, previous_value, domain, namespace, flags }
+        Event { code, value, previous_value, domain, namespace, flags, slot: None, origin: domain }
     }
 
     pub fn with_domain(mut self, new_domain: Domain) -> Event {
@@ -185,6 +214,11 @@ impl Event {
         self
     }
 
+    pub fn with_slot(mut self, slot: Option<EventValue>) -> Event {
+        self.slot = slot;
+        self
+    }
+
     pub fn ev_type(self) -> EventType {
         self.code.ev_type()
     }
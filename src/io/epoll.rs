@@ -2,8 +2,10 @@
 
 use crate::error::{Context, SystemError};
 use crate::io::fd::{OwnedFd, HasFixedFd};
+use crate::io::uring::{KernelTimespec, Sqe, Uring};
+use crate::logging;
 use std::collections::HashMap;
-use std::os::unix::io::{AsRawFd};
+use std::os::unix::io::{AsRawFd, RawFd};
 
 pub const INDEFINITE_TIMEOUT: i32 = -1;
 
@@ -17,12 +19,44 @@ pub struct FileIndex(u64);
 /// 
 /// It also keeps track of when input devices unexpectedly close.
 pub struct Epoll<T: HasFixedFd> {
-    fd: OwnedFd,
+    backend: Backend,
     files: HashMap<FileIndex, T>,
     /// A counter, so every file registered can get an unique index in the files map.
     counter: u64,
 }
 
+/// How an Epoll waits for its files to become ready.
+enum Backend {
+    Epoll(OwnedFd),
+    /// Every file has a poll operation on the io_uring. Each time one completes, it gets
+    /// resubmitted along with the next wait, so waiting never takes more than one system call.
+    Uring(Box<UringState>),
+}
+
+struct UringState {
+    ring: Uring,
+    /// The files whose poll operation has completed and needs to be submitted again.
+    unarmed: Vec<FileIndex>,
+    /// The timeout of the current wait. The kernel reads it while submitting.
+    timeout: KernelTimespec,
+    /// Whether a timeout operation has been submitted that has not completed yet.
+    timeout_pending: bool,
+}
+
+/// The user data of the operations on the io_uring tells what kind of operation they were. The
+/// rest of the bits hold the index of the file for polls, or the index of the write for writes.
+const URING_TAG_MASK: u64 = 3 << 62;
+const URING_TAG_POLL: u64 = 0;
+const URING_TAG_WRITE: u64 = 1 << 62;
+const URING_TAG_TIMEOUT: u64 = 2 << 62;
+const URING_TAG_OTHER: u64 = 3 << 62;
+
+/// Data that shall be written to a file while polling, see `Epoll::poll_and_write()`.
+pub struct PendingWrite<'a> {
+    pub fd: RawFd,
+    pub data: &'a [u8],
+}
+
 /// Represents a result that an Epoll may return.
 pub enum Message {
     Ready(FileIndex),
@@ -40,12 +74,48 @@ impl<T: HasFixedFd> Epoll<T> {
         };
 
         Ok(Epoll {
-            fd: epoll_fd,
+            backend: Backend::Epoll(epoll_fd),
             files: HashMap::new(),
             counter: 0,
         })
     }
 
+    /// Like `new()`, but uses an io_uring instead of an epoll if evsieve was compiled with the
+    /// io-uring feature and the kernel supports it.
+    pub fn with_preferred_backend() -> Result<Epoll<T>, SystemError> {
+        if ! cfg!(feature = "io-uring") {
+            return Epoll::new();
+        }
+        // The number 256 was chosen arbitrarily. More operations than that get submitted in batches.
+        match Uring::new(256) {
+            Ok(ring) => Ok(Epoll {
+                backend: Backend::Uring(Box::new(UringState {
+                    ring,
+                    unarmed: Vec::new(),
+                    timeout: KernelTimespec::default(),
+                    timeout_pending: false,
+                })),
+                files: HashMap::new(),
+                counter: 0,
+            }),
+            Err(error) => {
+                if logging::enabled(logging::LogLevel::Debug) {
+                    error.with_context("While trying to use io_uring instead of epoll:").print_err();
+                }
+                Epoll::new()
+            },
+        }
+    }
+
+    /// Returns true if waiting and writing go through an io_uring, in which case writing data
+    /// along with `poll_and_write()` is cheaper than writing it right away.
+    pub fn uses_io_uring(&self) -> bool {
+        match self.backend {
+            Backend::Uring(_) => true,
+            Backend::Epoll(_) => false,
+        }
+    }
+
     fn get_unique_index(&mut self) -> FileIndex {
         self.counter += 1;
         FileIndex(self.counter)
@@ -63,6 +133,15 @@ impl<T: HasFixedFd> Epoll<T> {
         }
         self.files.insert(index, file);
 
+        let epoll_fd = match &mut self.backend {
+            Backend::Epoll(fd) => fd.as_raw_fd(),
+            Backend::Uring(state) => {
+                // The poll operation gets submitted along with the next wait.
+                state.unarmed.push(index);
+                return Ok(index);
+            },
+        };
+
         // We set the data to the index of said file, so we know which file is ready for reading.
         let mut event = libc::epoll_event {
             events: libc::EPOLLIN as u32,
@@ -70,7 +149,7 @@ impl<T: HasFixedFd> Epoll<T> {
         };
 
         let result = unsafe { libc::epoll_ctl(
-            self.fd.as_raw_fd(),
+            epoll_fd,
             libc::EPOLL_CTL_ADD,
             file_fd,
             &mut event,
@@ -88,6 +167,15 @@ impl<T: HasFixedFd> Epoll<T> {
         self.files.values()
     }
 
+    pub fn files_mut(&mut self) -> impl Iterator<Item=&mut T> {
+        self.files.values_mut()
+    }
+
+    /// Returns the indices of all files belonging to this epoll.
+    pub fn indices(&self) -> Vec<FileIndex> {
+        self.files.keys().copied().collect()
+    }
+
     pub fn contains_index(&self, index: FileIndex) -> bool {
         self.files.contains_key(&index)
     }
@@ -107,8 +195,23 @@ impl<T: HasFixedFd> Epoll<T> {
             None => return None,
         };
 
+        let epoll_fd = match &mut self.backend {
+            Backend::Epoll(fd) => fd.as_raw_fd(),
+            Backend::Uring(state) => {
+                match state.unarmed.iter().position(|&unarmed_index| unarmed_index == index) {
+                    Some(position) => { state.unarmed.remove(position); },
+                    // The poll operation must be cancelled before the file gets closed. Until
+                    // it is, its completions get ignored because the index no longer exists.
+                    None => if let Err(error) = state.ring.push(Sqe::poll_remove(URING_TAG_POLL | index.0, URING_TAG_OTHER)) {
+                        error.with_context("While removing a device from an io_uring:").print_err();
+                    },
+                }
+                return Some(file);
+            },
+        };
+
         let result = unsafe { libc::epoll_ctl(
-            self.fd.as_raw_fd(),
+            epoll_fd,
             libc::EPOLL_CTL_DEL,
             file.as_raw_fd(),
             std::ptr::null_mut(),
@@ -129,9 +232,9 @@ impl<T: HasFixedFd> Epoll<T> {
         Some(file)
     }
 
-    fn poll_raw(&mut self, timeout: i32) -> Result<Vec<libc::epoll_event>, std::io::Error> {
+    fn poll_raw(epoll_fd: RawFd, num_files: usize, timeout: i32) -> Result<Vec<libc::epoll_event>, std::io::Error> {
         // The number 8 was chosen arbitrarily.
-        let max_events: i32 = std::cmp::min(self.files.len(), 8) as i32;
+        let max_events: i32 = std::cmp::min(num_files, 8) as i32;
         let mut events: Vec<libc::epoll_event> = (0 .. max_events).map(|_| libc::epoll_event {
             // The following values don't matter since the kernel will overwrite them anyway.
             // We're just initialzing them to make the compiler happy.
@@ -140,7 +243,7 @@ impl<T: HasFixedFd> Epoll<T> {
 
         let result = unsafe {
             libc::epoll_wait(
-                self.fd.as_raw_fd(),
+                epoll_fd,
                 events.as_mut_ptr(),
                 max_events,
                 timeout,
@@ -161,8 +264,27 @@ impl<T: HasFixedFd> Epoll<T> {
     /// Timeout means the same thing as in the `epoll_wait()` syscall: time in milliseconds,
     /// -1 means forever, 0 means instant return.
     pub fn poll(&mut self, timeout: i32) -> Result<impl Iterator<Item=Message>, SystemError> {
+        let (messages, _) = self.poll_and_write(timeout, &[])?;
+        Ok(messages.into_iter())
+    }
+
+    /// Like `poll()`, but also writes data to some files first. With io_uring, the writes get
+    /// submitted together with the wait. Returns for each write whether all data was written.
+    pub fn poll_and_write(&mut self, timeout: i32, writes: &[PendingWrite]) -> Result<(Vec<Message>, Vec<bool>), SystemError> {
+        let epoll_fd = match &mut self.backend {
+            Backend::Epoll(fd) => fd.as_raw_fd(),
+            Backend::Uring(state) => return poll_uring(state, &self.files, timeout, writes),
+        };
+
+        let write_results = writes.iter().map(|write| {
+            let result = unsafe {
+                libc::write(write.fd, write.data.as_ptr() as *const libc::c_void, write.data.len())
+            };
+            result >= 0 && result as usize == write.data.len()
+        }).collect();
+
         let events = loop {
-            match self.poll_raw(timeout) {
+            match Epoll::<T>::poll_raw(epoll_fd, self.files.len(), timeout) {
                 Ok(events) => break events,
                 Err(error) => match error.kind() {
                     std::io::ErrorKind::Interrupted => continue,
@@ -187,7 +309,7 @@ impl<T: HasFixedFd> Epoll<T> {
             }
         }
 
-        Ok(messages.into_iter())
+        Ok((messages, write_results))
     }
 
     /// Returns whether currently any files are opened under this epoll.
@@ -196,6 +318,87 @@ impl<T: HasFixedFd> Epoll<T> {
     }
 }
 
+/// The io_uring version of `Epoll::poll_and_write()`.
+fn poll_uring<T: HasFixedFd>(state: &mut UringState, files: &HashMap<FileIndex, T>, timeout: i32, writes: &[PendingWrite])
+    -> Result<(Vec<Message>, Vec<bool>), SystemError>
+{
+    for index in state.unarmed.drain(..) {
+        if let Some(file) = files.get(&index) {
+            state.ring.push(Sqe::poll_add(file.as_raw_fd(), libc::POLLIN, URING_TAG_POLL | index.0))?;
+        }
+    }
+    for (write_index, write) in writes.iter().enumerate() {
+        // The data outlives this function, and this function waits for all writes to complete.
+        state.ring.push(unsafe { Sqe::write(write.fd, write.data, URING_TAG_WRITE | write_index as u64) })?;
+    }
+    // The timeout of the previous wait may still be pending if a file became ready before it expired.
+    if state.timeout_pending {
+        state.ring.push(Sqe::timeout_remove(URING_TAG_TIMEOUT, URING_TAG_OTHER))?;
+    }
+    if timeout > 0 {
+        state.timeout = KernelTimespec::from_millis(timeout as u64);
+        state.ring.push(Sqe::timeout(&state.timeout, URING_TAG_TIMEOUT))?;
+        state.timeout_pending = true;
+    }
+    // Wait for all writes to complete and for one more completion, which is usually either a file
+    // becoming ready or the timeout expiring. Other completions only cause a spurious wakeup.
+    let min_complete = match timeout {
+        0 => 0,
+        _ => writes.len() as u32 + 1,
+    };
+    state.ring.submit(min_complete)?;
+
+    let mut messages: Vec<Message> = Vec::new();
+    let mut write_results: Vec<Option<bool>> = vec![None; writes.len()];
+    loop {
+        while let Some(completion) = state.ring.pop_completion() {
+            let data = completion.user_data & ! URING_TAG_MASK;
+            match completion.user_data & URING_TAG_MASK {
+                URING_TAG_POLL => {
+                    let file_index = FileIndex(data);
+                    if ! files.contains_key(&file_index) {
+                        continue;
+                    }
+                    state.unarmed.push(file_index);
+                    let events = completion.result;
+                    if events < 0 {
+                        messages.push(Message::Broken(file_index));
+                        continue;
+                    }
+                    if events & libc::POLLIN as i32 != 0 {
+                        messages.push(Message::Ready(file_index));
+                    }
+                    if events & libc::POLLERR as i32 != 0 {
+                        messages.push(Message::Broken(file_index));
+                    } else if events & libc::POLLHUP as i32 != 0 {
+                        messages.push(Message::Hup(file_index));
+                    }
+                },
+                URING_TAG_WRITE => {
+                    let write_index = data as usize;
+                    let expected_size = writes.get(write_index).map(|write| write.data.len());
+                    if let Some(result) = write_results.get_mut(write_index) {
+                        *result = Some(completion.result >= 0 && Some(completion.result as usize) == expected_size);
+                    }
+                },
+                // A timeout that got cancelled was replaced by a newer one.
+                URING_TAG_TIMEOUT if completion.result != -libc::ECANCELED => state.timeout_pending = false,
+                _ => (),
+            }
+        }
+        // The data of the writes must remain valid until they have completed. In practice, writes
+        // to virtual devices complete while being submitted.
+        let num_unfinished_writes = write_results.iter().filter(|result| result.is_none()).count();
+        if num_unfinished_writes == 0 {
+            break;
+        }
+        state.ring.submit(num_unfinished_writes as u32)?;
+    }
+
+    let write_results = write_results.into_iter().map(|result| result.unwrap_or(false)).collect();
+    Ok((messages, write_results))
+}
+
 impl<T: HasFixedFd> std::ops::Index<FileIndex> for Epoll<T> {
     type Output = T;
     fn index(&self, index: FileIndex) -> &Self::Output {
@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Force feedback passthrough. If an output device has the forward-ff= clause, it advertises the
+//! force feedback capabilities of some input device. Whenever a program uploads, erases or plays
+//! an effect on the output device, the uinput module notifies us, and we do the same thing on the
+//! input device. See the kernel documentation on uinput for the protocol used here.
+
+use std::os::unix::io::RawFd;
+
+use crate::error::SystemError;
+use crate::event::{EventCode, EventType};
+
+/// The event type that uinput uses to notify us of upload and erase requests.
+const EV_UINPUT: u16 = 0x0101;
+const UI_FF_UPLOAD: u16 = 1;
+const UI_FF_ERASE: u16 = 2;
+
+/// Codes of EV_FF at or above this value do not refer to effects, but set device properties.
+pub const FF_GAIN: u16 = 0x60;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UinputFfUpload {
+    pub request_id: u32,
+    pub retval: i32,
+    pub effect: libc::ff_effect,
+    pub old: libc::ff_effect,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UinputFfErase {
+    pub request_id: u32,
+    pub retval: i32,
+    pub effect_id: u32,
+}
+
+/// Computes an ioctl request number like the kernel's _IOC macro does.
+const fn ioc(direction: libc::c_ulong, kind: u8, number: u8, size: usize) -> libc::c_ulong {
+    (direction << 30) | ((size as libc::c_ulong) << 16) | ((kind as libc::c_ulong) << 8) | number as libc::c_ulong
+}
+const IOC_WRITE: libc::c_ulong = 1;
+const IOC_READ_WRITE: libc::c_ulong = 3;
+
+const UI_BEGIN_FF_UPLOAD: libc::c_ulong = ioc(IOC_READ_WRITE, b'U', 200, std::mem::size_of::<UinputFfUpload>());
+const UI_END_FF_UPLOAD: libc::c_ulong = ioc(IOC_WRITE, b'U', 201, std::mem::size_of::<UinputFfUpload>());
+const UI_BEGIN_FF_ERASE: libc::c_ulong = ioc(IOC_READ_WRITE, b'U', 202, std::mem::size_of::<UinputFfErase>());
+const UI_END_FF_ERASE: libc::c_ulong = ioc(IOC_WRITE, b'U', 203, std::mem::size_of::<UinputFfErase>());
+pub const EVIOCSFF: libc::c_ulong = ioc(IOC_WRITE, b'E', 0x80, std::mem::size_of::<libc::ff_effect>());
+pub const EVIOCRMFF: libc::c_ulong = ioc(IOC_WRITE, b'E', 0x81, std::mem::size_of::<libc::c_int>());
+
+/// Something a program asked an output device to do with respect to force feedback.
+pub enum FfRequest {
+    /// Upload or update an effect. The id of the effect is the id on the output device.
+    Upload(UinputFfUpload),
+    /// Erase a previously uploaded effect.
+    Erase(UinputFfErase),
+    /// An EV_FF event was written to the output device, which either starts or stops playing an
+    /// effect, or sets a property like FF_GAIN.
+    Event(EventCode, i32),
+}
+
+/// Interprets an event that was read from the file descriptor of an uinput device. Returns None
+/// if the event is not related to force feedback. Upload and erase requests block the requesting
+/// program until they are finished with `finish_request()`.
+pub fn begin_request(uinput_fd: RawFd, event: &libc::input_event) -> Result<Option<FfRequest>, SystemError> {
+    match (event.type_, event.code) {
+        (EV_UINPUT, UI_FF_UPLOAD) => {
+            let mut upload: UinputFfUpload = unsafe { std::mem::zeroed() };
+            upload.request_id = event.value as u32;
+            if unsafe { libc::ioctl(uinput_fd, UI_BEGIN_FF_UPLOAD, &mut upload) } < 0 {
+                return Err(SystemError::os_with_context("While receiving a force feedback effect:"));
+            }
+            Ok(Some(FfRequest::Upload(upload)))
+        },
+        (EV_UINPUT, UI_FF_ERASE) => {
+            let mut erase: UinputFfErase = unsafe { std::mem::zeroed() };
+            erase.request_id = event.value as u32;
+            if unsafe { libc::ioctl(uinput_fd, UI_BEGIN_FF_ERASE, &mut erase) } < 0 {
+                return Err(SystemError::os_with_context("While receiving a request to erase a force feedback effect:"));
+            }
+            Ok(Some(FfRequest::Erase(erase)))
+        },
+        (crate::ecodes::EV_FF, code) => {
+            Ok(Some(FfRequest::Event(EventCode::new(EventType::FF, code), event.value)))
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Tells the uinput module the outcome of an upload or erase request, where `retval` is zero on
+/// success or a negative error code otherwise. Does nothing for other requests.
+pub fn finish_request(uinput_fd: RawFd, request: FfRequest, retval: i32) -> Result<(), SystemError> {
+    let res = match request {
+        FfRequest::Upload(mut upload) => {
+            upload.retval = retval;
+            unsafe { libc::ioctl(uinput_fd, UI_END_FF_UPLOAD, &mut upload) }
+        },
+        FfRequest::Erase(mut erase) => {
+            erase.retval = retval;
+            unsafe { libc::ioctl(uinput_fd, UI_END_FF_ERASE, &mut erase) }
+        },
+        FfRequest::Event(..) => return Ok(()),
+    };
+    if res < 0 {
+        return Err(SystemError::os_with_context("While finishing a force feedback request:"));
+    }
+    Ok(())
+}
+
+#[test]
+fn unittest() {
+    // Compare against the values from the kernel headers on 64-bit platforms.
+    if cfg!(target_pointer_width = "64") {
+        assert_eq!(EVIOCSFF, 0x40304580);
+        assert_eq!(EVIOCRMFF, 0x40044581);
+        assert_eq!(UI_BEGIN_FF_UPLOAD, 0xc06855c8);
+        assert_eq!(UI_END_FF_UPLOAD, 0x406855c9);
+        assert_eq!(UI_BEGIN_FF_ERASE, 0xc00c55ca);
+        assert_eq!(UI_END_FF_ERASE, 0x400c55cb);
+    }
+}
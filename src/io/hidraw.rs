@@ -0,0 +1,398 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Reads HID reports from hidraw devices. Some devices report part of their buttons on vendor-defined
+//! usage pages that the kernel does not translate to evdev events. The report descriptor of the
+//! device tells us where each usage is located within the reports, and usage= clauses tell us which
+//! event each usage should be translated to.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use crate::capability::{AbsInfo, AbsMeta, Capabilities};
+use crate::domain::Domain;
+use crate::error::{Context, SystemError};
+use crate::event::{Event, EventCode, EventType, EventValue};
+use crate::io::fd::HasFixedFd;
+use crate::io::source::{EventSource, SourceState};
+
+/// The maximum size of a report descriptor according to linux/hid.h.
+const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+/// _IOR('H', 0x01, int) from linux/hidraw.h.
+const HIDIOCGRDESCSIZE: libc::c_ulong = 0x8004_4801;
+/// _IOR('H', 0x02, struct hidraw_report_descriptor) from linux/hidraw.h.
+const HIDIOCGRDESC: libc::c_ulong = 0x9004_4802;
+
+#[repr(C)]
+struct HidrawReportDescriptor {
+    size: u32,
+    value: [u8; HID_MAX_DESCRIPTOR_SIZE],
+}
+
+/// Tells which event a usage shall be translated to. Represents a usage=PAGE:ID=EVENT clause.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UsageMapping {
+    pub page: u16,
+    pub id: u16,
+    pub code: EventCode,
+}
+
+/// A single value within the input reports of a device, as declared by its report descriptor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct HidField {
+    report_id: Option<u8>,
+    /// The offset of this field in bits, counted from the start of the report excluding its id.
+    bit_offset: usize,
+    bit_size: usize,
+    page: u16,
+    id: u16,
+    logical_min: i32,
+    logical_max: i32,
+}
+
+impl HidField {
+    /// Reads the value of this field from a report whose id byte has already been stripped.
+    fn extract(&self, report: &[u8]) -> Option<i32> {
+        if self.bit_size == 0 || self.bit_size > 32 || self.bit_offset + self.bit_size > report.len() * 8 {
+            return None;
+        }
+        let mut raw: u32 = 0;
+        for bit in 0 .. self.bit_size {
+            let position = self.bit_offset + bit;
+            if report[position / 8] & (1 << (position % 8)) != 0 {
+                raw |= 1 << bit;
+            }
+        }
+        // Fields with a negative logical minimum are stored in two's complement.
+        if self.logical_min < 0 && self.bit_size < 32 && raw & (1 << (self.bit_size - 1)) != 0 {
+            raw |= !0u32 << self.bit_size;
+        }
+        Some(raw as i32)
+    }
+}
+
+pub struct HidrawDevice {
+    file: File,
+    path: PathBuf,
+    /// The fields that have been mapped to some event.
+    fields: Vec<(HidField, EventCode)>,
+    /// Whether the reports of this device start with a report id.
+    has_report_ids: bool,
+    capabilities: Capabilities,
+    /// Maps (type, code) pairs to the last value they were emitted with.
+    state: SourceState,
+}
+
+impl HidrawDevice {
+    /// Opens a hidraw device. If `descriptor` is Some, the report descriptor is read from that file
+    /// instead of being queried from the device.
+    pub fn open(path: PathBuf, domain: Domain, descriptor: Option<PathBuf>, mappings: &[UsageMapping]) -> Result<HidrawDevice, SystemError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(&path)?;
+
+        let descriptor_bytes = match descriptor {
+            Some(descriptor_path) => std::fs::read(&descriptor_path).map_err(SystemError::from)
+                .with_context_of(|| format!("While reading the report descriptor {}:", descriptor_path.display()))?,
+            None => query_report_descriptor(&file)?,
+        };
+        let all_fields = parse_report_descriptor(&descriptor_bytes)?;
+        let has_report_ids = all_fields.iter().any(|field| field.report_id.is_some());
+
+        let mut fields: Vec<(HidField, EventCode)> = Vec::new();
+        let mut capabilities = Capabilities::new();
+        for mapping in mappings {
+            let matching_fields: Vec<HidField> = all_fields.iter()
+                .filter(|field| field.page == mapping.page && field.id == mapping.id)
+                .copied()
+                .collect();
+            if matching_fields.is_empty() {
+                return Err(SystemError::new(format!(
+                    "The report descriptor of {} does not declare an input with usage {:04x}:{:04x}.",
+                    path.display(), mapping.page, mapping.id
+                )));
+            }
+            for field in matching_fields {
+                capabilities.codes.insert(mapping.code);
+                if mapping.code.ev_type().is_abs() {
+                    capabilities.abs_info.insert(mapping.code, AbsInfo {
+                        min_value: field.logical_min,
+                        max_value: field.logical_max,
+                        meta: AbsMeta { fuzz: 0, flat: 0, resolution: 0, value: 0 },
+                    });
+                }
+                fields.push((field, mapping.code));
+            }
+        }
+        capabilities.codes.insert(EventCode::new(EventType::SYN, crate::ecodes::SYN_REPORT));
+
+        Ok(HidrawDevice {
+            file, path, fields, has_report_ids, capabilities,
+            state: SourceState::new(domain),
+        })
+    }
+
+    /// Reads all reports that are available and translates them to events.
+    pub fn poll(&mut self) -> Result<Vec<Event>, SystemError> {
+        let mut events: Vec<Event> = Vec::new();
+        let mut buffer = [0u8; HID_MAX_DESCRIPTOR_SIZE];
+        loop {
+            // Every read returns a single report.
+            let num_bytes = match self.file.read(&mut buffer) {
+                Ok(0) => return Err(SystemError::new("The device has been disconnected.")),
+                Ok(num_bytes) => num_bytes,
+                Err(error) => match error.kind() {
+                    std::io::ErrorKind::WouldBlock => break,
+                    std::io::ErrorKind::Interrupted => continue,
+                    _ => return Err(error.into()),
+                },
+            };
+            let report = &buffer[.. num_bytes];
+            let (report_id, report) = match self.has_report_ids {
+                true => (Some(report[0]), &report[1..]),
+                false => (None, report),
+            };
+
+            let mut changes: Vec<(EventCode, EventValue)> = Vec::new();
+            for (field, code) in &self.fields {
+                if field.report_id != report_id {
+                    continue;
+                }
+                let value = match field.extract(report) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let value = match code.ev_type() {
+                    EventType::KEY => (value != 0) as EventValue,
+                    _ => value,
+                };
+                let previous_value = self.state.value(*code);
+                // Relative axes report a movement every report, everything else reports a state.
+                let is_change = match code.ev_type().is_rel() {
+                    true => value != 0,
+                    false => value != previous_value,
+                };
+                if is_change {
+                    changes.push((*code, value));
+                }
+            }
+
+            if ! changes.is_empty() {
+                for (code, value) in changes {
+                    let event = self.state.synthesize_event(code, value);
+                    events.push(event);
+                }
+                let syn_event = self.state.synthesize_event(EventCode::new(EventType::SYN, crate::ecodes::SYN_REPORT), 0);
+                events.push(syn_event);
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.state.domain()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl EventSource for HidrawDevice {
+    fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event {
+        self.state.synthesize_event(code, value)
+    }
+
+    fn pressed_keys(&self) -> Vec<EventCode> {
+        self.state.pressed_keys()
+    }
+}
+
+impl AsRawFd for HidrawDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for HidrawDevice {}
+
+fn query_report_descriptor(file: &File) -> Result<Vec<u8>, SystemError> {
+    let mut size: libc::c_int = 0;
+    if unsafe { libc::ioctl(file.as_raw_fd(), HIDIOCGRDESCSIZE, &mut size) } < 0 {
+        return Err(SystemError::os_with_context("While querying the size of the report descriptor:"));
+    }
+    let mut descriptor = HidrawReportDescriptor { size: size as u32, value: [0; HID_MAX_DESCRIPTOR_SIZE] };
+    if unsafe { libc::ioctl(file.as_raw_fd(), HIDIOCGRDESC, &mut descriptor) } < 0 {
+        return Err(SystemError::os_with_context("While querying the report descriptor:"));
+    }
+    let size = std::cmp::min(descriptor.size as usize, HID_MAX_DESCRIPTOR_SIZE);
+    Ok(descriptor.value[.. size].to_vec())
+}
+
+/// The global items of a report descriptor, which can be saved and restored by push and pop items.
+#[derive(Clone, Copy)]
+struct GlobalState {
+    page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    report_size: usize,
+    report_count: usize,
+    report_id: Option<u8>,
+}
+
+/// Finds all variable input fields declared by a report descriptor. Array fields are skipped
+/// because they do not map to a single usage.
+fn parse_report_descriptor(bytes: &[u8]) -> Result<Vec<HidField>, SystemError> {
+    let mut fields: Vec<HidField> = Vec::new();
+    let mut global = GlobalState { page: 0, logical_min: 0, logical_max: 0, report_size: 0, report_count: 0, report_id: None };
+    let mut global_stack: Vec<GlobalState> = Vec::new();
+    // Local items: (page, id) pairs, and the usage minimum and maximum.
+    let mut usages: Vec<(u16, u16)> = Vec::new();
+    let mut usage_min: Option<(u16, u16)> = None;
+    let mut usage_max: Option<(u16, u16)> = None;
+    // The bit offset of the next input field for each report id.
+    let mut offsets: HashMap<Option<u8>, usize> = HashMap::new();
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let prefix = bytes[index];
+        // Long items carry no information we are interested in.
+        if prefix == 0xfe {
+            let data_size = *bytes.get(index + 1).unwrap_or(&0) as usize;
+            index += 3 + data_size;
+            continue;
+        }
+        let data_size = match prefix & 0x03 {
+            3 => 4,
+            size => size as usize,
+        };
+        if index + 1 + data_size > bytes.len() {
+            return Err(SystemError::new("The report descriptor is truncated."));
+        }
+        let data = &bytes[index + 1 .. index + 1 + data_size];
+        index += 1 + data_size;
+
+        let unsigned_value: u32 = data.iter().rev().fold(0, |acc, &byte| (acc << 8) | byte as u32);
+        let signed_value: i32 = match data_size {
+            0 => 0,
+            1 => unsigned_value as u8 as i8 as i32,
+            2 => unsigned_value as u16 as i16 as i32,
+            _ => unsigned_value as i32,
+        };
+        // Usages with four bytes of data include their own page.
+        let usage = |global: &GlobalState| match data_size {
+            4 => ((unsigned_value >> 16) as u16, unsigned_value as u16),
+            _ => (global.page, unsigned_value as u16),
+        };
+
+        match prefix & 0xfc {
+            // Input
+            0x80 => {
+                let is_constant = unsigned_value & 0x01 != 0;
+                let is_variable = unsigned_value & 0x02 != 0;
+                let offset = offsets.entry(global.report_id).or_insert(0);
+                for i in 0 .. global.report_count {
+                    let usage = match (usages.get(i).or_else(|| usages.last()), usage_min, usage_max) {
+                        (Some(&usage), _, _) => Some(usage),
+                        (None, Some((page, min)), Some((_, max))) => Some((page, std::cmp::min(min as usize + i, max as usize) as u16)),
+                        _ => None,
+                    };
+                    if let (Some((page, id)), false, true) = (usage, is_constant, is_variable) {
+                        fields.push(HidField {
+                            report_id: global.report_id,
+                            bit_offset: *offset,
+                            bit_size: global.report_size,
+                            page, id,
+                            logical_min: global.logical_min,
+                            logical_max: global.logical_max,
+                        });
+                    }
+                    *offset += global.report_size;
+                }
+                usages.clear();
+                usage_min = None;
+                usage_max = None;
+            },
+            // Output, Feature, Collection, End Collection
+            0x90 | 0xb0 | 0xa0 | 0xc0 => {
+                usages.clear();
+                usage_min = None;
+                usage_max = None;
+            },
+            // Global items
+            0x04 => global.page = unsigned_value as u16,
+            0x14 => global.logical_min = signed_value,
+            // The logical maximum is only signed if the logical minimum is negative.
+            0x24 => global.logical_max = match global.logical_min < 0 {
+                true => signed_value,
+                false => unsigned_value as i32,
+            },
+            0x74 => global.report_size = unsigned_value as usize,
+            0x84 => global.report_id = Some(unsigned_value as u8),
+            0x94 => global.report_count = unsigned_value as usize,
+            0xa4 => global_stack.push(global),
+            0xb4 => match global_stack.pop() {
+                Some(state) => global = state,
+                None => return Err(SystemError::new("The report descriptor contains a pop item without a matching push item.")),
+            },
+            // Local items
+            0x08 => usages.push(usage(&global)),
+            0x18 => usage_min = Some(usage(&global)),
+            0x28 => usage_max = Some(usage(&global)),
+            _ => {},
+        }
+    }
+
+    Ok(fields)
+}
+
+#[test]
+fn unittest() {
+    // A vendor-defined report with id 2, containing two one-bit buttons, six bits of padding,
+    // and a signed byte.
+    let descriptor: &[u8] = &[
+        0x06, 0x00, 0xff, // Usage Page (Vendor Defined 0xFF00)
+        0x09, 0x01,       // Usage (0x01)
+        0xa1, 0x01,       // Collection (Application)
+        0x85, 0x02,       //   Report ID (2)
+        0x19, 0x10,       //   Usage Minimum (0x10)
+        0x29, 0x11,       //   Usage Maximum (0x11)
+        0x15, 0x00,       //   Logical Minimum (0)
+        0x25, 0x01,       //   Logical Maximum (1)
+        0x75, 0x01,       //   Report Size (1)
+        0x95, 0x02,       //   Report Count (2)
+        0x81, 0x02,       //   Input (Data, Variable, Absolute)
+        0x75, 0x06,       //   Report Size (6)
+        0x95, 0x01,       //   Report Count (1)
+        0x81, 0x03,       //   Input (Constant)
+        0x09, 0x20,       //   Usage (0x20)
+        0x15, 0x81,       //   Logical Minimum (-127)
+        0x25, 0x7f,       //   Logical Maximum (127)
+        0x75, 0x08,       //   Report Size (8)
+        0x95, 0x01,       //   Report Count (1)
+        0x81, 0x02,       //   Input (Data, Variable, Absolute)
+        0xc0,             // End Collection
+    ];
+    let fields = parse_report_descriptor(descriptor).unwrap();
+    assert_eq!(fields.len(), 3);
+    assert_eq!((fields[0].page, fields[0].id, fields[0].bit_offset, fields[0].bit_size), (0xff00, 0x10, 0, 1));
+    assert_eq!((fields[1].page, fields[1].id, fields[1].bit_offset, fields[1].bit_size), (0xff00, 0x11, 1, 1));
+    assert_eq!((fields[2].page, fields[2].id, fields[2].bit_offset, fields[2].bit_size), (0xff00, 0x20, 8, 8));
+    assert_eq!((fields[2].logical_min, fields[2].logical_max), (-127, 127));
+    assert_eq!(fields[0].report_id, Some(2));
+
+    let report = [0b0000_0010, 0xfe];
+    assert_eq!(fields[0].extract(&report), Some(0));
+    assert_eq!(fields[1].extract(&report), Some(1));
+    assert_eq!(fields[2].extract(&report), Some(-2));
+    assert_eq!(fields[2].extract(&report[.. 1]), None);
+
+    assert!(parse_report_descriptor(&[0x06, 0x00]).is_err());
+}
@@ -2,7 +2,7 @@
 
 use std::ffi::{CStr, CString};
 use std::fs::{File, OpenOptions};
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
@@ -12,9 +12,15 @@ use crate::event::{Event, EventType, EventValue, EventCode, Namespace};
 use crate::domain::Domain;
 use crate::capability::{AbsInfo, Capabilities, InputCapabilites, RepeatInfo};
 use crate::ecodes;
-use crate::predevice::{GrabMode, PersistMode, PreInputDevice};
+use crate::predevice::{CapabilityFilter, DeviceId, DeviceSelector, EventClock, GrabCondition, GrabMode, PersistMode, PreInputDevice, ReopenBackoff, SynDroppedPolicy};
+use crate::state::State;
+use crate::latency::LatencyStats;
+use crate::time::Duration;
 use crate::error::{SystemError, Context};
 use crate::persist::blueprint::Blueprint;
+use crate::io::ff::{self, FfRequest};
+use crate::io::source::EventSource;
+use crate::logging;
 
 use super::fd::HasFixedFd;
 
@@ -29,6 +35,15 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
                 .with_context(format!("While opening the device \"{}\":", device_path.display()))
     }).collect::<Result<Vec<InputDevice>, SystemError>>()?;
 
+    // Skip the devices that were excluded by their require-caps= or exclude-caps= clause.
+    input_devices.retain(|device| {
+        let is_accepted = device.is_accepted_by_cap_filter();
+        if ! is_accepted {
+            logging::info(format!("The device {} is not used because its capabilities do not satisfy the require-caps= or exclude-caps= clauses.", device.path().display()));
+        }
+        is_accepted
+    });
+
     // Return an error if a device with grab=force cannot be grabbed.
     for device in &mut input_devices {
         device.grab_if_desired()?;
@@ -37,13 +52,111 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
     // Precompute the capabilities of the input devices.
     let mut capabilities: InputCapabilites = InputCapabilites::new();
     for device in &input_devices {
-        // TODO: Consider using an Rc instead of a clone.
-        capabilities.insert(device.domain, device.capabilities.clone());
+        // Devices that were found through the same pattern share their domain.
+        match capabilities.get_mut(&device.domain) {
+            Some(existing_caps) => existing_caps.merge(&device.capabilities),
+            // TODO: Consider using an Rc instead of a clone.
+            None => { capabilities.insert(device.domain, device.capabilities.clone()); },
+        }
     }
 
     Ok((input_devices, capabilities))
 }
 
+/// Returns the paths of all event devices in /dev/input whose name, vendor and product match
+/// the selector. Devices that cannot be opened are skipped.
+pub fn find_devices(selector: &DeviceSelector) -> Result<Vec<PathBuf>, SystemError> {
+    let entries = std::fs::read_dir("/dev/input")
+        .map_err(SystemError::from)
+        .with_context("While scanning /dev/input for input devices:")?;
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let is_event_device = match entry.file_name().to_str() {
+            Some(name) => name.starts_with("event"),
+            None => false,
+        };
+        if ! is_event_device {
+            continue;
+        }
+
+        let path = entry.path();
+        if let Ok(id) = query_device_id(&path) {
+            if selector.matches(&id) {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Derives a domain name for the device at a path for the domain=auto clause. The same device
+/// should get the same name across runs, so we prefer the name of its link in /dev/input/by-id,
+/// then the name the device reports, and finally the name of its event device node.
+pub fn auto_domain_name(path: &Path) -> String {
+    let by_id = Path::new("/dev/input/by-id");
+    let name = if path.parent() == Some(by_id) {
+        path.file_name().map(|name| name.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+
+    let name = name.or_else(|| {
+        let real_path = std::fs::canonicalize(path).ok()?;
+        let mut links: Vec<PathBuf> = std::fs::read_dir(by_id).ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|link| std::fs::canonicalize(link).ok().as_ref() == Some(&real_path))
+            .collect();
+        links.sort();
+        links.first().and_then(|link| link.file_name()).map(|name| name.to_string_lossy().into_owned())
+    });
+
+    let name = name.or_else(|| match query_device_id(path) {
+        Ok(id) if ! id.name.is_empty() => Some(id.name),
+        _ => None,
+    });
+
+    let name = name.unwrap_or_else(|| match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    });
+    crate::domain::sanitize(&name)
+}
+
+/// Reads the name, vendor and product of the device at a path without keeping it open.
+fn query_device_id(path: &Path) -> Result<DeviceId, SystemError> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+        .open(path)?;
+
+    let mut evdev: *mut libevdev::libevdev = std::ptr::null_mut();
+    let res = unsafe {
+        libevdev::libevdev_new_from_fd(file.as_raw_fd(), &mut evdev)
+    };
+    if res < 0 {
+        return Err(SystemError::new(
+            format!("Failed to open a libevdev instance: {}.", path.to_string_lossy())
+        ));
+    }
+
+    let id = unsafe {
+        DeviceId {
+            name: CStr::from_ptr(libevdev::libevdev_get_name(evdev)).to_string_lossy().into_owned(),
+            vendor: libevdev::libevdev_get_id_vendor(evdev) as u16,
+            product: libevdev::libevdev_get_id_product(evdev) as u16,
+        }
+    };
+    unsafe {
+        libevdev::libevdev_free(evdev);
+    }
+
+    Ok(id)
+}
+
 /// Represents a name as reported by libevdev_get_name().
 pub type InputDeviceName = CString;
 
@@ -62,6 +175,12 @@ pub struct InputDevice {
     grab_mode: GrabMode,
     /// Whether the device is actually grabbed.
     grabbed: bool,
+    /// If Some, this device may only be grabbed while this condition is met.
+    grab_condition: Option<GrabCondition>,
+    /// False if the grab condition was not met the last time it was checked.
+    grab_allowed: bool,
+    /// While evsieve is paused by the pause command, devices do not get grabbed.
+    paused: bool,
 
     /// The domain, though not part of libevdev, is a handy tag we use
     /// to track which device emitted the events.
@@ -70,8 +189,41 @@ pub struct InputDevice {
     /// Maps (type, code) pairs to the last known value of said pair.
     state: HashMap<EventCode, EventValue>,
 
+    /// Maps (slot, ABS_MT_* code) pairs to the last known value of said pair. Only used if
+    /// this device supports ABS_MT_SLOT; the current slot is tracked in `state`.
+    slot_state: HashMap<(EventValue, EventCode), EventValue>,
+
     /// What should happen if this device disconnects.
     persist_mode: PersistMode,
+    /// Whether this device was found by matching a pattern, see PreInputDevice::from_pattern.
+    from_pattern: bool,
+    /// How often this device should be retried while it is disconnected.
+    reopen_backoff: Option<ReopenBackoff>,
+    /// If Some, this device is only used if its capabilities pass this filter.
+    cap_filter: Option<CapabilityFilter>,
+    /// The clock that was requested to timestamp the events of this device, if any.
+    clock: Option<EventClock>,
+    /// What to do when the kernel has dropped events of this device.
+    syn_dropped: SynDroppedPolicy,
+    /// If Some, latency statistics are being collected for this device.
+    latency_stats: Option<LatencyStats>,
+    /// How many events have been read from this device since it was opened, for --metrics.
+    events_read: u64,
+    /// Whether the initial state of this device shall be announced to the stream.
+    sync_state: bool,
+    /// Whether this device was opened with write access so force feedback can be forwarded to it.
+    forward_ff: bool,
+    /// Maps the domain of an output device and the id of an effect on that output device to
+    /// the id of the corresponding effect uploaded to this device.
+    ff_effect_ids: HashMap<(Domain, i16), i16>,
+    /// Whether LED changes made to the output devices shall be mirrored on this device.
+    forward_leds: bool,
+    /// Whether this device was opened with write access, which forwarding force feedback and
+    /// LED changes requires.
+    writable: bool,
+    /// The buffer that read_raw() reads into. Kept around so polling does not need to allocate
+    /// once the buffer has grown to the size of the largest burst of events.
+    raw_events: Vec<(EventCode, EventValue)>,
 }
 
 impl InputDevice {
@@ -86,6 +238,8 @@ impl InputDevice {
         // Open the file itself.
         let file = OpenOptions::new()
             .read(true)
+            // Force feedback effects can only be played and LEDs can only be set by writing to the device.
+            .write(pre_device.forward_ff || pre_device.forward_leds)
             // O_CLOEXEC is already set by default in the std source code, but I'm providing it
             // anyway to clearly signify we _need_ that flag.
             .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
@@ -102,8 +256,21 @@ impl InputDevice {
             ));
         }
 
+        if let Some(clock) = pre_device.clock {
+            let res = unsafe {
+                libevdev::libevdev_set_clock_id(evdev, clock.clock_id())
+            };
+            if res < 0 {
+                unsafe { libevdev::libevdev_free(evdev) };
+                return Err(SystemError::new(
+                    format!("Failed to set the clock of the input device: {}.", path.to_string_lossy())
+                ));
+            }
+        }
+
         let capabilities = unsafe { get_capabilities(evdev) };
         let state = unsafe { get_device_state(evdev, &capabilities) };
+        let slot_state = unsafe { get_slot_state(evdev, &capabilities) };
 
         // According to the documentation, libevdev_get_name() never returns a null pointer
         // but may return an empty string. We are not sure whether the return value is guaranteed
@@ -113,9 +280,28 @@ impl InputDevice {
         }.to_owned();
 
         Ok(InputDevice {
-            file, path, evdev, domain, capabilities, state, name,
-            grab_mode: pre_device.grab_mode, grabbed: false,
+            file, path, evdev, domain, capabilities, state, slot_state, name,
+            grab_mode: pre_device.grab_mode, grabbed: false, paused: false,
+            grab_condition: pre_device.grab_condition,
+            // The grab condition cannot be checked until the state of the program is known.
+            grab_allowed: pre_device.grab_condition.is_none(),
             persist_mode: pre_device.persist_mode,
+            from_pattern: pre_device.from_pattern,
+            reopen_backoff: pre_device.reopen_backoff,
+            cap_filter: pre_device.cap_filter,
+            clock: pre_device.clock,
+            syn_dropped: pre_device.syn_dropped,
+            sync_state: pre_device.sync_state,
+            forward_ff: pre_device.forward_ff,
+            ff_effect_ids: HashMap::new(),
+            forward_leds: pre_device.forward_leds,
+            writable: pre_device.forward_ff || pre_device.forward_leds,
+            events_read: 0,
+            raw_events: Vec::new(),
+            latency_stats: match pre_device.measure_latency {
+                true => Some(LatencyStats::new()),
+                false => None,
+            },
         })
     }
 
@@ -123,10 +309,33 @@ impl InputDevice {
         self.domain
     }
 
-    fn read_raw(&mut self) -> Result<Vec<(EventCode, EventValue)>, SystemError> {
+    /// Records how long evsieve took to process the events returned by the last call to `poll()`.
+    pub fn record_processing_time(&mut self, duration: Duration) {
+        if let Some(stats) = &mut self.latency_stats {
+            stats.record_processing_time(duration);
+        }
+    }
+
+    pub fn latency_stats(&self) -> Option<&LatencyStats> {
+        self.latency_stats.as_ref()
+    }
+
+    /// Returns false if the user asked for devices with these capabilities to not be used.
+    pub fn is_accepted_by_cap_filter(&self) -> bool {
+        match &self.cap_filter {
+            Some(filter) => filter.accepts(&self.capabilities),
+            None => true,
+        }
+    }
+
+    /// Reads all events that are available into `self.raw_events`. libevdev reads as many events
+    /// as it can fit in its own buffer with each read() on the device, so this takes a single
+    /// system call per burst of events in most cases.
+    fn read_raw(&mut self) -> Result<(), SystemError> {
         let mut event: MaybeUninit<libevdev::input_event> = MaybeUninit::uninit();
         let mut should_sync = false;
-        let mut events: Vec<(EventCode, EventValue)> = Vec::new();
+        let events = &mut self.raw_events;
+        events.clear();
 
         loop {
             let flags = match should_sync {
@@ -144,11 +353,35 @@ impl InputDevice {
 
             match res {
                 SUCCESS | SYNC => {
+                    // The first event returned with the SYNC status is the SYN_DROPPED event
+                    // itself, and libevdev expects us to read the events that resynchronize the
+                    // state of the device next. If we read in normal mode instead, libevdev
+                    // discards those events.
+                    if res == SYNC && ! should_sync {
+                        match self.syn_dropped {
+                            SynDroppedPolicy::Resync => {},
+                            SynDroppedPolicy::Log => logging::warning(format!(
+                                "Warning: the kernel dropped events of the device {} because evsieve did not read them fast enough. Resynchronizing the state of the device.",
+                                self.path.display()
+                            )),
+                            SynDroppedPolicy::Ignore => continue,
+                        }
+                    }
+
                     unsafe {
                         let event = event.assume_init();
                         let event_type = EventType::new(event.type_);
                         let event_code = EventCode::new(event_type, event.code);
                         events.push((event_code, event.value));
+
+                        if let Some(stats) = &mut self.latency_stats {
+                            if event_code == EventCode::SYN_REPORT {
+                                // The casts are necessary on platforms where time_t is 32 bits.
+                                #[allow(clippy::unnecessary_cast)]
+                                let (tv_sec, tv_usec) = (event.time.tv_sec as i64, event.time.tv_usec as i64);
+                                stats.record_report(tv_sec, tv_usec, self.clock == Some(EventClock::Monotonic));
+                            }
+                        }
                     }
 
                     should_sync = res == SYNC;
@@ -161,7 +394,7 @@ impl InputDevice {
             }
         }
 
-        Ok(events)
+        Ok(())
     }
 
     /// Given an event code and value, creates an `Event` that has all entries filled
@@ -174,6 +407,17 @@ impl InputDevice {
     /// to process real events, as well as from other parts in the code to simulate
     /// having received events.
     pub fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event {
+        if let Some(slot) = self.current_slot() {
+            if ecodes::is_abs_mt(code) {
+                let previous_value_mut: &mut EventValue = self.slot_state.entry((slot, code)).or_insert(0);
+                let previous_value: EventValue = *previous_value_mut;
+                *previous_value_mut = value;
+                return Event::new(
+                    code, value, previous_value, self.domain, Namespace::Input,
+                ).with_slot(Some(slot));
+            }
+        }
+
         let previous_value_mut: &mut EventValue = self.state.entry(code).or_insert(0);
         let previous_value: EventValue = *previous_value_mut;
         *previous_value_mut = value;
@@ -182,13 +426,34 @@ impl InputDevice {
         )
     }
 
+    /// Returns the multitouch slot that ABS_MT_* events currently apply to, or None if this
+    /// device does not use multitouch slots.
+    fn current_slot(&self) -> Option<EventValue> {
+        if self.capabilities.codes.contains(&EventCode::ABS_MT_SLOT) {
+            self.state.get(&EventCode::ABS_MT_SLOT).copied()
+        } else {
+            None
+        }
+    }
+
     /// Reads the raw events from the device and attached additional information such as the
     /// domain of this device and whatever value this event had the last time it was seen.
+    ///
+    /// ABS_MT_SLOT events are not returned. Instead, the ABS_MT_* events that follow them
+    /// have their slot property set.
     pub fn poll(&mut self) -> Result<Vec<Event>, SystemError> {
-        let events: Vec<Event> = self.read_raw()?
-            .into_iter()
-            .map(|(code, value)| self.synthesize_event(code, value))
-            .collect();
+        self.read_raw()?;
+        let raw_events = std::mem::take(&mut self.raw_events);
+        let mut events: Vec<Event> = Vec::with_capacity(raw_events.len());
+        for &(code, value) in &raw_events {
+            if code == EventCode::ABS_MT_SLOT && self.current_slot().is_some() {
+                self.state.insert(code, value);
+                continue;
+            }
+            events.push(self.synthesize_event(code, value));
+        }
+        self.raw_events = raw_events;
+        self.events_read += events.len() as u64;
 
         self.grab_if_desired()?;
         Ok(events)
@@ -200,12 +465,12 @@ impl InputDevice {
     /// Returns Err(SystemError) if we tried to grab the device, but failed because the OS didn't
     /// let us grab the device.
     pub fn grab_if_desired(&mut self) -> Result<(), SystemError> {
-        if self.grabbed {
+        if self.grabbed || ! self.grab_allowed || self.paused {
             return Ok(());
         }
         match self.grab_mode {
             GrabMode::None => Ok(()),
-            GrabMode::Force => self.grab(),
+            GrabMode::Force | GrabMode::Required => self.grab(),
             GrabMode::Auto => {
                 // Grab if no key is currently pressed.
                 if self.get_pressed_keys().count() > 0 {
@@ -216,7 +481,178 @@ impl InputDevice {
         }
     }
 
+    /// Whether evsieve must exit if this device cannot be grabbed, as requested by grab=required.
+    pub fn grab_is_required(&self) -> bool {
+        self.grab_mode == GrabMode::Required
+    }
+
+    /// Ungrabs this device while evsieve is paused, and grabs it again if desired once evsieve
+    /// gets resumed.
+    pub fn set_paused(&mut self, paused: bool) -> Result<(), SystemError> {
+        if paused == self.paused {
+            return Ok(());
+        }
+        self.paused = paused;
+        if paused && self.grabbed {
+            self.ungrab()
+        } else {
+            self.grab_if_desired()
+        }
+    }
+
+    /// Returns false if this device has been handed back to the system because its grab
+    /// condition is not met. Its events should then not enter the stream.
+    pub fn grab_allowed(&self) -> bool {
+        self.grab_allowed
+    }
+
+    /// Checks whether the grab condition of this device is met, and grabs or ungrabs the
+    /// device if that changed.
+    pub fn update_grab_condition(&mut self, state: &State) -> Result<(), SystemError> {
+        let grab_allowed = match &self.grab_condition {
+            Some(condition) => condition.is_met(state),
+            None => return Ok(()),
+        };
+        if grab_allowed == self.grab_allowed {
+            return Ok(());
+        }
+
+        self.grab_allowed = grab_allowed;
+        if grab_allowed {
+            self.grab_if_desired()
+        } else if self.grabbed {
+            self.ungrab()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Applies the clauses of a reloaded configuration to this device without closing it, so it
+    /// stays grabbed if it should still be grabbed. The clock= clause and the need for write access
+    /// cannot change while the device is open; those only take effect once it gets reopened.
+    pub fn reconfigure(&mut self, pre_device: PreInputDevice, state: &State) -> Result<(), SystemError> {
+        if (pre_device.forward_ff || pre_device.forward_leds) && ! self.writable {
+            logging::warning(format!("Warning: the device {} cannot forward force feedback or LED changes until evsieve is restarted.", self.path.display()));
+        }
+
+        self.domain = pre_device.domain;
+        self.grab_mode = pre_device.grab_mode;
+        self.grab_condition = pre_device.grab_condition;
+        self.grab_allowed = match &self.grab_condition {
+            Some(condition) => condition.is_met(state),
+            None => true,
+        };
+        self.persist_mode = pre_device.persist_mode;
+        self.from_pattern = pre_device.from_pattern;
+        self.reopen_backoff = pre_device.reopen_backoff;
+        self.cap_filter = pre_device.cap_filter;
+        self.syn_dropped = pre_device.syn_dropped;
+        self.sync_state = pre_device.sync_state;
+        self.forward_ff = pre_device.forward_ff && self.writable;
+        self.forward_leds = pre_device.forward_leds && self.writable;
+        // The effects were uploaded on behalf of output devices that no longer have the same domain.
+        self.ff_effect_ids.clear();
+        if pre_device.measure_latency != self.latency_stats.is_some() {
+            self.latency_stats = match pre_device.measure_latency {
+                true => Some(LatencyStats::new()),
+                false => None,
+            };
+        }
+
+        let should_be_grabbed = self.grab_allowed && self.grab_mode != GrabMode::None;
+        if self.grabbed && ! should_be_grabbed {
+            self.ungrab()?;
+        }
+        self.grab_if_desired()
+    }
+
     /// Returns an iterator of all EV_KEY codes that are currently pressed.
+    /// If the sync-state flag was specified, returns events for all keys that were already held
+    /// and all switches that were already on when this device was opened, as if they changed
+    /// from zero just now. Returns nothing otherwise.
+    pub fn synthesize_initial_events(&self) -> Vec<Event> {
+        if ! self.sync_state {
+            return Vec::new();
+        }
+        initial_events(&self.state, self.domain)
+    }
+
+    /// Does whatever a program requested the output device with the given domain to do with
+    /// respect to force feedback on this device instead. Returns zero on success or a negative
+    /// error code otherwise.
+    pub fn forward_ff(&mut self, output_domain: Domain, request: &FfRequest) -> i32 {
+        let fd = self.file.as_raw_fd();
+        match request {
+            FfRequest::Upload(upload) => {
+                let output_id = upload.effect.id;
+                let mut effect = upload.effect;
+                // An id of -1 tells the kernel to upload a new effect rather than update one.
+                effect.id = *self.ff_effect_ids.get(&(output_domain, output_id)).unwrap_or(&-1);
+                if unsafe { libc::ioctl(fd, ff::EVIOCSFF, &mut effect) } < 0 {
+                    return -std::io::Error::last_os_error().raw_os_error().unwrap_or(libc::EIO);
+                }
+                self.ff_effect_ids.insert((output_domain, output_id), effect.id);
+                0
+            },
+            FfRequest::Erase(erase) => {
+                let input_id = match self.ff_effect_ids.remove(&(output_domain, erase.effect_id as i16)) {
+                    Some(id) => id,
+                    None => return 0,
+                };
+                if unsafe { libc::ioctl(fd, ff::EVIOCRMFF, input_id as libc::c_int) } < 0 {
+                    return -std::io::Error::last_os_error().raw_os_error().unwrap_or(libc::EIO);
+                }
+                0
+            },
+            FfRequest::Event(code, value) => {
+                let input_code = if code.code() >= ff::FF_GAIN {
+                    code.code()
+                } else {
+                    match self.ff_effect_ids.get(&(output_domain, code.code() as i16)) {
+                        Some(&id) => id as u16,
+                        None => return 0,
+                    }
+                };
+                match self.write_raw_event(ecodes::EV_FF, input_code, *value) {
+                    Ok(()) => 0,
+                    Err(error) => -error.raw_os_error().unwrap_or(libc::EIO),
+                }
+            },
+        }
+    }
+
+    /// Whether LED changes made to the output devices shall be mirrored on this device.
+    pub fn forwards_leds(&self) -> bool {
+        self.forward_leds
+    }
+
+    /// Turns an LED of this device on or off. Does nothing if this device does not have that LED.
+    pub fn set_led(&mut self, code: EventCode, value: EventValue) -> Result<(), SystemError> {
+        if ! self.capabilities.codes.contains(&code) {
+            return Ok(());
+        }
+        self.write_raw_event(code.ev_type().into(), code.code(), value)
+            .and_then(|()| self.write_raw_event(ecodes::EV_SYN, ecodes::SYN_REPORT, 0))
+            .map_err(SystemError::from)
+            .with_context_of(|| format!("While setting the LEDs of {}:", self.path.display()))
+    }
+
+    /// Writes an event to the device itself, as opposed to an output device.
+    fn write_raw_event(&self, ev_type: u16, code: u16, value: EventValue) -> Result<(), std::io::Error> {
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = ev_type;
+        event.code = code;
+        event.value = value;
+        let size = std::mem::size_of::<libc::input_event>();
+        let res = unsafe {
+            libc::write(self.file.as_raw_fd(), &event as *const libc::input_event as *const libc::c_void, size)
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
     pub fn get_pressed_keys(&self) -> impl Iterator<Item=EventCode> + '_ {
         self.state.iter()
             .filter(|(code, value)| code.ev_type().is_key() && **value > 0)
@@ -239,7 +675,7 @@ impl InputDevice {
 
     fn ungrab(&mut self) -> Result<(), SystemError> {
         let res = unsafe {
-            libevdev::libevdev_grab(self.evdev, libevdev::libevdev_grab_mode_LIBEVDEV_GRAB)
+            libevdev::libevdev_grab(self.evdev, libevdev::libevdev_grab_mode_LIBEVDEV_UNGRAB)
         };
         if res < 0 {
             Err(SystemError::new(
@@ -251,6 +687,16 @@ impl InputDevice {
         }
     }
 
+    /// Returns the names of all keys that are currently held down on this device.
+    pub fn held_keys(&self) -> Vec<String> {
+        let mut keys: Vec<EventCode> = self.state.iter()
+            .filter(|(code, value)| code.ev_type().is_key() && **value != 0)
+            .map(|(code, _)| *code)
+            .collect();
+        keys.sort_by_key(|code| code.code());
+        keys.into_iter().map(|code| crate::ecodes::event_name(code).into_owned()).collect()
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
@@ -263,22 +709,50 @@ impl InputDevice {
         &self.name
     }
 
+    pub fn is_grabbed(&self) -> bool {
+        self.grabbed
+    }
+
+    pub fn events_read(&self) -> u64 {
+        self.events_read
+    }
+
     pub fn persist_mode(&self) -> PersistMode {
         self.persist_mode
     }
 
+    pub fn is_from_pattern(&self) -> bool {
+        self.from_pattern
+    }
+
+    /// The device number of the opened event device. Unlike its path, it is the same regardless
+    /// of through which link the device was opened.
+    pub fn device_number(&self) -> Option<u64> {
+        self.file.metadata().ok().map(|metadata| metadata.rdev())
+    }
+
     // Closes the device and returns a blueprint from which it can be reopened.
     pub fn to_blueprint(self) -> Blueprint {
-        Blueprint {
-            capabilities: self.capabilities.clone(),
-            name: self.name.clone(),
-            pre_device: PreInputDevice {
+        Blueprint::new(
+            PreInputDevice {
                 path: self.path.clone(),
                 grab_mode: self.grab_mode,
                 domain: self.domain,
                 persist_mode: self.persist_mode,
+                from_pattern: self.from_pattern,
+                reopen_backoff: self.reopen_backoff,
+                grab_condition: self.grab_condition,
+                cap_filter: self.cap_filter.clone(),
+                clock: self.clock,
+                syn_dropped: self.syn_dropped,
+                measure_latency: self.latency_stats.is_some(),
+                sync_state: self.sync_state,
+                forward_ff: self.forward_ff,
+                forward_leds: self.forward_leds,
             },
-        }
+            self.capabilities.clone(),
+            self.name.clone(),
+        )
     }
 }
 
@@ -333,13 +807,16 @@ unsafe fn get_capabilities(evdev: *mut libevdev::libevdev) -> Capabilities {
 unsafe fn get_device_state(evdev: *mut libevdev::libevdev, capabilities: &Capabilities) -> HashMap<EventCode, EventValue> {
     let mut device_state: HashMap<EventCode, EventValue> = HashMap::new();
     for &code in &capabilities.codes {
-        // ISSUE: ABS_MT support
-        if ! ecodes::is_abs_mt(code) {
+        if code == EventCode::ABS_MT_SLOT {
+            let value: i32 = libevdev::libevdev_get_current_slot(evdev);
+            device_state.insert(code, value);
+        } else if ! ecodes::is_abs_mt(code) {
             let value: i32 = libevdev::libevdev_get_event_value(evdev, code.ev_type().into(), code.code() as u32);
             device_state.insert(code, value);
         } else {
-            // The return value of libevdev_get_event_value() for ABS_MT_* is undefined. Until we
-            // get proper ABS_MT support, we'll use an arbitrary placeholder value.
+            // The return value of libevdev_get_event_value() for ABS_MT_* is undefined. If the
+            // device does not support slots, we use an arbitrary placeholder value. Otherwise,
+            // the actual values are tracked per slot by get_slot_state().
             let value = match capabilities.abs_info.get(&code) {
                 Some(abs_info) => 
                     EventValue::checked_add(abs_info.min_value, abs_info.max_value)
@@ -353,6 +830,50 @@ unsafe fn get_device_state(evdev: *mut libevdev::libevdev, capabilities: &Capabi
     device_state
 }
 
+/// # Safety
+/// Exhibits undefined behaviour if evdev is not a valid pointer or the capabilities are invalid.
+unsafe fn get_slot_state(evdev: *mut libevdev::libevdev, capabilities: &Capabilities) -> HashMap<(EventValue, EventCode), EventValue> {
+    let mut slot_state: HashMap<(EventValue, EventCode), EventValue> = HashMap::new();
+    if ! capabilities.codes.contains(&EventCode::ABS_MT_SLOT) {
+        return slot_state;
+    }
+
+    let num_slots: i32 = libevdev::libevdev_get_num_slots(evdev);
+    for slot in 0 .. num_slots {
+        for &code in &capabilities.codes {
+            if ecodes::is_abs_mt(code) && code != EventCode::ABS_MT_SLOT {
+                let value: i32 = libevdev::libevdev_get_slot_value(evdev, slot as u32, code.code() as u32);
+                slot_state.insert((slot, code), value);
+            }
+        }
+    }
+    slot_state
+}
+
+impl EventSource for InputDevice {
+    fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event {
+        InputDevice::synthesize_event(self, code, value)
+    }
+
+    fn pressed_keys(&self) -> Vec<EventCode> {
+        self.get_pressed_keys().collect()
+    }
+}
+
+/// Returns events for all keys that are held and all switches that are on according to `state`,
+/// as if they changed from zero just now.
+fn initial_events(state: &HashMap<EventCode, EventValue>, domain: Domain) -> Vec<Event> {
+    let mut events: Vec<Event> = state.iter()
+        .filter(|(code, value)| (code.ev_type().is_key() || code.ev_type().is_sw()) && **value != 0)
+        .map(|(&code, &value)| Event::new(
+            code, value, 0, domain, Namespace::Input,
+        ))
+        .collect();
+    // Make the order deterministic.
+    events.sort_by_key(|event| (u16::from(event.code.ev_type()), event.code.code()));
+    events
+}
+
 impl AsRawFd for InputDevice {
     fn as_raw_fd(&self) -> RawFd {
         self.file.as_raw_fd()
@@ -374,3 +895,22 @@ impl Drop for InputDevice {
         }
     }
 }
+
+#[test]
+fn unittest() {
+    let domain = crate::domain::get_unique_domain();
+    let key_a = EventCode::new(EventType::KEY, 30);
+    let key_b = EventCode::new(EventType::KEY, 48);
+    let sw_lid = EventCode::new(EventType::SW, 0);
+    let abs_x = EventCode::new(EventType::ABS, 0);
+    let state: HashMap<EventCode, EventValue> = vec![
+        (key_b, 1), (key_a, 2), (EventCode::new(EventType::KEY, 31), 0), (sw_lid, 1), (abs_x, 100),
+    ].into_iter().collect();
+
+    // Only held keys and switches that are on get synthesized, in a deterministic order.
+    let events: Vec<(EventCode, EventValue, EventValue)> = initial_events(&state, domain).iter()
+        .map(|event| (event.code, event.value, event.previous_value))
+        .collect();
+    assert_eq!(events, vec![(key_a, 2, 0), (key_b, 1, 0), (sw_lid, 1, 0)]);
+    assert!(initial_events(&HashMap::new(), domain).is_empty());
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Reads events from legacy joystick devices like /dev/input/js0, which use the joydev protocol
+//! instead of the evdev protocol. The joydev protocol numbers the buttons and axes of a device
+//! consecutively; the kernel tells us which evdev codes those numbers correspond to.
+
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use crate::capability::{AbsInfo, AbsMeta, Capabilities};
+use crate::domain::Domain;
+use crate::ecodes;
+use crate::error::SystemError;
+use crate::event::{Event, EventCode, EventType, EventValue};
+use crate::io::fd::HasFixedFd;
+use crate::io::source::{EventSource, SourceState};
+
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+/// Set on the events the kernel sends upon opening the device to tell its initial state.
+const JS_EVENT_INIT: u8 = 0x80;
+
+/// The range that joydev scales all axes to.
+const JS_AXIS_MAX: EventValue = 32767;
+
+/// _IOR('j', 0x11, __u8) from linux/joystick.h.
+const JSIOCGAXES: libc::c_ulong = 0x8001_6a11;
+/// _IOR('j', 0x12, __u8)
+const JSIOCGBUTTONS: libc::c_ulong = 0x8001_6a12;
+/// _IOR('j', 0x32, __u8[ABS_CNT])
+const JSIOCGAXMAP: libc::c_ulong = 0x8040_6a32;
+/// _IOR('j', 0x34, __u16[KEY_MAX - BTN_MISC + 1])
+const JSIOCGBTNMAP: libc::c_ulong = 0x8400_6a34;
+const ABS_CNT: usize = 0x40;
+const BTN_MAP_SIZE: usize = 0x200;
+/// Used for numbering buttons in case the kernel does not tell us their codes.
+const BTN_JOYSTICK: u16 = 0x120;
+
+/// The size of struct js_event, which consists of a u32 timestamp in milliseconds, an i16 value,
+/// and u8 type and number. The timestamp is not interesting to us.
+const JS_EVENT_SIZE: usize = 8;
+
+struct JsEvent {
+    value: i16,
+    type_: u8,
+    number: u8,
+}
+
+pub struct JoystickDevice {
+    file: File,
+    path: PathBuf,
+    /// Maps the numbers of the axes to their event codes.
+    axes: Vec<EventCode>,
+    /// Maps the numbers of the buttons to their event codes.
+    buttons: Vec<EventCode>,
+    capabilities: Capabilities,
+    /// Maps (type, code) pairs to the last value they were emitted with.
+    state: SourceState,
+}
+
+impl JoystickDevice {
+    pub fn open(path: PathBuf, domain: Domain) -> Result<JoystickDevice, SystemError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(&path)?;
+        let fd = file.as_raw_fd();
+
+        let mut num_axes: u8 = 0;
+        let mut num_buttons: u8 = 0;
+        if unsafe { libc::ioctl(fd, JSIOCGAXES, &mut num_axes) } < 0
+            || unsafe { libc::ioctl(fd, JSIOCGBUTTONS, &mut num_buttons) } < 0 {
+            return Err(SystemError::os_with_context("While querying the number of axes and buttons of the joystick:"));
+        }
+
+        let mut axis_map = [0u8; ABS_CNT];
+        let axes: Vec<EventCode> = match unsafe { libc::ioctl(fd, JSIOCGAXMAP, axis_map.as_mut_ptr()) } {
+            res if res >= 0 => axis_map[.. num_axes as usize].iter()
+                .map(|&code| EventCode::new(EventType::ABS, code as u16))
+                .collect(),
+            _ => (0 .. num_axes as u16)
+                .map(|number| EventCode::new(EventType::ABS, number))
+                .collect(),
+        };
+        let mut button_map = [0u16; BTN_MAP_SIZE];
+        let buttons: Vec<EventCode> = match unsafe { libc::ioctl(fd, JSIOCGBTNMAP, button_map.as_mut_ptr()) } {
+            res if res >= 0 => button_map[.. num_buttons as usize].iter()
+                .map(|&code| EventCode::new(EventType::KEY, code))
+                .collect(),
+            _ => (0 .. num_buttons as u16)
+                .map(|number| EventCode::new(EventType::KEY, BTN_JOYSTICK + number))
+                .collect(),
+        };
+
+        let mut capabilities = Capabilities::new();
+        for &code in &axes {
+            capabilities.codes.insert(code);
+            capabilities.abs_info.insert(code, AbsInfo {
+                min_value: -JS_AXIS_MAX,
+                max_value: JS_AXIS_MAX,
+                meta: AbsMeta { fuzz: 0, flat: 0, resolution: 0, value: 0 },
+            });
+        }
+        capabilities.codes.extend(buttons.iter().copied());
+        capabilities.codes.insert(EventCode::new(EventType::SYN, ecodes::SYN_REPORT));
+
+        Ok(JoystickDevice {
+            file, path, axes, buttons, capabilities,
+            state: SourceState::new(domain),
+        })
+    }
+
+    /// Reads all available events from the device.
+    pub fn poll(&mut self) -> Result<Vec<Event>, SystemError> {
+        let mut events: Vec<Event> = Vec::new();
+        let mut buffer = [0u8; JS_EVENT_SIZE * 64];
+        loop {
+            let num_bytes = match self.file.read(&mut buffer) {
+                Ok(0) => return Err(SystemError::new("The device has been disconnected.")),
+                Ok(num_bytes) => num_bytes,
+                Err(error) => match error.kind() {
+                    std::io::ErrorKind::WouldBlock => break,
+                    std::io::ErrorKind::Interrupted => continue,
+                    _ => return Err(error.into()),
+                },
+            };
+
+            for chunk in buffer[.. num_bytes].chunks_exact(JS_EVENT_SIZE) {
+                let js_event = decode_js_event(chunk);
+                let code = match js_event.type_ & !JS_EVENT_INIT {
+                    JS_EVENT_BUTTON => self.buttons.get(js_event.number as usize),
+                    JS_EVENT_AXIS => self.axes.get(js_event.number as usize),
+                    _ => None,
+                };
+                let code = match code {
+                    Some(&code) => code,
+                    None => continue,
+                };
+                let value = js_event.value as EventValue;
+
+                // The initial state is not announced to the stream, just like with event devices.
+                if js_event.type_ & JS_EVENT_INIT != 0 {
+                    self.state.set_value(code, value);
+                    continue;
+                }
+                events.push(self.state.synthesize_event(code, value));
+            }
+        }
+
+        // Joysticks do not report when a batch of events ends, so we consider everything read
+        // at once to belong together.
+        if ! events.is_empty() {
+            let syn_event = self.state.synthesize_event(EventCode::new(EventType::SYN, ecodes::SYN_REPORT), 0);
+            events.push(syn_event);
+        }
+        Ok(events)
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.state.domain()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl EventSource for JoystickDevice {
+    fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event {
+        self.state.synthesize_event(code, value)
+    }
+
+    fn pressed_keys(&self) -> Vec<EventCode> {
+        self.state.pressed_keys()
+    }
+}
+
+impl AsRawFd for JoystickDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for JoystickDevice {}
+
+fn decode_js_event(bytes: &[u8]) -> JsEvent {
+    JsEvent {
+        value: i16::from_ne_bytes([bytes[4], bytes[5]]),
+        type_: bytes[6],
+        number: bytes[7],
+    }
+}
+
+#[test]
+fn unittest() {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&1000u32.to_ne_bytes());
+    bytes.extend_from_slice(&(-300i16).to_ne_bytes());
+    bytes.push(JS_EVENT_AXIS | JS_EVENT_INIT);
+    bytes.push(3);
+    let event = decode_js_event(&bytes);
+    assert_eq!(event.value, -300);
+    assert_eq!(event.type_ & !JS_EVENT_INIT, JS_EVENT_AXIS);
+    assert_eq!(event.number, 3);
+}
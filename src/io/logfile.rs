@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! A file that lines of text get appended to, such as the output of --print arguments. If the file
+//! grows beyond a maximum size, it gets rotated: the file gets renamed to PATH.1, the old PATH.1
+//! gets renamed to PATH.2, and so on.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use crate::error::{Context, SystemError};
+
+pub struct LogFile {
+    path: PathBuf,
+    /// If Some, the file gets rotated before it would grow beyond this amount of bytes.
+    max_size: Option<u64>,
+    /// How many rotated files are kept around. If zero, the file simply gets truncated.
+    rotate: u32,
+    /// None if the file could not be reopened. We try again at the next line.
+    file: Option<File>,
+    /// The current size of the file in bytes.
+    size: u64,
+    /// Set after a write fails, so we don't flood the user with the same error for every line.
+    has_failed: bool,
+}
+
+impl LogFile {
+    pub fn open(path: PathBuf, max_size: Option<u64>, rotate: u32) -> Result<LogFile, SystemError> {
+        let mut log_file = LogFile {
+            path, max_size, rotate,
+            file: None,
+            size: 0,
+            has_failed: false,
+        };
+        log_file.try_open()
+            .with_context(format!("While opening the file \"{}\":", log_file.path.display()))?;
+        Ok(log_file)
+    }
+
+    fn try_open(&mut self) -> Result<(), SystemError> {
+        self.file = None;
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Closes and reopens the file, in case some other program like logrotate moved it.
+    pub fn reopen(&mut self) {
+        self.has_failed = false;
+        let result = self.try_open();
+        self.report(result);
+    }
+
+    /// Appends a line to the file. A newline is added automatically.
+    pub fn write_line(&mut self, line: &str) {
+        let length = line.len() as u64 + 1;
+        if let Some(max_size) = self.max_size {
+            if self.size > 0 && self.size + length > max_size {
+                let result = self.rotate_files();
+                self.report(result);
+            }
+        }
+        if self.file.is_none() {
+            let result = self.try_open();
+            self.report(result);
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let result = writeln!(file, "{}", line).map_err(SystemError::from);
+        if result.is_ok() {
+            self.size += length;
+            self.has_failed = false;
+        }
+        self.report(result);
+    }
+
+    fn rotate_files(&mut self) -> Result<(), SystemError> {
+        self.file = None;
+        if self.rotate == 0 {
+            File::create(&self.path)?;
+        } else {
+            for index in (1 .. self.rotate).rev() {
+                let source = self.rotated_path(index);
+                if source.exists() {
+                    std::fs::rename(&source, self.rotated_path(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.try_open()
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn report(&mut self, result: Result<(), SystemError>) {
+        if let Err(error) = result {
+            if ! self.has_failed {
+                self.has_failed = true;
+                error.with_context(format!("While writing to \"{}\":", self.path.display()))
+                    .print_err();
+            }
+        }
+    }
+}
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Sends events to and receives events from other instances of evsieve over the network. The events
+//! are encoded in the format described in the serialize module.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::capability::Capabilities;
+use crate::domain::Domain;
+use crate::error::SystemError;
+use crate::event::{Event, EventCode, EventValue};
+use crate::io::fd::HasFixedFd;
+use crate::io::serialize::{self, TimedEvent};
+use crate::io::source::{EventSource, SourceState};
+use crate::logging;
+use crate::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NetworkProtocol {
+    Tcp,
+    Udp,
+}
+
+/// Waits for other evsieves to connect over TCP. Every accepted connection becomes a NetworkInput.
+pub struct NetworkListener {
+    listener: TcpListener,
+    address: String,
+    domain: Domain,
+}
+
+impl NetworkListener {
+    pub fn bind(address: String, domain: Domain) -> Result<NetworkListener, SystemError> {
+        let listener = TcpListener::bind(&address)?;
+        listener.set_nonblocking(true)?;
+        Ok(NetworkListener { listener, address, domain })
+    }
+
+    /// Returns None if no connection was waiting to be accepted after all.
+    pub fn accept(&mut self) -> Result<Option<NetworkInput>, SystemError> {
+        let (stream, peer_address) = match self.listener.accept() {
+            Ok(result) => result,
+            Err(error) => match error.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted => return Ok(None),
+                _ => return Err(error.into()),
+            },
+        };
+        stream.set_nonblocking(true)?;
+        // Events should arrive as soon as possible rather than get buffered.
+        stream.set_nodelay(true)?;
+        Ok(Some(NetworkInput::new(Socket::Tcp(stream), peer_address.to_string(), self.domain)))
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+impl AsRawFd for NetworkListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for NetworkListener {}
+
+enum Socket {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+pub struct NetworkInput {
+    socket: Socket,
+    /// The address of the peer for TCP connections, or the local address for UDP sockets.
+    description: String,
+    /// Bytes that have been received but do not make up a whole event yet.
+    buffer: Vec<u8>,
+    /// Maps (type, code) pairs to the last value they were received with.
+    state: SourceState,
+    /// Since the remote side does not tell us its capabilities, we assume it is capable of
+    /// exactly those events that it has sent us so far.
+    capabilities: Capabilities,
+    /// Whether the capabilities have changed since the last call to `take_capabilities_changed()`.
+    capabilities_changed: bool,
+}
+
+impl NetworkInput {
+    fn new(socket: Socket, description: String, domain: Domain) -> NetworkInput {
+        NetworkInput {
+            socket, description,
+            buffer: Vec::new(),
+            state: SourceState::new(domain),
+            capabilities: Capabilities::new(),
+            capabilities_changed: false,
+        }
+    }
+
+    pub fn bind_udp(address: String, domain: Domain) -> Result<NetworkInput, SystemError> {
+        let socket = UdpSocket::bind(&address)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetworkInput::new(Socket::Udp(socket), address, domain))
+    }
+
+    pub fn poll(&mut self) -> Result<Vec<Event>, SystemError> {
+        let mut buf = [0u8; 65536];
+        let received = match &mut self.socket {
+            Socket::Tcp(stream) => stream.read(&mut buf),
+            Socket::Udp(socket) => socket.recv(&mut buf),
+        };
+        let num_bytes = match received {
+            Ok(0) if self.is_tcp() => return Err(SystemError::new("The connection has been closed.")),
+            Ok(num_bytes) => num_bytes,
+            Err(error) => match error.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted => return Ok(Vec::new()),
+                _ => return Err(error.into()),
+            },
+        };
+
+        let data: Vec<u8> = match self.socket {
+            Socket::Tcp(_) => {
+                // TCP is a stream protocol, so events may be split over several reads.
+                self.buffer.extend_from_slice(&buf[.. num_bytes]);
+                let num_whole_bytes = self.buffer.len() - self.buffer.len() % serialize::EVENT_SIZE;
+                self.buffer.drain(.. num_whole_bytes).collect()
+            },
+            Socket::Udp(_) => {
+                if num_bytes % serialize::EVENT_SIZE > 0 {
+                    logging::warning(format!("Warning: received a malformed datagram on {}.", self.description));
+                    return Ok(Vec::new());
+                }
+                buf[.. num_bytes].to_vec()
+            },
+        };
+
+        let decoded: Result<Vec<TimedEvent>, SystemError> = data.chunks_exact(serialize::EVENT_SIZE)
+            .map(serialize::decode_event)
+            .collect();
+        let decoded = match decoded {
+            Ok(decoded) => decoded,
+            // A single bad datagram must not close a socket that other datagrams keep arriving on.
+            Err(_) if ! self.is_tcp() => {
+                logging::warning(format!("Warning: received a datagram with an invalid event on {}.", self.description));
+                return Ok(Vec::new());
+            },
+            Err(error) => return Err(error),
+        };
+
+        let mut events: Vec<Event> = Vec::new();
+        for event in decoded {
+            if self.capabilities.add_observed_event(event.code, event.value) {
+                self.capabilities_changed = true;
+            }
+            events.push(self.state.synthesize_event(event.code, event.value));
+        }
+        Ok(events)
+    }
+
+    fn is_tcp(&self) -> bool {
+        match self.socket {
+            Socket::Tcp(_) => true,
+            Socket::Udp(_) => false,
+        }
+    }
+
+    /// Returns whether this input has been discovered to be capable of new events since the
+    /// last time this function was called.
+    pub fn take_capabilities_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.capabilities_changed, false)
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.state.domain()
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl EventSource for NetworkInput {
+    fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event {
+        self.state.synthesize_event(code, value)
+    }
+
+    fn pressed_keys(&self) -> Vec<EventCode> {
+        self.state.pressed_keys()
+    }
+}
+
+impl AsRawFd for NetworkInput {
+    fn as_raw_fd(&self) -> RawFd {
+        match &self.socket {
+            Socket::Tcp(stream) => stream.as_raw_fd(),
+            Socket::Udp(socket) => socket.as_raw_fd(),
+        }
+    }
+}
+unsafe impl HasFixedFd for NetworkInput {}
+
+/// How long to wait between attempts to reconnect after the connection has been lost.
+const RECONNECT_INTERVAL_SECS: u64 = 1;
+/// How long a single attempt to connect may block the event loop.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+/// How long writing to a connection may block before we assume the peer is gone.
+const WRITE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Sends events to another evsieve (or any other program) over TCP. If the connection is lost,
+/// events are dropped until a new connection has been made.
+pub struct NetworkOutput {
+    address: String,
+    stream: Option<TcpStream>,
+    /// When the last attempt to (re)connect was made.
+    last_attempt: Instant,
+}
+
+impl NetworkOutput {
+    pub fn connect(address: String) -> Result<NetworkOutput, SystemError> {
+        let stream = connect_tcp(&address)?;
+        Ok(NetworkOutput {
+            address,
+            stream: Some(stream),
+            last_attempt: Instant::now(),
+        })
+    }
+
+    /// Sends the given bytes. The bytes must consist of whole events: if the connection gets lost
+    /// halfway through, the rest of the bytes are dropped rather than sent over a future connection.
+    pub fn send(&mut self, bytes: &[u8]) -> Result<(), SystemError> {
+        if self.stream.is_none() {
+            let now = Instant::now();
+            let may_reconnect = match now.checked_duration_since(self.last_attempt) {
+                Some(elapsed) => elapsed >= Duration::from_secs(RECONNECT_INTERVAL_SECS),
+                None => true,
+            };
+            if ! may_reconnect {
+                return Err(SystemError::new("Not connected."));
+            }
+            self.last_attempt = now;
+            self.stream = Some(connect_tcp(&self.address)?);
+        }
+
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => return Err(SystemError::new("Not connected.")),
+        };
+        let result = stream.write_all(bytes);
+        if result.is_err() {
+            self.stream = None;
+            self.last_attempt = Instant::now();
+        }
+        Ok(result?)
+    }
+}
+
+fn connect_tcp(address: &str) -> Result<TcpStream, SystemError> {
+    let mut last_error: Option<std::io::Error> = None;
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_nodelay(true)?;
+                stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                return Ok(stream);
+            },
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(match last_error {
+        Some(error) => error.into(),
+        None => SystemError::new(format!("The address \"{}\" could not be resolved.", address)),
+    })
+}
+
+#[test]
+fn unittest() {
+    use crate::event::EventType;
+    let mut input = NetworkInput::bind_udp("127.0.0.1:0".to_owned(), crate::domain::get_unique_domain()).unwrap();
+    let address = match &input.socket {
+        Socket::Udp(socket) => socket.local_addr().unwrap(),
+        Socket::Tcp(_) => unreachable!(),
+    };
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let key_a = EventCode::new(EventType::KEY, 30);
+    let encode = |code, value| serialize::encode_event(TimedEvent { time_us: 0, code, value });
+
+    // Malformed datagrams and datagrams with invalid events get dropped without breaking the socket.
+    sender.send_to(&encode(key_a, 1)[.. serialize::EVENT_SIZE - 1], address).unwrap();
+    assert!(input.poll().unwrap().is_empty());
+    let mut invalid_event = encode(key_a, 1);
+    // The type is followed by a u16 code and an i32 value at the end of an input_event.
+    invalid_event[serialize::EVENT_SIZE - 8 .. serialize::EVENT_SIZE - 6]
+        .copy_from_slice(&(crate::ecodes::EV_MAX + 1).to_ne_bytes());
+    let mut datagram = encode(key_a, 1).to_vec();
+    datagram.extend_from_slice(&invalid_event);
+    sender.send_to(&datagram, address).unwrap();
+    assert!(input.poll().unwrap().is_empty());
+    assert!(input.pressed_keys().is_empty());
+
+    sender.send_to(&encode(key_a, 1), address).unwrap();
+    let events = input.poll().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].code, events[0].value), (key_a, 1));
+    assert!(input.take_capabilities_changed());
+}
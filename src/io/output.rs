@@ -5,52 +5,258 @@ use std::io;
 use std::fs;
 use std::ffi::{CString};
 use std::ptr;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path};
 use std::path::PathBuf;
 use std::fmt::Write;
 use crate::event::EventType;
 use crate::bindings::libevdev;
-use crate::capability::{Capability, Capabilities};
-use crate::event::Event;
+use crate::capability::{Capability, Capabilities, RepeatInfo};
+use crate::event::{Event, EventCode, EventValue, Namespace};
 use crate::domain::Domain;
 use crate::ecodes;
-use crate::error::{InternalError, RuntimeError, SystemError, Context};
-use crate::predevice::{PreOutputDevice, RepeatMode};
+use crate::error::{ArgumentError, InternalError, RuntimeError, SystemError, Context};
+use crate::predevice::{DeviceClass, NodePermissions, OutputIdentity, PreOutputDevice, RepeatMode, SynMode};
+use crate::io::fd::OwnedFd;
+use crate::io::epoll::PendingWrite;
+use crate::io::ff::{self, FfRequest};
+use crate::io::record::{Recorder, RecordTarget};
+use crate::io::serialize::quote_json;
+use crate::metrics;
+use crate::time::{Duration, Instant};
+use crate::logging::{self, LogLevel};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, RawFd};
 
 pub struct OutputSystem {
     pre_devices: Vec<PreOutputDevice>,
     devices: HashMap<Domain, OutputDevice>,
+    /// Outputs that record their events to a file or send them over the network instead of writing
+    /// them to a virtual device.
+    recorders: HashMap<Domain, Recorder>,
+    /// The capabilities of output devices with the defer clause that do not currently exist.
+    /// They get created when the first event is routed to them.
+    deferred: HashMap<Domain, Capabilities>,
+    /// For deferred output devices that exist and have an idle timeout, the moment at which
+    /// they should be destroyed unless more events get routed to them, and their idle timeout.
+    idle_deadlines: HashMap<Domain, (Instant, Duration)>,
+    /// For outputs with the split-by-type flag, maps the domain that events are sent to and the
+    /// class of the event to the domain of the device that should receive them.
+    splits: HashMap<(Domain, DeviceClass), Domain>,
+    /// How many events have been routed to each output, for statistics.
+    routed_counts: HashMap<Domain, u64>,
+    /// How many events routed to each output could not be written, for --metrics.
+    dropped_counts: HashMap<Domain, u64>,
+    /// If some output device forwards force feedback or LED changes, this epoll becomes readable
+    /// whenever any such device has received something to forward.
+    feedback_epoll: Option<OwnedFd>,
+    /// If true, the reports written to the output devices are not handed to the kernel right
+    /// away, but get collected by `pending_writes()` instead.
+    batch_writes: bool,
+}
+
+/// Something that a program wrote to an output device that should be forwarded to an input device.
+pub enum Feedback {
+    /// A force feedback request that should be forwarded to the input device at the given path.
+    ForceFeedback(PathBuf, FfRequest),
+    /// An LED was turned on or off, which should be mirrored on input devices with the
+    /// forward-leds flag.
+    Led(EventCode, EventValue),
+    /// A program started or stopped playing a force feedback effect on an output device with
+    /// the ff= clause, or changed an LED of an output device with the leds= clause. The event has
+    /// a code like ff:rumble or led:capsl and the amount of repetitions or the LED state as value.
+    Effect(Event),
 }
 
 impl OutputSystem {
+    /// Creates all output devices. If `previous` is Some, the configuration is being reloaded
+    /// and the devices of the previous system that would be created the same way are moved to the
+    /// new system instead of being created anew, provided that nothing fails along the way.
     pub fn create(
             pre_devices: Vec<PreOutputDevice>,
-            capabilities: Vec<Capability>
+            capabilities: Vec<Capability>,
+            mut previous: Option<&mut OutputSystem>,
     ) -> Result<OutputSystem, RuntimeError> {
         // Sort the capabilities based on domain.
-        let mut capability_map = capabilites_by_device(&capabilities, &pre_devices);
+        let splits = find_splits(&pre_devices);
+        let mut capability_map = capabilites_by_device(&capabilities, &pre_devices, &splits);
 
         // Create domains with capabilities.
+        let feedback_epoll = match pre_devices.iter().any(receives_feedback) {
+            true => Some(unsafe { OwnedFd::from_syscall(libc::epoll_create1(libc::EPOLL_CLOEXEC))? }),
+            false => None,
+        };
+
         let mut devices: HashMap<Domain, OutputDevice> = HashMap::new();
+        let mut recorders: HashMap<Domain, Recorder> = HashMap::new();
+        let mut deferred: HashMap<Domain, Capabilities> = HashMap::new();
+        // The domains of the previous devices that shall be reused, and the domains they get in
+        // the new system.
+        let mut reused: Vec<(Domain, Domain)> = Vec::new();
         for pre_device in &pre_devices {
             let domain = pre_device.domain;
 
-            if devices.contains_key(&domain) {
+            if devices.contains_key(&domain) || recorders.contains_key(&domain) {
                 return Err(InternalError::new("Multiple output devices with the same domain have been created.").into());
             }
+
+            let previous_domain = previous.as_ref().and_then(|previous| {
+                let claimed: Vec<Domain> = reused.iter().map(|&(old_domain, _)| old_domain).collect();
+                previous.find_equivalent(pre_device, &claimed)
+            });
+
+            if let Some((target, format)) = &pre_device.record {
+                if let Some(previous_domain) = previous_domain {
+                    reused.push((previous_domain, domain));
+                    continue;
+                }
+                let recorder = Recorder::open(target, *format)
+                    .with_context_of(|| match target {
+                        RecordTarget::File(path) => format!("While opening the file \"{}\" to record events to:", path.display()),
+                        RecordTarget::Tcp(address) => format!("While connecting to \"{}\":", address),
+                        RecordTarget::Stdout => "While preparing to write events to stdout:".to_owned(),
+                    })?;
+                recorders.insert(domain, recorder);
+                continue;
+            }
     
             let capabilities = capability_map.remove(&pre_device.domain).expect("Internal invariant violated: capabilites_by_device() did not create a capability entry for each output device.");
+            // Do not create devices for classes of events that the split output will never receive.
+            if pre_device.split_from.is_some() && capabilities.has_no_content() {
+                deferred.insert(domain, capabilities);
+                continue;
+            }
             if capabilities.has_no_content() {
-                eprintln!("Warning: an output device has been specified to which no events can possibly be routed.");
+                logging::warning("Warning: an output device has been specified to which no events can possibly be routed.");
+            }
+
+            if let (Some(previous), Some(previous_domain)) = (&previous, previous_domain) {
+                let is_compatible = match previous.devices.get(&previous_domain) {
+                    Some(previous_device) => capabilities.is_compatible_with(&previous_device.capabilities),
+                    None => false,
+                };
+                if is_compatible {
+                    reused.push((previous_domain, domain));
+                    continue;
+                }
+            }
+
+            if pre_device.defer {
+                deferred.insert(domain, capabilities);
+                continue;
             }
 
             let device = create_output_device(pre_device, capabilities)?;
-            
+            if receives_feedback(pre_device) {
+                watch_feedback(&feedback_epoll, &device)?;
+            }
+
             devices.insert(domain, device);
         }
 
-        Ok(OutputSystem { pre_devices, devices })
+        let mut idle_deadlines = HashMap::new();
+        if let Some(previous) = &mut previous {
+            for (previous_domain, domain) in reused {
+                if let Some(recorder) = previous.recorders.remove(&previous_domain) {
+                    recorders.insert(domain, recorder);
+                    continue;
+                }
+                let device = match previous.devices.remove(&previous_domain) {
+                    Some(device) => device,
+                    None => continue,
+                };
+                let pre_device = match pre_devices.iter().find(|pre_device| pre_device.domain == domain) {
+                    Some(pre_device) => pre_device,
+                    None => continue,
+                };
+                if receives_feedback(pre_device) {
+                    watch_feedback(&feedback_epoll, &device)
+                        .with_context("While trying to forward feedback from an output device after reloading:")
+                        .print_err();
+                }
+                if let Some(idle_timeout) = pre_device.idle_timeout {
+                    idle_deadlines.insert(domain, (Instant::now() + idle_timeout, idle_timeout));
+                }
+                devices.insert(domain, device);
+            }
+
+            // The devices of the previous system that were not reused get destroyed along with it.
+            // Their symlinks must not take the symlinks of the new devices down with them.
+            let new_links: Vec<&PathBuf> = pre_devices.iter()
+                .flat_map(|pre_device| pre_device.create_links.iter())
+                .collect();
+            for device in previous.devices.values_mut() {
+                for symlink in device.take_symlinks() {
+                    if new_links.iter().any(|link| link.as_path() == symlink.location()) {
+                        std::mem::forget(symlink);
+                    }
+                }
+            }
+        }
+
+        // The devices reused from the previous system keep writing the way they did before.
+        let batch_writes = previous.map(|previous| previous.batch_writes).unwrap_or(false);
+        for device in devices.values_mut() {
+            device.batch_writes = batch_writes;
+        }
+
+        Ok(OutputSystem {
+            pre_devices, devices, recorders, deferred, splits, feedback_epoll, idle_deadlines,
+            routed_counts: HashMap::new(),
+            dropped_counts: HashMap::new(),
+            batch_writes,
+        })
+    }
+
+    /// If enabled, the reports written to output devices are held back until they get collected by
+    /// `pending_writes()`, so they can be handed to the kernel along with the next wait.
+    pub fn set_batch_writes(&mut self, batch_writes: bool) {
+        self.batch_writes = batch_writes;
+        for device in self.devices.values_mut() {
+            device.batch_writes = batch_writes;
+            if ! batch_writes {
+                device.flush();
+            }
+        }
+    }
+
+    /// Returns the events that have been written to each output device but not handed to the
+    /// kernel yet, as raw bytes that can be written to the file descriptor of the device. Every
+    /// write must be reported back through `finish_pending_writes()`.
+    pub fn pending_writes(&self) -> Vec<(Domain, PendingWrite<'_>)> {
+        self.devices.iter()
+            .filter(|(_, device)| ! device.pending.is_empty())
+            .map(|(&domain, device)| {
+                let data = unsafe { std::slice::from_raw_parts(
+                    device.pending.as_ptr() as *const u8,
+                    std::mem::size_of_val(device.pending.as_slice()),
+                )};
+                (domain, PendingWrite { fd: device.uinput_fd(), data })
+            })
+            .collect()
+    }
+
+    /// Tells each output device whether its pending events were written successfully.
+    pub fn finish_pending_writes(&mut self, results: impl IntoIterator<Item=(Domain, bool)>) {
+        for (domain, success) in results {
+            if let Some(device) = self.devices.get_mut(&domain) {
+                if ! success {
+                    logging::warning(format!("Warning: an error occurred while writing events to {}.", device.description()));
+                }
+                device.pending.clear();
+            }
+        }
+    }
+
+    /// Returns the domain of an existing device or recorder of this system whose pre-device is
+    /// equivalent to the given one, skipping the domains that have already been claimed.
+    fn find_equivalent(&self, pre_device: &PreOutputDevice, claimed: &[Domain]) -> Option<Domain> {
+        self.pre_devices.iter()
+            .filter(|candidate| ! claimed.contains(&candidate.domain))
+            .filter(|candidate| self.devices.contains_key(&candidate.domain) || self.recorders.contains_key(&candidate.domain))
+            .find(|candidate| candidate.creates_same_device_as(pre_device))
+            .map(|candidate| candidate.domain)
     }
 
     /// Tries to make sure that all output devices have at least the given capabilities. The output 
@@ -59,7 +265,7 @@ impl OutputSystem {
     /// This may cause output devices to be destroyed and recreated.
     pub fn update_caps(&mut self, new_capabilities: Vec<Capability>) {
         // Sort the capabilities based on domain.
-        let mut capability_map = capabilites_by_device(&new_capabilities, &self.pre_devices);
+        let mut capability_map = capabilites_by_device(&new_capabilities, &self.pre_devices, &self.splits);
 
         let old_output_devices = std::mem::take(&mut self.devices);
         let mut recreated_output_devices: Vec<&PreOutputDevice> = Vec::new();
@@ -93,18 +299,18 @@ impl OutputSystem {
             }
 
             // The device is supposed to have more capabilities than it used to. We must recreate it.
-            // Free up the old symlink so the new device can create a symlink in its place.
-            let symlink = old_device.take_symlink();
-            drop(symlink); // TODO: make this operation atomical with its recreation.
+            // Free up the old symlinks so the new device can create symlinks in their place.
+            let symlinks = old_device.take_symlinks();
+            drop(symlinks); // TODO: make this operation atomical with its recreation.
 
-            let new_device = match create_output_device(pre_device, capabilities) {
+            let mut new_device = match create_output_device(pre_device, capabilities) {
                 Ok(device) => device,
                 Err(error) => {
                     eprintln!("Error: failed to recreate an output device. The remaining output devices may have incorrect capabilities.");
                     error.print_err();
-                    // Try to restore the old link if possible.
-                    if let Some(ref path) = pre_device.create_link {
-                        old_device.set_link(path.clone()).print_err();
+                    // Try to restore the old links if possible.
+                    if ! pre_device.create_links.is_empty() {
+                        old_device.set_links(&pre_device.create_links).print_err();
                     }
                     self.devices.insert(domain, old_device);
                     continue;
@@ -114,11 +320,25 @@ impl OutputSystem {
             old_device.syn_if_required();
             drop(old_device);
 
+            if receives_feedback(pre_device) {
+                watch_feedback(&self.feedback_epoll, &new_device)
+                    .with_context("While trying to forward feedback from a recreated output device:")
+                    .print_err();
+            }
+
+            new_device.batch_writes = self.batch_writes;
             self.devices.insert(domain, new_device);
             recreated_output_devices.push(pre_device);
         }
 
-        if ! recreated_output_devices.is_empty() {
+        // Deferred devices that do not exist yet will simply be created with the new capabilities.
+        for (domain, capabilities) in &mut self.deferred {
+            if let Some(new_capabilities) = capability_map.remove(domain) {
+                *capabilities = new_capabilities;
+            }
+        }
+
+        if ! recreated_output_devices.is_empty() && logging::enabled(LogLevel::Warning) {
             if let Ok(warning_msg) = format_output_device_recreation_warning(&recreated_output_devices) {
                 println!("{}", warning_msg);
             } else {
@@ -131,19 +351,318 @@ impl OutputSystem {
     /// Writes all events to their respective output devices.
     pub fn route_events(&mut self, events: &[Event]) {
         for &event in events {
-            let device_opt = self.devices.get_mut(&event.domain);
-            match device_opt {
-                Some(device) => device.write_event(event),
+            let mut event = event;
+            *self.routed_counts.entry(event.domain).or_insert(0) += 1;
+            if let Some(&domain) = self.splits.get(&(event.domain, DeviceClass::of(event.code))) {
+                event.domain = domain;
+            }
+            if self.deferred.contains_key(&event.domain) {
+                self.create_deferred_device(event.domain);
+            }
+            if let Some((deadline, idle_timeout)) = self.idle_deadlines.get_mut(&event.domain) {
+                *deadline = Instant::now() + *idle_timeout;
+            }
+            if let Some(device) = self.devices.get_mut(&event.domain) {
+                if device.rejects(&event) {
+                    *self.dropped_counts.entry(event.domain).or_insert(0) += 1;
+                } else if ! (device.dedup && device.is_redundant(&event)) {
+                    device.write_event(event);
+                    if device.syn_mode == SynMode::PerEvent {
+                        device.syn_if_required();
+                    }
+                }
+                continue;
+            }
+            match self.recorders.get_mut(&event.domain) {
+                Some(recorder) => recorder.write_event(event),
+                // Happens if creating a deferred device failed.
+                None if self.deferred.contains_key(&event.domain) => {
+                    *self.dropped_counts.entry(event.domain).or_insert(0) += 1;
+                },
                 None => eprintln!("Internal error: an event {} with unknown domain has been routed to output; event dropped. This is a bug.", event),
             };
         }
     }
 
+    /// Creates a device with the defer clause now that the first event has been routed to it.
+    /// If that fails, the events routed to it will be dropped, and creation will be retried for
+    /// the next event.
+    fn create_deferred_device(&mut self, domain: Domain) {
+        let pre_device = match self.pre_devices.iter().find(|device| device.domain == domain) {
+            Some(pre_device) => pre_device,
+            None => {
+                eprintln!("Internal invariant violated: OutputDeviceSystem contains a deferred output device with a domain for which it does not have a PreOutputDevice. This is a bug.");
+                return;
+            },
+        };
+        let capabilities = match self.deferred.get(&domain) {
+            Some(capabilities) => capabilities.clone(),
+            None => return,
+        };
+        let mut device = match create_output_device(pre_device, capabilities) {
+            Ok(device) => device,
+            Err(error) => {
+                error.print_err();
+                return;
+            },
+        };
+        if receives_feedback(pre_device) {
+            watch_feedback(&self.feedback_epoll, &device)
+                .with_context("While trying to forward feedback from a deferred output device:")
+                .print_err();
+        }
+        if let Some(idle_timeout) = pre_device.idle_timeout {
+            self.idle_deadlines.insert(domain, (Instant::now() + idle_timeout, idle_timeout));
+        }
+        device.batch_writes = self.batch_writes;
+        self.deferred.remove(&domain);
+        self.devices.insert(domain, device);
+    }
+
+    /// The earliest moment at which some deferred device should be destroyed for being idle.
+    pub fn next_idle_deadline(&self) -> Option<Instant> {
+        self.idle_deadlines.values().map(|&(deadline, _)| deadline).min()
+    }
+
+    /// Destroys all deferred output devices that have not received events for longer than their
+    /// idle timeout. They will be recreated when the next event gets routed to them.
+    pub fn destroy_idle_devices(&mut self) {
+        let now = Instant::now();
+        let idle_domains: Vec<Domain> = self.idle_deadlines.iter()
+            .filter(|(_, &(deadline, _))| deadline <= now)
+            .map(|(&domain, _)| domain)
+            .collect();
+        for domain in idle_domains {
+            self.idle_deadlines.remove(&domain);
+            if let Some(mut device) = self.devices.remove(&domain) {
+                device.syn_if_required();
+                self.deferred.insert(domain, device.capabilities.clone());
+            }
+        }
+    }
+
+    /// Releases all keys that are held on any output device.
+    pub fn release_keys(&mut self) {
+        for device in self.devices.values_mut() {
+            device.release_keys();
+        }
+    }
+
+    /// Changes the name of the output that has the given path among its create-link= clauses or
+    /// has the given name. If its device exists, it gets destroyed and recreated with the new name,
+    /// because the kernel offers no way to rename a device. Keys that were held on the old device
+    /// are released first; the state of its switches, LEDs and absolute axes is carried over.
+    pub fn rename(&mut self, target: &str, name: String) -> Result<(), RuntimeError> {
+        let parent_domain = match self.pre_devices.iter()
+            .filter(|pre_device| pre_device.split_from.is_none() && pre_device.record.is_none())
+            .find(|pre_device| pre_device.name == target
+                || pre_device.create_links.iter().any(|link| link.as_path() == Path::new(target)))
+        {
+            Some(pre_device) => pre_device.domain,
+            None => return Err(ArgumentError::new(format!(
+                "No output device has the name \"{}\" or a create-link= clause with that path.", target
+            )).into()),
+        };
+
+        // The devices made by the split-by-type flag are named after the output they were split from.
+        let mut new_names: Vec<(Domain, String)> = vec![(parent_domain, name.clone())];
+        for pre_device in &self.pre_devices {
+            if let Some((split_parent, class)) = pre_device.split_from {
+                if split_parent == parent_domain {
+                    new_names.push((pre_device.domain, format!("{} {}", name, class.name_suffix())));
+                }
+            }
+        }
+
+        for (domain, new_name) in new_names {
+            let pre_device = match self.pre_devices.iter_mut().find(|pre_device| pre_device.domain == domain) {
+                Some(pre_device) => pre_device,
+                None => return Err(InternalError::new("An output device to rename has no PreOutputDevice.").into()),
+            };
+            let old_name = std::mem::replace(&mut pre_device.name, new_name);
+            let mut old_device = match self.devices.remove(&domain) {
+                Some(device) => device,
+                // Deferred devices will be created with the new name later.
+                None => continue,
+            };
+
+            old_device.release_keys();
+            drop(old_device.take_symlinks());
+            let mut new_device = match create_output_device(pre_device, old_device.capabilities.clone()) {
+                Ok(device) => device,
+                Err(error) => {
+                    pre_device.name = old_name;
+                    if ! pre_device.create_links.is_empty() {
+                        old_device.set_links(&pre_device.create_links).print_err();
+                    }
+                    self.devices.insert(domain, old_device);
+                    return Err(error.with_context("While recreating an output device with a new name:"));
+                },
+            };
+            new_device.restore_state(&old_device);
+            drop(old_device);
+
+            if receives_feedback(pre_device) {
+                watch_feedback(&self.feedback_epoll, &new_device)
+                    .with_context("While trying to forward feedback from a renamed output device:")
+                    .print_err();
+            }
+            new_device.batch_writes = self.batch_writes;
+            self.devices.insert(domain, new_device);
+        }
+
+        Ok(())
+    }
+
+    /// The file descriptor that becomes readable when some output device has received force
+    /// feedback requests or LED changes, if any output device forwards those.
+    pub fn feedback_fd(&self) -> Option<RawFd> {
+        self.feedback_epoll.as_ref().map(OwnedFd::as_raw_fd)
+    }
+
+    /// Returns everything that programs wrote to the output devices that should be forwarded to
+    /// input devices, along with the domain of the device that received it. Every force feedback
+    /// request must be finished by `finish_ff_request()`.
+    pub fn read_feedback(&mut self) -> Vec<(Domain, Feedback)> {
+        // Reset the readiness of the epoll; we check all devices anyway.
+        if let Some(feedback_epoll) = &self.feedback_epoll {
+            let mut events: [libc::epoll_event; 8] = unsafe { std::mem::zeroed() };
+            unsafe { libc::epoll_wait(feedback_epoll.as_raw_fd(), events.as_mut_ptr(), events.len() as i32, 0) };
+        }
+
+        let mut feedback = Vec::new();
+        for pre_device in &self.pre_devices {
+            if ! receives_feedback(pre_device) {
+                continue;
+            }
+            let device = match self.devices.get_mut(&pre_device.domain) {
+                Some(device) => device,
+                None => continue,
+            };
+            let result = device.read_feedback(pre_device, |item| feedback.push((pre_device.domain, item)));
+            if let Err(error) = result {
+                error.with_context(format!("While reading feedback from {}:", device.description()))
+                    .print_err();
+            }
+        }
+        feedback
+    }
+
+    /// Tells the program that made an upload or erase request whether it succeeded.
+    pub fn finish_ff_request(&mut self, domain: Domain, request: FfRequest, retval: i32) {
+        if let Some(device) = self.devices.get(&domain) {
+            ff::finish_request(device.uinput_fd(), request, retval)
+                .with_context_of(|| format!("While forwarding force feedback from {}:", device.description()))
+                .print_err();
+        }
+    }
+
+    /// Returns a table of how many events have been routed to each --output argument.
+    pub fn format_stats(&self) -> String {
+        let mut report = format!("{:>12}  output\n", "events");
+        for pre_device in &self.pre_devices {
+            // The events of split devices are counted towards the output they were split from.
+            if pre_device.split_from.is_some() {
+                continue;
+            }
+            let count = self.routed_counts.get(&pre_device.domain).copied().unwrap_or(0);
+            report.push_str(&format!("{:>12}  {}\n", count, describe_output(pre_device)));
+        }
+        report
+    }
+
+    /// Appends the counters of the outputs to a report for --metrics.
+    pub fn write_metrics(&self, report: &mut String) {
+        let outputs: Vec<&PreOutputDevice> = self.pre_devices.iter()
+            .filter(|pre_device| pre_device.split_from.is_none())
+            .collect();
+        let count = |counts: &HashMap<Domain, u64>, pre_device: &PreOutputDevice| -> u64 {
+            // The events of split devices are counted towards the output they were split from.
+            self.pre_devices.iter()
+                .filter(|other| other.domain == pre_device.domain
+                    || other.split_from.map(|(parent, _)| parent) == Some(pre_device.domain))
+                .map(|other| counts.get(&other.domain).copied().unwrap_or(0))
+                .sum()
+        };
+        let routed: Vec<(String, u64)> = outputs.iter()
+            .map(|pre_device| (describe_output(pre_device), count(&self.routed_counts, pre_device)))
+            .collect();
+        let dropped: Vec<(String, u64)> = outputs.iter()
+            .map(|pre_device| (describe_output(pre_device), count(&self.dropped_counts, pre_device)))
+            .collect();
+        metrics::write_metric(report, "evsieve_output_events_total", "counter",
+            "Events routed to each output.", Some("output"), &routed);
+        metrics::write_metric(report, "evsieve_output_events_dropped_total", "counter",
+            "Events routed to each output that could not be written to it.", Some("output"), &dropped);
+    }
+
+    /// Returns one line for each output, for the list-devices command.
+    pub fn format_devices(&self) -> String {
+        let mut report = String::new();
+        for pre_device in &self.pre_devices {
+            if pre_device.split_from.is_some() {
+                continue;
+            }
+            report.push_str(&format!("output {}\n", describe_output(pre_device)));
+        }
+        report
+    }
+
+    /// Returns a JSON object for each output, for the list-devices command. Outputs that record
+    /// their events only have the field "record"; devnode is null while a device does not exist.
+    pub fn format_devices_json(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        for pre_device in &self.pre_devices {
+            if pre_device.record.is_some() {
+                result.push(format!("{{\"record\":{}}}", quote_json(&describe_output(pre_device))));
+                continue;
+            }
+            let device = self.devices.get(&pre_device.domain);
+            let capabilities = match (device, self.deferred.get(&pre_device.domain)) {
+                (Some(device), _) => &device.capabilities,
+                (None, Some(capabilities)) => capabilities,
+                // Devices split off by split-by-type without any events to receive do not exist.
+                (None, None) => continue,
+            };
+            let mut codes: Vec<EventCode> = capabilities.codes.iter().copied().collect();
+            codes.sort_by_key(|code| (u16::from(code.ev_type()), code.code()));
+            let devnode = match device.map(OutputDevice::devnode) {
+                Some(Ok(path)) => quote_json(&path.to_string_lossy()),
+                _ => "null".to_owned(),
+            };
+            let links: Vec<String> = pre_device.create_links.iter()
+                .map(|link| quote_json(&link.to_string_lossy()))
+                .collect();
+            let capabilities: Vec<String> = codes.into_iter()
+                .map(|code| quote_json(&ecodes::event_name(code)))
+                .collect();
+            result.push(format!(
+                "{{\"name\":{},\"devnode\":{},\"links\":[{}],\"capabilities\":[{}]}}",
+                quote_json(&pre_device.name), devnode, links.join(","), capabilities.join(","),
+            ));
+        }
+        result
+    }
+
     /// The maps may generate events without folling them up with SYN events.
     /// This function generates all SYN events for user convenience.
-    pub fn synchronize(&mut self) {
+    ///
+    /// If `is_report_boundary` is false, the events were generated by a single input event, and
+    /// devices with syn=coalesce shall wait for the report of the input device to end.
+    pub fn synchronize(&mut self, is_report_boundary: bool) {
         for device in self.devices.values_mut() {
-            device.syn_if_required();
+            if is_report_boundary || device.syn_mode != SynMode::Coalesce {
+                device.syn_if_required();
+            }
+        }
+        for recorder in self.recorders.values_mut() {
+            recorder.syn_if_required();
+        }
+        // Devices with syn=coalesce may still have events from an unfinished report.
+        if ! self.batch_writes {
+            for device in self.devices.values_mut() {
+                device.flush();
+            }
         }
     }
 }
@@ -152,17 +671,41 @@ pub struct OutputDevice {
     device: *mut libevdev::libevdev_uinput,
     /// Keeps track of whether we've sent any events to the output since the last SYN event.
     should_syn: bool,
-    /// If some symlink to the device was created, store it here.
-    symlink: Option<Symlink>,
+    /// The symlinks that were created to this device.
+    symlinks: Vec<Symlink>,
+    /// When this device writes SYN_REPORT events.
+    syn_mode: SynMode,
     /// If false, all repeat events sent to this device will be dropped.
     /// Does not prevent the kernel from generating repeat events.
     allows_repeat: bool,
     /// The capabilities of this output device.
     capabilities: Capabilities,
+    /// The multitouch slot that was most recently selected by writing an ABS_MT_SLOT event.
+    current_slot: Option<EventValue>,
+    /// If true, events that this device does not have the capabilities for shall be dropped.
+    strict_capabilities: bool,
+    /// If true, events that would not change the state of this device shall be dropped.
+    dedup: bool,
+    /// The events that have been dropped because of strict_capabilities, so each of them only
+    /// gets reported once.
+    rejected_codes: HashSet<EventCode>,
+    /// Maps the ids of the force feedback effects uploaded to this device to their type.
+    ff_effect_types: HashMap<i16, EventCode>,
+    /// The last value of each event that was generated by playing force feedback effects.
+    ff_effect_state: HashMap<EventCode, EventValue>,
+    /// The keys that are held, and the last value written of each switch, LED and absolute axis
+    /// other than the multitouch ones, so they can be carried over when the device is recreated.
+    state: HashMap<EventCode, EventValue>,
+    /// The events that have been written since the last report. They are handed to the kernel
+    /// all at once when the report ends, or when flush() gets called.
+    pending: Vec<libc::input_event>,
+    /// If true, the pending events are not handed to the kernel when the report ends, but are left
+    /// for the OutputSystem to collect.
+    batch_writes: bool,
 }
 
 impl OutputDevice {
-    pub fn with_name_and_capabilities(name_str: String, caps: Capabilities) -> Result<OutputDevice, RuntimeError> {
+    pub fn with_name_and_capabilities(name_str: String, identity: OutputIdentity, caps: Capabilities) -> Result<OutputDevice, RuntimeError> {
         unsafe {
             let dev = libevdev::libevdev_new();
 
@@ -173,6 +716,28 @@ impl OutputDevice {
 
             libevdev::libevdev_set_name(dev, name);
 
+            if let Some(vendor) = identity.vendor {
+                libevdev::libevdev_set_id_vendor(dev, vendor.into());
+            }
+            if let Some(product) = identity.product {
+                libevdev::libevdev_set_id_product(dev, product.into());
+            }
+            if let Some(version) = identity.version {
+                libevdev::libevdev_set_id_version(dev, version.into());
+            }
+            if let Some(bustype) = identity.bustype {
+                libevdev::libevdev_set_id_bustype(dev, bustype.into());
+            }
+            // libevdev copies these strings, so they only need to live until the calls return.
+            if let Some(phys) = identity.phys {
+                let phys = CString::new(phys).unwrap();
+                libevdev::libevdev_set_phys(dev, phys.as_ptr());
+            }
+            if let Some(uniq) = identity.uniq {
+                let uniq = CString::new(uniq).unwrap();
+                libevdev::libevdev_set_uniq(dev, uniq.as_ptr());
+            }
+
             // If EV_MSC events are automatically generated, we may need to manually activate
             // their capabilities.
             // TODO: prevent EV_MSC capabilities from getting activated by capabilities.
@@ -201,15 +766,15 @@ impl OutputDevice {
                             dev, code.ev_type().into(), code.code() as u32, libevdev_abs_info_ptr as *const libc::c_void)
                     },
                     EventType::REP => {
-                        // Known issue: due to limitations in the uinput kernel module, the REP_DELAY
-                        // and REP_PERIOD values are ignored and the kernel defaults will be used instead,
-                        // according to the libevdev documentation. Status: won't fix.
+                        // Due to limitations in the uinput kernel module, the REP_DELAY and REP_PERIOD
+                        // values are ignored and the kernel defaults will be used instead, according to
+                        // the libevdev documentation. See set_repeat_info() for how to change them.
                         if let Some(rep_info) = caps.rep_info {
                             let value: libc::c_int = match code.code() {
                                 ecodes::REP_DELAY => rep_info.delay,
                                 ecodes::REP_PERIOD => rep_info.period,
                                 _ => {
-                                    eprintln!("Warning: encountered an unknown capability code under EV_REP: {}.", code.code());
+                                    logging::warning(format!("Warning: encountered an unknown capability code under EV_REP: {}.", code.code()));
                                     continue;
                                 },
                             };
@@ -222,7 +787,7 @@ impl OutputDevice {
                     _ => libevdev::libevdev_enable_event_code(dev, code.ev_type().into(), code.code() as u32, ptr::null_mut()),
                 };
                 if res < 0 {
-                    eprintln!("Warning: failed to enable event {} on uinput device.", ecodes::event_name(*code));
+                    logging::warning(format!("Warning: failed to enable event {} on uinput device.", ecodes::event_name(*code)));
                 }
             }
 
@@ -245,9 +810,19 @@ impl OutputDevice {
             Ok(OutputDevice {
                 device: uinput_dev,
                 should_syn: false,
-                symlink: None,
+                symlinks: Vec::new(),
+                syn_mode: SynMode::Passthrough,
                 allows_repeat: true,
                 capabilities: caps,
+                current_slot: None,
+                strict_capabilities: false,
+                dedup: false,
+                rejected_codes: HashSet::new(),
+                ff_effect_types: HashMap::new(),
+                ff_effect_state: HashMap::new(),
+                state: HashMap::new(),
+                pending: Vec::new(),
+                batch_writes: false,
             })
         }
     }
@@ -256,16 +831,60 @@ impl OutputDevice {
         if ! self.allows_repeat && ev_type == ecodes::EV_KEY.into() && value == 2 {
             return;
         }
-        let res = unsafe { libevdev::libevdev_uinput_write_event(self.device, ev_type, code, value) };
-        if res < 0 {
-            eprintln!("Warning: an error occurred while writing an event to {}.", self.description());
-        }
+        // The kernel fills in the time of events written to uinput devices.
+        self.pending.push(libc::input_event {
+            time: libc::timeval { tv_sec: 0, tv_usec: 0 },
+            type_: ev_type as u16,
+            code: code as u16,
+            value,
+        });
         self.should_syn = ev_type as u32 != libevdev::EV_SYN;
+        if ! self.should_syn && ! self.batch_writes {
+            self.flush();
+        }
+    }
+
+    /// Writes all pending events to the uinput device with a single system call.
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let size = std::mem::size_of_val(self.pending.as_slice());
+        let res = unsafe {
+            libc::write(self.uinput_fd(), self.pending.as_ptr() as *const libc::c_void, size)
+        };
+        if res < 0 || res as usize != size {
+            logging::warning(format!("Warning: an error occurred while writing events to {}.", self.description()));
+        }
+        self.pending.clear();
+    }
+
+    /// If the event belongs to a different multitouch slot than the one that is currently
+    /// selected, writes an ABS_MT_SLOT event to select the right slot.
+    fn write_slot_if_required(&mut self, event: &Event) {
+        if event.code == EventCode::ABS_MT_SLOT {
+            self.current_slot = Some(event.value);
+            return;
+        }
+        let slot = match event.slot {
+            Some(slot) => slot,
+            None => return,
+        };
+        if self.current_slot == Some(slot)
+            || ! self.capabilities.codes.contains(&EventCode::ABS_MT_SLOT)
+            || ! ecodes::is_abs_mt(event.code)
+        {
+            return;
+        }
+        self.current_slot = Some(slot);
+        self.write(EventType::ABS.into(), ecodes::ABS_MT_SLOT.into(), slot);
     }
 
     #[cfg(not(feature = "auto-scan"))]
     fn write_event(&mut self, event: Event) {
+        self.write_slot_if_required(&event);
         self.write(event.code.ev_type().into(), event.code.code() as u32, event.value as i32);
+        self.remember_state(&event);
     }
 
     #[cfg(feature = "auto-scan")]
@@ -276,7 +895,80 @@ impl OutputDevice {
                 self.write(EventType::MSC.into(), crate::event::EventCode::MSC_SCAN.code().into(), scancode)
             }
         }
+        self.write_slot_if_required(&event);
         self.write(event.code.ev_type().into(), event.code.code() as u32, event.value as i32);
+        self.remember_state(&event);
+    }
+
+    fn remember_state(&mut self, event: &Event) {
+        match event.ev_type() {
+            EventType::KEY => match event.value {
+                0 => { self.state.remove(&event.code); },
+                1 => { self.state.insert(event.code, event.value); },
+                _ => (),
+            },
+            EventType::SW | EventType::LED => { self.state.insert(event.code, event.value); },
+            EventType::ABS if ! ecodes::is_abs_mt(event.code) => { self.state.insert(event.code, event.value); },
+            _ => (),
+        }
+    }
+
+    /// Returns true if writing this event would not change anything, e.g. because it presses a key
+    /// that is already held, or sets an axis to the value it already has. Multitouch axes are
+    /// never considered redundant, because their value depends on the slot.
+    fn is_redundant(&self, event: &Event) -> bool {
+        match event.ev_type() {
+            EventType::KEY => match event.value {
+                1 => self.state.contains_key(&event.code),
+                // Releases and repeats of keys that are not held.
+                _ => ! self.state.contains_key(&event.code),
+            },
+            EventType::SW | EventType::LED => self.state.get(&event.code) == Some(&event.value),
+            EventType::ABS if ! ecodes::is_abs_mt(event.code) => self.state.get(&event.code) == Some(&event.value),
+            EventType::REL => event.value == 0,
+            _ => false,
+        }
+    }
+
+    /// Releases all keys that are held on this device, so nothing keeps them pressed once this
+    /// device gets destroyed.
+    fn release_keys(&mut self) {
+        let held_keys: Vec<EventCode> = self.state.keys()
+            .copied()
+            .filter(|code| code.ev_type() == EventType::KEY)
+            .collect();
+        for code in held_keys {
+            self.write(code.ev_type().into(), code.code() as u32, 0);
+            self.state.remove(&code);
+        }
+        self.syn_if_required();
+    }
+
+    /// Writes the state of another device to this device, except for the keys that were held.
+    fn restore_state(&mut self, other: &OutputDevice) {
+        for (&code, &value) in &other.state {
+            if code.ev_type() == EventType::KEY {
+                continue;
+            }
+            self.write(code.ev_type().into(), code.code() as u32, value);
+            self.state.insert(code, value);
+        }
+        self.syn_if_required();
+    }
+
+    /// Returns true if the device has pinned capabilities that do not include this event. Complains
+    /// the first time that happens for each event code.
+    fn rejects(&mut self, event: &Event) -> bool {
+        if ! self.strict_capabilities || self.capabilities.codes.contains(&event.code) {
+            return false;
+        }
+        if self.rejected_codes.insert(event.code) {
+            eprintln!(
+                "Error: the event {} was routed to {}, but it is not part of the capabilities specified for that device. Such events will be dropped.",
+                ecodes::event_name(event.code), self.description()
+            );
+        }
+        true
     }
 
     fn syn_if_required(&mut self) {
@@ -285,39 +977,156 @@ impl OutputDevice {
         }
     }
 
+    fn uinput_fd(&self) -> RawFd {
+        unsafe { libevdev::libevdev_uinput_get_fd(self.device) }
+    }
+
+    /// Reads all events that programs wrote to this device and hands those that are relevant to
+    /// the given pre_device to the callback.
+    fn read_feedback(&mut self, pre_device: &PreOutputDevice, mut callback: impl FnMut(Feedback)) -> Result<(), SystemError> {
+        let fd = self.uinput_fd();
+        loop {
+            let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+            let size = std::mem::size_of::<libc::input_event>();
+            let res = unsafe {
+                libc::read(fd, &mut event as *mut libc::input_event as *mut libc::c_void, size)
+            };
+            if res < 0 {
+                let error = io::Error::last_os_error();
+                match error.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => return Ok(()),
+                    _ => return Err(error.into()),
+                }
+            }
+            if res as usize != size {
+                return Ok(());
+            }
+
+            if event.type_ == ecodes::EV_LED {
+                let code = EventCode::new(EventType::LED, event.code);
+                if pre_device.forward_leds {
+                    callback(Feedback::Led(code, event.value));
+                }
+                if pre_device.leds.contains(&code) {
+                    callback(Feedback::Effect(
+                        Event::new(code, event.value, 0, pre_device.domain, Namespace::Input)
+                    ));
+                }
+                continue;
+            }
+            if pre_device.forward_ff.is_none() && pre_device.ff_effects.is_empty() {
+                continue;
+            }
+            let request = match ff::begin_request(fd, &event)? {
+                Some(request) => request,
+                None => continue,
+            };
+            if ! pre_device.ff_effects.is_empty() {
+                if let Some(event) = self.track_ff_request(pre_device.domain, &request) {
+                    callback(Feedback::Effect(event));
+                }
+            }
+            match &pre_device.forward_ff {
+                Some((path, _)) => callback(Feedback::ForceFeedback(path.clone(), request)),
+                // Nobody else is going to play the effect, so the upload trivially succeeds.
+                None => ff::finish_request(fd, request, 0)?,
+            }
+        }
+    }
+
+    /// Keeps track of which effects have been uploaded to this device. If the request starts or
+    /// stops playing an effect, returns an event that tells the stream about it.
+    fn track_ff_request(&mut self, domain: Domain, request: &FfRequest) -> Option<Event> {
+        let (effect_type, value) = match request {
+            FfRequest::Upload(upload) => {
+                let effect_type = EventCode::new(EventType::FF, upload.effect.type_);
+                self.ff_effect_types.insert(upload.effect.id, effect_type);
+                return None;
+            },
+            FfRequest::Erase(erase) => {
+                self.ff_effect_types.remove(&(erase.effect_id as i16));
+                return None;
+            },
+            FfRequest::Event(code, value) => {
+                if code.code() >= ff::FF_GAIN {
+                    return None;
+                }
+                (*self.ff_effect_types.get(&(code.code() as i16))?, *value)
+            },
+        };
+        let previous_value = self.ff_effect_state.insert(effect_type, value).unwrap_or(0);
+        Some(Event::new(effect_type, value, previous_value, domain, Namespace::Input))
+    }
+
     /// Returns a handy name for this device, useful for error logging.main
     fn description(&self) -> String {
-        if let Some(link) = &self.symlink {
+        if let Some(link) = self.symlinks.first() {
             format!("the output device \"{}\"", link.location().to_string_lossy())
         } else {
             "an output device".to_string()
         }
     }
 
-    fn set_link(&mut self, path: PathBuf) -> Result<(), SystemError> {
-        // Try to figure out the path of the uinput device node.
+    /// Tries to figure out the path of the uinput device node.
+    fn devnode(&self) -> Result<PathBuf, SystemError> {
         let my_path_cstr_ptr = unsafe {
             libevdev::libevdev_uinput_get_devnode(self.device)
         };
         if my_path_cstr_ptr.is_null() {
-            return Err(SystemError::new("Failed to createa a symlink to an output device: cannot determine the path to the virtual device's device node."))
+            return Err(SystemError::new("cannot determine the path to the virtual device's device node."))
         };
         let my_path_cstr = unsafe { std::ffi::CStr::from_ptr(my_path_cstr_ptr) };
         let my_path_str = my_path_cstr.to_str().map_err(|_|
-            SystemError::new("Failed to createa a symlink to an output device: the path to the virtual device node is not valid UTF-8.")
+            SystemError::new("the path to the virtual device node is not valid UTF-8.")
         )?;
-        let my_path = Path::new(my_path_str).to_owned();
+        Ok(Path::new(my_path_str).to_owned())
+    }
+
+    /// Creates symlinks to this device at all given paths, replacing any links this device had before.
+    /// Either all links are created or none are.
+    fn set_links(&mut self, paths: &[PathBuf]) -> Result<(), SystemError> {
+        let my_path = self.devnode().with_context("Failed to create a symlink to an output device:")?;
 
-        // Drop the old link before creating a new one, in case the old and new link are both at the
-        // same location.
-        drop(self.take_symlink());
-        self.symlink = Some(Symlink::create(my_path, path)?);
+        // Drop the old links before creating new ones, in case the old and new links are at the
+        // same locations.
+        drop(self.take_symlinks());
+        let mut symlinks: Vec<Symlink> = Vec::new();
+        for path in paths {
+            // If this fails, the links created so far get removed when `symlinks` is dropped.
+            let symlink = Symlink::create(my_path.clone(), path.clone())
+                .map_err(SystemError::from)
+                .with_context(format!("While creating a symlink at \"{}\":", path.display()))?;
+            symlinks.push(symlink);
+        }
+        self.symlinks = symlinks;
+        Ok(())
+    }
+
+    /// Changes the mode and ownership of the device node of this device.
+    fn set_permissions(&self, permissions: NodePermissions) -> Result<(), SystemError> {
+        let path = self.devnode()?;
+        wait_for_udev(&path);
+
+        let path_cstr = CString::new(path.as_os_str().as_bytes()).unwrap();
+        if permissions.owner.is_some() || permissions.group.is_some() {
+            // An id of -1 tells chown() to leave that id unchanged.
+            let owner = permissions.owner.unwrap_or(-1i32 as libc::uid_t);
+            let group = permissions.group.unwrap_or(-1i32 as libc::gid_t);
+            if unsafe { libc::chown(path_cstr.as_ptr(), owner, group) } < 0 {
+                return Err(SystemError::os_with_context(format!("While changing the owner of \"{}\":", path.display())));
+            }
+        }
+        if let Some(mode) = permissions.mode {
+            if unsafe { libc::chmod(path_cstr.as_ptr(), mode) } < 0 {
+                return Err(SystemError::os_with_context(format!("While changing the mode of \"{}\":", path.display())));
+            }
+        }
         Ok(())
     }
 
-    /// Decouples this device from the symlink pointing to it.
-    fn take_symlink(&mut self) -> Option<Symlink> {
-        self.symlink.take()
+    /// Decouples this device from the symlinks pointing to it.
+    fn take_symlinks(&mut self) -> Vec<Symlink> {
+        std::mem::take(&mut self.symlinks)
     }
 
     fn set_repeat_mode(&mut self, mode: RepeatMode) {
@@ -328,6 +1137,15 @@ impl OutputDevice {
         });
     }
 
+    /// Changes the delay and period with which the kernel generates repeat events for this device.
+    /// The kernel does not respect the values we provide upon creation, but it does update them
+    /// when EV_REP events are written to the device.
+    fn set_repeat_info(&mut self, repeat_info: RepeatInfo) {
+        self.write(ecodes::EV_REP.into(), ecodes::REP_DELAY.into(), repeat_info.delay);
+        self.write(ecodes::EV_REP.into(), ecodes::REP_PERIOD.into(), repeat_info.period);
+        self.syn_if_required();
+    }
+
     fn allow_repeat(&mut self, value: bool) {
         self.allows_repeat = value;
     }
@@ -335,6 +1153,7 @@ impl OutputDevice {
 
 impl Drop for OutputDevice {
     fn drop(&mut self) {
+        self.flush();
         unsafe {
             libevdev::libevdev_uinput_destroy(self.device);
         }
@@ -384,51 +1203,166 @@ impl Drop for Symlink {
 
 /// Sorts a vector of capabilities by domain and adjusts them based on explicit flags/clauses specified
 /// for the output devices. Guarantees that an entry exists for each pre-output device.
-fn capabilites_by_device(capabilities: &[Capability], pre_devices: &[PreOutputDevice])
+fn capabilites_by_device(capabilities: &[Capability], pre_devices: &[PreOutputDevice], splits: &HashMap<(Domain, DeviceClass), Domain>)
         -> HashMap<Domain, Capabilities>
 {
     let mut capability_map: HashMap<Domain, Capabilities> = HashMap::new();
-    for capability in capabilities.iter().copied() {
+    for mut capability in capabilities.iter().copied() {
+        if let Some(&domain) = splits.get(&(capability.domain, DeviceClass::of(capability.code))) {
+            capability.domain = domain;
+        }
         let domain_capabilities = capability_map.entry(capability.domain).or_insert_with(Capabilities::new);
         domain_capabilities.add_capability(capability);
     }
 
     for device in pre_devices {
         let device_caps = capability_map.entry(device.domain).or_insert_with(Capabilities::new);
+        if let Some(pinned_caps) = &device.pinned_caps {
+            // The ranges of the pinned axes are set by the absinfo= clauses below.
+            device_caps.codes = pinned_caps.iter().copied().collect();
+            device_caps.abs_info.clear();
+        }
         match device.repeat_mode {
             RepeatMode::Disable => device_caps.remove_ev_rep(),
             RepeatMode::Passive => device_caps.remove_ev_rep(),
-            RepeatMode::Enable  => device_caps.require_ev_rep(),
+            RepeatMode::Enable  => match device.repeat_info {
+                Some(repeat_info) => device_caps.set_ev_rep(repeat_info),
+                None => device_caps.require_ev_rep(),
+            },
         };
+        for &(code, abs_info) in &device.abs_overrides {
+            device_caps.codes.insert(code);
+            device_caps.abs_info.insert(code, abs_info);
+        }
     }
 
     capability_map
 }
 
-fn create_output_device(pre_device: &PreOutputDevice, capabilities: Capabilities) -> Result<OutputDevice, RuntimeError> {
-    let mut device = OutputDevice::with_name_and_capabilities(pre_device.name.clone(), capabilities)
-        .with_context(match pre_device.create_link.clone() {
+/// Finds out which devices receive the events of outputs with the split-by-type flag.
+fn find_splits(pre_devices: &[PreOutputDevice]) -> HashMap<(Domain, DeviceClass), Domain> {
+    pre_devices.iter()
+        .filter_map(|device| device.split_from.map(|split_from| (split_from, device.domain)))
+        .collect()
+}
+
+/// Describes which events each output device would be able to emit given the capabilities that
+/// leave the stream, without creating any of them. Used by --dry-run.
+pub fn describe_capabilities(pre_devices: &[PreOutputDevice], capabilities: &[Capability]) -> String {
+    let splits = find_splits(pre_devices);
+    let capability_map = capabilites_by_device(capabilities, pre_devices, &splits);
+
+    let mut report = String::new();
+    for pre_device in pre_devices {
+        let _ = write!(&mut report, "Output {}", describe_output(pre_device));
+        if let Some((split_from, _)) = pre_device.split_from {
+            if let Some(parent) = pre_devices.iter().find(|parent| parent.domain == split_from) {
+                let _ = write!(&mut report, " (split from {})", describe_output(parent));
+            }
+        }
+        let mut names: Vec<String> = match capability_map.get(&pre_device.domain) {
+            Some(caps) => caps.codes.iter()
+                .filter(|code| ! code.ev_type().is_syn())
+                .map(|&code| ecodes::event_name(code).into_owned())
+                .collect(),
+            None => Vec::new(),
+        };
+        names.sort();
+        if names.is_empty() {
+            report.push_str(": no events can be routed to this output.\n");
+        } else {
+            report.push_str(":\n");
+            for name in names {
+                let _ = writeln!(&mut report, "    {}", name);
+            }
+        }
+    }
+    report
+}
+
+fn create_output_device(pre_device: &PreOutputDevice, mut capabilities: Capabilities) -> Result<OutputDevice, RuntimeError> {
+    if let Some((_, ff_codes)) = &pre_device.forward_ff {
+        capabilities.codes.extend(ff_codes.iter().copied());
+    }
+    capabilities.codes.extend(pre_device.ff_effects.iter().copied());
+    capabilities.codes.extend(pre_device.leds.iter().copied());
+    let mut device = OutputDevice::with_name_and_capabilities(pre_device.name.clone(), pre_device.identity.clone(), capabilities)
+        .with_context(match pre_device.create_links.first() {
             Some(path) => format!("While creating the output device \"{}\":", path.display()),
             None => "While creating an output device:".to_string(),
         })?;
 
     device.set_repeat_mode(pre_device.repeat_mode);
+    device.syn_mode = pre_device.syn_mode;
+    device.strict_capabilities = pre_device.pinned_caps.is_some();
+    device.dedup = pre_device.dedup;
+    if let Some(repeat_info) = pre_device.repeat_info {
+        device.set_repeat_info(repeat_info);
+    }
 
-    if let Some(ref path) = pre_device.create_link {
-        device.set_link(path.clone())
-            .map_err(SystemError::from)
-            .with_context(format!("While creating a symlink at \"{}\":", path.display()))?;
-    };
+    if ! pre_device.permissions.is_default() {
+        device.set_permissions(pre_device.permissions)
+            .with_context("While setting the permissions of an output device:")?;
+    }
+    if ! pre_device.create_links.is_empty() {
+        device.set_links(&pre_device.create_links)?;
+    }
 
     Ok(device)
 }
 
+/// Udev sets the permissions of new device nodes asynchronously, which would undo our own changes
+/// if udev gets to it after us. Udev writes its database entry for a device after it has set up
+/// the device node, so we wait for that entry to show up for a short while.
+fn wait_for_udev(devnode: &Path) {
+    const UDEV_DATA_DIR: &str = "/run/udev/data";
+    const MAX_ATTEMPTS: u32 = 50;
+    if ! Path::new(UDEV_DATA_DIR).is_dir() {
+        return;
+    }
+    let rdev = match fs::metadata(devnode) {
+        Ok(metadata) => metadata.rdev(),
+        Err(_) => return,
+    };
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+    let entry = PathBuf::from(format!("{}/c{}:{}", UDEV_DATA_DIR, major, minor));
+    for _ in 0 .. MAX_ATTEMPTS {
+        if entry.exists() {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+/// Returns whether anything that programs write to this output device needs to be read.
+fn receives_feedback(pre_device: &PreOutputDevice) -> bool {
+    pre_device.forward_ff.is_some() || pre_device.forward_leds
+        || ! pre_device.ff_effects.is_empty() || ! pre_device.leds.is_empty()
+}
+
+/// Makes the feedback epoll watch for force feedback requests and LED changes made to the given device.
+fn watch_feedback(feedback_epoll: &Option<OwnedFd>, device: &OutputDevice) -> Result<(), SystemError> {
+    let feedback_epoll = match feedback_epoll {
+        Some(feedback_epoll) => feedback_epoll,
+        None => return Err(SystemError::new("Internal error: no epoll for feedback has been created. This is a bug.")),
+    };
+    let mut event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: 0 };
+    let res = unsafe {
+        libc::epoll_ctl(feedback_epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, device.uinput_fd(), &mut event)
+    };
+    if res < 0 {
+        return Err(SystemError::os_with_context("While watching an output device for feedback:"));
+    }
+    Ok(())
+}
+
 fn format_output_device_recreation_warning(recreated_devices: &[&PreOutputDevice]) -> Result<String, Error>  {
     if recreated_devices.is_empty() {
         return Ok("".to_owned());
     }
     let named_recreated_devices: Vec<String> = recreated_devices.iter().filter_map(
-        |device| device.create_link.as_ref().map(
+        |device| device.create_links.first().map(
             |path| format!("\"{}\"", path.display())
         )
     ).collect();
@@ -460,4 +1394,15 @@ fn format_output_device_recreation_warning(recreated_devices: &[&PreOutputDevice
     write!(&mut msg, "This may cause other programs that have grabbed the output devices to lose track of them.")?;
 
     Ok(msg)
-}
\ No newline at end of file
+}
+
+/// Describes an output the way the user would recognize it from the --output argument.
+fn describe_output(pre_device: &PreOutputDevice) -> String {
+    match (&pre_device.record, pre_device.create_links.first()) {
+        (Some((RecordTarget::File(path), _)), _) => format!("file={}", path.display()),
+        (Some((RecordTarget::Tcp(address), _)), _) => format!("tcp={}", address),
+        (Some((RecordTarget::Stdout, _)), _) => "stdout".to_owned(),
+        (None, Some(path)) => format!("create-link={}", path.display()),
+        (None, None) => format!("name=\"{}\"", pre_device.name),
+    }
+}
@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Records the events that reach an output to a file or stdout, or sends them over the network
+//! instead of writing them to a virtual device. Recordings in the binary format can be replayed with
+//! `--input file=PATH` and received with `--input tcp=HOST:PORT`.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::FromRawFd;
+use std::path::PathBuf;
+
+use crate::ecodes;
+use crate::error::{Context, SystemError};
+use crate::event::{Event, EventCode, EventType, EventValue};
+use crate::io::network::NetworkOutput;
+use crate::io::serialize::{self, current_time_us, TimedEvent};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordFormat {
+    /// The same format as the kernel's `struct input_event`, as described in the serialize module.
+    Binary,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Where a Recorder sends its events to.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RecordTarget {
+    File(PathBuf),
+    /// An address of the form HOST:PORT to connect to over TCP.
+    Tcp(String),
+    Stdout,
+}
+
+enum Destination {
+    File(File),
+    Network(NetworkOutput),
+}
+
+pub struct Recorder {
+    format: RecordFormat,
+    destination: Destination,
+    /// The path or address of the destination, for error messages.
+    description: String,
+    /// The encoded events that have not been written to the destination yet.
+    buffer: Vec<u8>,
+    /// Keeps track of whether we've written any events since the last SYN event.
+    should_syn: bool,
+    /// Set after a write fails, so we don't flood the user with the same error for every event.
+    has_failed: bool,
+}
+
+impl Recorder {
+    pub fn open(target: &RecordTarget, format: RecordFormat) -> Result<Recorder, SystemError> {
+        let destination = match target {
+            RecordTarget::File(path) => Destination::File(
+                OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .custom_flags(libc::O_CLOEXEC)
+                    .open(path)?
+            ),
+            RecordTarget::Tcp(address) => Destination::Network(NetworkOutput::connect(address.clone())?),
+            RecordTarget::Stdout => Destination::File(take_stdout()?),
+        };
+        Ok(Recorder {
+            format, destination,
+            description: match target {
+                RecordTarget::File(path) => path.display().to_string(),
+                RecordTarget::Tcp(address) => address.clone(),
+                RecordTarget::Stdout => "stdout".to_owned(),
+            },
+            buffer: Vec::new(),
+            should_syn: false,
+            has_failed: false,
+        })
+    }
+
+    pub fn write_event(&mut self, event: Event) {
+        self.write(event.code, event.value);
+    }
+
+    fn write(&mut self, code: EventCode, value: EventValue) {
+        let timed_event = TimedEvent {
+            time_us: current_time_us(),
+            code, value,
+        };
+        match self.format {
+            RecordFormat::Binary => self.buffer.extend_from_slice(&serialize::encode_event(timed_event)),
+            RecordFormat::Json => {
+                self.buffer.extend_from_slice(serialize::encode_event_json(timed_event).as_bytes());
+                self.buffer.push(b'\n');
+            },
+        };
+        self.should_syn = ! code.ev_type().is_syn();
+    }
+
+    /// Writes a SYN event if the last written event was not one, and sends everything written
+    /// so far to the destination.
+    pub fn syn_if_required(&mut self) {
+        if self.should_syn {
+            self.write(EventCode::new(EventType::SYN, ecodes::SYN_REPORT), 0);
+        }
+        if self.buffer.is_empty() {
+            return;
+        }
+        let result = match &mut self.destination {
+            Destination::File(file) => file.write_all(&self.buffer).map_err(SystemError::from),
+            Destination::Network(output) => output.send(&self.buffer),
+        };
+        // Whatever could not be written is dropped, so a failure does not leave half an event
+        // at the start of the next write.
+        self.buffer.clear();
+
+        match result {
+            Ok(()) => self.has_failed = false,
+            Err(error) => if ! self.has_failed {
+                self.has_failed = true;
+                error.with_context(format!("While sending events to \"{}\":", self.description))
+                    .print_err();
+            },
+        }
+    }
+}
+
+/// Returns a file that writes to the original stdout, and redirects the stdout of this process to
+/// stderr so the messages printed by evsieve do not end up between the events.
+fn take_stdout() -> Result<File, SystemError> {
+    let stdout = std::io::stdout();
+    let mut stdout_lock = stdout.lock();
+    stdout_lock.flush()?;
+    unsafe {
+        let fd = libc::fcntl(libc::STDOUT_FILENO, libc::F_DUPFD_CLOEXEC, 0);
+        if fd < 0 {
+            return Err(SystemError::os_with_context("While duplicating stdout:"));
+        }
+        let file = File::from_raw_fd(fd);
+        if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(SystemError::os_with_context("While redirecting stdout to stderr:"));
+        }
+        Ok(file)
+    }
+}
@@ -0,0 +1,237 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Replays event streams that were recorded to a file. Recordings are stored in the format described
+//! in the serialize module, which means that a recording can be made by simply copying the data
+//! of an event device, e.g. `cat /dev/input/event3 > recording.bin`.
+
+use std::collections::VecDeque;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use crate::capability::Capabilities;
+use crate::domain::Domain;
+use crate::error::SystemError;
+use crate::event::{Event, EventCode, EventValue};
+use crate::io::fd::{HasFixedFd, OwnedFd};
+use crate::io::serialize;
+use crate::io::source::{EventSource, SourceState};
+use crate::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplayTiming {
+    /// Replay the events with the same intervals between them as they were recorded with.
+    Original,
+    /// Replay the events as fast as possible.
+    Fast,
+}
+
+/// An event as read from a recording, along with the time since the start of the recording
+/// at which it was recorded.
+type RecordedEvent = (Duration, EventCode, EventValue);
+
+pub struct ReplayDevice {
+    path: PathBuf,
+    timing: ReplayTiming,
+    capabilities: Capabilities,
+    /// The events that have not been replayed yet.
+    events: VecDeque<RecordedEvent>,
+    /// Maps (type, code) pairs to the last value they were replayed with.
+    state: SourceState,
+    /// The point in time that corresponds to the start of the recording.
+    start_time: Instant,
+    /// A timerfd that becomes readable when the next event is due.
+    timer: OwnedFd,
+}
+
+impl ReplayDevice {
+    pub fn open(path: PathBuf, domain: Domain, timing: ReplayTiming) -> Result<ReplayDevice, SystemError> {
+        let bytes = std::fs::read(&path)?;
+        let events = parse_recording(&bytes)?;
+        if events.is_empty() {
+            return Err(SystemError::new("The recording does not contain any events."));
+        }
+        let capabilities = capabilities_of_recording(&events);
+        let timer = unsafe {
+            OwnedFd::from_syscall(libc::timerfd_create(
+                libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC
+            ))?
+        };
+
+        let mut device = ReplayDevice {
+            path, timing, capabilities, events,
+            state: SourceState::new(domain),
+            start_time: Instant::now(),
+            timer,
+        };
+        device.arm_timer()?;
+        Ok(device)
+    }
+
+    /// Returns all events that are due to be replayed by now.
+    pub fn poll(&mut self) -> Result<Vec<Event>, SystemError> {
+        // Reset the expiration count of the timer.
+        let mut buffer = [0u8; 8];
+        unsafe {
+            libc::read(self.timer.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len());
+        }
+
+        let mut events: Vec<Event> = Vec::new();
+        match self.timing {
+            ReplayTiming::Original => {
+                let elapsed = Instant::now().checked_duration_since(self.start_time)
+                    .unwrap_or_else(|| Duration::from_nanos(0));
+                while let Some(&(time, code, value)) = self.events.front() {
+                    if time > elapsed {
+                        break;
+                    }
+                    self.events.pop_front();
+                    events.push(self.state.synthesize_event(code, value));
+                }
+            },
+            ReplayTiming::Fast => {
+                // Replay a single report at a time, so the other input devices get a chance to
+                // interleave their events with ours.
+                while let Some((_, code, value)) = self.events.pop_front() {
+                    events.push(self.state.synthesize_event(code, value));
+                    if code.ev_type().is_syn() {
+                        break;
+                    }
+                }
+            },
+        }
+
+        self.arm_timer()?;
+        Ok(events)
+    }
+
+    /// Makes the timer fire when the next event is due. Does nothing if there are no events left.
+    fn arm_timer(&mut self) -> Result<(), SystemError> {
+        let next_time = match self.events.front() {
+            Some(&(time, _, _)) => time,
+            None => return Ok(()),
+        };
+        let delay = match self.timing {
+            ReplayTiming::Original => (self.start_time + next_time)
+                .checked_duration_since(Instant::now())
+                .unwrap_or_else(|| Duration::from_nanos(0)),
+            ReplayTiming::Fast => Duration::from_nanos(0),
+        };
+        // A timer with a value of zero would be disarmed instead.
+        let delay = std::cmp::max(delay, Duration::from_nanos(1));
+
+        let timer_spec = libc::itimerspec {
+            it_interval: Duration::from_nanos(0).into(),
+            it_value: delay.into(),
+        };
+        let result = unsafe {
+            libc::timerfd_settime(self.timer.as_raw_fd(), 0, &timer_spec, std::ptr::null_mut())
+        };
+        if result < 0 {
+            return Err(SystemError::os_with_context("While arming the timer of a replay:"));
+        }
+        Ok(())
+    }
+
+    /// Returns the events of the recording that have not been replayed yet, without their timing.
+    pub fn remaining_events(&self) -> impl Iterator<Item=(EventCode, EventValue)> + '_ {
+        self.events.iter().map(|&(_, code, value)| (code, value))
+    }
+
+    /// Returns true if all events of the recording have been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.state.domain()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl EventSource for ReplayDevice {
+    fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event {
+        self.state.synthesize_event(code, value)
+    }
+
+    fn pressed_keys(&self) -> Vec<EventCode> {
+        self.state.pressed_keys()
+    }
+}
+
+impl AsRawFd for ReplayDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timer.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for ReplayDevice {}
+
+/// Interprets a sequence of bytes as a sequence of serialized events.
+fn parse_recording(bytes: &[u8]) -> Result<VecDeque<RecordedEvent>, SystemError> {
+    let chunks = bytes.chunks_exact(serialize::EVENT_SIZE);
+    if ! chunks.remainder().is_empty() {
+        return Err(SystemError::new(format!(
+            "The recording is {} bytes long, which is not a multiple of the size of an event ({} bytes). The recording may be truncated or have been made on a different architecture.",
+            bytes.len(), serialize::EVENT_SIZE
+        )));
+    }
+
+    let mut events: VecDeque<RecordedEvent> = VecDeque::new();
+    let mut first_time: Option<i64> = None;
+    for chunk in chunks {
+        let event = serialize::decode_event(chunk)?;
+        let start_time = *first_time.get_or_insert(event.time_us);
+        // Clock adjustments may have made the timestamps go backwards. Treat such events as if
+        // they happened at the start of the recording.
+        let offset = Duration::from_micros(std::cmp::max(event.time_us - start_time, 0) as u64);
+        events.push_back((offset, event.code, event.value));
+    }
+
+    Ok(events)
+}
+
+/// Since a recording does not include the capabilities of the device it was recorded from, we
+/// assume that it was capable of exactly the events that show up in the recording.
+fn capabilities_of_recording(events: &VecDeque<RecordedEvent>) -> Capabilities {
+    let mut capabilities = Capabilities::new();
+    for &(_, code, value) in events {
+        capabilities.add_observed_event(code, value);
+    }
+    capabilities
+}
+
+#[test]
+fn unittest() {
+    let make_event = |sec, usec, type_, code, value| libc::input_event {
+        time: libc::timeval { tv_sec: sec, tv_usec: usec },
+        type_, code, value,
+    };
+    let raw_events = [
+        make_event(100, 500_000, crate::ecodes::EV_KEY, 30, 1),
+        make_event(100, 500_000, 0, 0, 0),
+        make_event(101, 250_000, crate::ecodes::EV_KEY, 30, 0),
+        make_event(101, 250_000, 0, 0, 0),
+    ];
+    let bytes: &[u8] = unsafe {
+        std::slice::from_raw_parts(
+            raw_events.as_ptr() as *const u8,
+            std::mem::size_of_val(&raw_events),
+        )
+    };
+
+    let events = parse_recording(bytes).unwrap();
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0].0, Duration::from_nanos(0));
+    assert_eq!(events[0].2, 1);
+    assert_eq!(events[2].0, Duration::from_millis(750));
+    assert_eq!(events[2].2, 0);
+
+    assert!(parse_recording(&bytes[1..]).is_err());
+    assert!(parse_recording(&[]).unwrap().is_empty());
+}
@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Whenever evsieve needs to store events in a file or send them to another evsieve, it uses the
+//! same binary format as the kernel's `struct input_event`. Besides being simple, this means that
+//! any stream of data read from an event device is also a valid stream of serialized events.
+
+use crate::ecodes;
+use crate::error::SystemError;
+use crate::event::{EventCode, EventType, EventValue};
+
+/// The amount of bytes that a single serialized event takes.
+pub const EVENT_SIZE: usize = std::mem::size_of::<libc::input_event>();
+
+/// An event along with the timestamp it was serialized with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimedEvent {
+    /// The amount of microseconds since an arbitrary starting point.
+    pub time_us: i64,
+    pub code: EventCode,
+    pub value: EventValue,
+}
+
+/// Decodes a single event. The slice must be exactly EVENT_SIZE bytes long.
+pub fn decode_event(bytes: &[u8]) -> Result<TimedEvent, SystemError> {
+    assert_eq!(bytes.len(), EVENT_SIZE);
+    let raw_event: libc::input_event = unsafe {
+        std::ptr::read_unaligned(bytes.as_ptr() as *const libc::input_event)
+    };
+    if raw_event.type_ > ecodes::EV_MAX {
+        return Err(SystemError::new(format!(
+            "Encountered an event of type {}, which exceeds the maximum value of {} defined by EV_MAX.",
+            raw_event.type_, ecodes::EV_MAX
+        )));
+    }
+
+    Ok(TimedEvent {
+        time_us: (raw_event.time.tv_sec as i64) * 1_000_000 + (raw_event.time.tv_usec as i64),
+        code: EventCode::new(EventType::new(raw_event.type_), raw_event.code),
+        value: raw_event.value,
+    })
+}
+
+/// Encodes a single event in the same format as decode_event() expects.
+pub fn encode_event(event: TimedEvent) -> [u8; EVENT_SIZE] {
+    let raw_event = libc::input_event {
+        time: libc::timeval {
+            tv_sec: event.time_us.div_euclid(1_000_000) as libc::time_t,
+            tv_usec: event.time_us.rem_euclid(1_000_000) as libc::suseconds_t,
+        },
+        type_: event.code.ev_type().into(),
+        code: event.code.code(),
+        value: event.value,
+    };
+    let mut bytes = [0u8; EVENT_SIZE];
+    unsafe {
+        std::ptr::write_unaligned(bytes.as_mut_ptr() as *mut libc::input_event, raw_event);
+    }
+    bytes
+}
+
+/// Encodes a single event as a line of JSON, for consumption by other programs rather than by evsieve.
+/// The line does not include a trailing newline.
+pub fn encode_event_json(event: TimedEvent) -> String {
+    let name = ecodes::event_name(event.code);
+    let (type_name, code_name) = match crate::utils::split_once(&name, ":") {
+        (type_name, Some(code_name)) => (type_name, code_name),
+        (type_name, None) => (type_name, ""),
+    };
+    format!(
+        "{{\"time\":{}.{:06},\"type\":\"{}\",\"code\":\"{}\",\"value\":{}}}",
+        event.time_us.div_euclid(1_000_000), event.time_us.rem_euclid(1_000_000),
+        type_name, code_name, event.value,
+    )
+}
+
+/// Turns a string into a JSON string literal, including the surrounding quotes.
+pub fn quote_json(string: &str) -> String {
+    let mut result = String::with_capacity(string.len() + 2);
+    result.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// The current time in microseconds since the epoch, like the timestamps of event devices.
+pub fn current_time_us() -> i64 {
+    let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut time) };
+    (time.tv_sec as i64) * 1_000_000 + (time.tv_nsec as i64) / 1_000
+}
+
+#[test]
+fn unittest() {
+    let event = TimedEvent {
+        time_us: 12_000_345,
+        code: EventCode::new(EventType::KEY, 30),
+        value: 1,
+    };
+    let bytes = encode_event(event);
+    assert_eq!(decode_event(&bytes).unwrap(), event);
+
+    assert_eq!(quote_json("/dev/input/by-id/kbd"), "\"/dev/input/by-id/kbd\"");
+    assert_eq!(quote_json("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Every device that events enter the stream from remembers the last value of each event code it
+//! has sent, so it can tell the stream the previous value of each event and knows which keys it
+//! holds. The latter is needed to release those keys when the device goes away, when evsieve gets
+//! paused, and when the active profile changes.
+
+use std::collections::HashMap;
+
+use crate::domain::Domain;
+use crate::event::{Event, EventCode, EventValue, Namespace};
+
+/// Implemented by all devices that events enter the stream from.
+pub trait EventSource {
+    /// Given an event code and value, creates an `Event` that has all entries filled out as if
+    /// it was a real event that was received from this source, and updates the state of this
+    /// source as if it was really received.
+    fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event;
+
+    /// Returns the EV_KEY codes that are currently held down on this source.
+    fn pressed_keys(&self) -> Vec<EventCode>;
+
+    /// Returns release events for all keys that are held down on this source. Afterwards, this
+    /// source considers them released.
+    fn release_pressed_keys(&mut self) -> Vec<Event> {
+        self.pressed_keys().into_iter()
+            .map(|code| self.synthesize_event(code, 0))
+            .collect()
+    }
+}
+
+/// The state that sources without any special needs keep.
+pub struct SourceState {
+    domain: Domain,
+    /// Maps (type, code) pairs to the last known value of said pair.
+    values: HashMap<EventCode, EventValue>,
+}
+
+impl SourceState {
+    pub fn new(domain: Domain) -> SourceState {
+        SourceState { domain, values: HashMap::new() }
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.domain
+    }
+
+    /// Returns the last known value of an event code, or 0 if it was never seen.
+    pub fn value(&self, code: EventCode) -> EventValue {
+        self.values.get(&code).copied().unwrap_or(0)
+    }
+
+    /// Changes the last known value of an event code without generating an event.
+    pub fn set_value(&mut self, code: EventCode, value: EventValue) {
+        self.values.insert(code, value);
+    }
+}
+
+impl EventSource for SourceState {
+    fn synthesize_event(&mut self, code: EventCode, value: EventValue) -> Event {
+        let previous_value = self.values.insert(code, value).unwrap_or(0);
+        Event::new(
+            code, value, previous_value, self.domain, Namespace::Input,
+        )
+    }
+
+    fn pressed_keys(&self) -> Vec<EventCode> {
+        self.values.iter()
+            .filter(|(code, value)| code.ev_type().is_key() && **value > 0)
+            .map(|(&code, &_value)| code)
+            .collect()
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::EventType;
+    let key_a = EventCode::new(EventType::KEY, 30);
+    let key_b = EventCode::new(EventType::KEY, 48);
+    let rel_x = EventCode::new(EventType::REL, 0);
+
+    let mut state = SourceState::new(crate::domain::get_unique_domain());
+    assert_eq!(state.synthesize_event(key_a, 1).previous_value, 0);
+    assert_eq!(state.synthesize_event(key_a, 2).previous_value, 1);
+    state.synthesize_event(key_b, 1);
+    state.synthesize_event(key_b, 0);
+    state.synthesize_event(rel_x, 5);
+    assert_eq!(state.pressed_keys(), vec![key_a]);
+
+    let releases = state.release_pressed_keys();
+    assert_eq!(releases.len(), 1);
+    assert_eq!((releases[0].code, releases[0].value, releases[0].previous_value), (key_a, 0, 2));
+    assert!(state.pressed_keys().is_empty());
+    assert_eq!(state.value(rel_x), 5);
+}
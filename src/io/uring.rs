@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! A minimal interface to the kernel's io_uring, which lets a program hand many I/O operations
+//! to the kernel and wait for them to complete with a single system call. Only the operations
+//! that the Epoll needs are implemented. The definitions mirror those in <linux/io_uring.h>,
+//! which are not part of the libc crate.
+
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::error::{Context, SystemError};
+use crate::io::fd::OwnedFd;
+
+// The io_uring system calls have the same number on all architectures that use the generic
+// system call table, but not on those that predate it.
+#[cfg(target_arch = "mips")]
+const SYSCALL_OFFSET: libc::c_long = 4000;
+#[cfg(target_arch = "mips64")]
+const SYSCALL_OFFSET: libc::c_long = 5000;
+#[cfg(not(any(target_arch = "mips", target_arch = "mips64")))]
+const SYSCALL_OFFSET: libc::c_long = 0;
+
+const SYS_IO_URING_SETUP: libc::c_long = SYSCALL_OFFSET + 425;
+const SYS_IO_URING_ENTER: libc::c_long = SYSCALL_OFFSET + 426;
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+
+const IORING_ENTER_GETEVENTS: u32 = 1;
+
+const IORING_FEAT_SINGLE_MMAP: u32 = 1;
+/// Introduced in Linux 5.7. Every kernel that has it also supports all operations used here.
+const IORING_FEAT_FAST_POLL: u32 = 1 << 5;
+
+const IORING_OP_POLL_ADD: u8 = 6;
+const IORING_OP_POLL_REMOVE: u8 = 7;
+const IORING_OP_TIMEOUT: u8 = 11;
+const IORING_OP_TIMEOUT_REMOVE: u8 = 12;
+const IORING_OP_WRITE: u8 = 23;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    resv2: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    resv2: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+/// Unlike libc::timespec, has the same size on 32-bit and 64-bit systems.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct KernelTimespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+impl KernelTimespec {
+    pub fn from_millis(msec: u64) -> KernelTimespec {
+        KernelTimespec {
+            tv_sec: (msec / 1000) as i64,
+            tv_nsec: ((msec % 1000) * 1_000_000) as i64,
+        }
+    }
+}
+
+/// A submission queue entry: an operation that the kernel shall perform.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    op_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    pad: [u64; 2],
+}
+
+impl Sqe {
+    /// Completes once the file becomes ready for any of the given poll events. The result of the
+    /// completion is the mask of events that the file is ready for.
+    pub fn poll_add(fd: RawFd, events: libc::c_short, user_data: u64) -> Sqe {
+        let events = events as u16 as u32;
+        // The kernel swaps the halves of the 32-bit event mask on big endian systems.
+        #[cfg(target_endian = "big")]
+        let events = events.rotate_left(16);
+        Sqe { opcode: IORING_OP_POLL_ADD, fd, op_flags: events, user_data, ..Sqe::default() }
+    }
+
+    /// Cancels the poll_add operation that was submitted with the given user data.
+    pub fn poll_remove(target_user_data: u64, user_data: u64) -> Sqe {
+        Sqe { opcode: IORING_OP_POLL_REMOVE, fd: -1, addr: target_user_data, user_data, ..Sqe::default() }
+    }
+
+    /// Completes once the given time has passed. The kernel reads the time when the entry gets
+    /// submitted.
+    pub fn timeout(time: &KernelTimespec, user_data: u64) -> Sqe {
+        Sqe { opcode: IORING_OP_TIMEOUT, fd: -1, addr: time as *const KernelTimespec as u64, len: 1, user_data, ..Sqe::default() }
+    }
+
+    /// Cancels the timeout operation that was submitted with the given user data.
+    pub fn timeout_remove(target_user_data: u64, user_data: u64) -> Sqe {
+        Sqe { opcode: IORING_OP_TIMEOUT_REMOVE, fd: -1, addr: target_user_data, user_data, ..Sqe::default() }
+    }
+
+    /// Writes the data to the file at its current position.
+    ///
+    /// # Safety
+    /// The data must remain valid until the completion of this entry has been received.
+    pub unsafe fn write(fd: RawFd, data: &[u8], user_data: u64) -> Sqe {
+        Sqe {
+            opcode: IORING_OP_WRITE, fd,
+            addr: data.as_ptr() as u64,
+            len: data.len() as u32,
+            // Use the current file position, like write() does.
+            off: u64::MAX,
+            user_data,
+            ..Sqe::default()
+        }
+    }
+}
+
+/// A completion queue entry: the result of an operation.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Completion {
+    pub user_data: u64,
+    /// What the equivalent system call would have returned, or a negative errno.
+    pub result: i32,
+    flags: u32,
+}
+
+/// A region of memory shared with the kernel.
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    fn new(fd: RawFd, len: usize, offset: libc::off_t) -> Result<Mmap, SystemError> {
+        let ptr = unsafe { libc::mmap(
+            std::ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED | libc::MAP_POPULATE, fd, offset,
+        )};
+        if ptr == libc::MAP_FAILED {
+            return Err(SystemError::os_with_context("While mapping the queues of an io_uring into memory:"));
+        }
+        Ok(Mmap { ptr, len })
+    }
+
+    /// # Safety
+    /// The offset must lie within the mapped region and be suitably aligned for T.
+    unsafe fn at<T>(&self, offset: u32) -> *mut T {
+        (self.ptr as *mut u8).add(offset as usize) as *mut T
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len); }
+    }
+}
+
+pub struct Uring {
+    /// The mappings must be dropped before the file descriptor is closed.
+    sq_ring: Mmap,
+    /// None if the completion queue shares its mapping with the submission queue.
+    cq_ring: Option<Mmap>,
+    sqes: Mmap,
+    fd: OwnedFd,
+
+    sq_off: SqringOffsets,
+    sq_entries: u32,
+    cq_off: CqringOffsets,
+    /// The amount of entries that have been added to the submission queue but not submitted yet.
+    to_submit: u32,
+}
+
+impl Uring {
+    /// Returns an error if the kernel does not support io_uring, or if it is too old to support
+    /// everything this module offers.
+    pub fn new(entries: u32) -> Result<Uring, SystemError> {
+        let mut params = Params::default();
+        let fd = unsafe {
+            OwnedFd::from_syscall(libc::syscall(SYS_IO_URING_SETUP, entries, &mut params as *mut Params) as libc::c_int)
+                .with_context("While trying to set up an io_uring:")?
+        };
+        if params.features & IORING_FEAT_FAST_POLL == 0 {
+            return Err(SystemError::new("The kernel's io_uring implementation is too old."));
+        }
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * std::mem::size_of::<u32>();
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * std::mem::size_of::<Completion>();
+        let (sq_ring, cq_ring) = match params.features & IORING_FEAT_SINGLE_MMAP != 0 {
+            true => (Mmap::new(fd.as_raw_fd(), std::cmp::max(sq_len, cq_len), IORING_OFF_SQ_RING)?, None),
+            false => (
+                Mmap::new(fd.as_raw_fd(), sq_len, IORING_OFF_SQ_RING)?,
+                Some(Mmap::new(fd.as_raw_fd(), cq_len, IORING_OFF_CQ_RING)?),
+            ),
+        };
+        let sqes = Mmap::new(fd.as_raw_fd(), params.sq_entries as usize * std::mem::size_of::<Sqe>(), IORING_OFF_SQES)?;
+
+        Ok(Uring {
+            sq_ring, cq_ring, sqes, fd,
+            sq_entries: params.sq_entries,
+            sq_off: params.sq_off,
+            cq_off: params.cq_off,
+            to_submit: 0,
+        })
+    }
+
+    fn cq_ring(&self) -> &Mmap {
+        self.cq_ring.as_ref().unwrap_or(&self.sq_ring)
+    }
+
+    /// Adds an entry to the submission queue. It gets submitted by the next call to `submit()`,
+    /// or right away if the submission queue is full.
+    pub fn push(&mut self, sqe: Sqe) -> Result<(), SystemError> {
+        if self.to_submit >= self.sq_entries {
+            self.submit(0)?;
+        }
+        unsafe {
+            let tail_ptr: &AtomicU32 = &*self.sq_ring.at(self.sq_off.tail);
+            let mask = *self.sq_ring.at::<u32>(self.sq_off.ring_mask);
+            let tail = tail_ptr.load(Ordering::Relaxed);
+            let index = tail & mask;
+            *self.sqes.at::<Sqe>(0).add(index as usize) = sqe;
+            *self.sq_ring.at::<u32>(self.sq_off.array).add(index as usize) = index;
+            tail_ptr.store(tail.wrapping_add(1), Ordering::Release);
+        }
+        self.to_submit += 1;
+        Ok(())
+    }
+
+    /// Submits all entries that were pushed so far and waits until at least `min_complete`
+    /// operations have completed.
+    pub fn submit(&mut self, min_complete: u32) -> Result<(), SystemError> {
+        let flags = match min_complete {
+            0 => 0,
+            _ => IORING_ENTER_GETEVENTS,
+        };
+        loop {
+            let result = unsafe { libc::syscall(
+                SYS_IO_URING_ENTER, self.fd.as_raw_fd(), self.to_submit, min_complete, flags,
+                std::ptr::null::<libc::sigset_t>(), 0 as libc::size_t,
+            )};
+            if result >= 0 {
+                // If waiting got interrupted after submitting, the amount submitted is returned
+                // instead of an error. The caller has to deal with fewer completions in that case.
+                self.to_submit -= std::cmp::min(result as u32, self.to_submit);
+                return Ok(());
+            }
+            let error = std::io::Error::last_os_error();
+            match error.kind() {
+                std::io::ErrorKind::Interrupted => continue,
+                _ => return Err(SystemError::from(error).with_context("While submitting operations to an io_uring:")),
+            }
+        }
+    }
+
+    /// Takes the oldest completion out of the completion queue.
+    pub fn pop_completion(&mut self) -> Option<Completion> {
+        let cq_ring = self.cq_ring();
+        unsafe {
+            let head_ptr: &AtomicU32 = &*cq_ring.at(self.cq_off.head);
+            let tail_ptr: &AtomicU32 = &*cq_ring.at(self.cq_off.tail);
+            let mask = *cq_ring.at::<u32>(self.cq_off.ring_mask);
+            let head = head_ptr.load(Ordering::Relaxed);
+            if head == tail_ptr.load(Ordering::Acquire) {
+                return None;
+            }
+            let completion = *cq_ring.at::<Completion>(self.cq_off.cqes).add((head & mask) as usize);
+            head_ptr.store(head.wrapping_add(1), Ordering::Release);
+            Some(completion)
+        }
+    }
+}
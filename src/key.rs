@@ -6,7 +6,7 @@ use crate::event::{Event, EventType, EventCode, Channel, Namespace, VirtualEvent
 use crate::utils;
 use crate::error::ArgumentError;
 use crate::capability::{Capability, CapMatch};
-use crate::range::Range;
+use crate::range::{ExtendedInteger, Range};
 use crate::ecodes;
 use crate::error::Context;
 
@@ -95,6 +95,12 @@ impl Key {
         self.properties.push(property);
     }
 
+    /// Restricts this key to only match ABS_MT_* events belonging to a multitouch slot within
+    /// the given range. Only valid for filter keys.
+    pub fn add_slot_filter(&mut self, range: Range) {
+        self.add_property(KeyProperty::Slot(range));
+    }
+
     /// Returns Some(EventType) if this Key will only ever accept events of a certain type.
     /// If it may accept different types of events, returns None.
     pub fn requires_event_type(&self) -> Option<EventType> {
@@ -107,6 +113,7 @@ impl Key {
                 | KeyProperty::Namespace(_)
                 | KeyProperty::Value(_)
                 | KeyProperty::PreviousValue(_)
+                | KeyProperty::Slot(_)
                 | KeyProperty::DeltaFactor(_)
                 | KeyProperty::AbsoluteFactor(_)
                 => (),
@@ -115,6 +122,22 @@ impl Key {
         None
     }
 
+    /// Returns Some(Namespace) if this Key will only ever accept events within a certain namespace.
+    pub fn requires_namespace(&self) -> Option<Namespace> {
+        self.properties.iter().find_map(|property| match property {
+            KeyProperty::Namespace(namespace) => Some(*namespace),
+            _ => None,
+        })
+    }
+
+    /// Returns Some(EventCode) if this Key will only ever accept events with a certain code.
+    pub fn requires_event_code(&self) -> Option<EventCode> {
+        self.properties.iter().find_map(|property| match property {
+            KeyProperty::Code(code) => Some(*code),
+            _ => None,
+        })
+    }
+
     /// Returns true if some event may match both key_1 and key_2.
     pub fn intersects_with(&self, other: &Key) -> bool {
         // Tests interaction between (Type, VirtualType) and (Type, Code).
@@ -142,6 +165,7 @@ impl Key {
 
                     (KeyProperty::Value(left), KeyProperty::Value(right))
                     | (KeyProperty::PreviousValue(left), KeyProperty::PreviousValue(right))
+                    | (KeyProperty::Slot(left), KeyProperty::Slot(right))
                         => left.intersects_with(right),
                     
                     (KeyProperty::Code(_), _)
@@ -151,6 +175,7 @@ impl Key {
                     | (KeyProperty::VirtualType(_), _)
                     | (KeyProperty::Value(_), _)
                     | (KeyProperty::PreviousValue(_), _)
+                    | (KeyProperty::Slot(_), _)
                     | (KeyProperty::DeltaFactor(_), _)
                     | (KeyProperty::AbsoluteFactor(_), _)
                         => true,
@@ -172,6 +197,8 @@ enum KeyProperty {
     Namespace(Namespace),
     Value(Range),
     PreviousValue(Range),
+    /// The multitouch slot of an ABS_MT_* event. Only valid for filter keys.
+    Slot(Range),
     /// Only valid for filter keys.
     Type(EventType),
     /// Only valid for filter keys.
@@ -195,6 +222,10 @@ impl KeyProperty {
             KeyProperty::Namespace(value) => event.namespace == value,
             KeyProperty::Value(range) => range.contains(event.value),
             KeyProperty::PreviousValue(range) => range.contains(event.previous_value),
+            KeyProperty::Slot(range) => match event.slot {
+                Some(slot) => range.contains(slot),
+                None => false,
+            },
             KeyProperty::AbsoluteFactor(_) | KeyProperty::DeltaFactor(_) => {
                 if cfg!(debug_assertions) {
                     panic!("Cannot filter events based on relative values. Panicked during event mapping.");
@@ -218,6 +249,7 @@ impl KeyProperty {
             | KeyProperty::AbsoluteFactor(_)
             | KeyProperty::DeltaFactor(_)
                 => true,
+            KeyProperty::Slot(_) => ecodes::is_abs_mt(code),
         }
     }
 
@@ -229,6 +261,7 @@ impl KeyProperty {
             KeyProperty::Namespace(value) => event.namespace = value,
             KeyProperty::Value(range) => event.value = range.bound(event.value),
             KeyProperty::PreviousValue(range) => event.previous_value = range.bound(event.previous_value),
+            KeyProperty::Slot(_) => {},
             KeyProperty::AbsoluteFactor(factor) => {
                 event.value = (
                     (event.value as f64 * factor).trunc()
@@ -276,6 +309,10 @@ impl KeyProperty {
                 }
             },
             KeyProperty::PreviousValue(_range) => CapMatch::Maybe,
+            KeyProperty::Slot(_range) => match ecodes::is_abs_mt(cap.code) {
+                true => CapMatch::Maybe,
+                false => CapMatch::No,
+            },
             KeyProperty::AbsoluteFactor(_) | KeyProperty::DeltaFactor(_) => {
                 panic!("Internal invariant violated: cannot filter events based on relative values.");
             },
@@ -289,6 +326,7 @@ impl KeyProperty {
             KeyProperty::Namespace(value) => cap.namespace = value,
             KeyProperty::Value(range) => cap.value_range = range.bound_range(&cap.value_range),
             KeyProperty::PreviousValue(_range) => {},
+            KeyProperty::Slot(_range) => {},
             KeyProperty::AbsoluteFactor(factor) => {
                 let bound_1 = cap.value_range.max.mul_f64_round(factor, f64::trunc);
                 let bound_2 = cap.value_range.min.mul_f64_round(factor, f64::trunc);
@@ -558,7 +596,7 @@ fn interpret_key(key_str: &str, parser: &KeyParser) -> Result<Key, ArgumentError
 }
 
 /// Interprets a string like "1" or "0~1" or "5~" or "". Does not handle relative values.
-fn interpret_event_value(value_str: &str, parser: &KeyParser) -> Result<Range, ArgumentError> {
+pub fn interpret_event_value(value_str: &str, parser: &KeyParser) -> Result<Range, ArgumentError> {
     if ! parser.allow_ranges && value_str.contains('~') {
         return Err(ArgumentError::new(format!("No ranges are allowed in the value \"{}\".", value_str)));
     }
@@ -580,6 +618,19 @@ fn interpret_event_value(value_str: &str, parser: &KeyParser) -> Result<Range, A
     Ok(Range::new(min, max))
 }
 
+/// Interprets the range of multitouch slots given to a clause like slot=0~1.
+pub fn interpret_slot_range(value_str: &str) -> Result<Range, ArgumentError> {
+    let range = interpret_event_value(value_str, &KeyParser::default_filter())?;
+    if let ExtendedInteger::Discrete(min) = range.min {
+        if min < 0 {
+            return Err(ArgumentError::new(format!(
+                "Multitouch slots cannot be negative: \"{}\".", value_str
+            )));
+        }
+    }
+    Ok(range)
+}
+
 /// Returns None for "", an integer for integer strings, and otherwise gives an error.
 fn parse_int_or_wildcard(value_str: &str) -> Result<Option<i32>, ArgumentError> {
     if value_str == "" {
@@ -660,4 +711,22 @@ fn unittest_intersection() {
         assert!(! parser.parse(key_1).unwrap().intersects_with(&parser.parse(key_2).unwrap()));
         assert!(! parser.parse(key_2).unwrap().intersects_with(&parser.parse(key_1).unwrap()));
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn unittest_slot() {
+    let parser = KeyParser::default_filter();
+    let mt_x = ecodes::event_code("abs", "mt_position_x").unwrap();
+    let event = Event::new(mt_x, 5, 0, domain::get_unique_domain(), Namespace::User);
+
+    let mut key = parser.parse("abs:mt_position_x").unwrap();
+    key.add_slot_filter(interpret_slot_range("1~2").unwrap());
+    assert!(! key.matches(&event));
+    assert!(! key.matches(&event.with_slot(Some(0))));
+    assert!(key.matches(&event.with_slot(Some(1))));
+    assert!(key.matches(&event.with_slot(Some(2))));
+    assert!(! key.matches(&event.with_slot(Some(3))));
+
+    assert!(interpret_slot_range("0~").is_ok());
+    assert!(interpret_slot_range("-1~1").is_err());
+}
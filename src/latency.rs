@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Optional measurements of how often input devices report events and how much latency evsieve
+//! adds to them. Enabled per device through the measure-latency flag on --input arguments, and
+//! reported on SIGUSR1 or the "stats" command of a control FIFO.
+
+use crate::time::{Duration, Instant};
+
+/// Keeps track of the minimum, average and maximum of a series of durations in microseconds.
+struct Summary {
+    count: u64,
+    total: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Summary {
+    fn new() -> Summary {
+        Summary { count: 0, total: 0, min: 0, max: 0 }
+    }
+
+    fn record(&mut self, value: u64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = std::cmp::min(self.min, value);
+            self.max = std::cmp::max(self.max, value);
+        }
+        self.count += 1;
+        self.total = self.total.saturating_add(value);
+    }
+
+    fn average(&self) -> Option<u64> {
+        match self.count {
+            0 => None,
+            count => Some(self.total / count),
+        }
+    }
+
+    fn format(&self) -> String {
+        match self.average() {
+            None => "no samples".to_owned(),
+            Some(average) => format!(
+                "min {}us, avg {}us, max {}us over {} samples", self.min, average, self.max, self.count
+            ),
+        }
+    }
+}
+
+pub struct LatencyStats {
+    /// The time between consecutive SYN_REPORT events according to the kernel's timestamps.
+    report_intervals: Summary,
+    /// The time between the kernel timestamping a SYN_REPORT event and evsieve reading it.
+    /// Only measured if the device timestamps its events using the monotonic clock.
+    read_latencies: Summary,
+    /// The time evsieve took to process all events that were read in a single go.
+    processing_times: Summary,
+    /// The kernel timestamp of the last SYN_REPORT in microseconds.
+    last_report_time: Option<i64>,
+}
+
+impl LatencyStats {
+    pub fn new() -> LatencyStats {
+        LatencyStats {
+            report_intervals: Summary::new(),
+            read_latencies: Summary::new(),
+            processing_times: Summary::new(),
+            last_report_time: None,
+        }
+    }
+
+    /// To be called for every SYN_REPORT event read from the device, along with the timestamp the
+    /// kernel gave it. If `is_monotonic` is false, the timestamp is not comparable to our own clock.
+    pub fn record_report(&mut self, tv_sec: i64, tv_usec: i64, is_monotonic: bool) {
+        let time = tv_sec * 1_000_000 + tv_usec;
+        if let Some(last_time) = self.last_report_time {
+            if time >= last_time {
+                self.report_intervals.record((time - last_time) as u64);
+            }
+        }
+        self.last_report_time = Some(time);
+
+        if is_monotonic {
+            let report_instant = Instant::from(libc::timespec {
+                tv_sec: tv_sec as libc::time_t,
+                tv_nsec: (tv_usec * 1_000) as libc::c_long,
+            });
+            if let Some(latency) = Instant::now().checked_duration_since(report_instant) {
+                self.read_latencies.record(latency.as_micros() as u64);
+            }
+        }
+    }
+
+    pub fn record_processing_time(&mut self, duration: Duration) {
+        self.processing_times.record(duration.as_micros() as u64);
+    }
+
+    /// Returns a few indented lines describing the measurements.
+    pub fn format(&self) -> String {
+        let polling_rate = match self.report_intervals.average() {
+            Some(average) if average > 0 => format!(" (about {} Hz)", 1_000_000 / average),
+            _ => String::new(),
+        };
+        let read_latencies = match self.read_latencies.count {
+            0 => "unknown, requires clock=monotonic".to_owned(),
+            _ => self.read_latencies.format(),
+        };
+        format!(
+            "    report interval: {}{}\n    kernel to evsieve: {}\n    processing time: {}\n",
+            self.report_intervals.format(), polling_rate, read_latencies, self.processing_times.format(),
+        )
+    }
+}
+
+#[test]
+fn unittest() {
+    let mut summary = Summary::new();
+    assert_eq!(summary.average(), None);
+    summary.record(10);
+    summary.record(30);
+    summary.record(20);
+    assert_eq!(summary.average(), Some(20));
+    assert_eq!(summary.min, 10);
+    assert_eq!(summary.max, 30);
+
+    let mut stats = LatencyStats::new();
+    stats.record_report(1, 0, false);
+    stats.record_report(1, 125, false);
+    stats.record_report(1, 250, false);
+    assert_eq!(stats.report_intervals.average(), Some(125));
+    assert_eq!(stats.read_latencies.count, 0);
+}
@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Decides which of the messages evsieve prints to stdout actually get printed. Errors get printed
+//! to stderr regardless of the log level. The level is global because messages get printed from
+//! all over the program, including the thread of the persistence subsystem.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::error::ArgumentError;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LogLevel {
+    /// Only errors get printed.
+    Error,
+    Warning,
+    /// Also reports devices getting connected, disconnected and the like. The default.
+    Info,
+    /// Also prints every event that enters the stream along with the events it turned into.
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Result<LogLevel, ArgumentError> {
+        match name {
+            "error" => Ok(LogLevel::Error),
+            "warning" => Ok(LogLevel::Warning),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(ArgumentError::new(format!(
+                "Invalid log level: {}. Valid levels are error, warning, info and debug.", name
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warning => "warning",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Error,
+        1 => LogLevel::Warning,
+        2 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns whether messages of the given level should be printed.
+pub fn enabled(level: LogLevel) -> bool {
+    level <= self::level()
+}
+
+/// Prints a warning to stderr, so it does not end up between the output of --print.
+pub fn warning(message: impl fmt::Display) {
+    if enabled(LogLevel::Warning) {
+        eprintln!("{}", message);
+    }
+}
+
+pub fn info(message: impl fmt::Display) {
+    if enabled(LogLevel::Info) {
+        println!("{}", message);
+    }
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(LogLevel::parse("warning").unwrap(), LogLevel::Warning);
+    assert!(LogLevel::parse("verbose").is_err());
+    for &level in &[LogLevel::Error, LogLevel::Warning, LogLevel::Info, LogLevel::Debug] {
+        assert_eq!(LogLevel::parse(level.name()).unwrap(), level);
+    }
+    assert!(LogLevel::Error < LogLevel::Debug);
+}
@@ -17,7 +17,14 @@ impl Token {
     }
 }
 
+/// Wakeups that are due within this window from now are handled right away, together with the
+/// wakeups that are due already. The main loop cannot wait for less than a millisecond, so it
+/// would otherwise spin until such wakeups are due.
+const COALESCE_WINDOW_MS: u64 = 1;
+
 pub struct Loopback {
+    /// Sorted by the moment each wakeup is due. Wakeups that are due at the same moment are
+    /// sorted in the order they were scheduled.
     schedule: Vec<(Instant, Token)>,
 
     /// A counter for the amount of `Token`s that were handed out. Ensures that all handed
@@ -60,20 +67,11 @@ pub enum Delay {
     Wait(NonZeroI32),
 }
 
-impl Loopback {
-    pub fn new() -> Loopback {
-        Loopback {
-            schedule: Vec::new(),
-            token_index: 0,
-        }
-    }
-
-    pub fn time_until_next_wakeup(&self) -> Delay {
-        let next_instant_opt = self.schedule.iter()
-            .map(|(instant, _token)| instant).min();
-        
+impl Delay {
+    /// How long to wait until the given moment arrives. If None, it never does.
+    pub fn until(next_instant: Option<Instant>) -> Delay {
         // If None, then then there are no events scheduled to happen.
-        let next_instant = match next_instant_opt {
+        let next_instant = match next_instant {
             Some(value) => value,
             None => return Delay::Never,
         };
@@ -97,37 +95,36 @@ impl Loopback {
             None => Delay::Now,
         }
     }
+}
+
+impl Loopback {
+    pub fn new() -> Loopback {
+        Loopback {
+            schedule: Vec::new(),
+            token_index: 0,
+        }
+    }
+
+    /// The moment at which the earliest scheduled wakeup should happen. None if there is none.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.schedule.first().map(|(instant, _token)| *instant)
+    }
 
     /// The most overdue token that is due or overdue and removes it from self's schedule.
     /// If two due tokens are due at the exact same time, returns them in the order they
-    /// were added to the Loopback device.
+    /// were added to the Loopback device. Tokens that are due within `COALESCE_WINDOW_MS` count
+    /// as due.
     /// 
     /// The reason this returns only one token is because it is possible that while processing
     /// that one token, new tokens get added to the schedule that are due before any other tokens
     /// that are actually due already. The new token should then be handled first, and that is
     /// not possible if this function were to return multiple tokens at once.
     pub fn poll_once(&mut self) -> Option<(Instant, Token)> {
-        let mut ready_tokens: Vec<(Instant, Token)> = Vec::new();
-        let mut remaining_schedule: Vec<(Instant, Token)> = Vec::new();
-        let now = Instant::now();
-
-        for (instant, token) in std::mem::take(&mut self.schedule) {
-            if instant <= now {
-                ready_tokens.push((instant, token));
-            } else {
-                remaining_schedule.push((instant, token));
-            }
+        let deadline = Instant::now() + Duration::from_millis(COALESCE_WINDOW_MS);
+        match self.schedule.first() {
+            Some((instant, _token)) if *instant <= deadline => Some(self.schedule.remove(0)),
+            _ => None,
         }
-        // Stably sort: make sure that the most overdue token is yielded first. Tokens that
-        // are due at the exact same time should be yielded in the order they were added.
-        ready_tokens.sort_by_key(|(time, _token)| *time);
-
-        // Take the first ready token, add the rest back to the schedule.
-        let mut ready_tokens_iter = ready_tokens.into_iter();
-        let first_token = ready_tokens_iter.next();
-        self.schedule = ready_tokens_iter.chain(remaining_schedule).collect();
-
-        first_token
     }
 
     fn generate_token(&mut self) -> Token {
@@ -155,9 +152,11 @@ impl Loopback {
 }
 
 impl<'a> LoopbackHandle<'a> {
-    fn schedule_wakeup_at(&mut self, time: Instant) -> Token {
+    pub fn schedule_wakeup_at(&mut self, time: Instant) -> Token {
         let token = self.loopback.generate_token();
-        self.loopback.schedule.push((time, token.clone()));
+        let schedule = &mut self.loopback.schedule;
+        let position = schedule.partition_point(|(other_time, _)| *other_time <= time);
+        schedule.insert(position, (time, token.clone()));
         token
     }
 
@@ -166,15 +165,15 @@ impl<'a> LoopbackHandle<'a> {
         self.schedule_wakeup_at(now + delay)
     }
 
-    /// If a previously-scheduled wakeup no longer seems needed, you can cancel it to save some
-    /// CPU cycles later.
+    /// If a previously-scheduled wakeup is no longer needed, cancel it so the main loop does not
+    /// wake up for nothing.
     pub fn cancel_token(&mut self, token: Token) {
         self.loopback.schedule.retain(|(_, other_token)| token != *other_token);
     }
 
     /// Like self.now, but lazily computes the current time if it wasn't already stored
     /// in self.now.
-    fn now(&mut self) -> Instant {
+    pub fn now(&mut self) -> Instant {
         let time = match self.now {
             Some(time) => time,
             None => Instant::now(),
@@ -182,4 +181,24 @@ impl<'a> LoopbackHandle<'a> {
         self.now = Some(time);
         time
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn unittest() {
+    let mut loopback = Loopback::new();
+    let now = Instant::now();
+    let mut handle = loopback.get_handle(now);
+    let token_late = handle.schedule_wakeup_in(Duration::from_secs(60));
+    let token_1 = handle.schedule_wakeup_in(Duration::from_nanos(0));
+    let token_cancelled = handle.schedule_wakeup_in(Duration::from_nanos(0));
+    let token_2 = handle.schedule_wakeup_in(Duration::from_nanos(0));
+    handle.cancel_token(token_cancelled);
+    assert!(loopback.next_wakeup() == Some(now));
+
+    assert!(loopback.poll_once().map(|(_, token)| token) == Some(token_1));
+    assert!(loopback.poll_once().map(|(_, token)| token) == Some(token_2));
+    assert!(loopback.poll_once().is_none());
+    assert!(loopback.next_wakeup() == Some(now + Duration::from_secs(60)));
+    loopback.get_handle_lazy().cancel_token(token_late);
+    assert!(loopback.next_wakeup().is_none());
+}
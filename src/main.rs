@@ -25,7 +25,9 @@ pub mod signal;
 pub mod utils;
 pub mod error;
 pub mod capability;
+pub mod capcache;
 pub mod range;
+pub mod random;
 pub mod ecodes;
 pub mod predevice;
 pub mod subprocess;
@@ -33,7 +35,13 @@ pub mod daemon;
 pub mod loopback;
 pub mod stream;
 pub mod control_fifo;
+pub mod control_socket;
+pub mod dbus;
+pub mod metrics;
+pub mod logging;
 pub mod time;
+pub mod latency;
+pub mod benchmark;
 
 #[cfg(feature = "auto-scan")]
 pub mod scancodes;
@@ -45,11 +53,22 @@ pub mod io {
     pub mod internal_pipe;
     pub mod fd;
     pub mod fifo;
+    pub mod replay;
+    pub mod hidraw;
+    pub mod joystick;
+    pub mod serialize;
+    pub mod record;
+    pub mod logfile;
+    pub mod network;
+    pub mod ff;
+    pub mod uring;
+    pub mod source;
 }
 
 pub mod persist {
     pub mod inotify;
     pub mod blueprint;
+    pub mod pattern;
     pub mod subsystem;
     pub mod interface;
 }
@@ -65,8 +84,33 @@ pub mod arguments {
     pub mod print;
     pub mod merge;
     pub mod delay;
+    pub mod debounce;
+    pub mod throttle;
+    pub mod latch;
+    pub mod sticky;
+    pub mod chord;
+    pub mod counter;
+    pub mod timer;
     pub mod withhold;
+    pub mod repeat;
+    pub mod macros;
+    pub mod script;
+    pub mod unicode;
+    pub mod rotary;
+    pub mod split;
+    pub mod touchpad;
+    pub mod accel;
+    pub mod combine;
+    pub mod trace;
     pub mod control_fifo;
+    pub mod control_socket;
+    pub mod dbus;
+    pub mod metrics;
+    pub mod log;
+    pub mod config;
+    pub mod define;
+    pub mod benchmark;
+    pub mod profile;
     pub mod test;
 }
 
@@ -78,21 +122,33 @@ pub mod bindings {
 #[macro_use]
 extern crate lazy_static;
 
+use std::collections::HashMap;
 use std::os::unix::prelude::{AsRawFd, RawFd};
+use std::path::PathBuf;
 
-use arguments::parser::Implementation;
+use arguments::parser::{Implementation, Reusables, ThreadPlan};
 use error::{RuntimeError, Context};
-use io::epoll::{Epoll, FileIndex, Message};
-use io::fd::HasFixedFd;
+use io::epoll::{Epoll, FileIndex, Message, PendingWrite};
+use io::fd::{HasFixedFd, OwnedFd};
 use io::input::InputDevice;
+use io::internal_pipe::{Receiver, Sender};
+use io::output::Feedback;
+use io::replay::ReplayDevice;
+use io::hidraw::HidrawDevice;
+use io::joystick::JoystickDevice;
+use io::network::{NetworkInput, NetworkListener};
+use io::source::EventSource;
 use persist::interface::{HostInterfaceState};
 use stream::Setup;
 use signal::{SigMask, SignalFd};
-use control_fifo::ControlFifo;
+use control_fifo::{Command, ControlFifo};
+use control_socket::{ControlConnection, ControlSocket};
+use metrics::{MetricsConnection, MetricsListener};
 
-use crate::event::EventCode;
+use crate::capability::Capabilities;
+use crate::event::{Event, EventCode, EventType, Namespace};
 use crate::persist::subsystem::Report;
-use crate::predevice::PersistMode;
+use crate::predevice::{PersistMode, PreInputDevice};
 
 
 fn main() {
@@ -121,20 +177,64 @@ fn run_and_interpret_exit_code() -> i32 {
 }
 
 pub enum Pollable {
-    InputDevice(InputDevice),
+    InputDevice(Box<InputDevice>),
+    Replay(ReplayDevice),
+    Hidraw(HidrawDevice),
+    Joystick(JoystickDevice),
+    NetworkListener(NetworkListener),
+    NetworkInput(NetworkInput),
     SignalFd(SignalFd),
     ControlFifo(ControlFifo),
+    ControlSocket(ControlSocket),
+    ControlConnection(ControlConnection),
+    MetricsListener(MetricsListener),
+    MetricsConnection(MetricsConnection),
+    DBus(dbus::DBusService),
+    /// Becomes readable when an output device receives force feedback requests or LED changes
+    /// that need to be forwarded to the input devices.
+    Feedback(OwnedFd),
     PersistSubsystem(persist::interface::HostInterface),
+    /// Becomes readable when the thread running this program shall stop, see --parallel.
+    Stop(Receiver<()>),
 }
 unsafe impl HasFixedFd for Pollable {}
 
+impl Pollable {
+    /// Returns this file as a source of events, if it is one.
+    fn as_event_source(&mut self) -> Option<&mut dyn EventSource> {
+        match self {
+            Pollable::InputDevice(device) => Some(&mut **device),
+            Pollable::Replay(device) => Some(device),
+            Pollable::Hidraw(device) => Some(device),
+            Pollable::Joystick(device) => Some(device),
+            Pollable::NetworkInput(input) => Some(input),
+            Pollable::NetworkListener(_) | Pollable::SignalFd(_) | Pollable::ControlFifo(_)
+            | Pollable::ControlSocket(_) | Pollable::ControlConnection(_) | Pollable::MetricsListener(_)
+            | Pollable::MetricsConnection(_) | Pollable::DBus(_) | Pollable::Feedback(_)
+            | Pollable::PersistSubsystem(_) | Pollable::Stop(_) => None,
+        }
+    }
+}
+
 impl AsRawFd for Pollable {
     fn as_raw_fd(&self) -> RawFd {
         match self {
             Pollable::InputDevice(device) => device.as_raw_fd(),
+            Pollable::Replay(device) => device.as_raw_fd(),
+            Pollable::Hidraw(device) => device.as_raw_fd(),
+            Pollable::Joystick(device) => device.as_raw_fd(),
+            Pollable::NetworkListener(listener) => listener.as_raw_fd(),
+            Pollable::NetworkInput(input) => input.as_raw_fd(),
             Pollable::SignalFd(fd) => fd.as_raw_fd(),
             Pollable::ControlFifo(fifo) => fifo.as_raw_fd(),
+            Pollable::ControlSocket(socket) => socket.as_raw_fd(),
+            Pollable::ControlConnection(connection) => connection.as_raw_fd(),
+            Pollable::MetricsListener(listener) => listener.as_raw_fd(),
+            Pollable::MetricsConnection(connection) => connection.as_raw_fd(),
+            Pollable::DBus(service) => service.as_raw_fd(),
+            Pollable::Feedback(fd) => fd.as_raw_fd(),
             Pollable::PersistSubsystem(interface) => interface.as_raw_fd(),
+            Pollable::Stop(receiver) => receiver.as_raw_fd(),
         }
     }
 }
@@ -143,9 +243,17 @@ struct Program {
     epoll: Epoll<Pollable>,
     setup: Setup,
     persist_subsystem: HostInterfaceState,
+    /// Present if systemd expects evsieve to ping its watchdog.
+    watchdog: Option<daemon::Watchdog>,
+    /// Set by the pause command. While paused, the input devices are not grabbed and the events
+    /// of all sources of events are ignored.
+    paused: bool,
+    /// Set by the log-level command with a duration: the moment at which the log level shall be
+    /// restored, and the level to restore.
+    log_revert: Option<(time::Instant, logging::LogLevel)>,
 }
 
-const TERMINATION_SIGNALS: [libc::c_int; 3] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP];
+const TERMINATION_SIGNALS: [libc::c_int; 2] = [libc::SIGTERM, libc::SIGINT];
 
 fn run() -> Result<(), RuntimeError> {
     // Check if the arguments contain --help or --version.
@@ -154,41 +262,120 @@ fn run() -> Result<(), RuntimeError> {
         daemon::notify_ready_async();
         return Ok(());
     }
+    if arguments::parser::is_dry_run(&args) {
+        print!("{}", arguments::parser::dry_run(args)?);
+        return Ok(());
+    }
+    if arguments::parser::is_benchmark(&args) {
+        let (implementation, num_events) = arguments::parser::implement_benchmark(args)?;
+        print!("{}", benchmark::run(implementation, num_events));
+        return Ok(());
+    }
 
-    // Listen for signals sent to this program.
+    if arguments::parser::is_parallel(&args) {
+        return run_parallel(args);
+    }
+
+    let (signal_fd, _signal_block) = listen_for_signals()?;
+    let mut epoll = Epoll::with_preferred_backend()?;
+    epoll.add_file(Pollable::SignalFd(signal_fd))?;
+
+    // Parse the arguments and set up the input/output devices.
+    let implementation = arguments::parser::implement(args)?;
+    run_program(epoll, implementation, daemon::notify_ready_async)
+}
+
+/// Blocks the signals that evsieve cares about and returns a SignalFd through which they can be
+/// read instead. Threads spawned afterwards inherit the blocked signals.
+fn listen_for_signals() -> Result<(SignalFd, signal::SignalBlock), RuntimeError> {
     let mut sigmask = SigMask::new();
     sigmask.add(libc::SIGPIPE);
+    sigmask.add(libc::SIGUSR1);
+    sigmask.add(libc::SIGHUP);
     for &signal in &TERMINATION_SIGNALS {
         sigmask.add(signal);
     }
     let signal_fd = signal::SignalFd::new(&sigmask)?;
-    let mut epoll = Epoll::new()?;
-    epoll.add_file(Pollable::SignalFd(signal_fd))?;
 
     // Additionally block SIGCHLD because another thread listens for it.
     sigmask.add(libc::SIGCHLD);
-    let _signal_block = unsafe { signal::SignalBlock::new(&sigmask)? };
+    let signal_block = unsafe { signal::SignalBlock::new(&sigmask)? };
+    Ok((signal_fd, signal_block))
+}
 
-    // Parse the arguments and set up the input/output devices.
-    let Implementation { setup, input_devices, control_fifos } = arguments::parser::implement(args)?;
+/// Runs the main loop of a program until it exits. The epoll must already contain the file that
+/// tells the program when to stop, which is either the SignalFd or the stop signal of a thread.
+/// `notify_ready` is called once all devices have been opened.
+fn run_program(mut epoll: Epoll<Pollable>, implementation: Implementation, notify_ready: impl FnOnce()) -> Result<(), RuntimeError> {
+    let Implementation { mut setup, input_devices, reused_input_devices: _, control_fifos, control_sockets, dbus_service, metrics_listener, patterns, replay_devices, network_listeners, network_inputs, hidraw_devices, joystick_devices }
+        = implementation;
     for device in input_devices {
-        epoll.add_file(Pollable::InputDevice(device))?;
+        epoll.add_file(Pollable::InputDevice(Box::new(device)))?;
+    }
+    for device in replay_devices {
+        epoll.add_file(Pollable::Replay(device))?;
+    }
+    for device in hidraw_devices {
+        epoll.add_file(Pollable::Hidraw(device))?;
+    }
+    for device in joystick_devices {
+        epoll.add_file(Pollable::Joystick(device))?;
+    }
+    for listener in network_listeners {
+        epoll.add_file(Pollable::NetworkListener(listener))?;
+    }
+    for input in network_inputs {
+        epoll.add_file(Pollable::NetworkInput(input))?;
     }
     for fifo in control_fifos {
         epoll.add_file(Pollable::ControlFifo(fifo))?;
     }
+    for socket in control_sockets {
+        epoll.add_file(Pollable::ControlSocket(socket))?;
+    }
+    if let Some(service) = dbus_service {
+        epoll.add_file(Pollable::DBus(service))?;
+    }
+    if let Some(listener) = metrics_listener {
+        epoll.add_file(Pollable::MetricsListener(listener))?;
+    }
+    // An io_uring can write the events to the output devices along with the next wait.
+    setup.output_mut().set_batch_writes(epoll.uses_io_uring());
+    if let Some(feedback_fd) = setup.output_mut().feedback_fd() {
+        let feedback_fd = unsafe { OwnedFd::from_syscall(libc::dup(feedback_fd))? };
+        epoll.add_file(Pollable::Feedback(feedback_fd))?;
+    }
 
     // If the persistence subsystem is running, this shall keep track of its index in the epoll.
-    let persist_subsystem: HostInterfaceState = HostInterfaceState::new();
+    let mut persist_subsystem: HostInterfaceState = HostInterfaceState::new();
+
+    // Let the persistence subsystem watch for new devices matching the patterns.
+    for pattern in patterns {
+        if let Some(interface) = persist_subsystem.require(&mut epoll) {
+            interface.add_pattern(pattern)
+                .with_context("While trying to watch for new input devices:")
+                .print_err();
+        }
+    }
 
     let mut program = Program {
-        epoll, setup, persist_subsystem
+        epoll, setup, persist_subsystem,
+        watchdog: daemon::Watchdog::from_env(),
+        paused: false,
+        log_revert: None,
     };
-    daemon::notify_ready_async();
+    for file in program.epoll.files() {
+        if let Pollable::InputDevice(device) = file {
+            sync_initial_state(&mut program.setup, device);
+        }
+    }
+    notify_ready();
 
     // Make sure evsieve has something to do.
     if has_no_activity(&program.epoll) {
-        println!("Warning: no input devices available. Evsieve will exit now.");
+        if logging::enabled(logging::LogLevel::Warning) {
+            println!("Warning: no input devices available. Evsieve will exit now.");
+        }
         return Ok(());
     }
 
@@ -201,6 +388,128 @@ fn run() -> Result<(), RuntimeError> {
     Ok(())
 }
 
+/// The files that the main thread polls while the programs run on other threads, see --parallel.
+enum Supervised {
+    Signals(SignalFd),
+    /// Becomes readable once a thread has opened its devices.
+    Ready(Receiver<()>),
+    /// Becomes readable once a thread has stopped, for whatever reason.
+    Finished(Receiver<()>),
+}
+unsafe impl HasFixedFd for Supervised {}
+
+impl AsRawFd for Supervised {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Supervised::Signals(fd) => fd.as_raw_fd(),
+            Supervised::Ready(receiver) => receiver.as_raw_fd(),
+            Supervised::Finished(receiver) => receiver.as_raw_fd(),
+        }
+    }
+}
+
+/// Splits the arguments into groups that do not interact with each other and runs each group on
+/// its own thread. The main thread only waits for signals and for the other threads to stop. If
+/// any thread stops, all other threads are stopped as well.
+fn run_parallel(args: Vec<String>) -> Result<(), RuntimeError> {
+    let (signal_fd, _signal_block) = listen_for_signals()?;
+    let plans = arguments::parser::plan_threads(args.clone())?;
+    if plans.len() <= 1 {
+        logging::info("The input devices cannot be processed independently of each other, so --parallel will only use a single thread.");
+    }
+
+    let mut supervisor: Epoll<Supervised> = Epoll::new()?;
+    supervisor.add_file(Supervised::Signals(signal_fd))?;
+
+    let mut stop_senders: Vec<Sender<()>> = Vec::new();
+    let mut threads = Vec::new();
+    for plan in plans {
+        let (stop_sender, stop_receiver) = io::internal_pipe::channel()?;
+        let (ready_sender, ready_receiver) = io::internal_pipe::channel()?;
+        let (finished_sender, finished_receiver) = io::internal_pipe::channel()?;
+        supervisor.add_file(Supervised::Ready(ready_receiver))?;
+        supervisor.add_file(Supervised::Finished(finished_receiver))?;
+        stop_senders.push(stop_sender);
+
+        let args = args.clone();
+        threads.push(std::thread::spawn(move || {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(
+                || run_thread(args, &plan, stop_receiver, ready_sender)
+            ));
+            let _ = finished_sender.send(());
+            result
+        }));
+    }
+
+    let mut num_threads_not_ready = threads.len();
+    'supervise: loop {
+        let messages: Vec<Message> = supervisor.poll(crate::io::epoll::INDEFINITE_TIMEOUT)
+            .with_context("While polling the epoll for events:")?.collect();
+        for message in messages {
+            let index = match message {
+                Message::Ready(index) => index,
+                // If something went wrong with a thread, its channel gets closed.
+                Message::Broken(_) | Message::Hup(_) => break 'supervise,
+            };
+            match &mut supervisor[index] {
+                Supervised::Signals(fd) => {
+                    let siginfo = fd.read_raw()?;
+                    let signal_no = siginfo.ssi_signo as i32;
+                    if TERMINATION_SIGNALS.contains(&signal_no) {
+                        break 'supervise;
+                    } else if signal_no == libc::SIGHUP {
+                        logging::warning("Warning: the configuration cannot be reloaded while running with --parallel.");
+                    }
+                },
+                Supervised::Ready(_) => {
+                    supervisor.remove(index);
+                    num_threads_not_ready -= 1;
+                    if num_threads_not_ready == 0 {
+                        daemon::notify_ready_async();
+                    }
+                },
+                Supervised::Finished(_) => break 'supervise,
+            }
+        }
+    }
+
+    for sender in &stop_senders {
+        // If this fails, the thread has already stopped.
+        let _ = sender.send(());
+    }
+
+    let mut first_error: Option<RuntimeError> = None;
+    let mut panic = None;
+    for thread in threads {
+        match thread.join() {
+            Ok(Ok(Ok(()))) => (),
+            Ok(Ok(Err(error))) => match first_error {
+                None => first_error = Some(error),
+                Some(_) => eprintln!("{}", error),
+            },
+            Ok(Err(payload)) | Err(payload) => panic = Some(payload),
+        }
+    }
+
+    if let Some(payload) = panic {
+        std::panic::resume_unwind(payload);
+    }
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Runs the part of the arguments described by a ThreadPlan until the stop signal is received.
+fn run_thread(args: Vec<String>, plan: &ThreadPlan, stop: Receiver<()>, ready: Sender<()>) -> Result<(), RuntimeError> {
+    let mut epoll = Epoll::with_preferred_backend()?;
+    epoll.add_file(Pollable::Stop(stop))?;
+    let implementation = arguments::parser::implement_thread(args, plan)?;
+    run_program(epoll, implementation, move || {
+        let _ = ready.send(());
+    })
+}
+
 /// An enum used to signal to the main loop which action should be taken: if a function returns
 /// Action::Continue, the program should go on, otherwise it should perform a clean exit.
 enum Action {
@@ -212,7 +521,31 @@ enum Action {
 /// is returned by `handle_ready_file()` or `handle_broken_file()`.
 fn enter_main_loop(program: &mut Program) -> Result<(), RuntimeError> {
     loop {
-        let timeout: i32 = match program.setup.time_until_next_wakeup() {
+        // Keys that were pressed while the previous profile was active get released through that
+        // profile, so they do not remain held on the output devices.
+        if program.setup.profile_changed() {
+            release_held_keys(program);
+            program.setup.apply_profile_change();
+        }
+        stream::sync_toggles(&mut program.setup);
+        update_grabs(program)?;
+
+        // The watchdog gets pinged from the main loop so it notices if the loop ever gets stuck.
+        if let Some(watchdog) = &mut program.watchdog {
+            watchdog.ping_if_due();
+        }
+        if let Some((deadline, level)) = program.log_revert {
+            if time::Instant::now() >= deadline {
+                logging::set_level(level);
+                program.log_revert = None;
+                logging::info(format!("The log level has been restored to {}.", level.name()));
+            }
+        }
+        let next_ping = program.watchdog.as_ref().map(daemon::Watchdog::next_ping);
+        let next_log_revert = program.log_revert.map(|(deadline, _)| deadline);
+        let next_wakeup = program.setup.next_wakeup().into_iter().chain(next_ping).chain(next_log_revert).min();
+
+        let timeout: i32 = match loopback::Delay::until(next_wakeup) {
             loopback::Delay::Now => {
                 stream::wakeup(&mut program.setup);
                 continue;
@@ -221,7 +554,12 @@ fn enter_main_loop(program: &mut Program) -> Result<(), RuntimeError> {
             loopback::Delay::Wait(time) => time.get(),
         };
 
-        let messages = program.epoll.poll(timeout).with_context("While polling the epoll for events:")?;
+        // The events written to the output devices since the last wait, if the epoll can write them.
+        let (domains, writes): (Vec<domain::Domain>, Vec<PendingWrite>)
+            = program.setup.output().pending_writes().into_iter().unzip();
+        let (messages, write_results) = program.epoll.poll_and_write(timeout, &writes)
+            .with_context("While polling the epoll for events:")?;
+        program.setup.output_mut().finish_pending_writes(domains.into_iter().zip(write_results));
 
         for message in messages {
             let action = match message {
@@ -242,6 +580,20 @@ fn enter_main_loop(program: &mut Program) -> Result<(), RuntimeError> {
                     // them as broken.
                     match program.epoll.get(index) {
                         Some(Pollable::ControlFifo(_)) => Action::Continue,
+                        // Clients of control sockets are expected to hang up at some point.
+                        Some(Pollable::ControlConnection(_)) => {
+                            program.epoll.remove(index);
+                            Action::Continue
+                        },
+                        // Clients of the metrics endpoint may hang up before their request gets
+                        // answered, in which case there is nobody left to answer.
+                        Some(Pollable::MetricsConnection(_)) => {
+                            program.epoll.remove(index);
+                            Action::Continue
+                        },
+                        // The file may already have been removed while handling the EPOLLIN
+                        // that was reported along with this EPOLLHUP.
+                        None => Action::Continue,
                         _ => handle_broken_file(program, index),
                     }
                 },
@@ -268,9 +620,83 @@ fn handle_ready_file(program: &mut Program, index: FileIndex) -> Result<Action,
     };
     match file {
         Pollable::InputDevice(device) => {
+            let start_time = time::Instant::now();
             let events = device.poll().with_context_of(||
                 format!("While polling the input device {}:", device.path().display())
             )?;
+            // The events still get read while paused or while the device has been handed back to
+            // the system to keep track of which keys are held.
+            if program.paused || ! device.grab_allowed() {
+                return Ok(Action::Continue);
+            }
+            for event in events {
+                stream::run(&mut program.setup, event);
+            }
+            if let Some(processing_time) = time::Instant::now().checked_duration_since(start_time) {
+                device.record_processing_time(processing_time);
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::Replay(device) => {
+            let events = device.poll().with_context_of(||
+                format!("While replaying {}:", device.path().display())
+            )?;
+            let is_finished = device.is_finished();
+            if ! program.paused {
+                for event in events {
+                    stream::run(&mut program.setup, event);
+                }
+            }
+            // A finished replay gets removed from the epoll the same way a broken one does.
+            match is_finished {
+                true => Ok(handle_broken_file(program, index)),
+                false => Ok(Action::Continue),
+            }
+        },
+        Pollable::Hidraw(device) => {
+            let events = device.poll().with_context_of(||
+                format!("While reading HID reports from {}:", device.path().display())
+            )?;
+            if program.paused {
+                return Ok(Action::Continue);
+            }
+            for event in events {
+                stream::run(&mut program.setup, event);
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::Joystick(device) => {
+            let events = device.poll().with_context_of(||
+                format!("While polling the joystick {}:", device.path().display())
+            )?;
+            if program.paused {
+                return Ok(Action::Continue);
+            }
+            for event in events {
+                stream::run(&mut program.setup, event);
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::NetworkListener(listener) => {
+            if let Some(input) = listener.accept()? {
+                logging::info(format!("Accepted a connection from {}.", input.description()));
+                program.epoll.add_file(Pollable::NetworkInput(input))
+                    .with_context("While adding a network connection to the epoll:")
+                    .print_err();
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::NetworkInput(input) => {
+            let events = input.poll().with_context_of(||
+                format!("While receiving events from {}:", input.description())
+            )?;
+            // Make sure the output devices are capable of any new events before writing them.
+            if input.take_capabilities_changed() {
+                program.setup.merge_domain_caps(input.domain(), input.capabilities());
+            }
+            if program.paused {
+                return Ok(Action::Continue);
+            }
             for event in events {
                 stream::run(&mut program.setup, event);
             }
@@ -279,8 +705,24 @@ fn handle_ready_file(program: &mut Program, index: FileIndex) -> Result<Action,
         Pollable::SignalFd(fd) => {
             let siginfo = fd.read_raw()?;
             let signal_no = siginfo.ssi_signo as i32;
-            if TERMINATION_SIGNALS.contains(&signal_no) {
+            if signal_no == libc::SIGHUP {
+                // Files written by --print arguments get reopened for the sake of logrotate, even
+                // if the configuration turns out to be impossible to reload.
+                program.setup.reopen_print_files();
+                match reload(program) {
+                    Ok(action) => Ok(action),
+                    Err(error) => {
+                        error.with_context("While reloading the configuration:").print_err();
+                        eprintln!("The previous configuration remains in effect.");
+                        Ok(Action::Continue)
+                    },
+                }
+            } else if TERMINATION_SIGNALS.contains(&signal_no) {
                 Ok(Action::Exit)
+            } else if signal_no == libc::SIGUSR1 {
+                print!("{}", program.setup.format_stats());
+                print!("{}", format_latency_stats(&program.epoll));
+                Ok(Action::Continue)
             } else {
                 // Ignore other signals, including SIGPIPE.
                 Ok(Action::Continue)
@@ -291,17 +733,123 @@ fn handle_ready_file(program: &mut Program, index: FileIndex) -> Result<Action,
                 || format!("While polling commands from {}:", fifo.path()),
             )?;
             for command in commands {
-                command.execute(&mut program.setup)
-                    .with_context("While executing a command:")
-                    .print_err();
+                match execute_command(program, command) {
+                    Ok(output) => print!("{}", output),
+                    Err(error) => error.with_context("While executing a command:").print_err(),
+                }
             }
 
             Ok(Action::Continue)
         },
+        Pollable::ControlSocket(socket) => {
+            if let Some(connection) = socket.accept()? {
+                program.epoll.add_file(Pollable::ControlConnection(connection))
+                    .with_context("While adding a control socket connection to the epoll:")
+                    .print_err();
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::ControlConnection(connection) => {
+            let lines = connection.poll()?;
+            let mut reply = String::new();
+            for line in lines {
+                let result = match control_fifo::parse_command(&line) {
+                    Ok(command) => execute_command(program, command).map_err(|error| error.to_string()),
+                    Err(error) => Err(error.to_string()),
+                };
+                reply.push_str(&control_socket::format_reply(result));
+            }
+            if let Some(Pollable::ControlConnection(connection)) = program.epoll.get_mut(index) {
+                if ! reply.is_empty() {
+                    connection.send(&reply)?;
+                }
+                if connection.is_closed() {
+                    program.epoll.remove(index);
+                }
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::MetricsListener(listener) => {
+            if let Some(connection) = listener.accept()? {
+                program.epoll.add_file(Pollable::MetricsConnection(connection))
+                    .with_context("While adding a metrics connection to the epoll:")
+                    .print_err();
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::MetricsConnection(connection) => {
+            if ! connection.poll()? {
+                return Ok(Action::Continue);
+            }
+            let report = format_metrics(&program.setup, &program.epoll);
+            if let Some(Pollable::MetricsConnection(mut connection)) = program.epoll.remove(index) {
+                connection.respond(&report)
+                    .with_context("While answering a metrics request:")
+                    .print_err();
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::DBus(service) => {
+            let calls = service.poll()?;
+            let mut replies = Vec::new();
+            for call in calls {
+                let result = match &call.command {
+                    Ok(line) => match control_fifo::parse_command(line) {
+                        Ok(command) => execute_command(program, command).map_err(|error| error.to_string()),
+                        Err(error) => Err(error.to_string()),
+                    },
+                    Err(message) => Err(message.clone()),
+                };
+                replies.push((call, result));
+            }
+            if let Some(Pollable::DBus(service)) = program.epoll.get_mut(index) {
+                for (call, result) in replies {
+                    service.reply(call, result)?;
+                }
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::Feedback(_fd) => {
+            let feedback = program.setup.output_mut().read_feedback();
+            for (domain, item) in feedback {
+                match item {
+                    Feedback::ForceFeedback(path, request) => {
+                        let target = program.epoll.files_mut().find_map(|file| match file {
+                            Pollable::InputDevice(device) if device.path() == path => Some(device),
+                            _ => None,
+                        });
+                        let retval = match target {
+                            Some(device) => device.forward_ff(domain, &request),
+                            // The input device is currently disconnected.
+                            None => -libc::ENODEV,
+                        };
+                        program.setup.output_mut().finish_ff_request(domain, request, retval);
+                    },
+                    Feedback::Effect(event) => {
+                        let syn_event = Event::new(
+                            EventCode::new(EventType::SYN, ecodes::SYN_REPORT), 0, 0, event.domain, Namespace::Input,
+                        );
+                        stream::run(&mut program.setup, event);
+                        stream::run(&mut program.setup, syn_event);
+                    },
+                    Feedback::Led(code, value) => {
+                        for file in program.epoll.files_mut() {
+                            if let Pollable::InputDevice(device) = file {
+                                if device.forwards_leds() {
+                                    device.set_led(code, value).print_err();
+                                }
+                            }
+                        }
+                    },
+                }
+            }
+            Ok(Action::Continue)
+        },
         Pollable::PersistSubsystem(ref mut interface) => {
             let report = interface.recv().with_context("While polling the persistence subsystem from the main thread:")?;
             Ok(handle_persist_subsystem_report(program, index, report))
         },
+        Pollable::Stop(_receiver) => Ok(Action::Exit),
     }
 }
 
@@ -313,26 +861,37 @@ fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
             return Action::Continue;
         }
     };
+    let mut broken_device = broken_device;
+
+    // Release all keys that this device had pressed, so we don't end up with a key stuck on
+    // an output device.
+    if let Some(source) = broken_device.as_event_source() {
+        for release_event in source.release_pressed_keys() {
+            stream::run(&mut program.setup, release_event);
+        }
+        stream::syn(&mut program.setup);
+    }
+
     match broken_device {
-        Pollable::InputDevice(mut device) => {
+        Pollable::InputDevice(device) => {
             eprintln!("The device {} has been disconnected.", device.path().display());
 
-            // Release all keys that this device had pressed, so we don't end up with a key stuck on
-            // an output device.
-            let pressed_keys: Vec<EventCode> = device.get_pressed_keys().collect();
-            for key_code in pressed_keys {
-                let release_event = device.synthesize_event(key_code, 0);
-                stream::run(&mut program.setup, release_event);
-            }
-            stream::syn(&mut program.setup);
-
             match device.persist_mode() {
                 // Mode None: drop the device and carry on without it, if possible.
                 PersistMode::None => {},
                 // Mode Exit: quit evsieve now.
                 PersistMode::Exit => return Action::Exit,
                 // Mode Reopen: try to reopen the device if it becomes available again later.
-                PersistMode::Reopen => {
+                // With mode Full, devices that were found through a pattern are picked up by that
+                // pattern instead.
+                PersistMode::Full if device.is_from_pattern() => {
+                    if let Some(interface) = program.persist_subsystem.get(&mut program.epoll) {
+                        interface.release_path(device.path().to_owned())
+                            .with_context("While trying to register a disconnected device for reopening:")
+                            .print_err()
+                    }
+                },
+                PersistMode::Reopen | PersistMode::Full => {
                     if let Some(interface) = program.persist_subsystem.require(&mut program.epoll) {
                         interface.add_blueprint(device.to_blueprint())
                             .with_context("While trying to register a disconnected device for reopening:")
@@ -343,13 +902,45 @@ fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
                 }
             };
         },
+        Pollable::Replay(device) => match device.is_finished() {
+            true => logging::info(format!("The replay of {} has finished.", device.path().display())),
+            false => eprintln!("Error: the replay of {} has been interrupted.", device.path().display()),
+        },
+        Pollable::Hidraw(device) => {
+            eprintln!("The hidraw device {} has been disconnected.", device.path().display());
+        },
+        Pollable::Joystick(device) => {
+            eprintln!("The joystick {} has been disconnected.", device.path().display());
+        },
+        Pollable::NetworkListener(listener) => {
+            eprintln!("Error: stopped listening for connections on {}.", listener.address());
+        },
+        Pollable::NetworkInput(input) => {
+            logging::info(format!("The connection from {} has been closed.", input.description()));
+        },
         Pollable::ControlFifo(fifo) => {
             eprintln!("Error: the FIFO at {} is no longer available.", fifo.path());
         },
+        Pollable::ControlSocket(socket) => {
+            eprintln!("Error: stopped listening for commands on {}.", socket.path().display());
+        },
+        Pollable::ControlConnection(_connection) => {},
+        Pollable::MetricsListener(listener) => {
+            eprintln!("Error: stopped serving metrics on {}.", listener.description());
+        },
+        Pollable::MetricsConnection(_connection) => {},
+        Pollable::DBus(_service) => {
+            eprintln!("Error: lost the connection to D-Bus.");
+        },
+        Pollable::Feedback(_fd) => {
+            eprintln!("Error: force feedback and LED changes can no longer be forwarded to the input devices.");
+        },
         Pollable::SignalFd(_fd) => {
             eprintln!("Fatal error: signal file descriptor broken.");
             return Action::Exit;
         },
+        // The main thread has stopped listening, so there is nobody left to tell us when to stop.
+        Pollable::Stop(_receiver) => return Action::Exit,
         Pollable::PersistSubsystem(mut interface) => {
             eprintln!("Internal error: the persistence subsystem has broken. Evsieve may fail to open devices specified with the persist flag.");
             let _ = interface.request_shutdown();
@@ -358,7 +949,7 @@ fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
     }
 
     if has_no_activity(&program.epoll) {
-        println!("No devices to poll events from. Evsieve will exit now.");
+        logging::info("No devices to poll events from. Evsieve will exit now.");
         Action::Exit
     } else {
         Action::Continue
@@ -374,26 +965,52 @@ fn handle_persist_subsystem_report(program: &mut Program, index: FileIndex, repo
         },
         Report::BlueprintDropped => {
             if has_no_activity(&program.epoll) {
-                println!("No devices remaining that can possibly generate events. Evsieve will exit now.");
+                logging::info("No devices remaining that can possibly generate events. Evsieve will exit now.");
                 Action::Exit
             } else {
                 Action::Continue
             }
         },
         Report::DeviceOpened(mut device) => {
+            // Patterns may match a device that is already opened through another link.
+            if is_already_opened(&program.epoll, &device) {
+                logging::warning(format!(
+                    "Warning: the device {} is not used because it has already been opened by another --input argument.",
+                    device.path().display()
+                ));
+                return Action::Continue;
+            }
+            // Devices that get opened while evsieve is paused must not be grabbed until it resumes.
+            if program.paused {
+                device.set_paused(true)
+                    .with_context_of(|| format!("While ungrabbing the device {}:", device.path().display()))
+                    .print_err();
+            }
             if let Err(error) = device.grab_if_desired() {
                 error.with_context(format!("While grabbing the device {}:", device.path().display()))
                     .print_err();
-                eprintln!("Warning: unable to reopen device {}. The device is most likely grabbed by another program.", device.path().display());
+                if device.grab_is_required() {
+                    eprintln!("Error: the device {} has grab=required, but could not be grabbed. Evsieve will exit now.", device.path().display());
+                    return Action::Exit;
+                }
+                logging::warning(format!("Warning: unable to reopen device {}. The device is most likely grabbed by another program.", device.path().display()));
                 return Action::Continue
             }
 
             let device_path = device.path().to_owned();
-            program.setup.update_caps(&device);
+            let from_pattern = device.is_from_pattern();
+            match from_pattern {
+                true => program.setup.merge_caps(&device),
+                false => program.setup.update_caps(&device),
+            }
+            sync_initial_state(&mut program.setup, &device);
 
-            match program.epoll.add_file(Pollable::InputDevice(device))
+            match program.epoll.add_file(Pollable::InputDevice(Box::new(device)))
             {
-                Ok(_) => println!("The device {} has been reconnected.", device_path.display()),
+                Ok(_) => match from_pattern {
+                    true => logging::info(format!("The device {} has been connected.", device_path.display())),
+                    false => logging::info(format!("The device {} has been reconnected.", device_path.display())),
+                },
                 Err(error) => {
                     error.with_context("While adding a newly opened device to the epoll:").print_err();
                 },
@@ -404,15 +1021,374 @@ fn handle_persist_subsystem_report(program: &mut Program, index: FileIndex, repo
     }
 }
 
+/// Re-parses the arguments evsieve was started with and replaces the processing stream with the
+/// result. Input devices that remain in use stay open, and output devices that would be created
+/// the same way with the same capabilities stay in place. Returns an error without changing
+/// anything but the held keys if the new configuration cannot be implemented.
+fn reload(program: &mut Program) -> Result<Action, RuntimeError> {
+    let args: Vec<String> = std::env::args().collect();
+
+    // The new stream does not know which keys are held, so release them through the current
+    // stream to make sure no key ends up stuck on an output device.
+    release_held_keys(program);
+
+    let open_input_devices: HashMap<PathBuf, Capabilities> = program.epoll.files()
+        .filter_map(|file| match file {
+            Pollable::InputDevice(device) => Some((
+                std::fs::canonicalize(device.path()).ok()?,
+                device.capabilities().clone(),
+            )),
+            _ => None,
+        })
+        .collect();
+    let reusables = Reusables {
+        input_devices: open_input_devices,
+        output: program.setup.output_mut(),
+    };
+    let Implementation { setup, input_devices, reused_input_devices, patterns, .. }
+        = arguments::parser::reimplement(args, reusables)?;
+    program.setup = setup;
+    // The reloaded configuration has set the log level of its own.
+    program.log_revert = None;
+
+    // Apply the new clauses to the input devices that remain in use and close the others.
+    let mut reused_input_devices: Vec<(Option<PathBuf>, PreInputDevice)> = reused_input_devices.into_iter()
+        .map(|pre_device| (std::fs::canonicalize(&pre_device.path).ok(), pre_device))
+        .collect();
+    for index in program.epoll.indices() {
+        let real_path = match program.epoll.get(index) {
+            Some(Pollable::InputDevice(device)) => std::fs::canonicalize(device.path()).ok(),
+            // The new output devices have their own feedback epoll, if they need one.
+            Some(Pollable::Feedback(_)) => {
+                program.epoll.remove(index);
+                continue;
+            },
+            _ => continue,
+        };
+        let position = reused_input_devices.iter()
+            .position(|(path, _)| path.is_some() && *path == real_path);
+        let pre_device = match position {
+            Some(position) => reused_input_devices.swap_remove(position).1,
+            None => {
+                if let Some(Pollable::InputDevice(device)) = program.epoll.remove(index) {
+                    logging::info(format!("The device {} is no longer used.", device.path().display()));
+                }
+                continue;
+            },
+        };
+        if let Some(Pollable::InputDevice(device)) = program.epoll.get_mut(index) {
+            let result = device.reconfigure(pre_device, program.setup.state())
+                .with_context_of(|| format!("While reconfiguring the device {}:", device.path().display()));
+            match result {
+                Ok(()) => (),
+                Err(error) if device.grab_is_required() => {
+                    error.print_err();
+                    eprintln!("Error: the device {} has grab=required, but could not be grabbed. Evsieve will exit now.", device.path().display());
+                    return Ok(Action::Exit);
+                },
+                Err(error) => error.print_err(),
+            }
+        }
+    }
+
+    for device in input_devices {
+        sync_initial_state(&mut program.setup, &device);
+        program.epoll.add_file(Pollable::InputDevice(Box::new(device)))
+            .with_context("While adding a newly opened device to the epoll:")
+            .print_err();
+    }
+    if let Some(feedback_fd) = program.setup.output_mut().feedback_fd() {
+        unsafe { OwnedFd::from_syscall(libc::dup(feedback_fd)) }
+            .and_then(|feedback_fd| program.epoll.add_file(Pollable::Feedback(feedback_fd)))
+            .with_context("While trying to forward force feedback and LED changes to the input devices:")
+            .print_err();
+    }
+
+    // Devices matching the patterns of the new configuration get opened with its clauses.
+    if let Some(interface) = program.persist_subsystem.get(&mut program.epoll) {
+        interface.clear()
+            .with_context("While trying to stop reopening the input devices of the previous configuration:")
+            .print_err();
+    }
+    for pattern in patterns {
+        if let Some(interface) = program.persist_subsystem.require(&mut program.epoll) {
+            interface.add_pattern(pattern)
+                .with_context("While trying to watch for new input devices:")
+                .print_err();
+        }
+    }
+
+    logging::info("The configuration has been reloaded.");
+    if has_no_activity(&program.epoll) {
+        logging::info("No input devices remaining. Evsieve will exit now.");
+        return Ok(Action::Exit);
+    }
+    Ok(Action::Continue)
+}
+
+/// Sends release events through the stream for all keys that are held on any source of events.
+fn release_held_keys(program: &mut Program) {
+    for file in program.epoll.files_mut() {
+        if let Some(source) = file.as_event_source() {
+            for release_event in source.release_pressed_keys() {
+                stream::run(&mut program.setup, release_event);
+            }
+        }
+    }
+    stream::syn(&mut program.setup);
+}
+
+/// Stops processing the events of all sources until the resume command is received. Keys that
+/// are held on those sources get released through the stream, and whatever keys remain
+/// held on the output devices after that get released as well. The input devices get ungrabbed
+/// by `update_grabs()`.
+fn pause(program: &mut Program) {
+    if program.paused {
+        return;
+    }
+    release_held_keys(program);
+    program.setup.output_mut().release_keys();
+    program.paused = true;
+}
+
+/// Lets the stream know which keys were held when a device with the sync-state flag was opened.
+fn sync_initial_state(setup: &mut Setup, device: &InputDevice) {
+    let events = device.synthesize_initial_events();
+    if events.is_empty() {
+        return;
+    }
+    for event in events {
+        stream::run(setup, event);
+    }
+    stream::syn(setup);
+}
+
+/// Grabs or ungrabs the input devices whose grab-while= condition may have changed. Devices that
+/// get handed back to the system have their held keys released through the stream.
+/// Returns an error if a device with grab=required could not be grabbed.
+fn update_grabs(program: &mut Program) -> Result<(), RuntimeError> {
+    let state = program.setup.state();
+    let paused = program.paused;
+    let mut release_events: Vec<Event> = Vec::new();
+    for file in program.epoll.files_mut() {
+        if let Pollable::InputDevice(device) = file {
+            let was_grab_allowed = device.grab_allowed();
+            let result = device.set_paused(paused)
+                .and_then(|()| device.update_grab_condition(state))
+                .with_context_of(|| format!("While updating the grab of the device {}:", device.path().display()));
+            if was_grab_allowed && ! device.grab_allowed() {
+                release_events.extend(device.release_pressed_keys());
+            }
+            match result {
+                Ok(()) => (),
+                Err(error) if device.grab_is_required() => return Err(error.into()),
+                Err(error) => error.print_err(),
+            }
+        }
+    }
+
+    if ! release_events.is_empty() {
+        for event in release_events {
+            stream::run(&mut program.setup, event);
+        }
+        stream::syn(&mut program.setup);
+    }
+    Ok(())
+}
+
+/// Prints the latency statistics of all input devices that have the measure-latency flag.
+fn format_latency_stats(epoll: &Epoll<Pollable>) -> String {
+    let mut report = String::new();
+    for file in epoll.files() {
+        if let Pollable::InputDevice(device) = file {
+            if let Some(stats) = device.latency_stats() {
+                report.push_str(&format!("{}:\n{}", device.path().display(), stats.format()));
+            }
+        }
+    }
+    if report.is_empty() {
+        report = "No latency statistics available. Add the measure-latency flag to an --input argument to collect them.\n".to_owned();
+    }
+    report
+}
+
+/// Returns the counters of the input devices, the stream and the output devices in the text
+/// format that Prometheus scrapes, for --metrics.
+fn format_metrics(setup: &Setup, epoll: &Epoll<Pollable>) -> String {
+    let mut report = String::new();
+    let devices: Vec<&InputDevice> = epoll.files().filter_map(|file| match file {
+        Pollable::InputDevice(device) => Some(&**device),
+        _ => None,
+    }).collect();
+    let events_read: Vec<(String, u64)> = devices.iter()
+        .map(|device| (device.path().display().to_string(), device.events_read()))
+        .collect();
+    metrics::write_metric(&mut report, "evsieve_input_events_total", "counter",
+        "Events read from each input device since it was last opened.", Some("device"), &events_read);
+    metrics::write_metric(&mut report, "evsieve_input_devices", "gauge",
+        "Input devices that are currently open.", None, &[(String::new(), devices.len() as u64)]);
+    setup.write_metrics(&mut report);
+    let reopen_attempts = persist::blueprint::REOPEN_ATTEMPTS.load(std::sync::atomic::Ordering::Relaxed);
+    metrics::write_metric(&mut report, "evsieve_reopen_attempts_total", "counter",
+        "How many times evsieve has tried to reopen a disconnected input device.", None, &[(String::new(), reopen_attempts)]);
+    report
+}
+
+/// Executes a command received through a control FIFO or socket and returns its output.
+fn execute_command(program: &mut Program, command: Command) -> Result<String, RuntimeError> {
+    match command {
+        // These commands need the input devices, which are not part of the Setup.
+        Command::Stats => Ok(format_latency_stats(&program.epoll)),
+        Command::DumpState => Ok(format_state(&program.setup, &program.epoll)),
+        Command::ListDevices(false) => Ok(format_devices(&program.setup, &program.epoll)),
+        Command::ListDevices(true) => Ok(format_devices_json(&program.setup, &program.epoll)),
+        // The input devices get ungrabbed or grabbed again by `update_grabs()`.
+        Command::Pause => {
+            pause(program);
+            Ok(String::new())
+        },
+        Command::Resume => {
+            program.paused = false;
+            Ok(String::new())
+        },
+        Command::LogLevel(None, _) => Ok(format!("{}\n", logging::level().name())),
+        Command::LogLevel(Some(level), duration) => {
+            // If a previous level is still to be restored, that one remains the level to restore.
+            let previous_level = match program.log_revert {
+                Some((_, previous_level)) => previous_level,
+                None => logging::level(),
+            };
+            program.log_revert = duration.map(|duration| (time::Instant::now() + duration, previous_level));
+            logging::set_level(level);
+            Ok(String::new())
+        },
+        command => command.execute(&mut program.setup),
+    }
+}
+
+/// Describes the state of the stream and the keys that are held on each input device, for the
+/// dump-state command.
+fn format_state(setup: &Setup, epoll: &Epoll<Pollable>) -> String {
+    let mut report = setup.format_state();
+    report.push_str("Held keys:\n");
+    let mut any_keys_held = false;
+    for file in epoll.files() {
+        if let Pollable::InputDevice(device) = file {
+            let keys = device.held_keys();
+            if ! keys.is_empty() {
+                any_keys_held = true;
+                report.push_str(&format!("    {}: {}\n", device.path().display(), keys.join(", ")));
+            }
+        }
+    }
+    if ! any_keys_held {
+        report.push_str("    none\n");
+    }
+    report
+}
+
+/// Returns one line for each device evsieve reads events from or writes events to.
+fn format_devices(setup: &Setup, epoll: &Epoll<Pollable>) -> String {
+    let mut report = String::new();
+    for file in epoll.files() {
+        match file {
+            Pollable::InputDevice(device) => report.push_str(&format!(
+                "input {} name=\"{}\"\n", device.path().display(), device.name().to_string_lossy()
+            )),
+            Pollable::Replay(device) => report.push_str(&format!("input file={}\n", device.path().display())),
+            Pollable::Hidraw(device) => report.push_str(&format!("input hidraw={}\n", device.path().display())),
+            Pollable::Joystick(device) => report.push_str(&format!("input joystick={}\n", device.path().display())),
+            Pollable::NetworkInput(input) => report.push_str(&format!("input {}\n", input.description())),
+            _ => (),
+        }
+    }
+    report.push_str(&setup.output().format_devices());
+    report
+}
+
+/// Like `format_devices()`, but returns a single JSON object for the sake of other programs.
+fn format_devices_json(setup: &Setup, epoll: &Epoll<Pollable>) -> String {
+    use crate::io::serialize::quote_json;
+    let quote_path = |path: &std::path::Path| quote_json(&path.to_string_lossy());
+    let mut inputs: Vec<String> = Vec::new();
+    for file in epoll.files() {
+        let input = match file {
+            Pollable::InputDevice(device) => format!(
+                "{{\"type\":\"evdev\",\"path\":{},\"name\":{},\"grabbed\":{},\"domain\":{}}}",
+                quote_path(device.path()),
+                quote_json(&device.name().to_string_lossy()),
+                device.is_grabbed(),
+                match domain::try_reverse_resolve_origin(device.domain()) {
+                    Some(name) => quote_json(&name),
+                    None => "null".to_owned(),
+                },
+            ),
+            Pollable::Replay(device) => format!("{{\"type\":\"file\",\"path\":{}}}", quote_path(device.path())),
+            Pollable::Hidraw(device) => format!("{{\"type\":\"hidraw\",\"path\":{}}}", quote_path(device.path())),
+            Pollable::Joystick(device) => format!("{{\"type\":\"joystick\",\"path\":{}}}", quote_path(device.path())),
+            Pollable::NetworkInput(input) => format!("{{\"type\":\"network\",\"path\":{}}}", quote_json(input.description())),
+            _ => continue,
+        };
+        inputs.push(input);
+    }
+    format!(
+        "{{\"inputs\":[{}],\"outputs\":[{}]}}\n",
+        inputs.join(","), setup.output().format_devices_json().join(","),
+    )
+}
+
 /// Returns true if evsieve has nothing to do and should just exit.
+/// Returns true if the same event device as this one is already among the opened input devices.
+fn is_already_opened(epoll: &Epoll<Pollable>, device: &InputDevice) -> bool {
+    let device_number = match device.device_number() {
+        Some(number) => number,
+        None => return false,
+    };
+    epoll.files().any(|file| match file {
+        Pollable::InputDevice(other_device) => other_device.device_number() == Some(device_number),
+        _ => false,
+    })
+}
+
 fn has_no_activity(epoll: &Epoll<Pollable>) -> bool {
     for file in epoll.files() {
         match file {
             Pollable::InputDevice(_) => return false,
+            Pollable::Replay(_) => return false,
+            Pollable::Hidraw(_) => return false,
+            Pollable::Joystick(_) => return false,
+            Pollable::NetworkListener(_) => return false,
+            Pollable::NetworkInput(_) => return false,
             Pollable::PersistSubsystem(_) => return false,
             Pollable::ControlFifo(_) => (),
+            Pollable::ControlSocket(_) => (),
+            Pollable::ControlConnection(_) => (),
+            Pollable::MetricsListener(_) => (),
+            Pollable::MetricsConnection(_) => (),
+            Pollable::DBus(_) => (),
+            Pollable::Feedback(_) => (),
             Pollable::SignalFd(_) => (),
+            Pollable::Stop(_) => (),
         }
     }
     true
 }
+
+#[test]
+fn unittest() {
+    // Keys that are held on any source of events must get released when evsieve pauses or
+    // changes profile, not only the keys held on evdev input devices.
+    let domain = domain::get_unique_domain();
+    let input = NetworkInput::bind_udp("127.0.0.1:0".to_owned(), domain).unwrap();
+    let mut file = Pollable::NetworkInput(input);
+    let key_a = EventCode::new(EventType::KEY, 30);
+    file.as_event_source().unwrap().synthesize_event(key_a, 1);
+
+    let releases = file.as_event_source().unwrap().release_pressed_keys();
+    assert_eq!(releases.len(), 1);
+    assert_eq!((releases[0].code, releases[0].value, releases[0].domain), (key_a, 0, domain));
+    assert!(file.as_event_source().unwrap().release_pressed_keys().is_empty());
+
+    let (_sender, receiver) = io::internal_pipe::channel().unwrap();
+    assert!(Pollable::Stop(receiver).as_event_source().is_none());
+}
@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements --metrics, which answers HTTP requests on a Unix domain socket or TCP port with
+//! counters in the text format that Prometheus scrapes. Every request gets the same response
+//! regardless of its method or path, after which the connection is closed.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::{RawFd, AsRawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+use crate::error::{Context, SystemError};
+use crate::io::fd::HasFixedFd;
+
+/// Where the metrics are served, as specified by the --metrics argument.
+pub enum MetricsAddress {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+enum Listener {
+    Unix(UnixListener, PathBuf),
+    Tcp(TcpListener, String),
+}
+
+pub struct MetricsListener {
+    listener: Listener,
+}
+
+impl MetricsListener {
+    pub fn bind(address: MetricsAddress) -> Result<MetricsListener, SystemError> {
+        let listener = match address {
+            MetricsAddress::Unix(path) => {
+                // A socket left behind by a previous instance of evsieve would make the bind fail.
+                if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+                    if metadata.file_type().is_socket() {
+                        std::fs::remove_file(&path)?;
+                    }
+                }
+                let listener = UnixListener::bind(&path)
+                    .map_err(SystemError::from)
+                    .with_context_of(|| format!("While creating a metrics socket at {}:", path.display()))?;
+                listener.set_nonblocking(true)?;
+                Listener::Unix(listener, path)
+            },
+            MetricsAddress::Tcp(address) => {
+                let listener = TcpListener::bind(&address)
+                    .map_err(SystemError::from)
+                    .with_context_of(|| format!("While listening for metrics requests on {}:", address))?;
+                listener.set_nonblocking(true)?;
+                Listener::Tcp(listener, address)
+            },
+        };
+        Ok(MetricsListener { listener })
+    }
+
+    /// Returns None if no connection was waiting to be accepted after all.
+    pub fn accept(&mut self) -> Result<Option<MetricsConnection>, SystemError> {
+        let accepted = match &self.listener {
+            Listener::Unix(listener, _) => listener.accept().map(|(stream, _)| Stream::Unix(stream)),
+            Listener::Tcp(listener, _) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+        };
+        let stream = match accepted {
+            Ok(stream) => stream,
+            Err(error) => match error.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted => return Ok(None),
+                _ => return Err(error.into()),
+            },
+        };
+        match &stream {
+            Stream::Unix(stream) => stream.set_nonblocking(true)?,
+            Stream::Tcp(stream) => stream.set_nonblocking(true)?,
+        }
+        Ok(Some(MetricsConnection { stream, request: Vec::new() }))
+    }
+
+    pub fn description(&self) -> String {
+        match &self.listener {
+            Listener::Unix(_, path) => path.display().to_string(),
+            Listener::Tcp(_, address) => address.clone(),
+        }
+    }
+}
+
+impl Drop for MetricsListener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = &self.listener {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl AsRawFd for MetricsListener {
+    fn as_raw_fd(&self) -> RawFd {
+        match &self.listener {
+            Listener::Unix(listener, _) => listener.as_raw_fd(),
+            Listener::Tcp(listener, _) => listener.as_raw_fd(),
+        }
+    }
+}
+unsafe impl HasFixedFd for MetricsListener {}
+
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+/// A client that connected to the metrics listener and is expected to send an HTTP request.
+pub struct MetricsConnection {
+    stream: Stream,
+    /// The part of the request that has been received so far.
+    request: Vec<u8>,
+}
+
+/// Requests longer than this are answered without waiting for the rest of them.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+impl MetricsConnection {
+    /// Returns true once the whole request header has been received, or the client stopped
+    /// sending, after which the request should be answered through `respond()`.
+    pub fn poll(&mut self) -> Result<bool, SystemError> {
+        let mut buf = [0u8; 1024];
+        let received = match &mut self.stream {
+            Stream::Unix(stream) => stream.read(&mut buf),
+            Stream::Tcp(stream) => stream.read(&mut buf),
+        };
+        let num_bytes = match received {
+            Ok(num_bytes) => num_bytes,
+            Err(error) => match error.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted => return Ok(false),
+                _ => return Err(error.into()),
+            },
+        };
+        self.request.extend_from_slice(&buf[.. num_bytes]);
+        Ok(num_bytes == 0
+            || self.request.len() >= MAX_REQUEST_SIZE
+            || self.request.windows(4).any(|window| window == b"\r\n\r\n")
+            || self.request.windows(2).any(|window| window == b"\n\n"))
+    }
+
+    /// Sends the metrics as HTTP response. The connection should be dropped afterwards.
+    pub fn respond(&mut self, metrics: &str) -> Result<(), SystemError> {
+        let response = format!(
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            metrics.len(), metrics,
+        );
+        // The response is small, so a short blocking write does not hold up the main loop for long.
+        let timeout = Some(std::time::Duration::from_secs(1));
+        match &mut self.stream {
+            Stream::Unix(stream) => {
+                stream.set_nonblocking(false)?;
+                stream.set_write_timeout(timeout)?;
+                stream.write_all(response.as_bytes())?;
+            },
+            Stream::Tcp(stream) => {
+                stream.set_nonblocking(false)?;
+                stream.set_write_timeout(timeout)?;
+                stream.write_all(response.as_bytes())?;
+            },
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for MetricsConnection {
+    fn as_raw_fd(&self) -> RawFd {
+        match &self.stream {
+            Stream::Unix(stream) => stream.as_raw_fd(),
+            Stream::Tcp(stream) => stream.as_raw_fd(),
+        }
+    }
+}
+unsafe impl HasFixedFd for MetricsConnection {}
+
+/// Appends a metric with its HELP and TYPE lines to the report. Every sample consists of the
+/// value of the label and the value of the metric. If `label` is None, there must be exactly
+/// one sample, which gets no labels.
+pub fn write_metric(report: &mut String, name: &str, kind: &str, help: &str, label: Option<&str>, samples: &[(String, u64)]) {
+    report.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+    for (label_value, value) in samples {
+        match label {
+            Some(label) => report.push_str(&format!("{}{{{}=\"{}\"}} {}\n", name, label, escape_label(label_value), value)),
+            None => report.push_str(&format!("{} {}\n", name, value)),
+        }
+    }
+}
+
+/// Escapes a label value as required by the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(escape_label("key:a \"x\"\\"), "key:a \\\"x\\\"\\\\");
+
+    let mut report = String::new();
+    write_metric(&mut report, "evsieve_test_total", "counter", "Test.", Some("device"), &[("/dev/input/event0".to_owned(), 3)]);
+    write_metric(&mut report, "evsieve_open", "gauge", "Open.", None, &[(String::new(), 1)]);
+    assert_eq!(report, "# HELP evsieve_test_total Test.\n# TYPE evsieve_test_total counter\nevsieve_test_total{device=\"/dev/input/event0\"} 3\n# HELP evsieve_open Open.\n# TYPE evsieve_open gauge\nevsieve_open 1\n");
+}
@@ -1,43 +1,92 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::io::input::{InputDevice, InputDeviceName};
-use crate::predevice::PreInputDevice;
+use crate::predevice::{PreInputDevice, ReopenBackoff};
 use crate::capability::Capabilities;
 use crate::error::{SystemError};
+use crate::time::{Duration, Instant};
+use crate::logging;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How many times evsieve has tried to reopen a device from a blueprint, for --metrics. Counted
+/// here because the attempts are made by the persistence subsystem's thread.
+pub static REOPEN_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
 
 /// Represents something can can be used to re-open a closed input device.
 pub struct Blueprint {
     pub pre_device: PreInputDevice,
     pub capabilities: Capabilities,
     pub name: InputDeviceName,
+    /// If Some, when this blueprint should be retried periodically.
+    retry: Option<RetrySchedule>,
+}
+
+struct RetrySchedule {
+    backoff: ReopenBackoff,
+    /// The interval that was waited before the next retry.
+    interval: Duration,
+    next_retry: Instant,
 }
 
 impl Blueprint {
+    pub fn new(pre_device: PreInputDevice, capabilities: Capabilities, name: InputDeviceName) -> Blueprint {
+        let retry = pre_device.reopen_backoff.map(|backoff| RetrySchedule {
+            backoff,
+            interval: backoff.interval,
+            next_retry: Instant::now() + backoff.interval,
+        });
+        Blueprint { pre_device, capabilities, name, retry }
+    }
+
+    /// Returns the time at which this blueprint should be retried if nothing else happens before.
+    pub fn next_retry(&self) -> Option<Instant> {
+        self.retry.as_ref().map(|retry| retry.next_retry)
+    }
+
+    /// Returns true if this blueprint should still be retried if opening it failed with an error.
+    pub fn retries_periodically(&self) -> bool {
+        self.retry.is_some()
+    }
+
+    /// If the periodic retry is due, schedules the next one after a doubled interval.
+    pub fn postpone_retry(&mut self, now: Instant) {
+        if let Some(retry) = &mut self.retry {
+            if retry.next_retry <= now {
+                retry.interval = std::cmp::min(retry.interval * 2, retry.backoff.max_interval);
+                retry.next_retry = now + retry.interval;
+            }
+        }
+    }
+
     /// Tries to reopen the device from which this blueprint was generated.
     /// On success, returns the device. On failure, returns Ok(None). In case of a grave
     /// error that signals reopening should not be retried, returns Err(SystemError).
     pub fn try_open(&self) -> Result<Option<InputDevice>, SystemError> {
+        REOPEN_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
         if ! self.pre_device.path.exists() {
             return Ok(None);
         }
         let input_device = InputDevice::open(self.pre_device.clone())?;
+        if ! input_device.is_accepted_by_cap_filter() {
+            return Ok(None);
+        }
 
         // Do sanity checks.
         if input_device.name() != &self.name {
-            println!(
+            logging::warning(format!(
                 "Warning: the reconnected device \"{}\" has a different name than expected. Expected name: \"{}\", new name: \"{}\".",
                 self.pre_device.path.display(),
                 self.name.to_string_lossy(),
                 input_device.name().to_string_lossy(),
-            );
+            ));
         }
 
         // TODO: this may print warnings on capabilities differing only in value.
         if *input_device.capabilities() != self.capabilities {
-            println!(
+            logging::warning(format!(
                 "Warning: the capabilities of the reconnected device \"{}\" are different than expected.",
                 self.pre_device.path.display()
-            );
+            ));
         }
         
         Ok(Some(input_device))
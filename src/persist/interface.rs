@@ -2,9 +2,12 @@
 
 use crate::persist::subsystem::{Command, Report};
 use crate::persist::blueprint::Blueprint;
+use crate::persist::pattern::Pattern;
 use crate::io::internal_pipe::{Sender, Receiver};
 use crate::io::epoll::{Epoll, FileIndex};
 use crate::{Pollable, error::*};
+use crate::logging;
+use std::path::PathBuf;
 use std::thread::JoinHandle;
 use std::os::unix::io::{AsRawFd, RawFd};
 
@@ -21,6 +24,21 @@ impl HostInterface {
         self.commander.send(Command::AddBlueprint(blueprint))
     }
 
+    /// Asks the subsystem to open all devices that match this pattern, now or in the future.
+    pub fn add_pattern(&mut self, pattern: Pattern) -> Result<(), SystemError> {
+        self.commander.send(Command::AddPattern(pattern))
+    }
+
+    /// Tells the subsystem that a device it opened through a pattern has been closed.
+    pub fn release_path(&mut self, path: PathBuf) -> Result<(), SystemError> {
+        self.commander.send(Command::ReleasePath(path))
+    }
+
+    /// Asks the subsystem to forget all blueprints and patterns it was given.
+    pub fn clear(&mut self) -> Result<(), SystemError> {
+        self.commander.send(Command::Clear)
+    }
+
     /// Asks the subsystem to start shutting down. Does not wait until it has actually shut down.
     pub fn request_shutdown(&mut self) -> Result<(), SystemError> {
         self.commander.send(Command::Shutdown)
@@ -69,7 +87,7 @@ impl HostInterfaceState {
             let interface = match crate::persist::subsystem::launch() {
                 Ok(interface) => interface,
                 Err(error) => {
-                    eprintln!("Warning: failed to start the persistence subsystem. Devices with the persist flag may not be (re)opened successfully.");
+                    logging::warning("Warning: failed to start the persistence subsystem. Devices with the persist flag may not be (re)opened successfully.");
                     error.print_err();
                     *self = Error;
                     return None;
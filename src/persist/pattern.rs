@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! A pattern is an --input path containing wildcards, such as /dev/input/by-id/usb-Vendor*-event-kbd.
+//! If persistence was requested, the persistence subsystem keeps watching for new devices matching
+//! the pattern and opens them as they show up, even if they were never present before.
+
+use crate::io::input::InputDevice;
+use crate::predevice::PreInputDevice;
+use crate::error::Context;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+pub struct Pattern {
+    /// All devices opened through this pattern are copies of this pre-device, except that their
+    /// path is replaced by the path that matched. The path of this pre-device is the pattern itself.
+    pub pre_device: PreInputDevice,
+    /// The paths that matched this pattern and whose devices are currently opened.
+    claimed_paths: HashSet<PathBuf>,
+    /// The paths that matched this pattern, but whose devices were rejected by the capability
+    /// filter. They are not tried again until a different device shows up at that path.
+    rejected_paths: HashSet<PathBuf>,
+}
+
+impl Pattern {
+    pub fn new(pre_device: PreInputDevice, claimed_paths: HashSet<PathBuf>) -> Pattern {
+        Pattern { pre_device, claimed_paths, rejected_paths: HashSet::new() }
+    }
+
+    /// The directory in which the devices matching this pattern appear.
+    pub fn directory(&self) -> PathBuf {
+        match self.pre_device.path.parent() {
+            Some(directory) => directory.to_owned(),
+            None => PathBuf::from("/"),
+        }
+    }
+
+    /// Opens all devices that match this pattern and are not already opened.
+    pub fn try_open(&mut self) -> Vec<InputDevice> {
+        let mut opened_devices = Vec::new();
+        let matching_paths = find_matching_paths(&self.pre_device.path);
+        // Devices that were turned down by the main thread are not released, so they are unclaimed
+        // once their path disappears.
+        self.claimed_paths.retain(|path| matching_paths.contains(path));
+        self.rejected_paths.retain(|path| matching_paths.contains(path));
+        for path in matching_paths {
+            if self.claimed_paths.contains(&path) || self.rejected_paths.contains(&path) {
+                continue;
+            }
+
+            let mut pre_device = self.pre_device.clone();
+            pre_device.path = path.clone();
+            match InputDevice::open(pre_device) {
+                Ok(device) if ! device.is_accepted_by_cap_filter() => {
+                    self.rejected_paths.insert(path);
+                },
+                Ok(device) => {
+                    self.claimed_paths.insert(path);
+                    opened_devices.push(device);
+                },
+                Err(error) => error
+                    .with_context(format!("While opening the device \"{}\":", path.display()))
+                    .print_err(),
+            }
+        }
+        opened_devices
+    }
+
+    /// Informs this pattern that the device at the given path is no longer opened, so it should
+    /// be opened again if a device shows up at that path. Returns whether that path was claimed
+    /// by this pattern.
+    pub fn release(&mut self, path: &Path) -> bool {
+        self.claimed_paths.remove(path)
+    }
+}
+
+/// Returns all paths that currently match a pattern. Only the last component of the pattern
+/// may contain wildcards.
+pub fn find_matching_paths(pattern: &Path) -> Vec<PathBuf> {
+    let (directory, file_pattern) = match (pattern.parent(), pattern.file_name().and_then(|name| name.to_str())) {
+        (Some(directory), Some(file_pattern)) => (directory, file_pattern),
+        _ => return Vec::new(),
+    };
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| match entry.file_name().to_str() {
+            Some(name) => crate::utils::glob_match(file_pattern, name),
+            None => false,
+        })
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn unittest() {
+    let directory = std::env::temp_dir().join(format!("evsieve-pattern-test-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    for name in &["usb-Vendor_A-event-kbd", "usb-Vendor_B-event-kbd", "usb-Vendor_A-event-mouse"] {
+        std::fs::File::create(directory.join(name)).unwrap();
+    }
+    assert_eq!(
+        find_matching_paths(&directory.join("usb-Vendor*-event-kbd")),
+        vec![directory.join("usb-Vendor_A-event-kbd"), directory.join("usb-Vendor_B-event-kbd")],
+    );
+    assert!(find_matching_paths(&directory.join("usb-Other*")).is_empty());
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(find_matching_paths(&directory.join("usb-Vendor*-event-kbd")).is_empty());
+}
@@ -13,18 +13,28 @@ use crate::io::input::InputDevice;
 use crate::io::internal_pipe;
 use crate::io::internal_pipe::{Sender, Receiver};
 use crate::persist::blueprint::Blueprint;
+use crate::persist::pattern::Pattern;
 use crate::persist::inotify::Inotify;
 use crate::persist::interface::HostInterface;
 use crate::error::{Context, RuntimeError, SystemError};
 use crate::io::epoll::{Epoll, Message};
+use crate::time::Instant;
+use std::convert::TryInto;
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::os::unix::io::{AsRawFd, RawFd};
 
 /// Commands that the main thread can send to this subsystem.
 pub enum Command {
     /// Requests this subsystem to try to reopen this blueprint.
     AddBlueprint(Blueprint),
+    /// Requests this subsystem to open all devices that show up matching this pattern.
+    AddPattern(Pattern),
+    /// Informs this subsystem that a device that was opened through a pattern is no longer open.
+    ReleasePath(PathBuf),
+    /// Requests this subsystem to forget all blueprints and patterns, because the configuration
+    /// they were made for has been reloaded.
+    Clear,
     /// Requests this subsystem to halt.
     Shutdown,
 }
@@ -58,6 +68,7 @@ unsafe impl HasFixedFd for Pollable {}
 
 pub struct Daemon {
     blueprints: Vec<Blueprint>,
+    patterns: Vec<Pattern>,
     inotify: Inotify,
 }
 
@@ -94,7 +105,20 @@ fn start_worker(comm_in: Receiver<Command>, comm_out: &mut Sender<Report>) -> Re
     epoll.add_file(Pollable::Command(comm_in))?;
     
     loop {
-        let (commands, mut reports) = poll(&mut epoll)?;
+        let timeout = match &epoll[daemon_index] {
+            Pollable::Daemon(daemon) => daemon.retry_timeout(),
+            _ => unreachable!(),
+        };
+        let (commands, mut reports) = poll(&mut epoll, timeout)?;
+
+        // Retry the blueprints that are retried periodically.
+        if let Pollable::Daemon(daemon) = &mut epoll[daemon_index] {
+            if daemon.is_retry_due() {
+                try_open_and_report(daemon, &mut reports)?;
+                daemon.postpone_retries();
+            }
+        }
+
         for command in commands {
             match command {
                 Command::Shutdown => return Ok(()),
@@ -107,7 +131,26 @@ fn start_worker(comm_in: Receiver<Command>, comm_out: &mut Sender<Report>) -> Re
                         try_open_and_report(daemon, &mut reports)?;
                     },
                     _ => unreachable!(),
-                }
+                },
+                Command::AddPattern(pattern) => match &mut epoll[daemon_index] {
+                    Pollable::Daemon(daemon) => {
+                        daemon.add_pattern(pattern)?;
+                        try_open_and_report(daemon, &mut reports)?;
+                    },
+                    _ => unreachable!(),
+                },
+                Command::Clear => match &mut epoll[daemon_index] {
+                    Pollable::Daemon(daemon) => daemon.clear()?,
+                    _ => unreachable!(),
+                },
+                Command::ReleasePath(path) => match &mut epoll[daemon_index] {
+                    Pollable::Daemon(daemon) => {
+                        daemon.release_path(&path);
+                        // The device may have already shown up again at the same path.
+                        try_open_and_report(daemon, &mut reports)?;
+                    },
+                    _ => unreachable!(),
+                },
             }
         }
         for report in reports {
@@ -116,11 +159,11 @@ fn start_worker(comm_in: Receiver<Command>, comm_out: &mut Sender<Report>) -> Re
     }
 }
 
-fn poll(epoll: &mut Epoll<Pollable>) -> Result<(Vec<Command>, Vec<Report>), RuntimeError> {
+fn poll(epoll: &mut Epoll<Pollable>, timeout: i32) -> Result<(Vec<Command>, Vec<Report>), RuntimeError> {
     let mut commands: Vec<Command> = Vec::new();
     let mut reports: Vec<Report> = Vec::new();
 
-    match epoll.poll(crate::io::epoll::INDEFINITE_TIMEOUT) {
+    match epoll.poll(timeout) {
         Err(error) => {
             error.with_context("While the persistence subsystem was polling for events:").print_err();
             commands.push(Command::Shutdown);
@@ -175,6 +218,7 @@ impl Daemon {
     pub fn new() -> Result<Daemon, SystemError> {
         Ok(Daemon {
             blueprints: Vec::new(),
+            patterns: Vec::new(),
             inotify: Inotify::new()?,
         })
     }
@@ -185,6 +229,57 @@ impl Daemon {
         Ok(())
     }
 
+    pub fn add_pattern(&mut self, pattern: Pattern) -> Result<(), RuntimeError> {
+        self.patterns.push(pattern);
+        self.update_watches()?;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<(), RuntimeError> {
+        self.blueprints.clear();
+        self.patterns.clear();
+        self.update_watches()?;
+        Ok(())
+    }
+
+    pub fn release_path(&mut self, path: &Path) {
+        for pattern in &mut self.patterns {
+            if pattern.release(path) {
+                return;
+            }
+        }
+    }
+
+    /// Returns how many milliseconds the subsystem can wait until some blueprint needs to be
+    /// retried periodically.
+    fn retry_timeout(&self) -> i32 {
+        let next_retry = match self.blueprints.iter().filter_map(Blueprint::next_retry).min() {
+            Some(instant) => instant,
+            None => return crate::io::epoll::INDEFINITE_TIMEOUT,
+        };
+        match next_retry.checked_duration_since(Instant::now()) {
+            // Round up, so we do not wake up slightly before the retry is due. If the retry is
+            // absurdly far in the future, we just wait until something else happens.
+            Some(duration) => (duration.as_millis() + 1).try_into()
+                .unwrap_or(crate::io::epoll::INDEFINITE_TIMEOUT),
+            None => 0,
+        }
+    }
+
+    fn is_retry_due(&self) -> bool {
+        let now = Instant::now();
+        self.blueprints.iter()
+            .filter_map(Blueprint::next_retry)
+            .any(|next_retry| next_retry <= now)
+    }
+
+    fn postpone_retries(&mut self) {
+        let now = Instant::now();
+        for blueprint in &mut self.blueprints {
+            blueprint.postpone_retry(now);
+        }
+    }
+
     /// Does nothing but clearing out the queued events. Call Daemon::try_open() to try to actually
     /// open the associated blueprints.
     pub fn poll(&mut self) -> Result<(), SystemError> {
@@ -219,11 +314,19 @@ impl Daemon {
                     Ok(None) => remaining_blueprints.push(blueprint),
                     Err(error) => {
                         error.print_err();
-                        result.broken_blueprints.push(blueprint);
+                        if blueprint.retries_periodically() {
+                            remaining_blueprints.push(blueprint);
+                        } else {
+                            result.broken_blueprints.push(blueprint);
+                        }
                     }
                 }
             }
             self.blueprints = remaining_blueprints;
+
+            for pattern in &mut self.patterns {
+                result.opened_devices.extend(pattern.try_open());
+            }
             
             // Just in case the relevant paths change between now and when we actually watch them
             // thanks to a race-condition, we do this within a loop until the paths are identical
@@ -285,6 +388,16 @@ impl Daemon {
             traversed_directories.extend(&mut directories);
         }
 
+        for pattern in &self.patterns {
+            match pattern.directory().into_os_string().into_string() {
+                Ok(directory) => traversed_directories.push(directory),
+                Err(os_string) => crate::utils::warn_once(format!(
+                    "Error: unable to deal with non-UTF8 path \"{}\".",
+                    os_string.to_string_lossy()
+                )),
+            }
+        }
+
         traversed_directories.sort_unstable();
         traversed_directories.dedup();
         
@@ -1,12 +1,20 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use crate::capability::{AbsInfo, Capabilities, RepeatInfo};
 use crate::domain::Domain;
+use crate::event::{EventCode, EventType};
+use crate::io::record::{RecordFormat, RecordTarget};
+use crate::state::{State, ToggleIndex};
+use crate::time::Duration;
 use std::path::PathBuf;
 
 /// Represents whether and how the user has requested the device to be grabbed.
 /// Set through the grab flag or grab= clause on --input arguments.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum GrabMode {
+    /// The user has requested this device to be grabbed immediately, and evsieve to exit if
+    /// it cannot be grabbed at any point.
+    Required,
     /// The user has requested this device to be grabbed immediately.
     Force,
     /// The user has requested this device to be grabbed once no EV_KEY keys are pressed.
@@ -19,7 +27,9 @@ impl GrabMode {
     /// If some input device is specified multiple times with different grab mode,
     /// this function finds the strongest of both of them.
     pub fn combine(first: GrabMode, second: GrabMode) -> GrabMode {
-        if first == GrabMode::Force || second == GrabMode::Force {
+        if first == GrabMode::Required || second == GrabMode::Required {
+            GrabMode::Required
+        } else if first == GrabMode::Force || second == GrabMode::Force {
             GrabMode::Force
         } else if first == GrabMode::Auto || second == GrabMode::Auto {
             GrabMode::Auto
@@ -29,6 +39,21 @@ impl GrabMode {
     }
 }
 
+/// If a device has a grab condition, it is only grabbed while a certain toggle is in a certain
+/// state, and ungrabbed otherwise. Set through the grab-while= clause on --input arguments.
+#[derive(Clone, Copy)]
+pub struct GrabCondition {
+    pub toggle: ToggleIndex,
+    /// The index of the state, starting at 0.
+    pub state_index: usize,
+}
+
+impl GrabCondition {
+    pub fn is_met(&self, state: &State) -> bool {
+        state[self.toggle].value() == self.state_index
+    }
+}
+
 /// Represents what should happen if the device is not available.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum PersistMode {
@@ -36,10 +61,82 @@ pub enum PersistMode {
     None,
     /// Try to reattach the device at runtime, or throw an error at startup time.
     Reopen,
+    /// Like Reopen, but for paths containing wildcards, also open matching devices that show
+    /// up at runtime, even if none matched at startup time.
+    Full,
     /// If a device with mode exit disconnects, evsieve shall exit, even if other devices are still available.
     Exit,
 }
 
+impl PersistMode {
+    /// Returns true if devices with this mode should be reopened after they disconnect.
+    pub fn reopens(self) -> bool {
+        match self {
+            PersistMode::Reopen | PersistMode::Full => true,
+            PersistMode::None | PersistMode::Exit => false,
+        }
+    }
+}
+
+/// How often a disconnected device with persist=reopen should be retried periodically, in addition
+/// to the retries that happen whenever something changes in the directories leading to its path.
+/// Set through the reopen-interval= and reopen-max-interval= clauses on --input arguments.
+#[derive(Clone, Copy)]
+pub struct ReopenBackoff {
+    /// The time to wait before the first retry.
+    pub interval: Duration,
+    /// The interval doubles after each failed retry, but never exceeds this.
+    pub max_interval: Duration,
+}
+
+/// The clock that the kernel uses to timestamp the events of an input device. Set through the
+/// clock= clause on --input arguments.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EventClock {
+    Realtime,
+    Monotonic,
+    Boottime,
+}
+
+impl EventClock {
+    pub fn clock_id(self) -> libc::clockid_t {
+        match self {
+            EventClock::Realtime => libc::CLOCK_REALTIME,
+            EventClock::Monotonic => libc::CLOCK_MONOTONIC,
+            EventClock::Boottime => libc::CLOCK_BOOTTIME,
+        }
+    }
+}
+
+/// What to do when the kernel reports that events of an input device were dropped because
+/// evsieve did not read them fast enough. Set through the syn-dropped= clause on --input arguments.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SynDroppedPolicy {
+    /// Query the current state of the device and generate events for everything that changed.
+    Resync,
+    /// Like Resync, but also print a warning.
+    Log,
+    /// Carry on as if nothing happened.
+    Ignore,
+}
+
+/// Restricts which devices an --input argument accepts based on their capabilities. Set through
+/// the require-caps= and exclude-caps= clauses on --input arguments.
+#[derive(Clone)]
+pub struct CapabilityFilter {
+    /// The device must be capable of all of these events.
+    pub required: Vec<EventCode>,
+    /// The device must not be capable of any of these events.
+    pub excluded: Vec<EventCode>,
+}
+
+impl CapabilityFilter {
+    pub fn accepts(&self, capabilities: &Capabilities) -> bool {
+        self.required.iter().all(|code| capabilities.codes.contains(code))
+            && ! self.excluded.iter().any(|code| capabilities.codes.contains(code))
+    }
+}
+
 #[derive(Clone)]
 pub struct PreInputDevice {
     /// The path to this device.
@@ -50,6 +147,64 @@ pub struct PreInputDevice {
     pub grab_mode: GrabMode,
     /// What should be done if the device is disconnected while running.
     pub persist_mode: PersistMode,
+    /// Whether this device was found by matching an --input path that contains wildcards.
+    /// Such devices share their domain with the other devices matching the same pattern. With
+    /// persist=full, they are not reopened by themselves, but by the pattern they matched.
+    pub from_pattern: bool,
+    /// If Some, the device is retried periodically while it is disconnected.
+    pub reopen_backoff: Option<ReopenBackoff>,
+    /// If Some, the device shall only be grabbed while this condition is met.
+    pub grab_condition: Option<GrabCondition>,
+    /// If Some, the device is only used if its capabilities pass this filter.
+    pub cap_filter: Option<CapabilityFilter>,
+    /// If Some, the kernel shall timestamp the events of this device using this clock.
+    pub clock: Option<EventClock>,
+    /// Whether latency statistics shall be collected for this device.
+    pub measure_latency: bool,
+    /// Whether the keys that are held and switches that are on when this device gets opened
+    /// shall be announced to the stream as if they were just pressed.
+    pub sync_state: bool,
+    /// Whether some output device forwards force feedback effects to this device. If so, the
+    /// device needs to be opened with write access.
+    pub forward_ff: bool,
+    /// Whether LED changes made to the output devices shall be mirrored on this device. Also
+    /// requires the device to be opened with write access.
+    pub forward_leds: bool,
+    pub syn_dropped: SynDroppedPolicy,
+}
+
+/// Identifies input devices by the properties they report rather than by their path.
+/// Set through the name=, vendor= and product= clauses on --input arguments.
+#[derive(Clone)]
+pub struct DeviceSelector {
+    pub name: Option<String>,
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+}
+
+/// The properties of an input device that a DeviceSelector can select on.
+pub struct DeviceId {
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+}
+
+impl DeviceSelector {
+    pub fn matches(&self, id: &DeviceId) -> bool {
+        let name_matches = match &self.name {
+            Some(name) => *name == id.name,
+            None => true,
+        };
+        let vendor_matches = match self.vendor {
+            Some(vendor) => vendor == id.vendor,
+            None => true,
+        };
+        let product_matches = match self.product {
+            Some(product) => product == id.product,
+            None => true,
+        };
+        name_matches && vendor_matches && product_matches
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -62,13 +217,221 @@ pub enum RepeatMode {
     Passive,
 }
 
+/// The ownership and permissions of the device node of an output device, as set by the mode=,
+/// owner= and group= clauses. Fields that are None are left the way udev set them up.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodePermissions {
+    pub mode: Option<libc::mode_t>,
+    pub owner: Option<libc::uid_t>,
+    pub group: Option<libc::gid_t>,
+}
+
+impl NodePermissions {
+    pub fn is_default(&self) -> bool {
+        self.mode.is_none() && self.owner.is_none() && self.group.is_none()
+    }
+}
+
+/// When SYN_REPORT events are written to an output device.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SynMode {
+    /// Whenever an input device reports, and after each event if a single event got mapped to
+    /// multiple events.
+    Passthrough,
+    /// Whenever an input device reports, even if a single event got mapped to multiple events.
+    Coalesce,
+    /// After every single event.
+    PerEvent,
+}
+
 pub struct PreOutputDevice {
     /// All events with this domain shall be written to this device.
     pub domain: Domain,
-    /// If Some, the user has requested a symlink to the device to be created at the given path.
-    pub create_link: Option<PathBuf>,
+    /// The user has requested symlinks to the device to be created at each of these paths.
+    pub create_links: Vec<PathBuf>,
     /// The output device will be given this name.
     pub name: String,
     /// Determined by "repeat" or "norepeat" flags on output devices.
     pub repeat_mode: RepeatMode,
+    /// If Some, the kernel shall generate repeat events with this delay and period instead of
+    /// the kernel defaults. Only set together with RepeatMode::Enable.
+    pub repeat_info: Option<RepeatInfo>,
+    pub syn_mode: SynMode,
+    /// If Some, force feedback effects played on this device shall be forwarded to the input
+    /// device at this path, and this device shall advertise the given force feedback capabilities.
+    pub forward_ff: Option<(PathBuf, Vec<EventCode>)>,
+    /// Force feedback effects that this device advertises because of the ff= clause. Whenever a
+    /// program plays one of them, an event like ff:rumble enters the stream.
+    pub ff_effects: Vec<EventCode>,
+    /// LEDs that this device advertises because of the leds= clause. Whenever a program changes
+    /// one of them, an event like led:capsl enters the stream.
+    pub leds: Vec<EventCode>,
+    /// Whether LED changes made to this device shall be mirrored on input devices with the
+    /// forward-leds flag.
+    pub forward_leds: bool,
+    /// How the output device identifies itself to other programs.
+    pub identity: OutputIdentity,
+    pub permissions: NodePermissions,
+    /// Axis ranges set by absinfo= clauses, which take precedence over the propagated capabilities.
+    pub abs_overrides: Vec<(EventCode, AbsInfo)>,
+    /// If Some, the device shall have exactly these capabilities regardless of which events
+    /// may be routed to it, and other events routed to it shall be dropped.
+    pub pinned_caps: Option<Vec<EventCode>>,
+    /// If Some, no virtual device shall be created; the events shall be recorded to a file or sent
+    /// over the network instead.
+    pub record: Option<(RecordTarget, RecordFormat)>,
+    /// If true, the device shall not be created until the first event is routed to it.
+    pub defer: bool,
+    /// If Some, a deferred device shall be destroyed after no events were routed to it for this long.
+    pub idle_timeout: Option<Duration>,
+    /// If Some, this device was created by the split-by-type flag and receives those events that
+    /// were sent to the given domain and belong to the given class.
+    pub split_from: Option<(Domain, DeviceClass)>,
+    /// If true, events that would not change the state of the device get dropped, such as a key
+    /// down event for a key that is already held.
+    pub dedup: bool,
+}
+
+impl PreOutputDevice {
+    /// Returns true if this pre-device and the other one would result in the same output device
+    /// given the same capabilities, i.e. if they differ in nothing but their domain.
+    pub fn creates_same_device_as(&self, other: &PreOutputDevice) -> bool {
+        self.create_links == other.create_links
+            && self.name == other.name
+            && self.repeat_mode == other.repeat_mode
+            && self.repeat_info == other.repeat_info
+            && self.syn_mode == other.syn_mode
+            && self.forward_ff == other.forward_ff
+            && self.ff_effects == other.ff_effects
+            && self.leds == other.leds
+            && self.forward_leds == other.forward_leds
+            && self.identity == other.identity
+            && self.permissions == other.permissions
+            && self.abs_overrides == other.abs_overrides
+            && self.pinned_caps == other.pinned_caps
+            && self.record == other.record
+            && self.defer == other.defer
+            && self.idle_timeout == other.idle_timeout
+            && self.dedup == other.dedup
+            && self.split_from.map(|(_, class)| class) == other.split_from.map(|(_, class)| class)
+    }
+
+    /// Creates a device that receives only the events of a certain class that were sent to this
+    /// device, for the split-by-type flag.
+    pub fn split(&self, class: DeviceClass) -> PreOutputDevice {
+        let is_keyboard = class == DeviceClass::Keyboard;
+        let is_gamepad = class == DeviceClass::Gamepad;
+        PreOutputDevice {
+            domain: crate::domain::get_unique_domain(),
+            create_links: self.create_links.iter().map(|path| {
+                let mut path = path.clone().into_os_string();
+                path.push(class.link_suffix());
+                PathBuf::from(path)
+            }).collect(),
+            name: format!("{} {}", self.name, class.name_suffix()),
+            // Repeat capabilities would make other devices look like keyboards.
+            repeat_mode: match is_keyboard {
+                true => self.repeat_mode,
+                false => RepeatMode::Passive,
+            },
+            repeat_info: self.repeat_info.filter(|_| is_keyboard),
+            syn_mode: self.syn_mode,
+            forward_ff: self.forward_ff.clone().filter(|_| is_gamepad),
+            forward_leds: self.forward_leds,
+            identity: self.identity.clone(),
+            permissions: self.permissions,
+            abs_overrides: self.abs_overrides.iter()
+                .filter(|(code, _)| DeviceClass::of(*code) == class)
+                .copied()
+                .collect(),
+            pinned_caps: self.pinned_caps.as_ref().map(|caps| caps.iter()
+                .filter(|&&code| DeviceClass::of(code) == class)
+                .copied()
+                .collect()
+            ),
+            record: None,
+            ff_effects: match is_gamepad {
+                true => self.ff_effects.clone(),
+                false => Vec::new(),
+            },
+            leds: match is_keyboard {
+                true => self.leds.clone(),
+                false => Vec::new(),
+            },
+            defer: self.defer,
+            idle_timeout: self.idle_timeout,
+            split_from: Some((self.domain, class)),
+            dedup: self.dedup,
+        }
+    }
+}
+
+/// The kinds of devices that the split-by-type flag splits an output device into.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DeviceClass {
+    Keyboard,
+    Pointer,
+    Gamepad,
+}
+
+impl DeviceClass {
+    pub fn all() -> &'static [DeviceClass] {
+        &[DeviceClass::Keyboard, DeviceClass::Pointer, DeviceClass::Gamepad]
+    }
+
+    /// Decides which kind of device an event belongs on. Events that do not clearly belong to
+    /// pointers or gamepads end up on the keyboard.
+    pub fn of(code: EventCode) -> DeviceClass {
+        let ev_type = code.ev_type();
+        if ev_type.is_key() {
+            match code.code() {
+                // BTN_MOUSE through BTN_TASK, and BTN_TOOL_PEN through BTN_TOOL_QUADTAP.
+                0x110 ..= 0x117 | 0x140 ..= 0x14f => DeviceClass::Pointer,
+                // BTN_JOYSTICK through BTN_THUMBR, the dpad, and BTN_TRIGGER_HAPPY*.
+                0x120 ..= 0x13f | 0x220 ..= 0x223 | 0x2c0 ..= 0x2e7 => DeviceClass::Gamepad,
+                _ => DeviceClass::Keyboard,
+            }
+        } else if ev_type.is_rel() {
+            DeviceClass::Pointer
+        } else if ev_type.is_abs() {
+            match code.code() {
+                // The multitouch axes.
+                0x2f ..= 0x3e => DeviceClass::Pointer,
+                _ => DeviceClass::Gamepad,
+            }
+        } else if ev_type == EventType::FF {
+            DeviceClass::Gamepad
+        } else {
+            DeviceClass::Keyboard
+        }
+    }
+
+    pub fn name_suffix(self) -> &'static str {
+        match self {
+            DeviceClass::Keyboard => "Keyboard",
+            DeviceClass::Pointer => "Mouse",
+            DeviceClass::Gamepad => "Gamepad",
+        }
+    }
+
+    /// Mimics the names of the links in /dev/input/by-id.
+    fn link_suffix(self) -> &'static str {
+        match self {
+            DeviceClass::Keyboard => "-kbd",
+            DeviceClass::Pointer => "-mouse",
+            DeviceClass::Gamepad => "-joystick",
+        }
+    }
+}
+
+/// The ids an output device reports, as set by the vendor=, product=, version=, bus=, phys= and
+/// uniq= clauses on --output arguments. Ids that are None are left at the uinput defaults.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct OutputIdentity {
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub version: Option<u16>,
+    pub bustype: Option<u16>,
+    pub phys: Option<String>,
+    pub uniq: Option<String>,
 }
\ No newline at end of file
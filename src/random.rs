@@ -0,0 +1,51 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! A small pseudorandom number generator for arguments that vary their timing on purpose. The
+//! numbers it generates are not suitable for anything related to security.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// An xorshift64* generator.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Returns a generator seeded with randomness from the operating system. The standard library
+    /// already obtains such randomness for the keys of its HashMaps, so that is where it comes from.
+    pub fn new() -> Rng {
+        Rng::from_seed(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn from_seed(seed: u64) -> Rng {
+        // The state of an xorshift generator must never be zero.
+        Rng { state: seed | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a number between zero and `max` inclusive, where every number is about equally likely.
+    pub fn up_to(&mut self, max: u64) -> u64 {
+        let range = u128::from(max) + 1;
+        ((u128::from(self.next_u64()) * range) >> 64) as u64
+    }
+}
+
+#[test]
+fn unittest() {
+    let mut rng = Rng::from_seed(0);
+    let mut seen = [false; 4];
+    for _ in 0 .. 1000 {
+        let value = rng.up_to(3);
+        assert!(value <= 3);
+        seen[value as usize] = true;
+    }
+    assert!(seen.iter().all(|&seen| seen));
+    assert!(Rng::from_seed(5).up_to(u64::MAX) == Rng::from_seed(5).up_to(u64::MAX));
+}
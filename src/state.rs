@@ -2,8 +2,11 @@
 
 use std::ops::{Index,IndexMut};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use crate::error::InternalError;
 use crate::event::Channel;
+use crate::error::Context;
+use crate::logging;
 
 /// Represents the state of the stream that can change as events flow through it.
 pub struct State {
@@ -66,12 +69,23 @@ pub struct ToggleState {
     /// If the last value of a specific channel was not zero, consistent maps will remember
     /// to which index that event was last routed.
     pub memory: HashMap<Channel, usize>,
+
+    /// The names of the states as specified by a states= clause. Empty if the states are unnamed.
+    names: Vec<String>,
+
+    /// If persist=state was specified, the file to which the active state is written.
+    persist_path: Option<PathBuf>,
+    /// Shell commands to run whenever the active state changes.
+    exec_shell: Vec<String>,
 }
 
 impl ToggleState {
     pub fn new(size: usize) -> Result<ToggleState, InternalError> {
         if size > 0 {
-            Ok(ToggleState { size, value: 0, memory: HashMap::new() })
+            Ok(ToggleState {
+                size, value: 0, memory: HashMap::new(), names: Vec::new(),
+                persist_path: None, exec_shell: Vec::new(),
+            })
         } else {
             Err(InternalError::new("A toggle requires at least one state."))
         }
@@ -79,8 +93,7 @@ impl ToggleState {
 
     /// Moves this toggle's active output to the next one.
     pub fn advance(&mut self) {
-        self.value += 1;
-        self.value %= self.size;
+        self.set_value((self.value + 1) % self.size);
     }
 
     pub fn value(&self) -> usize {
@@ -88,10 +101,143 @@ impl ToggleState {
     }
 
     pub fn set_value_wrapped(&mut self, value: usize) {
-        self.value = value % self.size
+        self.set_value(value % self.size);
+    }
+
+    /// All changes of the active state go through here, so they get written to the state file
+    /// and run the exec-shell commands right away, even if the state changes again before the
+    /// main loop gets to do anything else.
+    fn set_value(&mut self, value: usize) {
+        if value == self.value {
+            return;
+        }
+        self.value = value;
+        if let Some(path) = &self.persist_path {
+            save_value(path, value);
+        }
+        for command in &self.exec_shell {
+            let args = vec![
+                "-c".to_owned(), command.clone(), "evsieve".to_owned(),
+                format!("{}", value + 1),
+                self.name().unwrap_or("").to_owned(),
+            ];
+            if let Err(error) = crate::subprocess::try_spawn("/bin/sh".to_owned(), args) {
+                error.print_err();
+            }
+        }
     }
 
     pub fn size(&self) -> usize {
         self.size
     }
-}
\ No newline at end of file
+
+    pub fn set_names(&mut self, names: Vec<String>) -> Result<(), InternalError> {
+        if names.len() == self.size {
+            self.names = names;
+            Ok(())
+        } else {
+            Err(InternalError::new("The amount of state names does not match the size of the toggle."))
+        }
+    }
+
+    /// Restores the active state from the given file if it exists, and writes the active state
+    /// to that file whenever it changes from now on.
+    pub fn persist_to(&mut self, path: PathBuf) {
+        self.restore_from(&path);
+        self.persist_path = Some(path);
+    }
+
+    /// Restores the active state from the given file if it exists, without ever writing to it.
+    pub fn restore_from(&mut self, path: &Path) {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => match contents.trim().parse::<usize>() {
+                Ok(value) if value >= 1 && value <= self.size => self.value = value - 1,
+                _ => logging::warning(format!(
+                    "Warning: ignoring the toggle state file {} because it does not contain a valid index.",
+                    path.display()
+                )),
+            },
+            Err(error) => if error.kind() != std::io::ErrorKind::NotFound {
+                logging::warning(format!("Warning: failed to read the toggle state file {}: {}", path.display(), error));
+            },
+        }
+    }
+
+    /// Adds a shell command that is run whenever the active state changes. The command receives
+    /// the new index as $1 and the name of the new state (if any) as $2.
+    pub fn add_exec_shell(&mut self, command: String) {
+        self.exec_shell.push(command);
+    }
+
+    /// Returns the name of the currently active state, if the states are named.
+    pub fn name(&self) -> Option<&str> {
+        self.names.get(self.value).map(String::as_str)
+    }
+
+    /// Returns the zero-indexed value of the state with a given name.
+    pub fn index_of_name(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|state_name| state_name == name)
+    }
+}
+
+/// Writes the one-indexed value of a toggle to its state file.
+fn save_value(path: &Path, value: usize) {
+    let result = match path.parent() {
+        Some(dir) => std::fs::create_dir_all(dir),
+        None => Ok(()),
+    }.and_then(|()| std::fs::write(path, format!("{}\n", value + 1)));
+    if let Err(error) = result {
+        logging::warning(format!("Warning: failed to write the toggle state file {}: {}", path.display(), error));
+    }
+}
+
+#[test]
+fn unittest() {
+    let directory = std::env::temp_dir().join(format!("evsieve-state-test-{}", std::process::id()));
+    let path = directory.join("toggle-mode");
+    let _ = std::fs::remove_dir_all(&directory);
+
+    // Without a state file, the toggle starts in its first state and creates the file once it changes.
+    let mut toggle = ToggleState::new(3).unwrap();
+    toggle.persist_to(path.clone());
+    assert_eq!(toggle.value(), 0);
+    assert!(! path.exists());
+    toggle.advance();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "2\n");
+    toggle.set_value_wrapped(5);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "3\n");
+
+    let mut restored = ToggleState::new(3).unwrap();
+    restored.persist_to(path.clone());
+    assert_eq!(restored.value(), 2);
+
+    // A state file that does not fit the toggle is ignored.
+    std::fs::write(&path, "4\n").unwrap();
+    let mut ignored = ToggleState::new(3).unwrap();
+    ignored.persist_to(path.clone());
+    assert_eq!(ignored.value(), 0);
+
+    // Every change runs the exec-shell commands, including changes back to an earlier state.
+    let log_path = directory.join("changes");
+    let mut toggle = ToggleState::new(4).unwrap();
+    toggle.add_exec_shell(format!("echo $1 >> {}", log_path.display()));
+    toggle.advance();
+    toggle.set_value_wrapped(0);
+    toggle.set_value_wrapped(0);
+    toggle.set_value_wrapped(2);
+    toggle.advance();
+    let mut changes: Vec<String> = Vec::new();
+    for _ in 0 .. 500 {
+        changes = std::fs::read_to_string(&log_path).unwrap_or_default()
+            .lines().map(str::to_owned).collect();
+        if changes.len() >= 4 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    // The commands run concurrently, so they may finish in any order.
+    changes.sort();
+    assert_eq!(changes, vec!["1", "2", "3", "4"]);
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -5,24 +5,59 @@ pub mod withhold;
 pub mod hook;
 pub mod map;
 pub mod delay;
+pub mod debounce;
+pub mod throttle;
+pub mod latch;
+pub mod sticky;
+pub mod chord;
+pub mod counter;
+pub mod timer;
 pub mod merge;
+pub mod repeat;
+pub mod macros;
+pub mod script;
+pub mod unicode;
+pub mod rotary;
+pub mod split;
+pub mod touchpad;
+pub mod accel;
+pub mod combine;
+pub mod trace;
+pub mod dispatch;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use self::map::{Map, Toggle};
+use self::map::{Map, MapTable, Toggle};
 use self::hook::Hook;
 use self::print::EventPrinter;
 use self::withhold::Withhold;
 use self::merge::Merge;
+use self::repeat::Repeat;
+use self::macros::Macro;
+use self::script::Script;
+use self::unicode::UnicodeMap;
+use self::rotary::Rotary;
+use self::split::Split;
+use self::touchpad::Touchpad;
+use self::accel::Accel;
+use self::combine::Combine;
+use self::trace::{Trace, Tracer};
+use self::dispatch::DispatchTable;
 
 use crate::io::input::InputDevice;
 use crate::predevice::PreOutputDevice;
 use crate::state::{State, ToggleIndex};
 use crate::event::{Event, Namespace};
-use crate::capability::{Capability, InputCapabilites};
+use crate::capability::{Capabilities, Capability, CapMatch, InputCapabilites};
+use crate::capcache::CapsCache;
+use crate::key::Key;
 use crate::io::output::OutputSystem;
+use crate::domain::Domain;
 use crate::error::RuntimeError;
-use crate::loopback::{Loopback, LoopbackHandle, Delay};
+use crate::loopback::{Loopback, LoopbackHandle};
+use crate::time::{Duration, Instant};
+use crate::metrics;
+use crate::logging::{self, LogLevel};
 
 /// An enum of everything that can be part of the event processing stream.
 ///
@@ -52,10 +87,49 @@ pub enum StreamEntry {
     Merge(Merge),
     Withhold(Withhold),
     Delay(self::delay::Delay),
+    Debounce(self::debounce::Debounce),
+    Throttle(self::throttle::Throttle),
+    Latch(self::latch::Latch),
+    Sticky(self::sticky::Sticky),
+    Chord(self::chord::Chord),
+    Counter(self::counter::Counter),
+    Timer(self::timer::Timer),
+    Repeat(Repeat),
+    Macro(Macro),
+    Script(Script),
+    Unicode(UnicodeMap),
+    Rotary(Rotary),
+    Split(Split),
+    Touchpad(Touchpad),
+    Accel(Accel),
+    Combine(Combine),
+    MapTable(MapTable),
+}
+
+/// Counts how many events entered and left a single entry of the stream.
+pub struct EntryStats {
+    /// The argument that created this entry.
+    label: String,
+    /// The id of entries that were added through the control FIFO, so they can be removed again.
+    id: Option<String>,
+    /// The index of the --profile this entry belongs to, if any. Such entries only apply while
+    /// their profile is active.
+    profile: Option<usize>,
+    events_in: u64,
+    events_out: u64,
+    /// The total time this entry took to process its events. Only measured if Some, which is
+    /// the case while running a --benchmark.
+    time_spent: Option<Duration>,
 }
 
 pub struct Setup {
     stream: Vec<StreamEntry>,
+    /// The statistics of each entry of the stream, in the same order as the stream.
+    stats: Vec<EntryStats>,
+    /// Tells which entries of the stream can be skipped for events with a certain code.
+    dispatch: DispatchTable,
+    /// If Some, the journey of some events through the stream gets printed.
+    tracer: Option<Tracer>,
     output: OutputSystem,
     state: State,
     toggle_indices: HashMap<String, ToggleIndex>,
@@ -67,22 +141,75 @@ pub struct Setup {
     /// A vector of events that have been "sent" to an output device but are not actually written
     /// to it yet because we await an EV_SYN event.
     staged_events: Vec<Event>,
+    /// If --profile arguments were specified, the toggle that decides which profile is active.
+    profile_toggle: Option<ToggleIndex>,
+    /// The profile whose entries currently apply. Follows the profile toggle with a delay, see
+    /// `profile_changed()`.
+    active_profile: usize,
+    /// The buffers that events flow through, kept around so processing an event does not need
+    /// to allocate once they have grown large enough.
+    buffers: EventBuffers,
+    /// The events that left the stream while processing the last event in `run()`.
+    events_out: Vec<Event>,
+}
+
+/// A pair of buffers that the entries of the stream pass events back and forth between: each
+/// entry reads from `events` and writes to `buffer`, after which they get swapped.
+#[derive(Default)]
+struct EventBuffers {
+    events: Vec<Event>,
+    buffer: Vec<Event>,
 }
 
 impl Setup {
+    /// If `previous_output` is Some, the output devices that would be created the same way
+    /// are taken from it instead of being created anew, as happens when reloading.
+    /// `profiles` contains the index of the profile each entry belongs to, if any.
+    #[allow(clippy::too_many_arguments)]
     pub fn create(
-        stream: Vec<StreamEntry>,
+        mut stream: Vec<StreamEntry>,
+        labels: Vec<String>,
+        profiles: Vec<Option<usize>>,
+        profile_toggle: Option<ToggleIndex>,
         pre_output: Vec<PreOutputDevice>,
         state: State,
         toggle_indices: HashMap<String, ToggleIndex>,
         input_caps: InputCapabilites,
+        tracer: Option<Tracer>,
+        previous_output: Option<&mut OutputSystem>,
+        caps_cache: Option<CapsCache>,
     ) -> Result<Setup, RuntimeError> {
-        let caps_vec: Vec<Capability> = crate::capability::input_caps_to_vec(&input_caps);
-        let caps_out = run_caps(&stream, caps_vec);
-        let output = OutputSystem::create(pre_output, caps_out)?;
+        let stats: Vec<EntryStats> = labels.into_iter().zip(profiles)
+            .map(|(label, profile)| EntryStats { label, id: None, profile, events_in: 0, events_out: 0, time_spent: None })
+            .collect();
+        let caps_out = match caps_cache.as_ref().and_then(CapsCache::load) {
+            Some(caps_out) => caps_out,
+            None => {
+                let caps_vec: Vec<Capability> = crate::capability::input_caps_to_vec(&input_caps);
+                let caps_out = run_caps(&stream, &stats, caps_vec);
+                if let Some(caps_cache) = &caps_cache {
+                    caps_cache.save(&caps_out);
+                }
+                caps_out
+            },
+        };
+        let output = OutputSystem::create(pre_output, caps_out, previous_output)?;
+        let active_profile = match profile_toggle {
+            Some(toggle) => state[toggle].value(),
+            None => 0,
+        };
+        let dispatch = DispatchTable::new(&stream, input_caps.values().flat_map(|caps| caps.codes.iter()));
+        let mut loopback = Loopback::new();
+        for entry in &mut stream {
+            if let StreamEntry::Timer(timer) = entry {
+                timer.start(&mut loopback.get_handle_lazy());
+            }
+        }
         Ok(Setup {
-            stream, output, state, toggle_indices, input_caps,
-            loopback: Loopback::new(), staged_events: Vec::new(),
+            stream, stats, dispatch, tracer, output, state, toggle_indices, input_caps,
+            loopback, staged_events: Vec::new(),
+            profile_toggle, active_profile,
+            buffers: EventBuffers::default(), events_out: Vec::new(),
         })
     }
 
@@ -101,13 +228,227 @@ impl Setup {
             }
         }
 
+        self.recompute_output_caps();
+    }
+
+    /// Like update_caps(), but for devices that share their domain with other devices because they
+    /// were opened through the same pattern. Their capabilities get added to those of the domain
+    /// instead of replacing them.
+    pub fn merge_caps(&mut self, new_device: &InputDevice) {
+        self.merge_domain_caps(new_device.domain(), new_device.capabilities());
+    }
+
+    /// Adds `new_caps` to the capabilities that the input domain `domain` is expected to generate.
+    pub fn merge_domain_caps(&mut self, domain: Domain, new_caps: &Capabilities) {
+        let caps = self.input_caps.entry(domain).or_insert_with(Capabilities::new);
+        let old_caps = caps.clone();
+        caps.merge(new_caps);
+        if caps.is_compatible_with(&old_caps) {
+            return;
+        }
+
+        self.recompute_output_caps();
+    }
+
+    /// Recomputes which capabilities the output devices need after the input capabilities or the
+    /// stream changed. Output devices whose capabilities changed get recreated.
+    fn recompute_output_caps(&mut self) {
         let caps_vec: Vec<Capability> = crate::capability::input_caps_to_vec(&self.input_caps);
-        let caps_out = run_caps(&self.stream, caps_vec);
+        let caps_out = run_caps(&self.stream, &self.stats, caps_vec);
         self.output.update_caps(caps_out);
     }
 
-    pub fn time_until_next_wakeup(&self) -> Delay {
-        self.loopback.time_until_next_wakeup()
+    /// Must be called whenever entries are added to or removed from the stream.
+    fn rebuild_dispatch(&mut self) {
+        self.dispatch = DispatchTable::new(&self.stream, self.input_caps.values().flat_map(|caps| caps.codes.iter()));
+    }
+
+    /// Adds entries to the running stream, as requested through the control FIFO. They get inserted
+    /// right before the first --output argument, so they apply after all other arguments but before
+    /// any events get sent to an output device.
+    pub fn add_entries(&mut self, entries: Vec<StreamEntry>, label: String, id: Option<String>) {
+        let index = self.stats.iter()
+            .position(|stats| stats.label.starts_with("--output"))
+            .unwrap_or(self.stream.len());
+        let stats = entries.iter().map(|_| EntryStats {
+            label: label.clone(), id: id.clone(), profile: None, events_in: 0, events_out: 0, time_spent: None,
+        }).collect::<Vec<_>>();
+        self.stream.splice(index..index, entries);
+        self.stats.splice(index..index, stats);
+        self.rebuild_dispatch();
+        self.recompute_output_caps();
+    }
+
+    /// Removes all entries that were added through the control FIFO with the given id.
+    /// Returns false if there were no such entries.
+    pub fn remove_entries(&mut self, id: &str) -> bool {
+        let keep: Vec<bool> = self.stats.iter()
+            .map(|stats| stats.id.as_deref() != Some(id))
+            .collect();
+        if keep.iter().all(|&keep| keep) {
+            return false;
+        }
+        let mut keep_iter = keep.iter();
+        self.stream.retain(|_| *keep_iter.next().unwrap());
+        let mut keep_iter = keep.iter();
+        self.stats.retain(|_| *keep_iter.next().unwrap());
+        self.rebuild_dispatch();
+        self.recompute_output_caps();
+        true
+    }
+
+    /// Returns a table of how many events entered and left each argument, and how many events
+    /// were written to each output device.
+    pub fn format_stats(&self) -> String {
+        let mut report = format!("{:>12} {:>12}  argument\n", "events in", "events out");
+        for stats in &self.stats {
+            report.push_str(&format!("{:>12} {:>12}  {}\n", stats.events_in, stats.events_out, stats.label));
+        }
+        report.push_str(&self.output.format_stats());
+        report
+    }
+
+    /// Makes every entry of the stream keep track of how much time it spends processing events,
+    /// at the cost of reading the clock twice for every entry an event passes through.
+    pub fn measure_time_spent(&mut self) {
+        for stats in &mut self.stats {
+            stats.time_spent = Some(Duration::from_nanos(0));
+        }
+    }
+
+    /// Returns a table of how much time each argument spent processing events since
+    /// `measure_time_spent()` was called, both in total and on average per event it received.
+    pub fn format_time_spent(&self) -> String {
+        let mut report = format!("{:>12} {:>12} {:>12}  argument\n", "events in", "total (us)", "avg (ns)");
+        for stats in &self.stats {
+            let time_spent = stats.time_spent.unwrap_or_else(|| Duration::from_nanos(0));
+            let average = match stats.events_in {
+                0 => 0,
+                events_in => time_spent.as_nanos() / u128::from(events_in),
+            };
+            report.push_str(&format!(
+                "{:>12} {:>12} {:>12}  {}\n", stats.events_in, time_spent.as_micros(), average, stats.label
+            ));
+        }
+        report
+    }
+
+    /// Returns true if a hook or command has switched the profile toggle to another profile than
+    /// the one whose entries currently apply. The switch only takes effect once
+    /// `apply_profile_change()` is called, which gives the caller a chance to release the keys
+    /// that were pressed under the previous profile through that same profile.
+    pub fn profile_changed(&self) -> bool {
+        match self.profile_toggle {
+            Some(toggle) => self.state[toggle].value() != self.active_profile,
+            None => false,
+        }
+    }
+
+    pub fn apply_profile_change(&mut self) {
+        if let Some(toggle) = self.profile_toggle {
+            self.active_profile = self.state[toggle].value();
+        }
+    }
+
+    /// Appends the counters of the stream and the output devices to a report for --metrics. The
+    /// same argument can show up several times, so every entry gets labelled with its position.
+    pub fn write_metrics(&self, report: &mut String) {
+        let label = |index: usize, stats: &EntryStats| format!("#{} {}", index + 1, stats.label);
+        let events_in: Vec<(String, u64)> = self.stats.iter().enumerate()
+            .map(|(index, stats)| (label(index, stats), stats.events_in))
+            .collect();
+        let events_out: Vec<(String, u64)> = self.stats.iter().enumerate()
+            .map(|(index, stats)| (label(index, stats), stats.events_out))
+            .collect();
+        let activations: Vec<(String, u64)> = self.stream.iter().zip(&self.stats).enumerate()
+            .filter_map(|(index, (entry, stats))| match entry {
+                StreamEntry::Hook(hook) => Some((label(index, stats), hook.activations())),
+                _ => None,
+            })
+            .collect();
+        metrics::write_metric(report, "evsieve_argument_events_in_total", "counter",
+            "Events that entered each argument.", Some("argument"), &events_in);
+        metrics::write_metric(report, "evsieve_argument_events_out_total", "counter",
+            "Events that left each argument.", Some("argument"), &events_out);
+        metrics::write_metric(report, "evsieve_hook_activations_total", "counter",
+            "How many times each hook has activated.", Some("hook"), &activations);
+        self.output.write_metrics(report);
+    }
+
+    /// Describes the runtime state of all toggles, all hooks of which some keys are held, and all
+    /// events that are currently being withheld, for the dump-state command.
+    pub fn format_state(&self) -> String {
+        let mut toggles = String::new();
+        let mut hooks = String::new();
+        let mut withheld = String::new();
+        for (entry, stats) in self.stream.iter().zip(&self.stats) {
+            match entry {
+                StreamEntry::Toggle(toggle) => {
+                    let toggle_state = &self.state[toggle.state_index];
+                    toggles.push_str(&format!("    {}: state {} of {}", stats.label, toggle_state.value() + 1, toggle_state.size()));
+                    if let Some(name) = toggle_state.name() {
+                        toggles.push_str(&format!(" ({})", name));
+                    }
+                    toggles.push('\n');
+                },
+                StreamEntry::Hook(hook) => if let Some(description) = hook.trigger().describe_state() {
+                    hooks.push_str(&format!("    {}: {}\n", stats.label, description));
+                },
+                StreamEntry::Withhold(withhold) => {
+                    let events = withhold.withheld_events();
+                    if ! events.is_empty() {
+                        withheld.push_str(&format!("    {}: {}\n", stats.label, trace::format_events(&events)));
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        let mut report = String::new();
+        for (title, section) in &[("Toggles", toggles), ("Hooks", hooks), ("Withheld events", withheld)] {
+            report.push_str(&format!("{}:\n", title));
+            match section.is_empty() {
+                true => report.push_str("    none\n"),
+                false => report.push_str(section),
+            }
+        }
+        report
+    }
+
+    /// Enables or disables all hooks with the given id. Returns false if there are none.
+    pub fn set_hooks_enabled(&mut self, id: &str, enabled: bool) -> bool {
+        let mut found_any = false;
+        for entry in &mut self.stream {
+            if let StreamEntry::Hook(hook) = entry {
+                if hook.id() == Some(id) {
+                    hook.set_enabled(enabled);
+                    found_any = true;
+                }
+            }
+        }
+        found_any
+    }
+
+    pub fn output(&self) -> &OutputSystem {
+        &self.output
+    }
+
+    /// Reopens the files that --print arguments write to. Returns false if there are none.
+    pub fn reopen_print_files(&mut self) -> bool {
+        let mut has_files = false;
+        for entry in &mut self.stream {
+            if let StreamEntry::Print(printer) = entry {
+                has_files |= printer.reopen_file();
+            }
+        }
+        has_files
+    }
+
+    /// The moment at which `wakeup()` needs to be called next, if ever.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.loopback.next_wakeup().into_iter()
+            .chain(self.output.next_idle_deadline())
+            .min()
     }
 
     pub fn toggle_indices(&self) -> &HashMap<String, ToggleIndex> {
@@ -118,6 +459,10 @@ impl Setup {
         &self.state
     }
 
+    pub fn output_mut(&mut self) -> &mut OutputSystem {
+        &mut self.output
+    }
+
     pub fn state_mut(&mut self) -> &mut State {
         &mut self.state
     }
@@ -139,15 +484,29 @@ pub fn run(setup: &mut Setup, event: Event) {
 
         // TODO: time handling.
         let mut loopback_handle = setup.loopback.get_handle_lazy();
-        let mut events_out = Vec::new();
+        let mut events_out = std::mem::take(&mut setup.events_out);
+        setup.buffers.events.push(event);
+        let mut trace = setup.tracer.as_ref().and_then(
+            |tracer| tracer.start(trace::format_events(&[event]), &[event])
+        );
 
         run_events(
-            vec![event],
+            &mut setup.buffers,
             &mut events_out,
             &mut setup.stream,
+            &mut setup.stats,
+            (&mut setup.dispatch, 0),
             &mut setup.state,
             &mut loopback_handle,
+            &mut trace,
+            setup.active_profile,
         );
+        if let Some(trace) = trace {
+            trace.finish(&events_out);
+        }
+        if logging::enabled(LogLevel::Debug) {
+            println!("Debug: {} → {}", trace::format_events(&[event]), trace::format_events(&events_out));
+        }
 
         // If a single event gets mapped to a single event, then the resulting event gets
         // synchronised whenever the input device does. This makes the result of
@@ -157,16 +516,20 @@ pub fn run(setup: &mut Setup, event: Event) {
         // However, when a single event gets mapped to multiple events, we want to add a
         // SYN event after each event, because otherwise the OS might misorder commands like
         //     --input PATH grab --map key:f12 key:leftctrl key:c --output
+        // Output devices with syn=coalesce or syn=per-event decide for themselves.
         match events_out.len() {
             0 => {},
-            1 => setup.staged_events.extend(events_out),
+            1 => setup.staged_events.append(&mut events_out),
             _ => {
-                for event in events_out {
+                for event in events_out.drain(..) {
                     setup.staged_events.push(event);
-                    syn(setup);
+                    setup.output.route_events(&setup.staged_events);
+                    setup.staged_events.clear();
+                    setup.output.synchronize(false);
                 }
             }
         }
+        setup.events_out = events_out;
     }
 }
 
@@ -175,20 +538,51 @@ pub fn wakeup(setup: &mut Setup) {
         let mut loopback_handle = setup.loopback.get_handle(instant);
         run_wakeup(
             token,
+            &mut setup.buffers,
             &mut setup.staged_events,
             &mut setup.stream,
+            &mut setup.stats,
+            &mut setup.dispatch,
             &mut setup.state,
             &mut loopback_handle,
+            setup.tracer.as_ref(),
+            setup.active_profile,
         );
         // TODO: consider the pooling behaviour for events with the same instant.
         syn(setup);
     };
+    setup.output.destroy_idle_devices();
+}
+
+/// Lets every toggle catch up with changes to its state that were made by something other than
+/// the events passing it, such as hooks further down the stream or control commands. The LED
+/// events this generates get processed by the rest of the stream.
+pub fn sync_toggles(setup: &mut Setup) {
+    let mut loopback_handle = setup.loopback.get_handle_lazy();
+    let mut generated_events = false;
+    for index in 0 .. setup.stream.len() {
+        if let StreamEntry::Toggle(toggle) = &mut setup.stream[index] {
+            toggle.sync(&mut setup.buffers.events, &setup.state, &mut loopback_handle);
+        }
+        if ! setup.buffers.events.is_empty() {
+            generated_events = true;
+            setup.stats[index].events_out += setup.buffers.events.len() as u64;
+            run_events(
+                &mut setup.buffers, &mut setup.staged_events,
+                &mut setup.stream[index+1..], &mut setup.stats[index+1..], (&mut setup.dispatch, index+1),
+                &mut setup.state, &mut loopback_handle, &mut None, setup.active_profile,
+            );
+        }
+    }
+    if generated_events {
+        syn(setup);
+    }
 }
 
 pub fn syn(setup: &mut Setup) {
     setup.output.route_events(&setup.staged_events);
     setup.staged_events.clear();
-    setup.output.synchronize();
+    setup.output.synchronize(true);
 }
 
 /// Starts processing the stream at a given starting point.
@@ -201,88 +595,267 @@ pub fn syn(setup: &mut Setup) {
 /// `wakeup()` function to be able to pause and resume event processing at a later point in time.
 /// 
 /// `stream` may be the empty slice.
-fn run_events(events_in: Vec<Event>, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], state: &mut State, loopback: &mut LoopbackHandle) {
-    let mut events: Vec<Event> = events_in;
-    let mut buffer: Vec<Event> = Vec::new();
+///
+/// If `trace` is Some, every entry that changes the events gets recorded in it.
+///
+/// `dispatch` contains the dispatch table of the whole stream along with the index of the first
+/// entry of `stream` within the whole stream.
+#[allow(clippy::too_many_arguments)]
+fn run_events(buffers: &mut EventBuffers, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], stats: &mut [EntryStats], dispatch: (&mut DispatchTable, usize), state: &mut State, loopback: &mut LoopbackHandle, trace: &mut Option<Trace>, active_profile: usize) {
+    let EventBuffers { events, buffer } = buffers;
+    let (dispatch, first_index) = dispatch;
+    let mut relevant_entries = dispatch.relevant_entries(events);
 
-    for entry in stream {
+    for (index, (entry, stats)) in stream.iter_mut().zip(stats.iter_mut()).enumerate() {
+        // The entries of inactive profiles let all events pass untouched.
+        if let Some(profile) = stats.profile {
+            if profile != active_profile {
+                continue;
+            }
+        }
+        stats.events_in += events.len() as u64;
+        // So do entries that cannot act on any of the events.
+        if ! relevant_entries[first_index + index] {
+            stats.events_out += events.len() as u64;
+            continue;
+        }
+        let events_before = trace.as_ref().map(|_| events.clone());
+        let start_time = stats.time_spent.map(|_| Instant::now());
         match entry {
             StreamEntry::Map(map) => {
-                map.apply_to_all(&events, &mut buffer);
+                map.apply_to_all(events, buffer);
                 events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                std::mem::swap(events, buffer);
             },
             StreamEntry::Toggle(toggle) => {
-                toggle.apply_to_all(&events, &mut buffer, state);
+                toggle.apply_to_all(events, buffer, state, loopback);
                 events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                std::mem::swap(events, buffer);
             },
             StreamEntry::Merge(merge) => {
-                merge.apply_to_all(&events, &mut buffer);
+                merge.apply_to_all(events, buffer);
                 events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                std::mem::swap(events, buffer);
             },
             StreamEntry::Hook(hook) => {
-                hook.apply_to_all(&events, &mut buffer, state, loopback);
+                hook.apply_to_all(events, buffer, state, loopback);
                 events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                std::mem::swap(events, buffer);
             },
             StreamEntry::Delay(delay) => {
-                delay.apply_to_all(&events, &mut buffer, loopback);
+                delay.apply_to_all(events, buffer, loopback);
                 events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                std::mem::swap(events, buffer);
             },
+            StreamEntry::Debounce(debounce) => {
+                debounce.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Throttle(throttle) => {
+                throttle.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Latch(latch) => {
+                latch.apply_to_all(events, buffer);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Sticky(sticky) => {
+                sticky.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Chord(chord) => {
+                chord.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Counter(counter) => {
+                counter.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            // A timer sends events on its own schedule, regardless of what passes by.
+            StreamEntry::Timer(_timer) => {},
             StreamEntry::Withhold(withhold) => {
-                withhold.apply_to_all(&events, &mut buffer, loopback);
+                withhold.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Repeat(repeat) => {
+                repeat.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Macro(macro_entry) => {
+                macro_entry.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Script(script) => {
+                script.apply_to_all(events, buffer);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Unicode(unicode) => {
+                unicode.apply_to_all(events, buffer);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Rotary(rotary) => {
+                rotary.apply_to_all(events, buffer);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Split(split) => {
+                split.apply_to_all(events, buffer);
                 events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Touchpad(touchpad) => {
+                touchpad.apply_to_all(events, buffer, loopback);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Accel(accel) => {
+                accel.apply_to_all(events, buffer);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::Combine(combine) => {
+                combine.apply_to_all(events, buffer);
+                events.clear();
+                std::mem::swap(events, buffer);
+            },
+            StreamEntry::MapTable(map_table) => {
+                map_table.apply_to_all(events, buffer);
+                events.clear();
+                std::mem::swap(events, buffer);
             },
             StreamEntry::Print(printer) => {
-                printer.apply_to_all(&events);
+                printer.apply_to_all(events, loopback);
             },
         }
+        stats.events_out += events.len() as u64;
+        if let (Some(time_spent), Some(start_time)) = (&mut stats.time_spent, start_time) {
+            if let Some(duration) = Instant::now().checked_duration_since(start_time) {
+                *time_spent = *time_spent + duration;
+            }
+        }
+
+        if let (Some(trace), Some(events_before)) = (trace.as_mut(), events_before) {
+            if let StreamEntry::Hook(hook) = entry {
+                trace.record_hook(&stats.label, hook.last_response());
+            }
+            if *events != events_before {
+                trace.record_change(&stats.label, events);
+            }
+        }
+        relevant_entries = dispatch.relevant_entries(events);
     }
 
     events_out.extend(
-        events.into_iter().filter(|event| event.namespace == Namespace::Output)
+        events.drain(..).filter(|event| event.namespace == Namespace::Output)
     );
 }
 
-fn run_wakeup(token: crate::loopback::Token, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], state: &mut State, loopback: &mut LoopbackHandle) {
-    let mut events: Vec<Event> = Vec::new();
-
+#[allow(clippy::too_many_arguments)]
+fn run_wakeup(token: crate::loopback::Token, buffers: &mut EventBuffers, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], stats: &mut [EntryStats], dispatch: &mut DispatchTable, state: &mut State, loopback: &mut LoopbackHandle, tracer: Option<&Tracer>, active_profile: usize) {
     for index in 0 .. stream.len() {
         match &mut stream[index] {
             StreamEntry::Map(_map) => {},
-            StreamEntry::Toggle(_toggle) => {},
+            StreamEntry::Toggle(toggle) => {
+                toggle.wakeup(&token, &mut buffers.events, state);
+            },
             StreamEntry::Merge(_merge) => {},
             StreamEntry::Hook(hook) => {
                 hook.wakeup(&token);
             },
             StreamEntry::Delay(delay) => {
-                delay.wakeup(&token, &mut events);
+                delay.wakeup(&token, &mut buffers.events);
+            },
+            StreamEntry::Debounce(debounce) => {
+                debounce.wakeup(&token, &mut buffers.events);
+            },
+            StreamEntry::Throttle(throttle) => {
+                throttle.wakeup(&token, &mut buffers.events);
+            },
+            StreamEntry::Latch(_latch) => {},
+            StreamEntry::Sticky(sticky) => {
+                sticky.wakeup(&token, &mut buffers.events);
+            },
+            StreamEntry::Chord(chord) => {
+                chord.wakeup(&token, &mut buffers.events);
+            },
+            StreamEntry::Counter(counter) => {
+                counter.wakeup(&token, &mut buffers.events);
+            },
+            StreamEntry::Timer(timer) => {
+                timer.wakeup(&token, &mut buffers.events, loopback);
             },
             StreamEntry::Withhold(withhold) => {
-                withhold.wakeup(&token, &mut events);
+                withhold.wakeup(&token, &mut buffers.events, loopback);
+            },
+            StreamEntry::Repeat(repeat) => {
+                repeat.wakeup(&token, &mut buffers.events, loopback);
+            },
+            StreamEntry::Macro(macro_entry) => {
+                macro_entry.wakeup(&token, &mut buffers.events);
+            },
+            StreamEntry::Script(_script) => {},
+            StreamEntry::Unicode(_unicode) => {},
+            StreamEntry::Rotary(_rotary) => {},
+            StreamEntry::Split(_split) => {},
+            StreamEntry::Touchpad(_touchpad) => {},
+            StreamEntry::Accel(_accel) => {},
+            StreamEntry::Combine(_combine) => {},
+            StreamEntry::MapTable(_map_table) => {},
+            StreamEntry::Print(printer) => {
+                printer.wakeup(&token);
             },
-            StreamEntry::Print(_printer) => {},
         }
 
-        if ! events.is_empty() {
+        if ! buffers.events.is_empty() {
+            stats[index].events_out += buffers.events.len() as u64;
+            let mut trace = tracer.and_then(|tracer| tracer.start(
+                format!("the events generated by {}", stats[index].label), &buffers.events
+            ));
+            if let Some(trace) = &mut trace {
+                trace.record_change(&stats[index].label, &buffers.events);
+            }
+            let num_events_out_before = events_out.len();
             // If index+1 == stream.len(), then stream[index+1..] is the empty slice.
-            run_events(events, events_out, &mut stream[index+1..], state, loopback);
-            events = Vec::new();
+            run_events(buffers, events_out, &mut stream[index+1..], &mut stats[index+1..], (dispatch, index+1), state, loopback, &mut trace, active_profile);
+            if let Some(trace) = trace {
+                trace.finish(&events_out[num_events_out_before..]);
+            }
         }
     }
 }
 
 /// A direct analogue for run_once(), except it runs through capabilities instead of events.
-pub fn run_caps(stream: &[StreamEntry], capabilities: Vec<Capability>) -> Vec<Capability> {
+pub fn run_caps(stream: &[StreamEntry], stats: &[EntryStats], capabilities: Vec<Capability>) -> Vec<Capability> {
+    run_caps_with(stream, stats, capabilities, |_, _| ())
+}
+
+/// Like run_caps(), but calls `inspect` with the index of each entry and the capabilities that enter it.
+fn run_caps_with(
+    stream: &[StreamEntry],
+    stats: &[EntryStats],
+    capabilities: Vec<Capability>,
+    mut inspect: impl FnMut(usize, &[Capability]),
+) -> Vec<Capability> {
     let mut caps: Vec<Capability> = capabilities;
     let mut buffer: Vec<Capability> = Vec::new();
     let mut last_num_caps = caps.len();
     
-    for entry in stream {
+    for (index, (entry, stats)) in stream.iter().zip(stats).enumerate() {
+        inspect(index, &caps);
+        // Entries of a profile let all events pass while the profile is inactive, so whatever
+        // capabilities enter them may leave them as well.
+        let caps_in = stats.profile.map(|_| caps.clone());
         match entry {
             StreamEntry::Map(map) => {
                 map.apply_to_all_caps(&caps, &mut buffer);
@@ -300,9 +873,84 @@ pub fn run_caps(stream: &[StreamEntry], capabilities: Vec<Capability>) -> Vec<Ca
                 caps.clear();
                 std::mem::swap(&mut caps, &mut buffer);
             },
+            StreamEntry::Rotary(rotary) => {
+                rotary.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Split(split) => {
+                split.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Touchpad(touchpad) => {
+                touchpad.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Accel(accel) => {
+                accel.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Combine(combine) => {
+                combine.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::MapTable(map_table) => {
+                map_table.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
             StreamEntry::Print(_) => (),
             StreamEntry::Delay(_) => (),
+            StreamEntry::Debounce(_) => (),
+            StreamEntry::Latch(_) => (),
+            StreamEntry::Sticky(_) => (),
+            StreamEntry::Chord(chord) => {
+                chord.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Counter(counter) => {
+                counter.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Timer(timer) => {
+                timer.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Throttle(throttle) => {
+                throttle.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
             StreamEntry::Withhold(_) => (),
+            // The events a macro replays are events that passed by it earlier.
+            StreamEntry::Macro(_) => (),
+            StreamEntry::Script(script) => {
+                script.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Repeat(repeat) => {
+                repeat.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Unicode(unicode) => {
+                unicode.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+        }
+
+        if let Some(caps_in) = caps_in {
+            let caps_out: HashSet<Capability> = caps.iter().copied().collect();
+            caps.extend(caps_in.into_iter().filter(|cap| ! caps_out.contains(cap)));
         }
 
         // Merge capabilities that differ only in value together when possible.
@@ -314,4 +962,133 @@ pub fn run_caps(stream: &[StreamEntry], capabilities: Vec<Capability>) -> Vec<Ca
     }
 
     caps.into_iter().filter(|cap| cap.namespace == Namespace::Output).collect()
-}
\ No newline at end of file
+}
+
+impl StreamEntry {
+    /// Returns the keys that events are matched against. The maps of a --map table= are left
+    /// out, because tables commonly contain maps for keys that a particular device lacks.
+    fn input_keys(&self) -> Vec<&Key> {
+        match self {
+            StreamEntry::Map(map) => map.input_keys(),
+            StreamEntry::Hook(hook) => hook.input_keys(),
+            StreamEntry::Toggle(toggle) => toggle.input_keys(),
+            StreamEntry::Print(printer) => printer.input_keys(),
+            StreamEntry::Merge(merge) => merge.input_keys(),
+            StreamEntry::Withhold(withhold) => withhold.input_keys(),
+            StreamEntry::Delay(delay) => delay.input_keys(),
+            StreamEntry::Debounce(debounce) => debounce.input_keys(),
+            StreamEntry::Throttle(throttle) => throttle.input_keys(),
+            StreamEntry::Latch(latch) => latch.input_keys(),
+            StreamEntry::Sticky(sticky) => sticky.input_keys(),
+            StreamEntry::Chord(chord) => chord.input_keys(),
+            StreamEntry::Counter(counter) => counter.input_keys(),
+            StreamEntry::Timer(timer) => timer.input_keys(),
+            StreamEntry::Repeat(repeat) => repeat.input_keys(),
+            StreamEntry::Macro(macro_entry) => macro_entry.input_keys(),
+            StreamEntry::Script(script) => script.input_keys(),
+            StreamEntry::Unicode(unicode) => unicode.input_keys(),
+            StreamEntry::Rotary(rotary) => rotary.input_keys(),
+            StreamEntry::Split(split) => split.input_keys(),
+            StreamEntry::Touchpad(touchpad) => touchpad.input_keys(),
+            StreamEntry::Accel(accel) => accel.input_keys(),
+            StreamEntry::Combine(combine) => combine.input_keys(),
+            StreamEntry::MapTable(_) => Vec::new(),
+        }
+    }
+}
+
+/// Where the events of a single input domain can go, as found out by `trace_input_domain()`.
+pub struct DomainReach {
+    /// The domains of the output devices that the events can reach.
+    pub outputs: HashSet<Domain>,
+    /// The indices of the entries that keep state and can be reached by the events. The events of
+    /// different domains that reach the same such entry can affect each other.
+    pub stateful_entries: HashSet<usize>,
+    /// Whether the events can reach a --toggle or --hook, which may change the toggles.
+    pub uses_toggles: bool,
+}
+
+/// For --parallel: follows the capabilities of a single input domain through the stream to find
+/// out which other input domains its events could interact with. Entries that only map events
+/// do not keep state, so every thread can run a copy of them without changing the outcome. The
+/// same goes for prints to stdout, but not for prints to a file, because the copies would rotate
+/// the same file independently of each other.
+pub fn trace_input_domain(stream: &[StreamEntry], domain: Domain, caps: &Capabilities) -> DomainReach {
+    let stats: Vec<EntryStats> = stream.iter()
+        .map(|_| EntryStats { label: String::new(), id: None, profile: None, events_in: 0, events_out: 0, time_spent: None })
+        .collect();
+    let caps_vec = caps.to_vec_from_domain_and_namespace(domain, Namespace::Input);
+
+    let mut stateful_entries: HashSet<usize> = HashSet::new();
+    let mut uses_toggles = false;
+    let caps_out = run_caps_with(stream, &stats, caps_vec, |index, caps| {
+        let entry = &stream[index];
+        match entry {
+            StreamEntry::Map(_) | StreamEntry::MapTable(_) => return,
+            StreamEntry::Print(printer) if ! printer.writes_to_file() => return,
+            _ => (),
+        }
+        let is_reached = entry.input_keys().into_iter()
+            .any(|key| caps.iter().any(|cap| key.matches_cap(cap) != CapMatch::No));
+        if ! is_reached {
+            return;
+        }
+        stateful_entries.insert(index);
+        if let StreamEntry::Hook(_) | StreamEntry::Toggle(_) = entry {
+            uses_toggles = true;
+        }
+    });
+
+    DomainReach {
+        outputs: caps_out.iter().map(|cap| cap.domain).collect(),
+        stateful_entries,
+        uses_toggles,
+    }
+}
+
+/// Describes what --dry-run found out about a configuration without creating any output devices:
+/// which capabilities each output would get, and which arguments have keys that cannot match
+/// any event that can reach them.
+pub fn describe_dry_run(
+    stream: &[StreamEntry],
+    labels: Vec<String>,
+    profiles: Vec<Option<usize>>,
+    pre_output: &[PreOutputDevice],
+    input_caps: &InputCapabilites,
+) -> String {
+    let stats: Vec<EntryStats> = labels.into_iter().zip(profiles)
+        .map(|(label, profile)| EntryStats { label, id: None, profile, events_in: 0, events_out: 0, time_spent: None })
+        .collect();
+
+    let mut dead_indices: Vec<usize> = Vec::new();
+    let caps_vec: Vec<Capability> = crate::capability::input_caps_to_vec(input_caps);
+    let caps_out = run_caps_with(stream, &stats, caps_vec, |index, caps| {
+        let has_dead_key = stream[index].input_keys().into_iter()
+            // The maps that move events from the input devices into the stream are not arguments
+            // of their own; devices that show up later may still provide their events.
+            .filter(|key| key.requires_namespace() != Some(Namespace::Input))
+            .any(|key| caps.iter().all(|cap| key.matches_cap(cap) == CapMatch::No));
+        if has_dead_key {
+            dead_indices.push(index);
+        }
+    });
+
+    let mut dead_labels: Vec<&str> = Vec::new();
+    for index in dead_indices {
+        let label = stats[index].label.as_str();
+        if ! dead_labels.contains(&label) {
+            dead_labels.push(label);
+        }
+    }
+
+    let mut report = crate::io::output::describe_capabilities(pre_output, &caps_out);
+    if dead_labels.is_empty() {
+        report.push_str("All arguments can match some of the events that can reach them.\n");
+    } else {
+        report.push_str("The following arguments have keys that cannot match any event that can reach them:\n");
+        for label in dead_labels {
+            report.push_str(&format!("    {}\n", label));
+        }
+    }
+    report
+}
@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::capability::{Capability, CapMatch};
+use crate::domain::Domain;
+use crate::event::{Event, Channel};
+use crate::key::Key;
+use crate::range::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccelProfile {
+    /// Multiplies all movement by a constant factor.
+    Flat,
+    /// Multiplies movement by a factor that increases with the speed of the pointer.
+    Adaptive,
+}
+
+/// Represents an --accel argument. Scales relative movement in the same spirit as the
+/// pointer acceleration that libinput applies to mice, which gets lost when a mouse is grabbed.
+pub struct Accel {
+    keys: Vec<Key>,
+    profile: AccelProfile,
+    /// The factor that gets applied to all movement.
+    speed: f64,
+    /// For the adaptive profile: the exponent of the acceleration curve.
+    curve: f64,
+    /// For the adaptive profile: the amount of units per report above which movement gets accelerated.
+    threshold: f64,
+
+    /// State: modifiable at runtime.
+    /// For each channel, the fractional part of the movement that has not been sent yet.
+    remainders: HashMap<Channel, f64>,
+}
+
+impl Accel {
+    pub fn new(keys: Vec<Key>, profile: AccelProfile, speed: f64, curve: f64, threshold: f64) -> Accel {
+        Accel {
+            keys, profile, speed, curve, threshold,
+            remainders: HashMap::new(),
+        }
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        self.keys.iter().any(|key| key.matches(event))
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        // The adaptive profile needs to know how fast the pointer moves, which depends on the
+        // movement along all axes of the same device within the current batch of events.
+        let mut magnitudes: HashMap<Domain, f64> = HashMap::new();
+        if self.profile == AccelProfile::Adaptive {
+            for event in events.iter().filter(|event| self.matches(event)) {
+                *magnitudes.entry(event.domain).or_insert(0.0) += (event.value as f64).powi(2);
+            }
+        }
+
+        for &event in events {
+            if ! self.matches(&event) {
+                output_events.push(event);
+                continue;
+            }
+
+            let factor = match self.profile {
+                AccelProfile::Flat => self.speed,
+                AccelProfile::Adaptive => {
+                    let magnitude = magnitudes.get(&event.domain).copied().unwrap_or(0.0).sqrt();
+                    adaptive_factor(magnitude, self.speed, self.curve, self.threshold)
+                },
+            };
+
+            let remainder = self.remainders.entry(event.channel()).or_insert(0.0);
+            let total = event.value as f64 * factor + *remainder;
+            let value = total.trunc();
+            *remainder = total - value;
+
+            if value != 0.0 {
+                let mut output_event = event;
+                output_event.value = value as i32;
+                output_events.push(output_event);
+            }
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            let matches = self.keys.iter()
+                .map(|key| key.matches_cap(&cap))
+                .max().unwrap_or(CapMatch::No);
+            match matches {
+                CapMatch::No => output_caps.push(cap),
+                CapMatch::Yes | CapMatch::Maybe => {
+                    let mut output_cap = cap;
+                    output_cap.value_range = Range::new(None, None);
+                    output_caps.push(output_cap);
+                },
+            }
+        }
+    }
+}
+
+/// Computes the factor by which movement of a given magnitude gets multiplied. Movement below the
+/// threshold is scaled by `speed`; above it, the factor grows as (magnitude/threshold)^curve.
+fn adaptive_factor(magnitude: f64, speed: f64, curve: f64, threshold: f64) -> f64 {
+    if magnitude <= threshold {
+        speed
+    } else {
+        speed * (magnitude / threshold).powf(curve)
+    }
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(adaptive_factor(1.0, 2.0, 1.0, 4.0), 2.0);
+    assert_eq!(adaptive_factor(4.0, 2.0, 1.0, 4.0), 2.0);
+    assert_eq!(adaptive_factor(8.0, 2.0, 1.0, 4.0), 4.0);
+    assert_eq!(adaptive_factor(8.0, 1.0, 2.0, 4.0), 4.0);
+    assert_eq!(adaptive_factor(8.0, 1.0, 0.0, 4.0), 1.0);
+}
@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashSet;
+
+use crate::capability::{Capability, CapMatch};
+use crate::event::{Event, Channel};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::Range;
+use crate::time::Duration;
+
+/// Represents a --chord argument. If all of its keys get pressed within a short window, their
+/// presses are dropped and the send-keys get pressed instead. The send-keys get released as soon
+/// as any of the keys gets released.
+pub struct Chord {
+    keys: Vec<Key>,
+    send_keys: Vec<Key>,
+    /// How long the first press may be withheld while waiting for the other keys.
+    window: Duration,
+
+    /// State: modifiable at runtime.
+    state: ChordState,
+}
+
+enum ChordState {
+    /// None of the keys have been pressed, or the chord was not completed in time.
+    Idle,
+    /// Some of the keys have been pressed and their presses are being withheld. `members` holds
+    /// for each withheld press the index of the key it matched.
+    Collecting { withheld: Vec<Event>, members: Vec<usize>, token: Token },
+    /// The chord was completed and the send-keys are pressed. `held` are the channels of the
+    /// keys that are still physically pressed; events on them are dropped until they are all released.
+    Active { first_press: Event, held: HashSet<Channel>, send_keys_pressed: bool },
+}
+
+impl Chord {
+    pub fn new(keys: Vec<Key>, send_keys: Vec<Key>, window: Duration) -> Chord {
+        Chord { keys, send_keys, window, state: ChordState::Idle }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key() {
+            return output_events.push(event);
+        }
+        let member = self.keys.iter().position(|key| key.matches(&event));
+
+        match &mut self.state {
+            ChordState::Idle => match (member, event.value) {
+                (Some(member), 1) => {
+                    let token = loopback.schedule_wakeup_in(self.window);
+                    self.state = ChordState::Collecting { withheld: vec![event], members: vec![member], token };
+                },
+                _ => output_events.push(event),
+            },
+            ChordState::Collecting { withheld, members, .. } => match (member, event.value) {
+                (Some(member), 1) => {
+                    if ! withheld.iter().any(|withheld_event| withheld_event.channel() == event.channel()) {
+                        withheld.push(event);
+                        members.push(member);
+                    }
+                    self.try_complete(output_events, loopback);
+                },
+                // Repeats of withheld keys are dropped.
+                (Some(_), 2) if withheld.iter().any(|withheld_event| withheld_event.channel() == event.channel()) => {},
+                // Any other key event means that this is not the chord: a key was released before
+                // the chord was completed, or a key that is not part of the chord was pressed.
+                _ => {
+                    self.give_up(output_events, loopback);
+                    output_events.push(event);
+                },
+            },
+            ChordState::Active { first_press, held, send_keys_pressed } => {
+                if ! held.contains(&event.channel()) {
+                    return output_events.push(event);
+                }
+                if event.value == 0 {
+                    held.remove(&event.channel());
+                    if *send_keys_pressed {
+                        *send_keys_pressed = false;
+                        for key in self.send_keys.iter().rev() {
+                            let mut release = key.merge(*first_press);
+                            release.value = 0;
+                            release.previous_value = 1;
+                            output_events.push(release);
+                        }
+                    }
+                    if held.is_empty() {
+                        self.state = ChordState::Idle;
+                    }
+                }
+            },
+        }
+    }
+
+    /// Presses the send-keys if all keys of the chord are being withheld.
+    fn try_complete(&mut self, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let (withheld, members) = match &self.state {
+            ChordState::Collecting { withheld, members, .. } => (withheld, members),
+            _ => return,
+        };
+        if ! (0 .. self.keys.len()).all(|index| members.contains(&index)) {
+            return;
+        }
+
+        let first_press = withheld[0];
+        let held = withheld.iter().map(|event| event.channel()).collect();
+        for key in &self.send_keys {
+            let mut press = key.merge(first_press);
+            press.value = 1;
+            press.previous_value = 0;
+            output_events.push(press);
+        }
+        if let ChordState::Collecting { token, .. } = std::mem::replace(
+            &mut self.state, ChordState::Active { first_press, held, send_keys_pressed: true }
+        ) {
+            loopback.cancel_token(token);
+        }
+    }
+
+    /// Passes on the withheld presses as if this argument did not exist.
+    fn give_up(&mut self, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if let ChordState::Collecting { withheld, token, .. } = std::mem::replace(&mut self.state, ChordState::Idle) {
+            loopback.cancel_token(token);
+            output_events.extend(withheld);
+        }
+    }
+
+    /// Passes on the withheld presses if the chord was not completed in time.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        let is_expired = match &self.state {
+            ChordState::Collecting { token: chord_token, .. } => chord_token == token,
+            _ => false,
+        };
+        if is_expired {
+            if let ChordState::Collecting { withheld, .. } = std::mem::replace(&mut self.state, ChordState::Idle) {
+                output_events.extend(withheld);
+            }
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        let mut additional_caps: HashSet<Capability> = HashSet::new();
+        for &cap in caps {
+            output_caps.push(cap);
+            if self.keys.iter().any(|key| key.matches_cap(&cap) != CapMatch::No) {
+                additional_caps.extend(self.send_keys.iter().map(|key| {
+                    let mut new_cap = key.merge_cap(cap);
+                    new_cap.value_range = Range::new(Some(0), Some(1));
+                    new_cap
+                }));
+            }
+        }
+        output_caps.extend(additional_caps);
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::capability::{Capability, CapMatch};
+use crate::domain::Domain;
+use crate::event::{Event, EventValue};
+use crate::key::Key;
+use crate::range::{ExtendedInteger, Range};
+
+/// Represents a --combine argument. Combines two half-axes, such as the triggers of a gamepad,
+/// into a single axis whose value is that of the positive axis minus that of the negative axis.
+pub struct Combine {
+    negative_key: Key,
+    positive_key: Key,
+    output_key: Key,
+
+    /// State: modifiable at runtime.
+    /// For each domain, the last known values of the negative and positive axis.
+    state: HashMap<Domain, (EventValue, EventValue)>,
+}
+
+impl Combine {
+    pub fn new(negative_key: Key, positive_key: Key, output_key: Key) -> Combine {
+        Combine {
+            negative_key, positive_key, output_key,
+            state: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        let is_negative = self.negative_key.matches(&event);
+        let is_positive = self.positive_key.matches(&event);
+        if ! is_negative && ! is_positive {
+            output_events.push(event);
+            return;
+        }
+
+        let (negative, positive) = self.state.entry(event.domain).or_insert_with(|| match is_negative {
+            true => (event.previous_value, 0),
+            false => (0, event.previous_value),
+        });
+        let previous_value = positive.saturating_sub(*negative);
+        if is_negative {
+            *negative = event.value;
+        } else {
+            *positive = event.value;
+        }
+        let value = positive.saturating_sub(*negative);
+
+        let mut output_event = self.output_key.merge(event);
+        output_event.value = value;
+        output_event.previous_value = previous_value;
+        output_events.push(output_event);
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        vec![&self.negative_key, &self.positive_key]
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        // For each domain, the capabilities of the negative and positive axes.
+        let mut axes: HashMap<Domain, (Option<Capability>, Option<Capability>)> = HashMap::new();
+
+        for &cap in caps {
+            let negative_match = self.negative_key.matches_cap(&cap);
+            let positive_match = self.positive_key.matches_cap(&cap);
+            if negative_match == CapMatch::No && positive_match == CapMatch::No {
+                output_caps.push(cap);
+                continue;
+            }
+            if negative_match == CapMatch::Maybe || positive_match == CapMatch::Maybe {
+                output_caps.push(cap);
+            }
+
+            let entry = axes.entry(cap.domain).or_insert((None, None));
+            if negative_match != CapMatch::No {
+                entry.0 = Some(cap);
+            } else {
+                entry.1 = Some(cap);
+            }
+        }
+
+        for (_domain, (negative_cap, positive_cap)) in axes {
+            let zero = Range::new(Some(0), Some(0));
+            let negative_range = negative_cap.map(|cap| cap.value_range).unwrap_or(zero);
+            let positive_range = positive_cap.map(|cap| cap.value_range).unwrap_or(zero);
+            let value_range = combined_range(negative_range, positive_range);
+
+            let base_cap = match positive_cap.or(negative_cap) {
+                Some(cap) => cap,
+                None => continue,
+            };
+            let mut output_cap = self.output_key.merge_cap(base_cap);
+            output_cap.value_range = value_range;
+            output_caps.push(output_cap);
+        }
+    }
+}
+
+/// Returns the range of all values that `positive - negative` can take.
+fn combined_range(negative: Range, positive: Range) -> Range {
+    Range {
+        min: positive.min.checked_sub(negative.max).unwrap_or(ExtendedInteger::NegativeInfinity),
+        max: positive.max.checked_sub(negative.min).unwrap_or(ExtendedInteger::PositiveInfinity),
+    }
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(
+        combined_range(Range::new(Some(0), Some(255)), Range::new(Some(0), Some(255))),
+        Range::new(Some(-255), Some(255))
+    );
+    assert_eq!(
+        combined_range(Range::new(Some(0), Some(0)), Range::new(Some(0), Some(1023))),
+        Range::new(Some(0), Some(1023))
+    );
+    assert_eq!(
+        combined_range(Range::new(Some(10), None), Range::new(Some(0), Some(5))),
+        Range::new(None, Some(-5))
+    );
+}
@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashSet;
+
+use crate::capability::{Capability, CapMatch};
+use crate::event::{Event, EventValue};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::Range;
+use crate::time::Duration;
+
+/// How much each matching event adds to the count.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CounterMode {
+    /// Each event adds one.
+    Count,
+    /// Each event adds its value.
+    Sum,
+}
+
+/// Represents a --counter argument. Keeps count of the events that match its keys and emits
+/// the count as the value of an absolute axis.
+pub struct Counter {
+    keys: Vec<Key>,
+    reset_keys: Vec<Key>,
+    output_key: Key,
+    mode: CounterMode,
+    /// The count never leaves this range.
+    range: Range,
+    /// If Some, the count is emitted at most once per period instead of every time it changes.
+    period: Option<Duration>,
+
+    /// State: modifiable at runtime.
+    count: EventValue,
+    /// The count that was last emitted.
+    emitted_count: EventValue,
+    /// The last event that changed the count. The output events are based on it.
+    last_event: Option<Event>,
+    /// The wakeup at which the count gets emitted, if the count changed since it was last emitted.
+    pending: Option<Token>,
+}
+
+impl Counter {
+    pub fn new(keys: Vec<Key>, reset_keys: Vec<Key>, output_key: Key, mode: CounterMode, range: Range, period: Option<Duration>) -> Counter {
+        let initial_count = range.bound(0);
+        Counter {
+            keys, reset_keys, output_key, mode, range, period,
+            count: initial_count,
+            emitted_count: initial_count,
+            last_event: None,
+            pending: None,
+        }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().chain(&self.reset_keys).collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        output_events.push(event);
+
+        let count = if self.reset_keys.iter().any(|key| key.matches(&event)) {
+            self.range.bound(0)
+        } else if self.keys.iter().any(|key| key.matches(&event)) {
+            let increment = match self.mode {
+                CounterMode::Count => 1,
+                CounterMode::Sum => event.value,
+            };
+            self.range.bound(self.count.saturating_add(increment))
+        } else {
+            return;
+        };
+
+        self.count = count;
+        self.last_event = Some(event);
+        match self.period {
+            None => self.emit(output_events),
+            Some(period) => if self.pending.is_none() {
+                self.pending = Some(loopback.schedule_wakeup_in(period));
+            },
+        }
+    }
+
+    /// Emits the count if it changed since it was last emitted.
+    fn emit(&mut self, output_events: &mut Vec<Event>) {
+        let last_event = match self.last_event {
+            Some(event) => event,
+            None => return,
+        };
+        if self.count == self.emitted_count {
+            return;
+        }
+
+        let mut output_event = self.output_key.merge(last_event);
+        output_event.value = self.count;
+        output_event.previous_value = self.emitted_count;
+        output_events.push(output_event);
+        self.emitted_count = self.count;
+    }
+
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        if self.pending.as_ref() == Some(token) {
+            self.pending = None;
+            self.emit(output_events);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        let mut additional_caps: HashSet<Capability> = HashSet::new();
+        for &cap in caps {
+            output_caps.push(cap);
+            if self.input_keys().iter().any(|key| key.matches_cap(&cap) != CapMatch::No) {
+                let mut output_cap = self.output_key.merge_cap(cap);
+                output_cap.value_range = self.range;
+                output_cap.abs_meta = None;
+                additional_caps.insert(output_cap);
+            }
+        }
+        output_caps.extend(additional_caps);
+    }
+}
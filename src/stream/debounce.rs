@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::event::{Event, Channel};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::time::Duration;
+
+/// Represents a --debounce argument. Worn switches tend to report a few rapid releases and presses
+/// when they get pressed or released once. To filter those out, releases are held back for a
+/// while. If the key gets pressed again before that time has passed, both the release and the
+/// press are dropped.
+pub struct Debounce {
+    keys: Vec<Key>,
+    /// How long releases are held back.
+    period: Duration,
+
+    /// State: modifiable at runtime.
+    /// The releases that are being held back, along with the token at which they get passed on.
+    pending_releases: Vec<(Token, Event)>,
+}
+
+impl Debounce {
+    pub fn new(keys: Vec<Key>, period: Duration) -> Debounce {
+        Debounce { keys, period, pending_releases: Vec::new() }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return output_events.push(event);
+        }
+
+        match event.value {
+            0 => {
+                if self.find_pending(event.channel()).is_some() {
+                    // The key was released twice in a row. The pending release covers this one.
+                    return;
+                }
+                let token = loopback.schedule_wakeup_in(self.period);
+                self.pending_releases.push((token, event));
+            },
+            1 => match self.find_pending(event.channel()) {
+                // The key bounced: it was released and pressed again too quickly to be real.
+                Some(index) => {
+                    let (token, _release) = self.pending_releases.remove(index);
+                    loopback.cancel_token(token);
+                },
+                None => output_events.push(event),
+            },
+            _ => output_events.push(event),
+        }
+    }
+
+    fn find_pending(&self, channel: Channel) -> Option<usize> {
+        self.pending_releases.iter().position(|(_, release)| release.channel() == channel)
+    }
+
+    /// Passes on a release that was held back long enough without the key getting pressed again.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        if let Some(index) = self.pending_releases.iter().position(|(pending_token, _)| pending_token == token) {
+            let (_, release) = self.pending_releases.remove(index);
+            output_events.push(release);
+        }
+    }
+}
@@ -3,7 +3,8 @@
 use crate::loopback::{LoopbackHandle, Token};
 use crate::event::Event;
 use crate::key::Key;
-use crate::time::Duration;
+use crate::random::Rng;
+use crate::time::{Duration, Instant};
 
 // TODO: it appears there is a function libevdev_set_clock_id() which can be used to make
 // event devices report their event times on a monotonic clock. This may be useful to
@@ -12,49 +13,86 @@ use crate::time::Duration;
 /// All events that reach the delay shall be removed and put back into the stream after 
 /// a certain amount of time passes.
 pub struct Delay {
-    keys: Vec<Key>,
-    period: Duration,
+    /// Events are delayed by the period of the first group that has a key matching them.
+    groups: Vec<DelayGroup>,
+    /// If Some, every batch of events is delayed by a random amount between period - jitter
+    /// and period + jitter instead.
+    jitter: Option<Jitter>,
 
     /// State: modifiable at runtime.
     /// Events that need to be put back into thes stream when the loopback releases a certain token.
     delayed_events: Vec<(Token, Vec<Event>)>,
 }
 
+struct DelayGroup {
+    keys: Vec<Key>,
+    period: Duration,
+    /// When the most recently delayed events of this group shall be released. Only tracked if
+    /// there is jitter: later events are never released before earlier ones of the same group,
+    /// even if they drew a shorter delay.
+    last_release: Option<Instant>,
+}
+
+struct Jitter {
+    amount: Duration,
+    rng: Rng,
+}
+
 impl Delay {
-    pub fn new(keys: Vec<Key>, period: Duration) -> Delay {
+    /// The jitter must not exceed any of the periods.
+    pub fn new(groups: Vec<(Vec<Key>, Duration)>, jitter: Option<Duration>) -> Delay {
         Delay {
-            keys, period,
+            groups: groups.into_iter()
+                .map(|(keys, period)| DelayGroup { keys, period, last_release: None })
+                .collect(),
+            jitter: jitter.map(|amount| Jitter { amount, rng: Rng::new() }),
             delayed_events: Vec::new(),
         }
     }
 
-    /// Checks if some events matches this delay's keys, and if so, withholds them for a
-    /// specified period.
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.groups.iter().flat_map(|group| &group.keys).collect()
+    }
+
+    /// Checks if some events matches this delay's keys, and if so, withholds them for the
+    /// period that belongs to the matching key.
     pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
-        let mut events_to_withhold: Vec<Event> = Vec::new();
+        let mut events_to_withhold: Vec<Vec<Event>> = vec![Vec::new(); self.groups.len()];
         for &event in events {
-            if self.keys.iter().any(|key| key.matches(&event)) {
-                events_to_withhold.push(event);
-            } else {
-                output_events.push(event);
+            match self.groups.iter().position(|group| group.keys.iter().any(|key| key.matches(&event))) {
+                Some(index) => events_to_withhold[index].push(event),
+                None => output_events.push(event),
             }
         }
 
-        if ! events_to_withhold.is_empty() {
-            let wakeup_token = loopback.schedule_wakeup_in(self.period);
-            self.delayed_events.push((wakeup_token, events_to_withhold));
+        for (group, events) in self.groups.iter_mut().zip(events_to_withhold) {
+            if events.is_empty() {
+                continue;
+            }
+            let wakeup_token = match &mut self.jitter {
+                None => loopback.schedule_wakeup_in(group.period),
+                Some(jitter) => {
+                    let jitter_ns = jitter.amount.as_nanos() as u64;
+                    let delay_ns = group.period.as_nanos() as u64 - jitter_ns + jitter.rng.up_to(2 * jitter_ns);
+                    let mut release = loopback.now() + Duration::from_nanos(delay_ns);
+                    if let Some(last_release) = group.last_release {
+                        release = std::cmp::max(release, last_release);
+                    }
+                    group.last_release = Some(release);
+                    loopback.schedule_wakeup_at(release)
+                },
+            };
+            self.delayed_events.push((wakeup_token, events));
         }
     }
 
-    /// All delayed events that are overdue will be put back into the stream.
+    /// Puts the events that were delayed until the given token back into the stream. Events
+    /// with different periods are not necessarily released in the order they were delayed.
     pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
-        while let Some((wakeup_token, delayed_events)) = self.delayed_events.first() {
-            if wakeup_token == token {
-                output_events.extend(delayed_events);
-                self.delayed_events.remove(0);
-            } else {
-                break;
-            }
+        if let Some(index) = self.delayed_events.iter().position(|(wakeup_token, _)| wakeup_token == token) {
+            let (_, delayed_events) = self.delayed_events.remove(index);
+            output_events.extend(delayed_events);
         }
     }
-}
\ No newline at end of file
+}
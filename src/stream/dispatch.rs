@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Most arguments only care about a handful of event codes. To avoid testing every event against
+//! the keys of every argument, the stream keeps a table of which entries may do anything to events
+//! with a certain code. Entries that cannot do anything to any of the events passing by get skipped.
+
+use std::collections::HashMap;
+
+use crate::event::{Event, EventCode, EventType};
+use crate::key::Key;
+use super::StreamEntry;
+
+/// Which events a single entry of the stream may do anything to.
+enum EntryFilter {
+    /// The entry may act on any event, or may do something even if no event matches its keys.
+    Any,
+    /// The entry lets all events pass untouched, except those whose code or type is listed here.
+    Only(Vec<EventCode>, Vec<EventType>),
+}
+
+impl EntryFilter {
+    fn from_keys<'a>(keys: impl IntoIterator<Item=&'a Key>) -> EntryFilter {
+        let mut codes = Vec::new();
+        let mut types = Vec::new();
+        for key in keys {
+            if let Some(code) = key.requires_event_code() {
+                codes.push(code);
+            } else if let Some(ev_type) = key.requires_event_type() {
+                types.push(ev_type);
+            } else {
+                return EntryFilter::Any;
+            }
+        }
+        EntryFilter::Only(codes, types)
+    }
+
+    fn for_entry(entry: &StreamEntry) -> EntryFilter {
+        match entry {
+            // These entries do nothing but pass through the events that do not match their keys.
+            StreamEntry::Map(_) | StreamEntry::Unicode(_) | StreamEntry::Rotary(_) | StreamEntry::Split(_) | StreamEntry::Combine(_)
+            | StreamEntry::Debounce(_) | StreamEntry::Throttle(_) | StreamEntry::Latch(_) | StreamEntry::Counter(_) | StreamEntry::Timer(_)
+                => EntryFilter::from_keys(entry.input_keys()),
+            StreamEntry::MapTable(map_table) => EntryFilter::Only(map_table.input_codes().collect(), Vec::new()),
+            _ => EntryFilter::Any,
+        }
+    }
+
+    fn is_any(&self) -> bool {
+        match self {
+            EntryFilter::Any => true,
+            EntryFilter::Only(_, _) => false,
+        }
+    }
+
+    fn may_act_on(&self, code: EventCode) -> bool {
+        match self {
+            EntryFilter::Any => true,
+            EntryFilter::Only(codes, types) => codes.contains(&code) || types.contains(&code.ev_type()),
+        }
+    }
+}
+
+pub struct DispatchTable {
+    /// The filter of each entry of the stream, in the same order as the stream.
+    filters: Vec<EntryFilter>,
+    /// For each event code, whether each entry of the stream may act on events with that code.
+    /// The codes that input devices can generate are filled in when the table is built, the rest
+    /// the first time an event with that code shows up.
+    by_code: HashMap<EventCode, Box<[bool]>>,
+    /// The buffer in which `relevant_entries()` combines the entries of several event codes,
+    /// kept around to avoid allocating it for every batch of events.
+    relevant: Vec<bool>,
+}
+
+impl DispatchTable {
+    pub fn new<'a>(stream: &[StreamEntry], input_codes: impl IntoIterator<Item=&'a EventCode>) -> DispatchTable {
+        let mut table = DispatchTable {
+            filters: stream.iter().map(EntryFilter::for_entry).collect(),
+            by_code: HashMap::new(),
+            relevant: Vec::new(),
+        };
+        for &code in input_codes {
+            table.for_code(code);
+        }
+        table
+    }
+
+    fn for_code(&mut self, code: EventCode) -> &[bool] {
+        let filters = &self.filters;
+        self.by_code.entry(code)
+            .or_insert_with(|| entries_acting_on(filters, code))
+    }
+
+    /// Returns for each entry of the stream whether it may act on any of the given events.
+    pub fn relevant_entries(&mut self, events: &[Event]) -> &[bool] {
+        match events {
+            [event] => self.for_code(event.code),
+            _ => {
+                let DispatchTable { filters, by_code, relevant } = self;
+                relevant.clear();
+                relevant.extend(filters.iter().map(EntryFilter::is_any));
+                for event in events {
+                    let may_act_on_event = by_code.entry(event.code)
+                        .or_insert_with(|| entries_acting_on(filters, event.code));
+                    for (is_relevant, &may_act) in relevant.iter_mut().zip(may_act_on_event.iter()) {
+                        *is_relevant |= may_act;
+                    }
+                }
+                relevant
+            },
+        }
+    }
+}
+
+/// Returns for each filter whether its entry may act on events with the given code.
+fn entries_acting_on(filters: &[EntryFilter], code: EventCode) -> Box<[bool]> {
+    filters.iter().map(|filter| filter.may_act_on(code)).collect()
+}
+
+#[test]
+fn unittest() {
+    use crate::key::KeyParser;
+    use crate::event::Namespace;
+    use super::map::Map;
+
+    let parser = KeyParser::default_filter();
+    let stream = vec![
+        StreamEntry::Map(Map::new(parser.parse("key:a").unwrap(), vec![KeyParser::default_mask().parse("key:b").unwrap()])),
+        StreamEntry::Map(Map::block(parser.parse("rel").unwrap())),
+        StreamEntry::Map(Map::block(parser.parse("@foo").unwrap())),
+    ];
+    let mut table = DispatchTable::new(&stream, &[EventCode::new(EventType::KEY, 30)]);
+
+    let domain = crate::domain::get_unique_domain();
+    let key_a = Event::new(EventCode::new(EventType::KEY, 30), 1, 0, domain, Namespace::User);
+    let rel_x = Event::new(EventCode::new(EventType::REL, 0), 1, 0, domain, Namespace::User);
+    assert_eq!(table.relevant_entries(&[key_a]), &[true, false, true]);
+    assert_eq!(table.relevant_entries(&[rel_x]), &[false, true, true]);
+    assert_eq!(table.relevant_entries(&[key_a, rel_x]), &[true, true, true]);
+    assert_eq!(table.relevant_entries(&[]), &[false, false, true]);
+}
@@ -2,7 +2,7 @@ use crate::error::Context;
 use crate::range::Range;
 use crate::key::Key;
 use crate::event::{Event, Channel, EventFlag};
-use crate::state::State;
+use crate::state::{State, ToggleIndex, ToggleState};
 use crate::subprocess;
 use crate::loopback;
 use crate::loopback::LoopbackHandle;
@@ -13,7 +13,50 @@ use std::collections::HashSet;
 // TODO: Add a unittest for a hook with multiple send-keys.
 // TODO: Check whether the ordering behaviour of --withhold is consistent with --hook send-key.
 
-pub type Effect = Box<dyn Fn(&mut State)>;
+/// Something a hook does when it activates, besides sending keys.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Effect {
+    /// Changes the active state of a single toggle.
+    ToggleSet(ToggleIndex, ToggleShift),
+    /// Changes the active state of all toggles except the listed ones.
+    ToggleSetOthers(Vec<ToggleIndex>, ToggleShift),
+    /// Spawns a subprocess with the given program and arguments.
+    RunCommand(String, Vec<String>),
+}
+
+/// How an Effect changes the active state of a toggle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ToggleShift {
+    /// Move the active index to the next one, wrapping around.
+    Next,
+    /// Set the active index to a specific index, wrapping around if it is out of range.
+    ToIndex(usize),
+}
+
+impl Effect {
+    pub fn apply(&self, state: &mut State) {
+        match self {
+            Effect::ToggleSet(index, shift) => shift.apply_to(&mut state[*index]),
+            Effect::ToggleSetOthers(excluded_indices, shift) => {
+                for toggle in state.get_toggles_except(excluded_indices) {
+                    shift.apply_to(toggle);
+                }
+            },
+            Effect::RunCommand(program, args) => {
+                subprocess::try_spawn(program.clone(), args.clone()).print_err();
+            },
+        }
+    }
+}
+
+impl ToggleShift {
+    fn apply_to(self, toggle: &mut ToggleState) {
+        match self {
+            ToggleShift::Next => toggle.advance(),
+            ToggleShift::ToIndex(value) => toggle.set_value_wrapped(value),
+        }
+    }
+}
 
 /// Represents the point at time after which a pressed tracker is no longer valid.
 /// Usually determined by the --hook period= clause.
@@ -92,6 +135,22 @@ impl Tracker {
         }
     }
 
+    fn is_inactive(&self) -> bool {
+        match self.state {
+            TrackerState::Inactive => true,
+            TrackerState::Active(_) | TrackerState::Invalid => false,
+        }
+    }
+
+    /// Changes the state of this tracker. If it was waiting to expire, the wakeup that would
+    /// have expired it gets cancelled.
+    fn set_state(&mut self, state: TrackerState, loopback: &mut LoopbackHandle) {
+        let old_state = std::mem::replace(&mut self.state, state);
+        if let TrackerState::Active(ExpirationTime::Until(token)) = old_state {
+            loopback.cancel_token(token);
+        }
+    }
+
     /// Like Clone::clone, but does not clone the runtime state of the Tracker.
     fn clone_empty(&self) -> Tracker {
         Tracker {
@@ -170,7 +229,7 @@ impl Trigger {
                     TrackerState::Active(..) | TrackerState::Invalid => {},
                 }
             } else {
-                tracker.state = TrackerState::Inactive;
+                tracker.set_state(TrackerState::Inactive, loopback);
             };
         }
         
@@ -187,8 +246,7 @@ impl Trigger {
                 // ... then find all trackers that are active but not consecutively so.
                 .filter(|tracker| tracker.is_active())
                 // ... and invalidate them.
-                // TODO: Consider canceling the activation token.
-                .for_each(|tracker| tracker.state = TrackerState::Invalid);
+                .for_each(|tracker| tracker.set_state(TrackerState::Invalid, loopback));
         }
 
         // Check if we transitioned between active and inactive.
@@ -197,9 +255,8 @@ impl Trigger {
         match (self.state, all_trackers_active) {
             (TriggerState::Inactive, true) => {
                 self.state = TriggerState::Active;
-                // TODO: Cancel tokens?
                 for tracker in &mut self.trackers {
-                    tracker.state = TrackerState::Active(ExpirationTime::Never);
+                    tracker.set_state(TrackerState::Active(ExpirationTime::Never), loopback);
                 }
                 TriggerResponse::Activates
             },
@@ -243,12 +300,37 @@ impl Trigger {
             .any(   |tracker| tracker.matches_channel(channel))
     }
 
+    /// Returns true if all trackers that match this event are inactive. If that is the case both
+    /// before and after applying the event, then the event did not change the state of this trigger.
+    pub fn is_idle_for(&self, event: &Event) -> bool {
+        self.trackers.iter()
+            .filter(|tracker| tracker.matches(event))
+            .all(   |tracker| tracker.is_inactive())
+    }
+
     /// Returns true if any of the might be activated by an event with the provided channel.
     pub fn has_tracker_matching_channel(&self, channel: Channel) -> bool {
         self.trackers.iter()
             .any(|tracker| tracker.matches_channel(channel))
     }
 
+    /// The keys that must all be held for this trigger to activate.
+    pub fn keys(&self) -> impl Iterator<Item=&Key> {
+        self.trackers.iter().map(|tracker| &tracker.key)
+    }
+
+    /// Describes how many keys of this trigger are currently held, or None if none are.
+    pub fn describe_state(&self) -> Option<String> {
+        if let TriggerState::Active = self.state {
+            return Some("active".to_owned());
+        }
+        let num_active = self.trackers.iter().filter(|tracker| tracker.is_active()).count();
+        match num_active {
+            0 => None,
+            _ => Some(format!("{} of {} keys held", num_active, self.trackers.len())),
+        }
+    }
+
     /// Like Clone::clone, but does not clone the runtime state of the Trigger.
     pub fn clone_empty(&self) -> Trigger {
         Trigger {
@@ -274,6 +356,19 @@ pub struct Hook {
 
     /// The substructure responsible for generating additinal events for the send-key clause.
     event_dispatcher: EventDispatcher,
+
+    /// The most notable response of the trigger during the last call to apply_to_all(), for --trace.
+    last_response: TriggerResponse,
+
+    /// Specified by the id= clause, so the hook can be enabled and disabled at runtime.
+    id: Option<String>,
+    /// Disabled hooks keep track of the keys, but do not activate.
+    enabled: bool,
+    /// Set if the trigger activated while this hook was disabled. The next release of the trigger
+    /// must then be ignored as well.
+    suppressed_activation: bool,
+    /// How many times this hook has activated, for --metrics.
+    activations: u64,
 }
 
 impl Hook {
@@ -284,17 +379,49 @@ impl Hook {
             effects: Vec::new(),
             release_effects: Vec::new(),
             event_dispatcher,
+            last_response: TriggerResponse::None,
+            id: None,
+            enabled: true,
+            suppressed_activation: false,
+            activations: 0,
         }
     }
 
     fn apply(&mut self, mut event: Event, events_out: &mut Vec<Event>, state: &mut State, loopback: &mut LoopbackHandle) {
-        let response = self.trigger.apply(event, loopback);
+        let was_idle = self.mark_withholdable && self.trigger.is_idle_for(&event);
+        let mut response = self.trigger.apply(event, loopback);
+        match response {
+            TriggerResponse::Activates if ! self.enabled => {
+                self.suppressed_activation = true;
+                response = TriggerResponse::Matches;
+            },
+            TriggerResponse::Releases if self.suppressed_activation => {
+                self.suppressed_activation = false;
+                response = TriggerResponse::Matches;
+            },
+            _ => (),
+        }
+        match response {
+            TriggerResponse::None => (),
+            TriggerResponse::Matches => if let TriggerResponse::None = self.last_response {
+                self.last_response = response;
+            },
+            TriggerResponse::Activates | TriggerResponse::Releases => self.last_response = response,
+        }
 
         if self.mark_withholdable {
-            match response {
-                TriggerResponse::Matches | TriggerResponse::Activates | TriggerResponse::Releases
-                    => event.flags.set(EventFlag::Withholdable),
-                TriggerResponse::None => (),
+            let is_withholdable = match response {
+                TriggerResponse::Activates | TriggerResponse::Releases => true,
+                // Axes keep reporting values outside the range of the trackers, e.g. while a stick
+                // rests near its center. Those events do not concern the --withhold, so they are
+                // not marked unless they move some tracker into or out of its range.
+                TriggerResponse::Matches if ! event.ev_type().is_key()
+                    => ! (was_idle && self.trigger.is_idle_for(&event)),
+                TriggerResponse::Matches => true,
+                TriggerResponse::None => false,
+            };
+            if is_withholdable {
+                event.flags.set(EventFlag::Withholdable);
             }
         }
 
@@ -302,6 +429,7 @@ impl Hook {
 
         match response {
             TriggerResponse::Activates => {
+                self.activations += 1;
                 self.apply_effects(state);
             },
             TriggerResponse::Releases => {
@@ -318,11 +446,43 @@ impl Hook {
         state: &mut State,
         loopback: &mut LoopbackHandle,
     ) {
+        self.last_response = TriggerResponse::None;
         for event in events {
             self.apply(*event, events_out, state, loopback);
         }
     }
 
+    pub fn last_response(&self) -> TriggerResponse {
+        self.last_response
+    }
+
+    pub fn trigger(&self) -> &Trigger {
+        &self.trigger
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.trigger.keys().collect()
+    }
+
+    pub fn set_id(&mut self, id: String) {
+        self.id = Some(id);
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn activations(&self) -> u64 {
+        self.activations
+    }
+
+    /// While a hook is disabled, it does not activate. If it was active when it got disabled,
+    /// it still gets released as usual.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
     pub fn apply_to_all_caps(
         &self,
         caps: &[Capability],
@@ -339,7 +499,7 @@ impl Hook {
     /// Runs all effects that should be ran when this hook triggers.
     fn apply_effects(&self, state: &mut State) {
         for effect in &self.effects {
-            effect(state);
+            effect.apply(state);
         }
     }
 
@@ -348,7 +508,7 @@ impl Hook {
     fn apply_release_effects(&self, state: &mut State)
     {
         for release_effect in &self.release_effects {
-            release_effect(state);
+            release_effect.apply(state);
         }
     }
 
@@ -359,11 +519,7 @@ impl Hook {
 
     /// Makes this hook invoke an external subprocess when this hook is triggered.
     pub fn add_command(&mut self, program: String, args: Vec<String>) {
-        self.add_effect(
-            Box::new(move |_| {
-                subprocess::try_spawn(program.clone(), args.clone()).print_err();
-            })
-        );
+        self.add_effect(Effect::RunCommand(program, args));
     }
 }
 
@@ -460,3 +616,36 @@ fn acquire_expiration_token(period: Option<Duration>, loopback: &mut LoopbackHan
         None => ExpirationTime::Never,
     }
 }
+
+#[test]
+fn unittest() {
+    let mut state = State::new();
+    let toggle_1 = state.create_toggle_with_size(3).unwrap();
+    let toggle_2 = state.create_toggle_with_size(2).unwrap();
+
+    Effect::ToggleSet(toggle_1, ToggleShift::Next).apply(&mut state);
+    assert_eq!((state[toggle_1].value(), state[toggle_2].value()), (1, 0));
+    Effect::ToggleSetOthers(vec![toggle_1], ToggleShift::Next).apply(&mut state);
+    assert_eq!((state[toggle_1].value(), state[toggle_2].value()), (1, 1));
+    Effect::ToggleSetOthers(Vec::new(), ToggleShift::ToIndex(2)).apply(&mut state);
+    assert_eq!((state[toggle_1].value(), state[toggle_2].value()), (2, 0));
+
+    // Axis events are only marked withholdable if they move a tracker into or out of its range.
+    use crate::event::{EventCode, EventType, Namespace};
+    let key = crate::key::KeyParser::default_filter().parse("abs:x:200~").unwrap();
+    let trigger = Trigger::new(vec![key], None, false);
+    let mut hook = Hook::new(trigger, EventDispatcher::from_send_keys(Vec::new()), true);
+    let mut loopback = crate::loopback::Loopback::new();
+    let domain = crate::domain::get_unique_domain();
+    let abs_x = EventCode::new(EventType::ABS, 0);
+    let mut is_marked = |value| {
+        let mut events_out = Vec::new();
+        let event = Event::new(abs_x, value, 0, domain, Namespace::User);
+        hook.apply(event, &mut events_out, &mut state, &mut loopback.get_handle_lazy());
+        events_out[0].flags.get(EventFlag::Withholdable)
+    };
+    assert_eq!(
+        [10, 250, 300, 20, 10].iter().map(|&value| is_marked(value)).collect::<Vec<bool>>(),
+        vec![false, true, true, true, false],
+    );
+}
@@ -0,0 +1,51 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashSet;
+
+use crate::event::{Event, Channel};
+use crate::key::Key;
+
+/// Represents a --latch argument: turns momentary keys into latching ones. The first press of
+/// a key presses it, and the next press releases it again. Releases and repeats are dropped.
+pub struct Latch {
+    keys: Vec<Key>,
+
+    /// State: modifiable at runtime.
+    /// The keys that are currently latched down.
+    latched: HashSet<Channel>,
+}
+
+impl Latch {
+    pub fn new(keys: Vec<Key>) -> Latch {
+        Latch { keys, latched: HashSet::new() }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    fn apply(&mut self, mut event: Event, output_events: &mut Vec<Event>) {
+        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return output_events.push(event);
+        }
+        if event.value != 1 {
+            return;
+        }
+
+        if self.latched.remove(&event.channel()) {
+            event.value = 0;
+            event.previous_value = 1;
+        } else {
+            self.latched.insert(event.channel());
+            event.previous_value = 0;
+        }
+        output_events.push(event);
+    }
+}
@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Context, SystemError};
+use crate::event::{Event, EventCode, EventValue, Namespace};
+use crate::io::serialize::{self, TimedEvent};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::stream::hook::{Trigger, TriggerResponse};
+use crate::time::{Duration, Instant};
+
+/// An event of a macro along with the time since the start of the macro at which it happens.
+type MacroEvent = (Duration, EventCode, EventValue);
+
+/// Represents a --macro argument. Records the events that pass by while the record chord is held,
+/// and puts them back into the stream with the same timing when the play chord is pressed.
+pub struct Macro {
+    /// Only events matching these keys get recorded.
+    keys: Vec<Key>,
+    record_trigger: Trigger,
+    play_trigger: Trigger,
+    /// The file in which the macro is stored, so it survives a restart.
+    path: PathBuf,
+    /// If false, the macro is loaded from the file but newly recorded macros are not saved to it.
+    saves: bool,
+
+    /// State: modifiable at runtime.
+    /// The events that shall be replayed when the play chord is pressed.
+    events: Vec<MacroEvent>,
+    /// If Some, the record chord is held. Contains the time at which the first event was recorded
+    /// if any event has been recorded so far, and the events recorded so far.
+    recording: Option<(Option<Instant>, Vec<MacroEvent>)>,
+    /// Events that need to be put back into the stream when the loopback releases a certain token.
+    scheduled_events: Vec<(Token, Vec<Event>)>,
+}
+
+impl Macro {
+    pub fn new(keys: Vec<Key>, record_trigger: Trigger, play_trigger: Trigger, path: PathBuf) -> Macro {
+        let events = match load(&path) {
+            Ok(events) => events,
+            Err(error) => {
+                error.with_context(format!("While loading the macro from {}:", path.display())).print_err();
+                Vec::new()
+            },
+        };
+        Macro {
+            keys, record_trigger, play_trigger, path, events,
+            saves: true,
+            recording: None,
+            scheduled_events: Vec::new(),
+        }
+    }
+
+    /// Keeps newly recorded macros in memory only, instead of also saving them to the file.
+    pub fn disable_saving(&mut self) {
+        self.saves = false;
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let record_response = self.record_trigger.apply(event, loopback);
+        let play_response = self.play_trigger.apply(event, loopback);
+        output_events.push(event);
+
+        match record_response {
+            TriggerResponse::Activates => self.recording = Some((None, Vec::new())),
+            TriggerResponse::Releases => self.finish_recording(),
+            TriggerResponse::Matches => (),
+            // The keys of the chords themselves are never part of the macro.
+            TriggerResponse::None => if let TriggerResponse::None = play_response {
+                self.record(event, loopback);
+            },
+        }
+
+        if let TriggerResponse::Activates = play_response {
+            // Playing a macro while recording it would be too confusing.
+            if self.recording.is_none() {
+                self.play(event, loopback);
+            }
+        }
+    }
+
+    /// Adds an event to the macro if the record chord is held.
+    fn record(&mut self, event: Event, loopback: &mut LoopbackHandle) {
+        let (start_time, recorded_events) = match &mut self.recording {
+            Some(recording) => recording,
+            None => return,
+        };
+        if ! self.keys.iter().any(|key| key.matches(&event)) {
+            return;
+        }
+        let now = loopback.now();
+        let start_time = *start_time.get_or_insert(now);
+        let offset = now.checked_duration_since(start_time)
+            .unwrap_or_else(|| Duration::from_nanos(0));
+        recorded_events.push((offset, event.code, event.value));
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().chain(self.record_trigger.keys()).chain(self.play_trigger.keys()).collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    /// Replaces the macro with the events recorded since the record chord was pressed. If no
+    /// events were recorded, the previous macro is kept, so pressing the record chord by accident
+    /// does not lose it.
+    fn finish_recording(&mut self) {
+        let recorded_events = match self.recording.take() {
+            Some((_, recorded_events)) => recorded_events,
+            None => return,
+        };
+        if recorded_events.is_empty() {
+            return;
+        }
+        self.events = recorded_events;
+        if ! self.saves {
+            return;
+        }
+        if let Err(error) = save(&self.path, &self.events) {
+            error.with_context(format!("While saving the macro to {}:", self.path.display())).print_err();
+        }
+    }
+
+    /// Schedules all events of the macro to be put back into the stream. The events take the
+    /// domain of the event that activated the play chord.
+    fn play(&mut self, activating_event: Event, loopback: &mut LoopbackHandle) {
+        let mut previous_values: HashMap<EventCode, EventValue> = HashMap::new();
+        let mut current_batch: Option<(Duration, Vec<Event>)> = None;
+
+        for &(offset, code, value) in &self.events {
+            let previous_value = previous_values.insert(code, value).unwrap_or(0);
+            let event = Event::new(code, value, previous_value, activating_event.domain, Namespace::User);
+            match &mut current_batch {
+                Some((batch_offset, batch)) if *batch_offset == offset => batch.push(event),
+                _ => {
+                    if let Some((batch_offset, batch)) = current_batch.take() {
+                        let token = loopback.schedule_wakeup_in(batch_offset);
+                        self.scheduled_events.push((token, batch));
+                    }
+                    current_batch = Some((offset, vec![event]));
+                },
+            }
+        }
+
+        if let Some((batch_offset, batch)) = current_batch {
+            let token = loopback.schedule_wakeup_in(batch_offset);
+            self.scheduled_events.push((token, batch));
+        }
+    }
+
+    /// Puts the events of the macro that are due back into the stream.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        // The macro may have been played several times in quick succession, so the tokens
+        // are not necessarily released in the order they were scheduled.
+        if let Some(index) = self.scheduled_events.iter().position(|(scheduled_token, _)| scheduled_token == token) {
+            let (_, events) = self.scheduled_events.remove(index);
+            output_events.extend(events);
+        }
+    }
+}
+
+/// Reads a macro from a file in the format described in the serialize module. Returns an empty
+/// macro if the file does not exist.
+fn load(path: &Path) -> Result<Vec<MacroEvent>, SystemError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let chunks = bytes.chunks_exact(serialize::EVENT_SIZE);
+    if ! chunks.remainder().is_empty() {
+        return Err(SystemError::new("The file is not a valid macro because its size is not a multiple of the size of an event."));
+    }
+
+    let mut events = Vec::new();
+    for chunk in chunks {
+        let event = serialize::decode_event(chunk)?;
+        if event.code.ev_type().is_syn() {
+            continue;
+        }
+        let offset = Duration::from_micros(std::cmp::max(event.time_us, 0) as u64);
+        events.push((offset, event.code, event.value));
+    }
+    Ok(events)
+}
+
+/// Writes a macro to a file. The timestamps of the events are the amount of microseconds since
+/// the start of the macro.
+fn save(path: &Path, events: &[MacroEvent]) -> Result<(), SystemError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for &(offset, code, value) in events {
+        let time_us = offset.as_micros() as i64;
+        bytes.extend_from_slice(&serialize::encode_event(TimedEvent { time_us, code, value }));
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
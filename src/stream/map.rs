@@ -1,11 +1,16 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use std::collections::HashMap;
+
 use crate::key::Key;
-use crate::event::{Event, Namespace};
+use crate::event::{Event, EventCode, EventValue, Namespace};
 use crate::domain::Domain;
 use crate::capability::{Capability, CapMatch};
+use crate::range::Range;
 use crate::error::InternalError;
 use crate::state::{State, ToggleIndex};
+use crate::loopback::{LoopbackHandle, Token};
+use crate::time::Duration;
 
 #[derive(Clone, Debug)]
 pub struct Map {
@@ -46,6 +51,11 @@ impl Map {
         output_events.extend(generated_events);
     }
 
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        vec![&self.input_key]
+    }
+
     /// Maps all events to output_events. Events that do not match this Map are mapped to themselfe.
     /// Preserves the order of the events.
     pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>) {
@@ -87,6 +97,88 @@ impl Map {
     }
 }
 
+/// Represents a --map table=FILE argument. Holds many maps that are applied simultaneously:
+/// each event is mapped by at most one of them, and the events they generate are not
+/// considered by the other maps of the same table. This makes it possible to e.g. swap two keys.
+///
+/// For efficiency, all maps are indexed by the event code of their input key.
+pub struct MapTable {
+    maps: HashMap<EventCode, Vec<Map>>,
+}
+
+impl MapTable {
+    /// Every map's input key must require a specific event code.
+    pub fn new(maps: Vec<Map>) -> Result<MapTable, InternalError> {
+        let mut indexed_maps: HashMap<EventCode, Vec<Map>> = HashMap::new();
+        for map in maps {
+            let code = map.input_key.requires_event_code().ok_or_else(||
+                InternalError::new("Every input key in a map table must have an event code.")
+            )?;
+            indexed_maps.entry(code).or_default().push(map);
+        }
+        Ok(MapTable { maps: indexed_maps })
+    }
+
+    /// The event codes that some map of this table may act on.
+    pub fn input_codes(&self) -> impl Iterator<Item=EventCode> + '_ {
+        self.maps.keys().copied()
+    }
+
+    fn apply(&self, event: Event, output_events: &mut Vec<Event>) {
+        let candidates = match self.maps.get(&event.code) {
+            Some(candidates) => candidates,
+            None => {
+                output_events.push(event);
+                return;
+            },
+        };
+        match candidates.iter().find(|map| map.input_key.matches(&event)) {
+            Some(map) => output_events.extend(
+                map.output_keys.iter().map(|key| key.merge(event))
+            ),
+            None => output_events.push(event),
+        }
+    }
+
+    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    fn apply_cap(&self, cap: Capability, output_caps: &mut Vec<Capability>) {
+        let candidates = match self.maps.get(&cap.code) {
+            Some(candidates) => candidates,
+            None => {
+                output_caps.push(cap);
+                return;
+            },
+        };
+
+        // Only the first matching map applies to any event, so we stop after the first map
+        // that is guaranteed to match.
+        for map in candidates {
+            match map.input_key.matches_cap(&cap) {
+                CapMatch::Yes => {
+                    output_caps.extend(map.output_keys.iter().map(|key| key.merge_cap(cap)));
+                    return;
+                },
+                CapMatch::Maybe => {
+                    output_caps.extend(map.output_keys.iter().map(|key| key.merge_cap(cap)));
+                },
+                CapMatch::No => {},
+            }
+        }
+        output_caps.push(cap);
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            self.apply_cap(cap, output_caps);
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ToggleMode {
     Passive,
@@ -99,6 +191,22 @@ pub struct Toggle {
     output_keys: Vec<Key>,
     pub mode: ToggleMode,
     pub state_index: ToggleIndex,
+    /// If Some, the toggle returns to its first state after this much time passes without
+    /// any event matching its input key.
+    timeout: Option<Duration>,
+
+    /// LEDs that shall be turned on while a certain state (zero-indexed) is active, and off otherwise.
+    leds: Vec<(usize, Key)>,
+    /// The domain of the generated LED events, unless the LED's key specifies another domain.
+    led_domain: Domain,
+
+    /// State: modifiable at runtime.
+    /// The token at which this toggle times out, if a timeout is pending.
+    timeout_token: Option<Token>,
+    /// The state that was active when the timeout was last updated.
+    last_timeout_state: Option<usize>,
+    /// The state that was active when the LED events were last generated.
+    last_led_state: Option<usize>,
 }
 
 impl Toggle {
@@ -121,7 +229,25 @@ impl Toggle {
             },
         };
         
-        Ok(Toggle { input_key, output_keys, mode, state_index })
+        Ok(Toggle {
+            input_key, output_keys, mode, state_index,
+            timeout: None, timeout_token: None, last_timeout_state: None,
+            leds: Vec::new(), led_domain: crate::domain::get_unique_domain(), last_led_state: None,
+        })
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        vec![&self.input_key]
+    }
+
+    /// Each LED key must require an event code.
+    pub fn set_leds(&mut self, leds: Vec<(usize, Key)>) {
+        self.leds = leds;
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
     }
 
     /// Returns the active output key. Specific events may use a different active output key
@@ -173,14 +299,145 @@ impl Toggle {
     }
 
     /// The apply_ functions are analogous to the Map::apply_ equivalents.
-    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>, state: &mut State) {
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, state: &mut State, loopback: &mut LoopbackHandle) {
         for &event in events {
             self.apply(event, output_events, state);
         }
+        if let Some(timeout) = self.timeout {
+            self.update_timeout(events, timeout, state, loopback);
+        }
+        self.update_leds(output_events, state);
+    }
+
+    /// If the active state changed since the last time this function was called, generates
+    /// events that turn off the LEDs of the previous state and turn on those of the new state.
+    fn update_leds(&mut self, output_events: &mut Vec<Event>, state: &State) {
+        if self.leds.is_empty() {
+            return;
+        }
+        let active_state = state[self.state_index].value();
+        let last_led_state = self.last_led_state;
+        if last_led_state == Some(active_state) {
+            return;
+        }
+        self.last_led_state = Some(active_state);
+
+        for (led_state, key) in &self.leds {
+            let code = match key.requires_event_code() {
+                Some(code) => code,
+                None => continue,
+            };
+            let value = (*led_state == active_state) as EventValue;
+            let previous_value = (last_led_state == Some(*led_state)) as EventValue;
+            if last_led_state.is_some() && value == previous_value {
+                continue;
+            }
+            let event = Event::new(code, value, previous_value, self.led_domain, Namespace::User);
+            output_events.push(key.merge(event));
+        }
+    }
+
+    /// Restarts the timeout whenever an event matches this toggle or the active state changes,
+    /// including changes made by something else, such as a --hook or a control command.
+    fn update_timeout(&mut self, events: &[Event], timeout: Duration, state: &State, loopback: &mut LoopbackHandle) {
+        let active_state = state[self.state_index].value();
+        let state_changed = self.last_timeout_state != Some(active_state);
+        self.last_timeout_state = Some(active_state);
+        if active_state == 0 {
+            if let Some(token) = self.timeout_token.take() {
+                loopback.cancel_token(token);
+            }
+            return;
+        }
+
+        let is_active = events.iter().any(|event| self.input_key.matches(event));
+        if is_active || state_changed || self.timeout_token.is_none() {
+            if let Some(token) = self.timeout_token.take() {
+                loopback.cancel_token(token);
+            }
+            self.timeout_token = Some(loopback.schedule_wakeup_in(timeout));
+        }
+    }
+
+    /// Catches up with changes to the active state that were not made by events passing this
+    /// toggle, such as those made by hooks and control commands. Also generates the LED events
+    /// for the initial state the first time it is called.
+    pub fn sync(&mut self, output_events: &mut Vec<Event>, state: &State, loopback: &mut LoopbackHandle) {
+        if let Some(timeout) = self.timeout {
+            self.update_timeout(&[], timeout, state, loopback);
+        }
+        self.update_leds(output_events, state);
+    }
+
+    /// Returns the toggle to its first state if the token belongs to this toggle's timeout.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>, state: &mut State) {
+        if self.timeout_token.as_ref() == Some(token) {
+            self.timeout_token = None;
+            state[self.state_index].set_value_wrapped(0);
+            self.update_leds(output_events, state);
+        }
     }
 
     pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
         let self_as_map = Map::new(self.input_key.clone(), self.output_keys.clone());
         self_as_map.apply_to_all_caps(caps, output_caps);
+
+        for (_led_state, key) in &self.leds {
+            if let Some(code) = key.requires_event_code() {
+                output_caps.push(key.merge_cap(Capability {
+                    code,
+                    domain: self.led_domain,
+                    namespace: Namespace::User,
+                    value_range: Range::new(Some(0), Some(1)),
+                    abs_meta: None,
+                }));
+            }
+        }
     }
 }
+
+#[test]
+fn unittest() {
+    use crate::key::KeyParser;
+    use crate::loopback::Loopback;
+    use crate::time::Instant;
+
+    let filter = KeyParser::default_filter();
+    let mask = KeyParser::default_mask();
+    let mut state = State::new();
+    let mut toggle = Toggle::new(
+        filter.parse("key:a").unwrap(),
+        vec![mask.parse("key:b").unwrap(), mask.parse("key:c").unwrap()],
+        ToggleMode::Consistent, &mut state, None,
+    ).unwrap();
+    toggle.set_leds(vec![(0, mask.parse("led:numl").unwrap()), (1, mask.parse("led:capsl").unwrap())]);
+    toggle.set_timeout(Duration::from_secs(5));
+    let numl = EventCode::new(crate::event::EventType::LED, 0);
+    let capsl = EventCode::new(crate::event::EventType::LED, 1);
+    let mut loopback = Loopback::new();
+    // Starting in the past makes the timeout due right away.
+    let start = Instant::from(libc::timespec { tv_sec: 0, tv_nsec: 0 });
+    let mut output = Vec::new();
+    let leds = |output: &Vec<Event>| output.iter().map(|event| (event.code, event.value)).collect::<Vec<_>>();
+
+    // The LEDs of the initial state are turned on at startup, without any event passing.
+    toggle.sync(&mut output, &state, &mut loopback.get_handle(start));
+    assert_eq!(leds(&output), vec![(numl, 1), (capsl, 0)]);
+    assert!(loopback.next_wakeup().is_none());
+    output.clear();
+    toggle.sync(&mut output, &state, &mut loopback.get_handle(start));
+    assert!(output.is_empty());
+
+    // Changes made by hooks or control commands update the LEDs and start the timeout.
+    state[toggle.state_index].advance();
+    toggle.sync(&mut output, &state, &mut loopback.get_handle(start));
+    assert_eq!(leds(&output), vec![(numl, 0), (capsl, 1)]);
+    assert!(loopback.next_wakeup() == Some(start + Duration::from_secs(5)));
+    output.clear();
+
+    // Once the timeout expires, the toggle returns to its first state.
+    let (_, token) = loopback.poll_once().unwrap();
+    toggle.wakeup(&token, &mut output, &mut state);
+    assert_eq!(state[toggle.state_index].value(), 0);
+    assert_eq!(leds(&output), vec![(numl, 1), (capsl, 0)]);
+}
@@ -3,31 +3,56 @@
 use std::collections::HashMap;
 
 use crate::key::Key;
-use crate::event::{Event, Channel};
+use crate::domain::Domain;
+use crate::event::{Event, EventCode, EventType};
+
+/// Which events with the same event code are merged with each other.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MergeScope {
+    /// Events with the same domain.
+    Domain,
+    /// Events that were generated by the same input device, regardless of their current domain.
+    Device,
+    /// All events, regardless of their domain.
+    Global,
+}
+
+impl MergeScope {
+    fn channel(self, event: &Event) -> (EventCode, Option<Domain>) {
+        match self {
+            MergeScope::Domain => (event.code, Some(event.domain)),
+            MergeScope::Device => (event.code, Some(event.origin)),
+            MergeScope::Global => (event.code, None),
+        }
+    }
+}
 
 /// Represents a --merge argument.
 pub struct Merge {
     /// The keys that are subject to getting merged by this argument.
     keys: Vec<Key>,
+    /// Only events of these types get merged. Each of them must only have the values 0 and 1.
+    types: Vec<EventType>,
+    scope: MergeScope,
     
-    /// How many down events each (type, code, domain) pair has.
-    state: HashMap<Channel, usize>,
+    /// How many down events each channel has, where the scope decides what a channel is.
+    state: HashMap<(EventCode, Option<Domain>), usize>,
 }
 
 impl Merge {
-    pub fn new(keys: Vec<Key>) -> Merge {
-        Merge { keys, state: HashMap::new() }
+    pub fn new(keys: Vec<Key>, types: Vec<EventType>, scope: MergeScope) -> Merge {
+        Merge { keys, types, scope, state: HashMap::new() }
     }
 
     #[allow(clippy::needless_return)]
     fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
         // If this merge is not applicable to this event, silently pass it on.
-        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+        if ! self.types.contains(&event.ev_type()) || ! self.keys.iter().any(|key| key.matches(&event)) {
             output_events.push(event);
             return;
         }
 
-        let current_down_count: &mut usize = self.state.entry(event.channel()).or_insert(0);
+        let current_down_count: &mut usize = self.state.entry(self.scope.channel(&event)).or_insert(0);
         let last_down_count: usize = *current_down_count;
         match event.value {
             // If this is a KEY_DOWN (1) event, add one to the down count.
@@ -51,6 +76,11 @@ impl Merge {
         }
     }
 
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
     pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
         for &event in events {
             self.apply(event, output_events);
@@ -1,43 +1,273 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::key::Key;
-use crate::event::{Event, EventType};
+use crate::event::{Event, EventCode, EventType, EventValue};
+use crate::loopback::{LoopbackHandle, Token};
+use crate::time::Duration;
+use std::collections::HashMap;
 use crate::ecodes;
-use crate::domain;
+use crate::domain::{self, Domain};
+use crate::io::logfile::LogFile;
+use crate::io::serialize::{current_time_us, quote_json};
 
+#[derive(Clone, PartialEq, Eq)]
 pub enum EventPrintMode {
     Detailed,
     Direct,
+    /// One JSON object per line, for consumption by other programs.
+    Json,
+    /// A format string provided by the user, like "{name}={value}".
+    Template(Vec<TemplatePart>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TemplatePart {
+    Literal(String),
+    Field(TemplateField),
+}
+
+/// The placeholders that can be used in format strings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TemplateField {
+    /// The type and code, like key:a.
+    Name,
+    Type,
+    Code,
+    /// The numeric value of the code.
+    CodeNum,
+    Value,
+    PreviousValue,
+    /// The difference between the value and the previous value.
+    Delta,
+    Domain,
+    Origin,
+    Time,
+}
+
+impl TemplateField {
+    pub fn from_name(name: &str) -> Option<TemplateField> {
+        Some(match name {
+            "name" => TemplateField::Name,
+            "type" => TemplateField::Type,
+            "code" => TemplateField::Code,
+            "code_num" => TemplateField::CodeNum,
+            "value" => TemplateField::Value,
+            "previous_value" => TemplateField::PreviousValue,
+            "delta" => TemplateField::Delta,
+            "domain" => TemplateField::Domain,
+            "origin" => TemplateField::Origin,
+            "time" => TemplateField::Time,
+            _ => return None,
+        })
+    }
+}
+
+/// Which time to print alongside each event, as set by the timestamp= clause.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TimestampMode {
+    None,
+    /// Seconds since the --print argument was created.
+    Relative,
+    /// Seconds since the epoch.
+    Absolute,
+    /// The current UTC date and time, like 2024-01-31T12:34:56.123456Z.
+    Iso8601,
+}
+
+/// Whether printed events shall be colored, as set by the color= clause.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Never,
+    Always,
+    /// Only when printing to a terminal.
+    Auto,
+}
+
+/// Which events get printed, as set by the mode= clause.
+pub enum PrintSelection {
+    /// Every event.
+    All,
+    /// Only events whose value differs from the previous event with the same code and domain.
+    Changes,
+    /// A digest of all events once per interval.
+    Summary(Duration),
 }
 
 /// Created by --print arguments.
 pub struct EventPrinter {
     keys: Vec<Key>,
     mode: EventPrintMode,
+    /// If Some, events are written to this file instead of stdout.
+    log_file: Option<LogFile>,
+    /// If Some, a digest of the events is printed once per interval instead of every event.
+    summary: Option<Summary>,
+    /// If Some, only events whose value differs from the last printed value are printed.
+    last_values: Option<HashMap<(EventCode, Domain), EventValue>>,
+    timestamp: TimestampMode,
+    /// Whether the events shall be printed with ANSI color codes.
+    color: bool,
+    /// Whether the difference from the previous value shall be shown for absolute axes.
+    delta: bool,
+    /// The time at which this printer was created, in microseconds since the epoch.
+    start_time_us: i64,
+}
+
+/// Keeps track of the events seen during the current interval for mode=summary.
+struct Summary {
+    interval: Duration,
+    /// The statistics of each event code, in the order in which the codes were first seen.
+    stats: Vec<(EventCode, SummaryStats)>,
+    /// The wakeup at which the current interval ends, if any events were seen during it.
+    token: Option<Token>,
+}
+
+struct SummaryStats {
+    count: u64,
+    min: EventValue,
+    max: EventValue,
 }
 
 impl EventPrinter {
-    pub fn new(keys: Vec<Key>, mode: EventPrintMode) -> EventPrinter {
-        EventPrinter { keys, mode }
+    pub fn new(keys: Vec<Key>, mode: EventPrintMode, log_file: Option<LogFile>, selection: PrintSelection, timestamp: TimestampMode, color: ColorMode, delta: bool) -> EventPrinter {
+        let color = match color {
+            ColorMode::Never => false,
+            ColorMode::Always => true,
+            ColorMode::Auto => log_file.is_none() && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1,
+        };
+        EventPrinter {
+            color, delta,
+            keys, mode, log_file, timestamp,
+            start_time_us: current_time_us(),
+            summary: match selection {
+                PrintSelection::Summary(interval) => Some(Summary {
+                    interval,
+                    stats: Vec::new(),
+                    token: None,
+                }),
+                _ => None,
+            },
+            last_values: match selection {
+                PrintSelection::Changes => Some(HashMap::new()),
+                _ => None,
+            },
+        }
     }
 
-    fn apply(&self, event: Event) {
-        if self.keys.iter().any(|key| key.matches(&event)) {
-            println!("{}", match self.mode {
-                EventPrintMode::Direct => print_event_direct(event),
-                EventPrintMode::Detailed => print_event_detailed(event),
-            });
+    fn apply(&mut self, event: Event, loopback: &mut LoopbackHandle) {
+        if ! self.keys.iter().any(|key| key.matches(&event)) {
+            return;
+        }
+        if let Some(summary) = &mut self.summary {
+            match summary.stats.iter_mut().find(|(code, _)| *code == event.code) {
+                Some((_, stats)) => {
+                    stats.count += 1;
+                    stats.min = stats.min.min(event.value);
+                    stats.max = stats.max.max(event.value);
+                },
+                None => summary.stats.push((event.code, SummaryStats {
+                    count: 1, min: event.value, max: event.value,
+                })),
+            }
+            if summary.token.is_none() {
+                summary.token = Some(loopback.schedule_wakeup_in(summary.interval));
+            }
+            return;
+        }
+        if let Some(last_values) = &mut self.last_values {
+            if last_values.insert((event.code, event.domain), event.value) == Some(event.value) {
+                return;
+            }
         }
+        let line = match &self.mode {
+            EventPrintMode::Direct => print_event_direct(event),
+            EventPrintMode::Detailed => print_event_detailed(event, self.color, self.delta),
+            EventPrintMode::Json => print_event_json(event, self.json_time(), self.delta),
+            EventPrintMode::Template(template) => print_event_template(event, template, self.time()),
+        };
+        self.write_line(line);
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
     }
 
-    pub fn apply_to_all(&self, events: &[Event]) {
+    /// Returns true if the events are written to a file= instead of stdout.
+    pub fn writes_to_file(&self) -> bool {
+        self.log_file.is_some()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], loopback: &mut LoopbackHandle) {
         for &event in events {
-            self.apply(event);
+            self.apply(event, loopback);
         }
     }
+
+    /// Prints the digest of the events seen during the interval that just ended.
+    pub fn wakeup(&mut self, token: &Token) {
+        let summary = match &mut self.summary {
+            Some(summary) if summary.token.as_ref() == Some(token) => summary,
+            _ => return,
+        };
+        summary.token = None;
+        let stats = std::mem::take(&mut summary.stats);
+        let line = match self.mode {
+            EventPrintMode::Json => print_summary_json(summary.interval, &stats, self.json_time()),
+            _ => print_summary(summary.interval, &stats),
+        };
+        self.write_line(line);
+    }
+
+    /// Returns the current time formatted according to the timestamp mode, if any.
+    fn time(&self) -> Option<String> {
+        let time_us = current_time_us();
+        match self.timestamp {
+            TimestampMode::None => None,
+            TimestampMode::Relative => Some(format_seconds(time_us - self.start_time_us)),
+            TimestampMode::Absolute => Some(format_seconds(time_us)),
+            TimestampMode::Iso8601 => Some(format_iso8601(time_us)),
+        }
+    }
+
+    /// Returns the current time as JSON value, if any.
+    fn json_time(&self) -> Option<String> {
+        match self.timestamp {
+            TimestampMode::Iso8601 => self.time().map(|time| quote_json(&time)),
+            _ => self.time(),
+        }
+    }
+
+    fn write_line(&mut self, mut line: String) {
+        // JSON objects and format strings include the time by themselves.
+        if self.mode == EventPrintMode::Detailed || self.mode == EventPrintMode::Direct {
+            if let Some(time) = self.time() {
+                line = format!("{}  {}", time, line);
+            }
+        }
+        match &mut self.log_file {
+            Some(log_file) => log_file.write_line(&line),
+            None => println!("{}", line),
+        }
+    }
+
+    /// Reopens the file this printer writes to. Returns false if it does not write to a file.
+    pub fn reopen_file(&mut self) -> bool {
+        match &mut self.log_file {
+            Some(log_file) => {
+                log_file.reopen();
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// The difference between the value of an event and the previous value of the same event code.
+fn delta(event: Event) -> i64 {
+    i64::from(event.value) - i64::from(event.previous_value)
 }
 
-pub fn print_event_detailed(event: Event) -> String {
+pub fn print_event_detailed(event: Event, color: bool, show_delta: bool) -> String {
     let name = ecodes::event_name(event.code);
     let value_str = match event.ev_type() {
         EventType::KEY => match event.value {
@@ -46,17 +276,73 @@ pub fn print_event_detailed(event: Event) -> String {
             2 => "2 (repeat)".to_string(),
             _ => format!("{}", event.value),
         },
+        EventType::ABS if show_delta => format!("{} ({:+})", event.value, delta(event)),
         _ => format!("{}", event.value),
     };
-    let name_and_value = format!("Event:  type:code = {:<13}  value = {}", name, value_str);
+    // The padding is computed from the uncolored text, so colors do not misalign the columns.
+    let name_and_value_len = "Event:  type:code = ".len() + name.len().max(13) + "  value = ".len() + value_str.len();
+    let name_and_value = format!(
+        "Event:  type:code = {}{}  value = {}",
+        paint(&name, type_color(event.ev_type()), color), padding(name.len(), 13),
+        paint(&value_str, value_color(event), color),
+    );
 
-    if let Some(domain_name) = domain::try_reverse_resolve(event.domain) {
-        format!("{:<53}  domain = {}", name_and_value, domain_name)
-    } else {
-        name_and_value
+    let domain_name = domain::try_reverse_resolve(event.domain);
+    let mut result = match &domain_name {
+        Some(domain_name) => format!(
+            "{}{}  domain = {}", name_and_value, padding(name_and_value_len, 53), paint(domain_name, DIM, color),
+        ),
+        None => name_and_value,
+    };
+    // Mention which device generated this event if maps have changed its domain since.
+    if let Some(origin_name) = domain::try_reverse_resolve_origin(event.origin) {
+        if domain_name.as_ref() != Some(&origin_name) {
+            if domain_name.is_none() {
+                result = format!("{}{}", result, padding(name_and_value_len, 53));
+            }
+            result = format!("{}  origin = {}", result, paint(&origin_name, DIM, color));
+        }
+    }
+    result
+}
+
+const DIM: &str = "2";
+
+/// The ANSI color code that the names of events of a certain type are printed with.
+fn type_color(ev_type: EventType) -> &'static str {
+    match ev_type {
+        EventType::KEY => "1;36",
+        EventType::REL => "35",
+        EventType::ABS => "34",
+        EventType::SYN => DIM,
+        _ => "37",
+    }
+}
+
+/// The ANSI color code that the value of an event is printed with, which tells presses, releases
+/// and repeats apart.
+fn value_color(event: Event) -> &'static str {
+    match (event.ev_type(), event.value) {
+        (EventType::KEY, 0) => "31",
+        (EventType::KEY, 1) => "1;32",
+        (EventType::KEY, 2) => "33",
+        _ => "0",
+    }
+}
+
+/// Surrounds the text with ANSI escape codes that give it the provided color, if color is enabled.
+fn paint(text: &str, color_code: &str, color: bool) -> String {
+    match color {
+        true => format!("\x1b[{}m{}\x1b[0m", color_code, text),
+        false => text.to_string(),
     }
 }
 
+/// The spaces needed to pad text of a certain length to a certain width.
+fn padding(len: usize, width: usize) -> String {
+    " ".repeat(width.saturating_sub(len))
+}
+
 pub fn print_event_direct(event: Event) -> String {
     let name = ecodes::event_name(event.code);
     if let Some(domain_name) = domain::try_reverse_resolve(event.domain) {
@@ -64,4 +350,129 @@ pub fn print_event_direct(event: Event) -> String {
     } else {
         format!("{}:{}", name, event.value)
     }
-}
\ No newline at end of file
+}
+
+pub fn print_event_template(event: Event, template: &[TemplatePart], time: Option<String>) -> String {
+    let name = ecodes::event_name(event.code);
+    let mut result = String::new();
+    for part in template {
+        match part {
+            TemplatePart::Literal(text) => result.push_str(text),
+            TemplatePart::Field(field) => match field {
+                TemplateField::Name => result.push_str(&name),
+                TemplateField::Type => result.push_str(&ecodes::type_name(event.ev_type())),
+                TemplateField::Code => result.push_str(crate::utils::split_once(&name, ":").1.unwrap_or("")),
+                TemplateField::CodeNum => result.push_str(&event.code.code().to_string()),
+                TemplateField::Value => result.push_str(&event.value.to_string()),
+                TemplateField::PreviousValue => result.push_str(&event.previous_value.to_string()),
+                TemplateField::Delta => result.push_str(&format!("{:+}", delta(event))),
+                TemplateField::Domain => if let Some(domain_name) = domain::try_reverse_resolve(event.domain) {
+                    result.push_str(&domain_name);
+                },
+                TemplateField::Origin => if let Some(origin_name) = domain::try_reverse_resolve_origin(event.origin) {
+                    result.push_str(&origin_name);
+                },
+                TemplateField::Time => if let Some(time) = &time {
+                    result.push_str(time);
+                },
+            },
+        }
+    }
+    result
+}
+
+/// Formats the time as a "time" field followed by a comma, or nothing if there is no time.
+fn json_time_field(time: Option<String>) -> String {
+    match time {
+        Some(time) => format!("\"time\":{},", time),
+        None => String::new(),
+    }
+}
+
+/// Formats an amount of microseconds as seconds with six decimals.
+fn format_seconds(time_us: i64) -> String {
+    format!("{}.{:06}", time_us.div_euclid(1_000_000), time_us.rem_euclid(1_000_000))
+}
+
+/// Formats an amount of microseconds since the epoch as UTC date and time in ISO 8601 format.
+fn format_iso8601(time_us: i64) -> String {
+    let seconds = time_us.div_euclid(1_000_000) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::gmtime_r(&seconds, &mut tm) };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec,
+        time_us.rem_euclid(1_000_000),
+    )
+}
+
+pub fn print_event_json(event: Event, time: Option<String>, show_delta: bool) -> String {
+    let name = ecodes::event_name(event.code);
+    let code_name = match crate::utils::split_once(&name, ":") {
+        (_, Some(code_name)) => code_name,
+        (_, None) => "",
+    };
+    let domain_str = match domain::try_reverse_resolve(event.domain) {
+        Some(domain_name) => quote_json(&domain_name),
+        None => "null".to_string(),
+    };
+    let delta_field = match event.ev_type() {
+        EventType::ABS if show_delta => format!(",\"delta\":{}", delta(event)),
+        _ => String::new(),
+    };
+    format!(
+        "{{{}\"type\":{},\"code\":{},\"code_num\":{},\"value\":{}{},\"domain\":{}}}",
+        json_time_field(time),
+        quote_json(&ecodes::type_name(event.ev_type())), quote_json(code_name), event.code.code(), event.value, delta_field, domain_str,
+    )
+}
+
+fn format_interval(interval: Duration) -> String {
+    let millis = interval.as_millis();
+    format!("{}.{:03}", millis / 1000, millis % 1000)
+}
+
+fn print_summary(interval: Duration, stats: &[(EventCode, SummaryStats)]) -> String {
+    let mut result = format!("Summary of {}s:", format_interval(interval));
+    for (code, stats) in stats {
+        result.push_str(&format!(
+            "  {}  count = {}  min = {}  max = {};",
+            ecodes::event_name(*code), stats.count, stats.min, stats.max,
+        ));
+    }
+    result.pop();
+    result
+}
+
+fn print_summary_json(interval: Duration, stats: &[(EventCode, SummaryStats)], time: Option<String>) -> String {
+    let entries: Vec<String> = stats.iter().map(|(code, stats)| {
+        let name = ecodes::event_name(*code);
+        let code_name = match crate::utils::split_once(&name, ":") {
+            (_, Some(code_name)) => code_name,
+            (_, None) => "",
+        };
+        format!(
+            "{{\"type\":{},\"code\":{},\"code_num\":{},\"count\":{},\"min\":{},\"max\":{}}}",
+            quote_json(&ecodes::type_name(code.ev_type())), quote_json(code_name), code.code(),
+            stats.count, stats.min, stats.max,
+        )
+    }).collect();
+    format!(
+        "{{{}\"interval\":{},\"events\":[{}]}}",
+        json_time_field(time),
+        format_interval(interval), entries.join(","),
+    )
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(format_seconds(12_000_345), "12.000345");
+    assert_eq!(format_seconds(-1), "-1.999999");
+    assert_eq!(format_iso8601(0), "1970-01-01T00:00:00.000000Z");
+    assert_eq!(format_iso8601(1_700_000_000_123_456), "2023-11-14T22:13:20.123456Z");
+
+    assert_eq!(padding(5, 13).len(), 8);
+    assert_eq!(padding(20, 13), "");
+    assert_eq!(paint("key:a", "31", false), "key:a");
+    assert_eq!(paint("key:a", "31", true), "\x1b[31mkey:a\x1b[0m");
+}
@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::{Capability, CapMatch};
+use crate::event::{Event, Channel};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::Range;
+use crate::time::Duration;
+
+/// Represents a --repeat argument. Generates value-2 repeat events for held-down keys
+/// that match one of its keys, similar to how the kernel would generate them for a real
+/// keyboard.
+pub struct Repeat {
+    keys: Vec<Key>,
+    /// The time between a key being pressed and the first repeat event.
+    delay: Duration,
+    /// The time between two consecutive repeat events.
+    period: Duration,
+
+    /// State: modifiable at runtime.
+    /// For each channel that is currently held down, the last event that was sent over it
+    /// and the token at which the next repeat event shall be generated.
+    held_channels: Vec<(Channel, Token, Event)>,
+}
+
+impl Repeat {
+    pub fn new(keys: Vec<Key>, delay: Duration, period: Duration) -> Repeat {
+        Repeat {
+            keys, delay, period,
+            held_channels: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            output_events.push(event);
+            return;
+        }
+
+        match event.value {
+            // Repeat events on channels we generate repeat events for are redundant: we
+            // generate our own repeat events at our own rate instead.
+            2 => return,
+            0 => self.release(event.channel(), loopback),
+            _ => {
+                self.release(event.channel(), loopback);
+                let token = loopback.schedule_wakeup_in(self.delay);
+                self.held_channels.push((event.channel(), token, event));
+            }
+        }
+
+        output_events.push(event);
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    /// Stops generating repeat events for a certain channel.
+    fn release(&mut self, channel: Channel, loopback: &mut LoopbackHandle) {
+        let mut index = 0;
+        while index < self.held_channels.len() {
+            if self.held_channels[index].0 == channel {
+                let (_, token, _) = self.held_channels.remove(index);
+                loopback.cancel_token(token);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Generates a repeat event if the token belongs to one of the held channels, and
+    /// schedules the next repeat event.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for (_channel, held_token, last_event) in &mut self.held_channels {
+            if held_token != token {
+                continue;
+            }
+
+            let mut repeat_event = *last_event;
+            repeat_event.previous_value = last_event.value;
+            repeat_event.value = 2;
+            output_events.push(repeat_event);
+
+            *last_event = repeat_event;
+            *held_token = loopback.schedule_wakeup_in(self.period);
+        }
+    }
+
+    /// Keys that match this --repeat may additionally get value 2.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            output_caps.push(cap);
+            if ! cap.code.ev_type().is_key() {
+                continue;
+            }
+            let matches_cap = self.keys.iter().map(|key| key.matches_cap(&cap)).max();
+            match matches_cap {
+                Some(CapMatch::Yes) | Some(CapMatch::Maybe) => {
+                    let mut repeat_cap = cap;
+                    repeat_cap.value_range = Range::new(Some(2), Some(2));
+                    output_caps.push(repeat_cap);
+                },
+                Some(CapMatch::No) | None => {},
+            }
+        }
+    }
+}
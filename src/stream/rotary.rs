@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::capability::{Capability, CapMatch};
+use crate::event::{Event, EventValue, Channel};
+use crate::key::Key;
+use crate::range::Range;
+
+/// Represents a --rotary argument. Turns changes of an absolute axis, such as the position
+/// of a jog dial, into relative events like scroll wheel clicks.
+pub struct Rotary {
+    input_key: Key,
+    output_key: Key,
+    /// How many units the absolute axis needs to move to generate a single relative unit.
+    step: EventValue,
+    /// If Some, the axis rolls over from its maximum to its minimum value and vice versa.
+    wrap: Option<(EventValue, EventValue)>,
+
+    /// State: modifiable at runtime.
+    /// For each channel, the last seen absolute value and the amount of units that were
+    /// moved but have not yet added up to a full step.
+    state: HashMap<Channel, (EventValue, EventValue)>,
+}
+
+impl Rotary {
+    pub fn new(input_key: Key, output_key: Key, step: EventValue, wrap: Option<(EventValue, EventValue)>) -> Rotary {
+        Rotary {
+            input_key, output_key, step, wrap,
+            state: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        if ! self.input_key.matches(&event) {
+            output_events.push(event);
+            return;
+        }
+
+        let (last_value, remainder) = self.state.entry(event.channel())
+            .or_insert((event.previous_value, 0));
+        let delta = wrapped_delta(*last_value, event.value, self.wrap);
+        *last_value = event.value;
+
+        let total = remainder.saturating_add(delta);
+        let clicks = total / self.step;
+        *remainder = total % self.step;
+
+        if clicks != 0 {
+            let mut output_event = self.output_key.merge(event);
+            output_event.previous_value = 0;
+            output_event.value = clicks;
+            output_events.push(output_event);
+        }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        vec![&self.input_key]
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            match self.input_key.matches_cap(&cap) {
+                CapMatch::Yes => {},
+                CapMatch::Maybe => output_caps.push(cap),
+                CapMatch::No => {
+                    output_caps.push(cap);
+                    continue;
+                },
+            }
+
+            let mut output_cap = self.output_key.merge_cap(cap);
+            output_cap.value_range = Range::new(None, None);
+            output_cap.abs_meta = None;
+            output_caps.push(output_cap);
+        }
+    }
+}
+
+/// Computes how far the axis moved from `previous` to `current`. If the axis wraps around,
+/// assumes that it moved along the shortest path.
+fn wrapped_delta(previous: EventValue, current: EventValue, wrap: Option<(EventValue, EventValue)>) -> EventValue {
+    let delta = current.saturating_sub(previous);
+    let (min, max) = match wrap {
+        Some(bounds) => bounds,
+        None => return delta,
+    };
+
+    let span = max.saturating_sub(min).saturating_add(1);
+    if delta > span / 2 {
+        delta - span
+    } else if delta < -(span / 2) {
+        delta + span
+    } else {
+        delta
+    }
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(wrapped_delta(3, 5, None), 2);
+    assert_eq!(wrapped_delta(5, 3, None), -2);
+    assert_eq!(wrapped_delta(255, 0, None), -255);
+    assert_eq!(wrapped_delta(255, 0, Some((0, 255))), 1);
+    assert_eq!(wrapped_delta(0, 255, Some((0, 255))), -1);
+    assert_eq!(wrapped_delta(250, 2, Some((0, 255))), 8);
+    assert_eq!(wrapped_delta(10, 20, Some((0, 255))), 10);
+}
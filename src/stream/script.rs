@@ -0,0 +1,333 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements --script, which hands events to a function written in Lua. Requires the lua feature,
+//! which links against liblua 5.4.
+//!
+//! The script must define a global function `process(event, state)`. The event is a table with
+//! the fields `type`, `code`, `value` and `domain`, e.g. `{type="key", code="a", value=1,
+//! domain="kb"}`. The state is a table that the script can use to remember things between calls;
+//! the same table is passed on every call. The function may return nil to let the event pass
+//! unchanged, or a list of events that replace it. Events in that list that have no domain get
+//! the domain of the original event.
+
+#![allow(unused_imports)]
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+
+use crate::capability::{Capability, CapMatch};
+use crate::domain;
+use crate::ecodes;
+use crate::error::{Context, RuntimeError, SystemError};
+use crate::event::{Event, Channel, EventValue, Namespace};
+use crate::key::Key;
+use crate::range::Range;
+
+#[cfg(feature = "lua")]
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub type lua_State = c_void;
+    pub type lua_Integer = i64;
+    pub type lua_KContext = isize;
+    pub type lua_KFunction = Option<extern "C" fn(*mut lua_State, c_int, lua_KContext) -> c_int>;
+
+    pub const LUA_OK: c_int = 0;
+    pub const LUA_TNIL: c_int = 0;
+    pub const LUA_TNUMBER: c_int = 3;
+    pub const LUA_TSTRING: c_int = 4;
+    pub const LUA_TTABLE: c_int = 5;
+    pub const LUA_TFUNCTION: c_int = 6;
+    /// Equal to -LUAI_MAXSTACK - 1000 with the default configuration of Lua 5.4.
+    pub const LUA_REGISTRYINDEX: c_int = -1_000_000 - 1000;
+
+    extern "C" {
+        pub fn luaL_newstate() -> *mut lua_State;
+        pub fn luaL_openlibs(state: *mut lua_State);
+        pub fn luaL_loadfilex(state: *mut lua_State, filename: *const c_char, mode: *const c_char) -> c_int;
+        pub fn luaL_ref(state: *mut lua_State, table: c_int) -> c_int;
+        pub fn lua_close(state: *mut lua_State);
+        pub fn lua_pcallk(state: *mut lua_State, nargs: c_int, nresults: c_int, msgh: c_int, ctx: lua_KContext, k: lua_KFunction) -> c_int;
+        pub fn lua_settop(state: *mut lua_State, index: c_int);
+        pub fn lua_type(state: *mut lua_State, index: c_int) -> c_int;
+        pub fn lua_getglobal(state: *mut lua_State, name: *const c_char) -> c_int;
+        pub fn lua_createtable(state: *mut lua_State, narr: c_int, nrec: c_int);
+        pub fn lua_setfield(state: *mut lua_State, index: c_int, key: *const c_char);
+        pub fn lua_rawget(state: *mut lua_State, index: c_int) -> c_int;
+        pub fn lua_rawgeti(state: *mut lua_State, index: c_int, n: lua_Integer) -> c_int;
+        pub fn lua_pushinteger(state: *mut lua_State, n: lua_Integer);
+        pub fn lua_pushlstring(state: *mut lua_State, string: *const c_char, len: usize) -> *const c_char;
+        pub fn lua_tointegerx(state: *mut lua_State, index: c_int, isnum: *mut c_int) -> lua_Integer;
+        pub fn lua_tolstring(state: *mut lua_State, index: c_int, len: *mut usize) -> *const c_char;
+    }
+}
+
+/// Represents a --script argument.
+pub struct Script {
+    /// Only events matching these keys are handed to the script.
+    keys: Vec<Key>,
+    /// The events the script may generate besides the events that it receives.
+    emit_keys: Vec<Key>,
+    #[cfg(feature = "lua")]
+    path: PathBuf,
+    #[cfg(feature = "lua")]
+    lua: *mut ffi::lua_State,
+    /// The reference to the state table in the registry of the Lua state.
+    #[cfg(feature = "lua")]
+    state_ref: c_int,
+
+    /// State: modifiable at runtime.
+    /// The last value that the script generated for each channel, to compute previous values.
+    #[cfg(feature = "lua")]
+    last_values: HashMap<Channel, EventValue>,
+    /// Set after the script fails, so we don't flood the user with the same error for every event.
+    #[cfg(feature = "lua")]
+    has_failed: bool,
+}
+
+/// Pushes a string onto the stack of the Lua state.
+#[cfg(feature = "lua")]
+unsafe fn push_str(lua: *mut ffi::lua_State, string: &str) {
+    ffi::lua_pushlstring(lua, string.as_ptr() as *const c_char, string.len());
+}
+
+/// Returns the string at the given index of the stack, if it is a string.
+#[cfg(feature = "lua")]
+unsafe fn get_str(lua: *mut ffi::lua_State, index: c_int) -> Option<String> {
+    if ffi::lua_type(lua, index) != ffi::LUA_TSTRING {
+        return None;
+    }
+    let mut len: usize = 0;
+    let ptr = ffi::lua_tolstring(lua, index, &mut len);
+    let bytes = std::slice::from_raw_parts(ptr as *const u8, len);
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Pushes the value of a field of the table at the top of the stack onto the stack, without
+/// invoking metamethods, so that no Lua error can occur outside of a protected call.
+#[cfg(feature = "lua")]
+unsafe fn get_field(lua: *mut ffi::lua_State, name: &str) -> c_int {
+    push_str(lua, name);
+    ffi::lua_rawget(lua, -2)
+}
+
+/// Pops the error message of a failed call from the stack.
+#[cfg(feature = "lua")]
+unsafe fn pop_error(lua: *mut ffi::lua_State) -> SystemError {
+    let message = get_str(lua, -1).unwrap_or_else(|| "Unknown error.".to_owned());
+    ffi::lua_settop(lua, 0);
+    SystemError::new(message)
+}
+
+#[cfg(feature = "lua")]
+impl Script {
+    pub fn load(path: PathBuf, keys: Vec<Key>, emit_keys: Vec<Key>) -> Result<Script, SystemError> {
+        let path_cstr = CString::new(path.as_os_str().to_string_lossy().as_bytes())
+            .map_err(|_| SystemError::new("Paths may not contain nul bytes."))?;
+        let lua = unsafe { ffi::luaL_newstate() };
+        if lua.is_null() {
+            return Err(SystemError::new("Failed to create a Lua state."));
+        }
+        // From here on, the Drop implementation takes care of closing the Lua state.
+        let mut script = Script {
+            keys, emit_keys, path, lua,
+            state_ref: 0,
+            last_values: HashMap::new(),
+            has_failed: false,
+        };
+
+        unsafe {
+            ffi::luaL_openlibs(lua);
+            if ffi::luaL_loadfilex(lua, path_cstr.as_ptr(), std::ptr::null()) != ffi::LUA_OK {
+                return Err(pop_error(lua));
+            }
+            if ffi::lua_pcallk(lua, 0, 0, 0, 0, None) != ffi::LUA_OK {
+                return Err(pop_error(lua));
+            }
+            let process_name = CString::new("process").unwrap();
+            let process_type = ffi::lua_getglobal(lua, process_name.as_ptr());
+            ffi::lua_settop(lua, 0);
+            if process_type != ffi::LUA_TFUNCTION {
+                return Err(SystemError::new("The script does not define a function called \"process\"."));
+            }
+            ffi::lua_createtable(lua, 0, 0);
+            script.state_ref = ffi::luaL_ref(lua, ffi::LUA_REGISTRYINDEX);
+        }
+
+        Ok(script)
+    }
+
+    /// Calls the process function of the script and returns the events it returned, or None if
+    /// it returned nil.
+    fn call(&mut self, event: Event) -> Result<Option<Vec<Event>>, RuntimeError> {
+        let lua = self.lua;
+        let name = ecodes::event_name(event.code);
+        let (type_name, code_name) = crate::utils::split_once(&name, ":");
+        let domain_name = domain::try_reverse_resolve(event.domain);
+        let process_name = CString::new("process").unwrap();
+
+        unsafe {
+            ffi::lua_settop(lua, 0);
+            ffi::lua_getglobal(lua, process_name.as_ptr());
+
+            ffi::lua_createtable(lua, 0, 4);
+            for &(field, value) in &[("type", Some(type_name)), ("code", code_name), ("domain", domain_name.as_deref())] {
+                if let Some(value) = value {
+                    let field = CString::new(field).unwrap();
+                    push_str(lua, value);
+                    ffi::lua_setfield(lua, -2, field.as_ptr());
+                }
+            }
+            let value_field = CString::new("value").unwrap();
+            ffi::lua_pushinteger(lua, event.value.into());
+            ffi::lua_setfield(lua, -2, value_field.as_ptr());
+
+            ffi::lua_rawgeti(lua, ffi::LUA_REGISTRYINDEX, self.state_ref.into());
+
+            if ffi::lua_pcallk(lua, 2, 1, 0, 0, None) != ffi::LUA_OK {
+                return Err(pop_error(lua).into());
+            }
+            let result = match ffi::lua_type(lua, -1) {
+                ffi::LUA_TNIL => Ok(None),
+                ffi::LUA_TTABLE => self.read_events(event).map(Some),
+                _ => Err(SystemError::new("The process function must return nil or a list of events.").into()),
+            };
+            ffi::lua_settop(lua, 0);
+            result
+        }
+    }
+
+    /// Reads the list of events at the top of the stack.
+    unsafe fn read_events(&mut self, original: Event) -> Result<Vec<Event>, RuntimeError> {
+        let lua = self.lua;
+        let mut events = Vec::new();
+        for index in 1 .. {
+            match ffi::lua_rawgeti(lua, -1, index) {
+                ffi::LUA_TNIL => break,
+                ffi::LUA_TTABLE => (),
+                _ => return Err(SystemError::new(format!(
+                    "Item {} of the list returned by the process function is not an event.", index
+                )).into()),
+            }
+            let mut event = self.read_event(original)
+                .with_context_of(|| format!("While interpreting item {} of the list returned by the process function:", index))?;
+            event.previous_value = self.last_values.insert(event.channel(), event.value).unwrap_or(0);
+            events.push(event);
+            ffi::lua_settop(lua, -2);
+        }
+        Ok(events)
+    }
+
+    /// Reads the event at the top of the stack.
+    unsafe fn read_event(&mut self, original: Event) -> Result<Event, RuntimeError> {
+        let lua = self.lua;
+        let read_str = |field: &str| -> Option<String> {
+            get_field(lua, field);
+            let result = get_str(lua, -1);
+            ffi::lua_settop(lua, -2);
+            result
+        };
+        let type_name = read_str("type").ok_or_else(|| SystemError::new("The type of the event is not a string."))?;
+        let code_name = read_str("code").ok_or_else(|| SystemError::new("The code of the event is not a string."))?;
+        let domain_name = read_str("domain");
+        let code = ecodes::event_code(&type_name, &code_name)?;
+
+        let value_type = get_field(lua, "value");
+        let mut is_integer: c_int = 0;
+        let value = ffi::lua_tointegerx(lua, -1, &mut is_integer);
+        ffi::lua_settop(lua, -2);
+        if value_type != ffi::LUA_TNUMBER || is_integer == 0 {
+            return Err(SystemError::new("The value of the event is not an integer.").into());
+        }
+        let value: EventValue = match std::convert::TryInto::try_into(value) {
+            Ok(value) => value,
+            Err(_) => return Err(SystemError::new(format!("The value {} is out of range.", value)).into()),
+        };
+
+        let event_domain = match domain_name {
+            Some(name) => domain::resolve(&name)?,
+            None => original.domain,
+        };
+
+        Ok(Event::new(code, value, 0, event_domain, Namespace::User))
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            if ! self.keys.iter().any(|key| key.matches(&event)) {
+                output_events.push(event);
+                continue;
+            }
+            match self.call(event) {
+                Ok(Some(new_events)) => {
+                    self.has_failed = false;
+                    output_events.extend(new_events);
+                },
+                Ok(None) => {
+                    self.has_failed = false;
+                    self.last_values.insert(event.channel(), event.value);
+                    output_events.push(event);
+                },
+                Err(error) => {
+                    // Let the event pass, so a buggy script does not make the keyboard unusable.
+                    output_events.push(event);
+                    if ! self.has_failed {
+                        self.has_failed = true;
+                        error.with_context(format!("While running the script {}:", self.path.display())).print_err();
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(feature = "lua")]
+impl Drop for Script {
+    fn drop(&mut self) {
+        unsafe { ffi::lua_close(self.lua) };
+    }
+}
+
+#[cfg(not(feature = "lua"))]
+impl Script {
+    pub fn load(_path: PathBuf, _keys: Vec<Key>, _emit_keys: Vec<Key>) -> Result<Script, SystemError> {
+        Err(SystemError::new("This version of evsieve was compiled without the lua feature."))
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        output_events.extend(events);
+    }
+}
+
+impl Script {
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    /// The script may change the values of the events it receives arbitrarily, and may generate
+    /// the events specified by the emit= clauses for any event it receives.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], caps_out: &mut Vec<Capability>) {
+        for &cap in caps {
+            let matches_cap = self.keys.iter().map(|key| key.matches_cap(&cap)).max();
+            match matches_cap {
+                Some(CapMatch::Yes) | Some(CapMatch::Maybe) => {},
+                Some(CapMatch::No) | None => {
+                    caps_out.push(cap);
+                    continue;
+                },
+            }
+
+            let mut any_value_cap = cap;
+            any_value_cap.value_range = Range::new(None, None);
+            caps_out.push(any_value_cap);
+            for key in &self.emit_keys {
+                let mut emitted_cap = key.merge_cap(cap);
+                emitted_cap.value_range = Range::new(None, None);
+                caps_out.push(emitted_cap);
+            }
+        }
+    }
+}
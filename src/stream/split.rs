@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::{Capability, CapMatch};
+use crate::domain::Domain;
+use crate::event::Event;
+use crate::key::Key;
+use crate::range::Range;
+
+/// Represents a --split argument. Sends the events of a single event code to different domains
+/// depending on their value.
+pub struct Split {
+    input_key: Key,
+    /// Each event goes to the domain of the first range that contains its value. The ranges are
+    /// disjoint. Events whose value lies in none of them keep their domain.
+    targets: Vec<(Range, Domain)>,
+}
+
+impl Split {
+    pub fn new(input_key: Key, targets: Vec<(Range, Domain)>) -> Split {
+        Split { input_key, targets }
+    }
+
+    fn apply(&self, mut event: Event, output_events: &mut Vec<Event>) {
+        if self.input_key.matches(&event) {
+            if let Some((_, domain)) = self.targets.iter().find(|(range, _)| range.contains(event.value)) {
+                event.domain = *domain;
+            }
+        }
+        output_events.push(event);
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        vec![&self.input_key]
+    }
+
+    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            let is_covered = match self.input_key.matches_cap(&cap) {
+                CapMatch::Yes => self.targets.iter().any(|(range, _)| cap.value_range.is_subset_of(range)),
+                CapMatch::Maybe => false,
+                CapMatch::No => {
+                    output_caps.push(cap);
+                    continue;
+                },
+            };
+            // The values that lie in none of the ranges keep their domain.
+            if ! is_covered {
+                output_caps.push(cap);
+            }
+
+            for (range, domain) in &self.targets {
+                if let Some(value_range) = cap.value_range.intersect(range) {
+                    output_caps.push(Capability { domain: *domain, value_range, ..cap });
+                }
+            }
+        }
+    }
+}
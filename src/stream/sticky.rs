@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::event::Event;
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::time::Duration;
+
+/// Represents a --sticky argument. Tapping one of its modifiers keeps it pressed until another
+/// key gets pressed, so shortcuts can be typed one key at a time. Tapping a modifier twice locks
+/// it until it gets tapped a third time.
+pub struct Sticky {
+    /// The modifiers.
+    keys: Vec<Key>,
+    /// If Some, a tapped modifier gets released if no other key gets pressed within this time.
+    timeout: Option<Duration>,
+
+    /// State: modifiable at runtime.
+    /// The modifiers that are currently pressed according to the output.
+    modifiers: Vec<Modifier>,
+}
+
+struct Modifier {
+    /// The event that pressed this modifier. Its release is generated based on it.
+    press: Event,
+    state: ModifierState,
+}
+
+enum ModifierState {
+    /// Physically held down. Whether it gets released when the physical key gets released
+    /// depends on whether another key was pressed while holding it (`used`), and the state
+    /// the modifier had before it was pressed.
+    Held { used: bool, previous: PreviousState },
+    /// Tapped: stays pressed until another key gets pressed or the token times out.
+    Latched(Option<Token>),
+    /// Tapped twice: stays pressed until it gets tapped again.
+    Locked,
+}
+
+#[derive(Clone, Copy)]
+enum PreviousState {
+    Released,
+    Latched,
+    Locked,
+}
+
+impl Sticky {
+    pub fn new(keys: Vec<Key>, timeout: Option<Duration>) -> Sticky {
+        Sticky { keys, timeout, modifiers: Vec::new() }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key() {
+            return output_events.push(event);
+        }
+        if self.keys.iter().any(|key| key.matches(&event)) {
+            self.apply_modifier(event, output_events, loopback);
+        } else {
+            self.apply_other_key(event, output_events, loopback);
+        }
+    }
+
+    fn apply_modifier(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let index = self.modifiers.iter().position(|modifier| modifier.press.channel() == event.channel());
+        match (event.value, index) {
+            (1, None) => {
+                self.modifiers.push(Modifier {
+                    press: event,
+                    state: ModifierState::Held { used: false, previous: PreviousState::Released },
+                });
+                output_events.push(event);
+            },
+            // The modifier is already pressed according to the output.
+            (1, Some(index)) => {
+                let modifier = &mut self.modifiers[index];
+                let previous = match std::mem::replace(&mut modifier.state, ModifierState::Locked) {
+                    ModifierState::Latched(token) => {
+                        if let Some(token) = token {
+                            loopback.cancel_token(token);
+                        }
+                        PreviousState::Latched
+                    },
+                    ModifierState::Locked => PreviousState::Locked,
+                    ModifierState::Held { previous, .. } => previous,
+                };
+                modifier.state = ModifierState::Held { used: false, previous };
+            },
+            (0, Some(index)) => {
+                let (used, previous) = match self.modifiers[index].state {
+                    ModifierState::Held { used, previous } => (used, previous),
+                    // The release of a press that this argument did not see.
+                    _ => return,
+                };
+                match (used, previous) {
+                    (false, PreviousState::Released) => {
+                        let token = self.timeout.map(|timeout| loopback.schedule_wakeup_in(timeout));
+                        self.modifiers[index].state = ModifierState::Latched(token);
+                    },
+                    (false, PreviousState::Latched) => self.modifiers[index].state = ModifierState::Locked,
+                    (true, _) | (false, PreviousState::Locked) => {
+                        self.modifiers.remove(index);
+                        output_events.push(event);
+                    },
+                }
+            },
+            (0, None) => output_events.push(event),
+            // Repeats only make sense while the modifier is held.
+            (_, Some(index)) => if let ModifierState::Held { .. } = self.modifiers[index].state {
+                output_events.push(event);
+            },
+            (_, None) => output_events.push(event),
+        }
+    }
+
+    fn apply_other_key(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        output_events.push(event);
+        if event.value != 1 {
+            return;
+        }
+
+        // The key got pressed along with the modifiers. Held modifiers are now part of a
+        // shortcut, and tapped modifiers have done their job.
+        let mut index = 0;
+        while index < self.modifiers.len() {
+            match &mut self.modifiers[index].state {
+                ModifierState::Held { used, .. } => *used = true,
+                ModifierState::Latched(token) => {
+                    if let Some(token) = token.take() {
+                        loopback.cancel_token(token);
+                    }
+                    let modifier = self.modifiers.remove(index);
+                    output_events.push(release_of(modifier.press));
+                    continue;
+                },
+                ModifierState::Locked => (),
+            }
+            index += 1;
+        }
+    }
+
+    /// Releases a tapped modifier if no other key was pressed in time.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        let index = self.modifiers.iter().position(|modifier| match &modifier.state {
+            ModifierState::Latched(Some(modifier_token)) => modifier_token == token,
+            _ => false,
+        });
+        if let Some(index) = index {
+            let modifier = self.modifiers.remove(index);
+            output_events.push(release_of(modifier.press));
+        }
+    }
+}
+
+fn release_of(mut press: Event) -> Event {
+    press.value = 0;
+    press.previous_value = 1;
+    press
+}
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::capability::{Capability, CapMatch};
+use crate::event::{Event, Channel};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::Range;
+use crate::time::{Duration, Instant};
+
+/// What happens to events that arrive too soon after the previous event of their channel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThrottleMode {
+    /// They are dropped.
+    Drop,
+    /// They are held back until the period is over, and then passed on as a single event: the
+    /// sum of their values for EV_REL events, or the last of them for other events.
+    Coalesce,
+}
+
+/// Represents a --throttle argument.
+pub struct Throttle {
+    keys: Vec<Key>,
+    /// The minimum time between two events of the same channel.
+    period: Duration,
+    mode: ThrottleMode,
+
+    /// State: modifiable at runtime.
+    channels: HashMap<Channel, ChannelState>,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    /// When the last event of this channel was passed on. For EV_KEY events, releases do not count.
+    last_passed: Option<Instant>,
+    /// For ThrottleMode::Coalesce: the event that is being held back, along with the token of the
+    /// wakeup at which it gets passed on and the time at which that wakeup is due.
+    pending: Option<(Token, Instant, Event)>,
+    /// Whether the last press of this key was dropped, in which case its release is dropped too.
+    dropped_press: bool,
+}
+
+impl Throttle {
+    pub fn new(keys: Vec<Key>, period: Duration, mode: ThrottleMode) -> Throttle {
+        Throttle { keys, period, mode, channels: HashMap::new() }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    fn apply(&mut self, mut event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if event.ev_type().is_syn() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return output_events.push(event);
+        }
+        let period = self.period;
+        let state = self.channels.entry(event.channel()).or_default();
+
+        // Keys are throttled by dropping presses and the releases that belong to them. Holding
+        // back presses and releases could otherwise leave keys pressed.
+        if event.ev_type().is_key() {
+            let now = loopback.now();
+            let too_soon = match state.last_passed {
+                Some(last_passed) => now < last_passed + period,
+                None => false,
+            };
+            match event.value {
+                0 if state.dropped_press => state.dropped_press = false,
+                0 => output_events.push(event),
+                1 if too_soon => state.dropped_press = true,
+                1 => {
+                    state.last_passed = Some(now);
+                    output_events.push(event);
+                },
+                _ if too_soon || state.dropped_press => (),
+                _ => {
+                    state.last_passed = Some(now);
+                    output_events.push(event);
+                },
+            }
+            return;
+        }
+
+        if let Some((_, _, pending_event)) = &mut state.pending {
+            if event.ev_type().is_rel() {
+                event.value = event.value.saturating_add(pending_event.value);
+            }
+            event.previous_value = pending_event.previous_value;
+            *pending_event = event;
+            return;
+        }
+
+        let now = loopback.now();
+        let next_allowed = state.last_passed.map(|last_passed| last_passed + period);
+        match next_allowed {
+            Some(next_allowed) if now < next_allowed => match self.mode {
+                ThrottleMode::Drop => (),
+                ThrottleMode::Coalesce => {
+                    let token = loopback.schedule_wakeup_at(next_allowed);
+                    state.pending = Some((token, next_allowed, event));
+                },
+            },
+            _ => {
+                state.last_passed = Some(now);
+                output_events.push(event);
+            },
+        }
+    }
+
+    /// Passes on the coalesced event that was held back until the given token.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        for state in self.channels.values_mut() {
+            let is_due = match &state.pending {
+                Some((pending_token, _, _)) => pending_token == token,
+                None => false,
+            };
+            if is_due {
+                if let Some((_, due_time, event)) = state.pending.take() {
+                    state.last_passed = Some(due_time);
+                    output_events.push(event);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Coalesced EV_REL events may have any value.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            let may_coalesce = self.mode == ThrottleMode::Coalesce && cap.code.ev_type().is_rel()
+                && self.keys.iter().any(|key| key.matches_cap(&cap) != CapMatch::No);
+            match may_coalesce {
+                true => output_caps.push(Capability { value_range: Range::new(None, None), ..cap }),
+                false => output_caps.push(cap),
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::Capability;
+use crate::domain::Domain;
+use crate::event::{Event, EventCode, Namespace};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::Range;
+use crate::time::{Duration, Instant};
+
+/// Represents a --timer argument. Presses and releases its send-keys once every period,
+/// regardless of which events pass by.
+pub struct Timer {
+    /// The keys that get sent, along with the event codes they were specified with.
+    send_keys: Vec<(Key, EventCode)>,
+    period: Duration,
+    /// The domain of the events that get sent if the key does not specify a domain.
+    domain: Domain,
+
+    /// State: modifiable at runtime.
+    /// The next wakeup and the time it is scheduled at.
+    next_tick: Option<(Token, Instant)>,
+}
+
+impl Timer {
+    pub fn new(send_keys: Vec<(Key, EventCode)>, period: Duration) -> Timer {
+        Timer {
+            send_keys, period,
+            domain: crate::domain::get_unique_domain(),
+            next_tick: None,
+        }
+    }
+
+    /// Schedules the first tick. Must be called before the stream starts running.
+    pub fn start(&mut self, loopback: &mut LoopbackHandle) {
+        let time = loopback.now() + self.period;
+        self.next_tick = Some((loopback.schedule_wakeup_at(time), time));
+    }
+
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let time = match &self.next_tick {
+            Some((tick_token, time)) if tick_token == token => *time,
+            _ => return,
+        };
+
+        for (key, code) in &self.send_keys {
+            output_events.push(key.merge(Event::new(*code, 1, 0, self.domain, Namespace::User)));
+        }
+        for (key, code) in self.send_keys.iter().rev() {
+            output_events.push(key.merge(Event::new(*code, 0, 1, self.domain, Namespace::User)));
+        }
+
+        // Schedule the next tick relative to when this one was due instead of when it happened,
+        // so the timer does not drift.
+        let next_time = time + self.period;
+        self.next_tick = Some((loopback.schedule_wakeup_at(next_time), next_time));
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        Vec::new()
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend_from_slice(caps);
+        for (key, code) in &self.send_keys {
+            output_caps.push(key.merge_cap(Capability {
+                code: *code,
+                domain: self.domain,
+                namespace: Namespace::User,
+                value_range: Range::new(Some(0), Some(1)),
+                abs_meta: None,
+            }));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::capability::{Capability, CapMatch};
+use crate::domain::Domain;
+use crate::event::{Event, EventCode, EventValue};
+use crate::key::Key;
+use crate::loopback::LoopbackHandle;
+use crate::range::Range;
+use crate::time::{Duration, Instant};
+
+/// The event codes that are consumed and generated by a --touchpad argument.
+#[derive(Clone, Copy)]
+pub struct TouchpadCodes {
+    pub abs_x: EventCode,
+    pub abs_y: EventCode,
+    pub btn_touch: EventCode,
+    pub rel_x: EventCode,
+    pub rel_y: EventCode,
+    pub btn_left: EventCode,
+}
+
+/// Represents a --touchpad argument. Turns the absolute position reported by a single-touch
+/// touchscreen into relative pointer movement, like a touchpad would.
+pub struct Touchpad {
+    keys: Vec<Key>,
+    codes: TouchpadCodes,
+    /// The amount of relative units generated for each unit moved on the absolute axes.
+    speed: f64,
+    /// If Some, a touch that lasts at most this long and moves at most `tap_distance` units
+    /// gets turned into a click of btn:left.
+    tap_period: Option<Duration>,
+    tap_distance: EventValue,
+
+    /// State: modifiable at runtime.
+    touches: HashMap<Domain, TouchState>,
+}
+
+#[derive(Default)]
+struct TouchState {
+    /// If Some, a finger is on the touchscreen and touched it at the given instant.
+    touched_at: Option<Instant>,
+    last_x: Option<EventValue>,
+    last_y: Option<EventValue>,
+    /// The parts of movement that were too small to generate a full relative unit.
+    remainder_x: f64,
+    remainder_y: f64,
+    /// The total distance moved along both axes since the touch started.
+    distance: EventValue,
+}
+
+impl Touchpad {
+    pub fn new(keys: Vec<Key>, codes: TouchpadCodes, speed: f64, tap_period: Option<Duration>, tap_distance: EventValue) -> Touchpad {
+        Touchpad {
+            keys, codes, speed, tap_period, tap_distance,
+            touches: HashMap::new(),
+        }
+    }
+
+    fn is_consumed_code(&self, code: EventCode) -> bool {
+        code == self.codes.abs_x || code == self.codes.abs_y || code == self.codes.btn_touch
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! self.is_consumed_code(event.code) || ! self.keys.iter().any(|key| key.matches(&event)) {
+            output_events.push(event);
+            return;
+        }
+
+        let codes = self.codes;
+        let speed = self.speed;
+        let state = self.touches.entry(event.domain).or_default();
+
+        if event.code == codes.btn_touch {
+            match event.value {
+                0 => {
+                    let is_tap = match (self.tap_period, state.touched_at) {
+                        (Some(tap_period), Some(touched_at)) => {
+                            let touch_duration = loopback.now().checked_duration_since(touched_at);
+                            let is_short = match touch_duration {
+                                Some(duration) => duration <= tap_period,
+                                None => false,
+                            };
+                            state.distance <= self.tap_distance && is_short
+                        },
+                        _ => false,
+                    };
+                    *state = TouchState::default();
+
+                    if is_tap {
+                        for &value in &[1, 0] {
+                            let mut click = event;
+                            click.code = codes.btn_left;
+                            click.previous_value = 1 - value;
+                            click.value = value;
+                            output_events.push(click);
+                        }
+                    }
+                },
+                1 => {
+                    *state = TouchState::default();
+                    state.touched_at = Some(loopback.now());
+                },
+                _ => {},
+            }
+            return;
+        }
+
+        // Positions reported while no finger is on the screen do not move the pointer.
+        if state.touched_at.is_none() {
+            return;
+        }
+
+        let (last_value, remainder, output_code) = if event.code == codes.abs_x {
+            (&mut state.last_x, &mut state.remainder_x, codes.rel_x)
+        } else {
+            (&mut state.last_y, &mut state.remainder_y, codes.rel_y)
+        };
+
+        // The first position after a touch only determines where the finger is.
+        let delta = match *last_value {
+            Some(last) => event.value.saturating_sub(last),
+            None => 0,
+        };
+        *last_value = Some(event.value);
+        state.distance = state.distance.saturating_add(delta.saturating_abs());
+
+        let movement = *remainder + delta as f64 * speed;
+        let units = movement.trunc();
+        *remainder = movement - units;
+
+        if units != 0.0 {
+            let mut output_event = event;
+            output_event.code = output_code;
+            output_event.previous_value = 0;
+            output_event.value = units as EventValue;
+            output_events.push(output_event);
+        }
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            if ! self.is_consumed_code(cap.code) {
+                output_caps.push(cap);
+                continue;
+            }
+            let matches_cap = self.keys.iter().map(|key| key.matches_cap(&cap)).max();
+            match matches_cap {
+                Some(CapMatch::Yes) => {},
+                Some(CapMatch::Maybe) => output_caps.push(cap),
+                Some(CapMatch::No) | None => {
+                    output_caps.push(cap);
+                    continue;
+                },
+            }
+
+            let mut output_cap = cap;
+            output_cap.abs_meta = None;
+            if cap.code == self.codes.btn_touch {
+                if self.tap_period.is_none() {
+                    continue;
+                }
+                output_cap.code = self.codes.btn_left;
+                output_cap.value_range = Range::new(Some(0), Some(1));
+            } else {
+                output_cap.code = match cap.code == self.codes.abs_x {
+                    true => self.codes.rel_x,
+                    false => self.codes.rel_y,
+                };
+                output_cap.value_range = Range::new(None, None);
+            }
+            output_caps.push(output_cap);
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventType, Namespace};
+    use crate::loopback::Loopback;
+
+    let codes = TouchpadCodes {
+        abs_x: EventCode::new(EventType::ABS, 0),
+        abs_y: EventCode::new(EventType::ABS, 1),
+        btn_touch: EventCode::new(EventType::KEY, 0x14a),
+        rel_x: EventCode::new(EventType::REL, 0),
+        rel_y: EventCode::new(EventType::REL, 1),
+        btn_left: EventCode::new(EventType::KEY, 0x110),
+    };
+    let mut touchpad = Touchpad::new(vec![Key::copy()], codes, 0.5, Some(Duration::from_millis(200)), 10);
+    let domain = crate::domain::get_unique_domain();
+    let event = |code, value| Event::new(code, value, 0, domain, Namespace::Input);
+    let mut loopback = Loopback::new();
+    let start = Instant::now();
+    let mut output = Vec::new();
+
+    // Taps are timed by when the stream processes the events, not by when this test happens to run.
+    touchpad.apply_to_all(&[event(codes.btn_touch, 1), event(codes.abs_x, 100)], &mut output, &mut loopback.get_handle(start));
+    touchpad.apply_to_all(&[event(codes.abs_x, 106)], &mut output, &mut loopback.get_handle(start + Duration::from_millis(50)));
+    assert_eq!(output.iter().map(|event| (event.code, event.value)).collect::<Vec<_>>(), vec![(codes.rel_x, 3)]);
+    output.clear();
+    touchpad.apply_to_all(&[event(codes.btn_touch, 0)], &mut output, &mut loopback.get_handle(start + Duration::from_millis(150)));
+    assert_eq!(output.iter().map(|event| (event.code, event.value)).collect::<Vec<_>>(), vec![(codes.btn_left, 1), (codes.btn_left, 0)]);
+    output.clear();
+
+    // Touches that last too long or move too far are not taps.
+    touchpad.apply_to_all(&[event(codes.btn_touch, 1)], &mut output, &mut loopback.get_handle(start));
+    touchpad.apply_to_all(&[event(codes.btn_touch, 0)], &mut output, &mut loopback.get_handle(start + Duration::from_millis(250)));
+    assert!(output.is_empty());
+    touchpad.apply_to_all(&[event(codes.btn_touch, 1), event(codes.abs_y, 0), event(codes.abs_y, 20)], &mut output, &mut loopback.get_handle(start));
+    output.clear();
+    touchpad.apply_to_all(&[event(codes.btn_touch, 0)], &mut output, &mut loopback.get_handle(start + Duration::from_millis(50)));
+    assert!(output.is_empty());
+}
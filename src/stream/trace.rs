@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements --trace, which reports which arguments changed an event on its way through
+//! the stream, to help figure out why a certain argument did or did not apply to an event.
+
+use crate::event::{Event, Namespace};
+use crate::key::Key;
+use crate::stream::hook::TriggerResponse;
+use crate::stream::print::print_event_direct;
+
+/// Created by a --trace argument. Decides which events get traced.
+pub struct Tracer {
+    keys: Vec<Key>,
+}
+
+/// The journey of a single event through the stream, as far as it has been recorded so far.
+pub struct Trace {
+    lines: Vec<String>,
+}
+
+impl Tracer {
+    pub fn new(keys: Vec<Key>) -> Tracer {
+        Tracer { keys }
+    }
+
+    /// Starts a trace if any of the given events is one we are interested in.
+    pub fn start(&self, description: String, events: &[Event]) -> Option<Trace> {
+        let is_interesting = events.iter().any(|&event| {
+            // Events from input devices are traced before they enter the user namespace.
+            let probe = Event { namespace: Namespace::User, ..event };
+            self.keys.iter().any(|key| key.matches(&probe))
+        });
+        match is_interesting {
+            true => Some(Trace { lines: vec![format!("Trace of {}:", description)] }),
+            false => None,
+        }
+    }
+}
+
+impl Trace {
+    /// Records that the argument with the given label turned the events into other events.
+    pub fn record_change(&mut self, label: &str, events: &[Event]) {
+        self.lines.push(format!("    {} → {}", label, format_events(events)));
+    }
+
+    /// Records how a --hook responded to the events, if they interacted with it at all.
+    pub fn record_hook(&mut self, label: &str, response: TriggerResponse) {
+        let description = match response {
+            TriggerResponse::None => return,
+            TriggerResponse::Matches => "matched",
+            TriggerResponse::Activates => "activated",
+            TriggerResponse::Releases => "released",
+        };
+        self.lines.push(format!("    {} ({})", label, description));
+    }
+
+    /// Prints the trace, given the events that eventually got sent to the output devices.
+    pub fn finish(mut self, events_out: &[Event]) {
+        if events_out.is_empty() {
+            self.lines.push("    no events were written to any output".to_owned());
+        }
+        println!("{}", self.lines.join("\n"));
+    }
+}
+
+pub fn format_events(events: &[Event]) -> String {
+    match events.is_empty() {
+        true => "nothing".to_owned(),
+        false => events.iter()
+            .map(|&event| print_event_direct(event))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
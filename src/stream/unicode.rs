@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::{Capability, CapMatch};
+use crate::event::{Event, EventCode, EventValue, Namespace};
+use crate::key::Key;
+use crate::range::Range;
+
+/// Represents a --unicode argument. Whenever an event matching its input key is pressed, it
+/// emits the sequence of key presses and releases that makes the desktop environment type a
+/// certain Unicode character.
+pub struct UnicodeMap {
+    input_key: Key,
+    /// The events that shall be emitted when the input key is pressed, in order.
+    sequence: Vec<(EventCode, EventValue)>,
+}
+
+impl UnicodeMap {
+    pub fn new(input_key: Key, sequence: Vec<(EventCode, EventValue)>) -> UnicodeMap {
+        UnicodeMap { input_key, sequence }
+    }
+
+    fn apply(&self, event: Event, output_events: &mut Vec<Event>) {
+        if ! self.input_key.matches(&event) {
+            output_events.push(event);
+            return;
+        }
+
+        // Only the initial press types the character. Repeat and release events of the
+        // input key are dropped.
+        if event.value != 1 {
+            return;
+        }
+
+        output_events.extend(self.sequence.iter().map(|&(code, value)| {
+            let previous_value = match value {
+                0 => 1,
+                _ => 0,
+            };
+            Event::new(code, value, previous_value, event.domain, Namespace::User)
+        }));
+    }
+
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        vec![&self.input_key]
+    }
+
+    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for &cap in caps {
+            match self.input_key.matches_cap(&cap) {
+                CapMatch::Yes => {},
+                CapMatch::Maybe => output_caps.push(cap),
+                CapMatch::No => {
+                    output_caps.push(cap);
+                    continue;
+                },
+            }
+
+            for &(code, _) in &self.sequence {
+                output_caps.push(Capability {
+                    code,
+                    domain: cap.domain,
+                    namespace: Namespace::User,
+                    value_range: Range::new(Some(0), Some(1)),
+                    abs_meta: None,
+                });
+            }
+        }
+    }
+}
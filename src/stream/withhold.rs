@@ -4,6 +4,7 @@ use crate::event::{Event, Channel, EventFlag};
 use crate::key::Key;
 use crate::loopback::{LoopbackHandle, Token};
 use crate::stream::hook::{Trigger, TriggerResponse};
+use crate::time::Duration;
 
 /// Represents a --withhold argument.
 pub struct Withhold {
@@ -13,17 +14,53 @@ pub struct Withhold {
     /// Only withhold events that match one of the following keys.
     keys: Vec<Key>,
 
+    /// If Some, withheld events are released after being withheld for this long, even if
+    /// the triggers have not made a decision yet.
+    timeout: Option<Duration>,
+
+    /// Where released events end up in the stream relative to events that passed in the meantime.
+    order: ReleaseOrder,
+
     channel_state: Vec<(Channel, ChannelState)>,
+    /// The tokens at which the withheld events of certain channels time out.
+    timeout_tokens: Vec<(Channel, Token)>,
+    /// If the order is ReleaseOrder::Original, the events that cannot be written to the stream
+    /// yet because an event that arrived before them is still being withheld.
+    pending: Vec<PendingEvent>,
+}
+
+/// Determines where withheld events end up in the stream once they get released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReleaseOrder {
+    /// Released events are written to the stream at the moment it is decided that they are
+    /// no longer withheld, i.e. after any events that passed while they were being withheld.
+    Decision,
+    /// Events that arrive while an earlier event is being withheld are held back as well,
+    /// so released events keep their original position relative to other events.
+    Original,
+}
+
+enum PendingEvent {
+    /// The withheld event of this channel goes here if it gets released.
+    Withheld(Channel),
+    Ready(Event),
 }
 
 impl Withhold {
-    pub fn new(keys: Vec<Key>, triggers: Vec<Trigger>) -> Withhold {
+    pub fn new(keys: Vec<Key>, triggers: Vec<Trigger>, timeout: Option<Duration>, order: ReleaseOrder) -> Withhold {
         Withhold {
-            keys, triggers,
+            keys, triggers, timeout, order,
             channel_state: Vec::new(),
+            timeout_tokens: Vec::new(),
+            pending: Vec::new(),
         }
     }
 
+    /// Returns the keys that events are matched against.
+    pub fn input_keys(&self) -> Vec<&Key> {
+        self.keys.iter().collect()
+    }
+
     pub fn apply_to_all(&mut self, events: &[Event], events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
         for event in events {
             self.apply(*event, events_out, loopback);
@@ -35,7 +72,7 @@ impl Withhold {
         if event.flags.get(EventFlag::Withholdable) {
             event.flags.unset(EventFlag::Withholdable);
         } else {
-            return events_out.push(event);
+            return self.write_event(event, events_out);
         }
 
         // Check which triggers just activated because of this event.
@@ -61,21 +98,43 @@ impl Withhold {
         //
         // Setting this to Some(event) is pretty much a delayed `events_out.push(event)` call.
         let final_event: Option<Event>;
+        let mut started_withholding = false;
 
         if self.keys.iter().any(|key| key.matches(&event)) {
             let current_channel_state: Option<&mut ChannelState> =
                 self.channel_state.iter_mut()
                 .find(|(channel, _state)| *channel == event.channel())
                 .map(|(_channel, state)| state);
-            if any_tracker_active_on_channel {
+            if any_tracker_active_on_channel && ! event.ev_type().is_key() {
+                // Withhold axis events as long as some tracker is active. Only the most recent
+                // state of the axis is remembered, so later events get merged into the withheld one.
+                match current_channel_state {
+                    None => {
+                        self.channel_state.push(
+                            (event.channel(), ChannelState::Withheld { withheld_event: event })
+                        );
+                        started_withholding = true;
+                    },
+                    Some(ChannelState::Withheld { withheld_event }) => {
+                        merge_withheld_axis_event(withheld_event, event);
+                    },
+                    // The events that belong to an activated trigger are dropped.
+                    Some(ChannelState::Residual) => {},
+                };
+                final_event = None;
+            } else if any_tracker_active_on_channel {
                 if event.value == 1 {
                     // Withhold the event unless an event was already being withheld.
                     match current_channel_state {
-                        None => self.channel_state.push(
-                            (event.channel(), ChannelState::Withheld { withheld_event: event })
-                        ),
+                        None => {
+                            self.channel_state.push(
+                                (event.channel(), ChannelState::Withheld { withheld_event: event })
+                            );
+                            started_withholding = true;
+                        },
                         Some(state @ &mut ChannelState::Residual) => {
-                            *state = ChannelState::Withheld { withheld_event: event }
+                            *state = ChannelState::Withheld { withheld_event: event };
+                            started_withholding = true;
                         },
                         Some(ChannelState::Withheld { .. }) => {},
                     }
@@ -85,7 +144,8 @@ impl Withhold {
                     final_event = None;
                 }
             } else { // No trackers active at the event's channel.
-                if event.value == 0 {
+                // For axes, the first event that deactivates the trackers plays the role of KEY_UP.
+                if event.value == 0 || ! event.ev_type().is_key() {
                     // Remove a Residual block. If no Residual block is present, pass the event on.
                     match current_channel_state {
                         // TODO: Consider whether a KEY_UP event should unconditionally release the withheld event.
@@ -107,6 +167,15 @@ impl Withhold {
             final_event = Some(event);
         }
 
+        if started_withholding {
+            if let Some(timeout) = self.timeout {
+                start_timeout(&mut self.timeout_tokens, event.channel(), timeout, loopback);
+            }
+            if self.order == ReleaseOrder::Original {
+                self.pending.push(PendingEvent::Withheld(event.channel()));
+            }
+        }
+
         // All events which were withheld by a trigger that just activated shall be considered
         // to have been consumed and their states are to be set to Residual.
         for (channel, state) in &mut self.channel_state {
@@ -114,6 +183,8 @@ impl Withhold {
                 for trigger in &activated_triggers {
                     if trigger.has_tracker_matching_channel(*channel) {
                         *state = ChannelState::Residual;
+                        remove_pending_channel(&mut self.pending, *channel);
+                        cancel_timeout(&mut self.timeout_tokens, *channel, loopback);
                         break;
                     }
                 }
@@ -121,14 +192,35 @@ impl Withhold {
         }
 
         // All events which are no longer withheld by any trigger shall be released.
-        self.release_events(events_out);
+        self.release_events(events_out, loopback);
 
         if let Some(event) = final_event {
-            events_out.push(event);
+            self.write_event(event, events_out);
         }
+        self.flush_pending(events_out);
     }
 
-    pub fn wakeup(&mut self, token: &Token, events_out: &mut Vec<Event>) {
+    pub fn wakeup(&mut self, token: &Token, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        // Release the withheld event of a channel whose timeout expired.
+        if let Some(index) = self.timeout_tokens.iter().position(|(_, other_token)| other_token == token) {
+            let (channel, _) = self.timeout_tokens.remove(index);
+            let mut released_event: Option<Event> = None;
+            self.channel_state.retain(|(other_channel, state)| {
+                match state {
+                    ChannelState::Withheld { withheld_event } if *other_channel == channel => {
+                        released_event = Some(*withheld_event);
+                        false
+                    },
+                    _ => true,
+                }
+            });
+            if let Some(event) = released_event {
+                self.write_released_event(channel, event, events_out);
+                self.flush_pending(events_out);
+            }
+            return;
+        }
+
         let mut some_tracker_expired = false;
         for trigger in &mut self.triggers {
             if trigger.wakeup(token) {
@@ -142,29 +234,87 @@ impl Withhold {
         // Some trackers have expired. For all events that are being withheld, check
         // whether the respective triggers are still withholding them. Events that
         // are no longer withheld by any trigger shall be released bach to the stream.
-        self.release_events(events_out);
+        self.release_events(events_out, loopback);
+        self.flush_pending(events_out);
     }
 
     /// Writes all events that are not withheld by any trigger to the output stream.
-    fn release_events(&mut self, events_out: &mut Vec<Event>) {
+    fn release_events(&mut self, events_out: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
         let triggers = &self.triggers;
+        let mut released_events: Vec<(Channel, Event)> = Vec::new();
         self.channel_state.retain(|(channel, state)| {
             if let ChannelState::Withheld { withheld_event } = state {
                 let is_still_withheld = triggers.iter().any(|trigger|
                     trigger.has_active_tracker_matching_channel(*channel)
                 );
                 if ! is_still_withheld {
-                    events_out.push(*withheld_event);
+                    released_events.push((*channel, *withheld_event));
                     return false;
                 }
             }
             true
         });
+
+        for (channel, event) in released_events {
+            cancel_timeout(&mut self.timeout_tokens, channel, loopback);
+            self.write_released_event(channel, event, events_out);
+        }
+    }
+
+    /// Writes an event that is not withheld to the output stream, unless it has to wait for
+    /// an earlier event that is still being withheld.
+    fn write_event(&mut self, event: Event, events_out: &mut Vec<Event>) {
+        if self.pending.is_empty() {
+            events_out.push(event);
+        } else {
+            self.pending.push(PendingEvent::Ready(event));
+        }
+    }
+
+    /// Returns all events that are currently withheld or waiting for an earlier event to be released.
+    pub fn withheld_events(&self) -> Vec<Event> {
+        let withheld = self.channel_state.iter().filter_map(|(_, state)| match state {
+            ChannelState::Withheld { withheld_event } => Some(*withheld_event),
+            ChannelState::Residual => None,
+        });
+        let pending = self.pending.iter().filter_map(|pending| match pending {
+            PendingEvent::Ready(event) => Some(*event),
+            PendingEvent::Withheld(_) => None,
+        });
+        withheld.chain(pending).collect()
+    }
+
+    /// Writes an event that was withheld on a certain channel and just got released.
+    fn write_released_event(&mut self, channel: Channel, event: Event, events_out: &mut Vec<Event>) {
+        let position = self.pending.iter_mut().find(|pending_event| match pending_event {
+            PendingEvent::Withheld(other_channel) => *other_channel == channel,
+            PendingEvent::Ready(_) => false,
+        });
+        match position {
+            Some(pending_event) => *pending_event = PendingEvent::Ready(event),
+            None => events_out.push(event),
+        }
+    }
+
+    /// Writes all pending events to the output stream that no longer need to wait for an
+    /// earlier event to get released.
+    fn flush_pending(&mut self, events_out: &mut Vec<Event>) {
+        let num_ready = self.pending.iter()
+            .take_while(|pending_event| match pending_event {
+                PendingEvent::Withheld(_) => false,
+                PendingEvent::Ready(_) => true,
+            })
+            .count();
+        for pending_event in self.pending.drain(.. num_ready) {
+            if let PendingEvent::Ready(event) = pending_event {
+                events_out.push(event);
+            }
+        }
     }
 }
 
-/// For each channel, at most one event can be withheld. This withheld event is always a
-/// KEY_DOWN event. Subsequent KEY_DOWN events that arrive while an event is being withheld
+/// For each channel, at most one event can be withheld. For events of type EV_KEY, this withheld
+/// event is always a KEY_DOWN event. Subsequent KEY_DOWN events that arrive while an event is being withheld
 /// shall be dropped. The event is withheld as long as some tracker returns true for
 /// `has_active_tracker_matching_channel(event.channel())`.
 /// 
@@ -174,8 +324,74 @@ impl Withhold {
 /// the state of the corresponding channel returns to undefined. Furthermore, a KEY_DOWN event
 /// arriving to a channel in Residual state cancels the Residual state and sets it back to
 /// Withheld.
+///
+/// Events of type EV_ABS or EV_REL do not have KEY_DOWN or KEY_UP events. Instead, the first
+/// event that activates a tracker starts withholding, subsequent events that keep a tracker
+/// active get merged into the withheld event, and the first event that deactivates all trackers
+/// acts like a KEY_UP event. A channel in Residual state stays Residual until then.
 #[derive(Debug, Clone, Copy)]
 enum ChannelState {
     Withheld { withheld_event: Event },
     Residual,
 }
+
+/// Schedules the withheld event on a channel to be released after the timeout, replacing
+/// any timeout that was previously scheduled for the same channel.
+fn start_timeout(timeout_tokens: &mut Vec<(Channel, Token)>, channel: Channel, timeout: Duration, loopback: &mut LoopbackHandle) {
+    cancel_timeout(timeout_tokens, channel, loopback);
+    timeout_tokens.push((channel, loopback.schedule_wakeup_in(timeout)));
+}
+
+/// Cancels the timeout of a channel whose withheld event got dropped or released, if any.
+fn cancel_timeout(timeout_tokens: &mut Vec<(Channel, Token)>, channel: Channel, loopback: &mut LoopbackHandle) {
+    if let Some(index) = timeout_tokens.iter().position(|(other_channel, _)| *other_channel == channel) {
+        let (_, token) = timeout_tokens.remove(index);
+        loopback.cancel_token(token);
+    }
+}
+
+/// Merges a new event into an axis event that was already being withheld, such that releasing
+/// the withheld event brings the axis to the same state as the new event would have.
+fn merge_withheld_axis_event(withheld_event: &mut Event, event: Event) {
+    if event.ev_type().is_rel() {
+        withheld_event.value = withheld_event.value.saturating_add(event.value);
+    } else {
+        withheld_event.value = event.value;
+    }
+}
+
+fn remove_pending_channel(pending: &mut Vec<PendingEvent>, channel: Channel) {
+    pending.retain(|pending_event| match pending_event {
+        PendingEvent::Withheld(other_channel) => *other_channel != channel,
+        PendingEvent::Ready(_) => true,
+    });
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+    let parser = KeyParser::default_filter();
+    let key_a = parser.parse("key:a").unwrap();
+    let key_b = parser.parse("key:b").unwrap();
+    let trigger = Trigger::new(vec![key_a.clone(), key_b.clone()], None, false);
+    let mut withhold = Withhold::new(
+        vec![key_a, key_b], vec![trigger], Some(Duration::from_secs(1)), ReleaseOrder::Decision
+    );
+    let mut loopback = crate::loopback::Loopback::new();
+    let domain = crate::domain::get_unique_domain();
+    let mut send = |code: u16, value| {
+        let mut event = Event::new(EventCode::new(EventType::KEY, code), value, 0, domain, Namespace::User);
+        event.flags.set(EventFlag::Withholdable);
+        let mut events_out = Vec::new();
+        withhold.apply(event, &mut events_out, &mut loopback.get_handle_lazy());
+        (events_out.len(), loopback.next_wakeup().is_some())
+    };
+
+    // The timeout of a withheld event is cancelled once the event is released...
+    assert_eq!(send(30, 1), (0, true));
+    assert_eq!(send(30, 0), (2, false));
+    // ... and once the trigger activates and the event is dropped.
+    assert_eq!(send(30, 1), (0, true));
+    assert_eq!(send(48, 1), (0, false));
+}
@@ -52,7 +52,7 @@ impl From<libc::timespec> for Instant {
 
 const NANOSECONDS_PER_SECOND: i128 = 1_000_000_000;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Duration {
     nsec: u128,
 }
@@ -77,6 +77,38 @@ impl Duration {
     pub fn as_millis(self) -> u128 {
         self.nsec / 1_000_000
     }
+
+    pub fn as_micros(self) -> u128 {
+        self.nsec / 1_000
+    }
+
+    pub fn as_nanos(self) -> u128 {
+        self.nsec
+    }
+}
+
+impl From<Duration> for libc::timespec {
+    fn from(duration: Duration) -> libc::timespec {
+        let nsec_per_sec = NANOSECONDS_PER_SECOND as u128;
+        libc::timespec {
+            tv_sec: (duration.nsec / nsec_per_sec) as libc::time_t,
+            tv_nsec: (duration.nsec % nsec_per_sec) as libc::c_long,
+        }
+    }
+}
+
+impl std::ops::Mul<u32> for Duration {
+    type Output = Duration;
+    fn mul(self, rhs: u32) -> Self::Output {
+        Duration { nsec: self.nsec.saturating_mul(rhs.into()) }
+    }
+}
+
+impl std::ops::Add<Duration> for Duration {
+    type Output = Duration;
+    fn add(self, rhs: Duration) -> Self::Output {
+        Duration { nsec: self.nsec.saturating_add(rhs.nsec) }
+    }
 }
 
 impl std::ops::Add<Duration> for Instant {
@@ -3,6 +3,7 @@
 use std::sync::Mutex;
 use std::ffi::CStr;
 use libc::c_char;
+use crate::logging;
 
 pub fn split_once<'a>(value: &'a str, deliminator: &str) -> (&'a str, Option<&'a str>) {
     let mut splitter = value.splitn(2, deliminator);
@@ -31,12 +32,12 @@ pub fn warn_once(message: impl Into<String>) {
     let message: String = message.into();
     if let Ok(mut printed_warnings) = PRINTED_WARNINGS.lock() {
         if ! printed_warnings.contains(&message) {
-            eprintln!("{}", message);
+            logging::warning(&message);
             printed_warnings.push(message);
         }
     } else {
-        eprintln!("Warning: internal lock poisoned.");
-        eprintln!("{}", message);
+        logging::warning("Warning: internal lock poisoned.");
+        logging::warning(message);
     }
 }
 
@@ -73,6 +74,50 @@ pub fn strip_suffix<'a>(string: &'a str, suffix: &str) -> Option<&'a str> {
     }
 }
 
+/// Returns true if `path` contains any of the wildcards that `glob_match` understands.
+pub fn is_glob_pattern(path: &str) -> bool {
+    path.contains('*') || path.contains('?')
+}
+
+/// Returns whether `name` matches the shell-style glob `pattern`, where `*` matches any sequence
+/// of characters and `?` matches any single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let mut pattern_pos: usize = 0;
+    let mut name_pos: usize = 0;
+    // The position of the last `*` in the pattern and the position in the name it was matched
+    // up to. If a later part of the pattern fails to match, we let that `*` consume one more char.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while name_pos < name.len() {
+        match pattern.get(pattern_pos) {
+            Some('*') => {
+                backtrack = Some((pattern_pos, name_pos));
+                pattern_pos += 1;
+                continue;
+            },
+            Some(&pattern_char) if pattern_char == '?' || pattern_char == name[name_pos] => {
+                pattern_pos += 1;
+                name_pos += 1;
+                continue;
+            },
+            _ => (),
+        }
+        match backtrack {
+            Some((star_pos, star_name_pos)) => {
+                pattern_pos = star_pos + 1;
+                name_pos = star_name_pos + 1;
+                backtrack = Some((star_pos, name_pos));
+            },
+            None => return false,
+        }
+    }
+
+    pattern[pattern_pos..].iter().all(|&pattern_char| pattern_char == '*')
+}
+
 #[test]
 fn unittest() {
     assert_eq!(strip_prefix("foobar", "foo"), Some("bar"));
@@ -82,4 +127,14 @@ fn unittest() {
     assert_eq!(strip_suffix("foobar", "bar"), Some("foo"));
     assert_eq!(strip_suffix("foobarbar", "bar"), Some("foobar"));
     assert_eq!(strip_suffix("foobaz", "bar"), None);
+
+    assert!(glob_match("usb-Vendor*-event-kbd", "usb-Vendor_Keyboard-event-kbd"));
+    assert!(glob_match("usb-Vendor*-event-kbd", "usb-Vendor-event-kbd"));
+    assert!(! glob_match("usb-Vendor*-event-kbd", "usb-Vendor_Keyboard-event-mouse"));
+    assert!(glob_match("event?", "event3"));
+    assert!(! glob_match("event?", "event12"));
+    assert!(glob_match("*a*b", "xaxxab"));
+    assert!(! glob_match("*a*b", "xaxxa"));
+    assert!(glob_match("*", ""));
+    assert!(! glob_match("?", ""));
 }
\ No newline at end of file
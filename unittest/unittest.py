@@ -618,6 +618,83 @@ def unittest_toggle():
         ],
     })
 
+def unittest_toggle_names():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-toggle-names-in", "grab=force",
+        "--hook", "key:x", "toggle=mode:gaming",
+        "--hook", "key:z", "toggle=mode:normal",
+        "--toggle", "key:a", "key:b", "key:c", "id=mode", "states=normal,gaming",
+        "--output", "create-link=/dev/input/by-id/unittest-toggle-names-out"],
+        {
+            "/dev/input/by-id/unittest-toggle-names-in": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_X, 1),
+                (e.EV_KEY, e.KEY_X, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_Z, 1),
+                (e.EV_KEY, e.KEY_Z, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-toggle-names-out": [
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_X, 1),
+                (e.EV_KEY, e.KEY_X, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_Z, 1),
+                (e.EV_KEY, e.KEY_Z, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+            ],
+        },
+    )
+
+def unittest_toggle_timeout():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-toggle-timeout-in", "grab=force",
+        "--hook", "key:x", "toggle=layer:2",
+        "--toggle", "key:a", "key:b", "key:c", "id=layer", "timeout=0.3",
+        "--output", "create-link=/dev/input/by-id/unittest-toggle-timeout-out"],
+        {
+            "/dev/input/by-id/unittest-toggle-timeout-in": [
+                (e.EV_KEY, e.KEY_X, 1),
+                (e.EV_KEY, e.KEY_X, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                Delay(0.2),
+                # Activity restarts the timeout.
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                Delay(0.2),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                Delay(0.5),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-toggle-timeout-out": [
+                (e.EV_KEY, e.KEY_X, 1),
+                (e.EV_KEY, e.KEY_X, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+            ],
+        },
+    )
+
 def unittest_yield():
     run_unittest(
         ["--input", "/dev/input/by-id/unittest-yield-in", "grab=force",
@@ -985,6 +1062,130 @@ def unittest_delay():
         },
     )
 
+def unittest_repeat():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-repeat-in", "grab=force",
+        "--repeat", "key:a", "delay=0.02", "period=0.02",
+        "--output", "create-link=/dev/input/by-id/unittest-repeat-out"],
+        {
+            "/dev/input/by-id/unittest-repeat-in": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 2),
+                Delay(0.05),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 2),
+                (e.EV_KEY, e.KEY_B, 0),
+                Delay(0.05),
+            ]
+        },
+        {
+            "/dev/input/by-id/unittest-repeat-out": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 2),
+                (e.EV_KEY, e.KEY_A, 2),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 2),
+                (e.EV_KEY, e.KEY_B, 0),
+            ],
+        },
+    )
+
+def unittest_unicode():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-unicode-in", "grab=force",
+        "--unicode", "key:f1", "char=U+00E9",
+        "--output", "create-link=/dev/input/by-id/unittest-unicode-out"],
+        {
+            "/dev/input/by-id/unittest-unicode-in": [
+                (e.EV_KEY, e.KEY_F1, 1),
+                (e.EV_KEY, e.KEY_F1, 2),
+                (e.EV_KEY, e.KEY_F1, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+            ]
+        },
+        {
+            "/dev/input/by-id/unittest-unicode-out": [
+                (e.EV_KEY, e.KEY_LEFTCTRL, 1),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 1),
+                (e.EV_KEY, e.KEY_U, 1),
+                (e.EV_KEY, e.KEY_U, 0),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 0),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 0),
+                (e.EV_KEY, e.KEY_E, 1),
+                (e.EV_KEY, e.KEY_E, 0),
+                (e.EV_KEY, e.KEY_9, 1),
+                (e.EV_KEY, e.KEY_9, 0),
+                (e.EV_KEY, e.KEY_SPACE, 1),
+                (e.EV_KEY, e.KEY_SPACE, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+    )
+
+def unittest_accel():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-accel-in", "grab=force",
+        "--accel", "rel:x", "profile=flat", "speed=1.5",
+        "--output", "create-link=/dev/input/by-id/unittest-accel-out"],
+        {
+            "/dev/input/by-id/unittest-accel-in": [
+                # The fractional part of the movement should be carried over to the next event.
+                (e.EV_REL, e.REL_X, 1),
+                (e.EV_REL, e.REL_X, 1),
+                (e.EV_REL, e.REL_X, 1),
+                (e.EV_REL, e.REL_X, 1),
+                (e.EV_REL, e.REL_Y, 1),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-accel-out": [
+                (e.EV_REL, e.REL_X, 1),
+                (e.EV_REL, e.REL_X, 2),
+                (e.EV_REL, e.REL_X, 1),
+                (e.EV_REL, e.REL_X, 2),
+                (e.EV_REL, e.REL_Y, 1),
+            ],
+        },
+    )
+
+def unittest_map_table():
+    table_path = "/tmp/evsieve-unittest-map-table.txt"
+    with open(table_path, "w") as table_file:
+        table_file.write("# Swap A and B, and block C.\nkey:a key:b\nkey:b key:a\nkey:c\n")
+
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-map-table-in", "grab=force",
+        "--map", f"table={table_path}",
+        "--output", "create-link=/dev/input/by-id/unittest-map-table-out"],
+        {
+            "/dev/input/by-id/unittest-map-table-in": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_D, 1),
+                (e.EV_KEY, e.KEY_D, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-map-table-out": [
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_D, 1),
+                (e.EV_KEY, e.KEY_D, 0),
+            ],
+        },
+    )
+    os.remove(table_path)
+
 def unittest_withhold():
     run_unittest(
         ["--input", "/dev/input/by-id/unittest-withhold-in", "grab=force",
@@ -1438,6 +1639,389 @@ def unittest_withhold_sequential():
         },
     )
 
+def unittest_withhold_timeout():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-withhold-timeout-in", "grab=force",
+        "--hook", "key:a", "key:b", "send-key=key:x",
+        "--withhold", "timeout=0.2",
+        "--output", "create-link=/dev/input/by-id/unittest-withhold-timeout-out"],
+        {
+            "/dev/input/by-id/unittest-withhold-timeout-in": [
+                # The withheld event is released once the timeout expires.
+                (e.EV_KEY, e.KEY_A, 1),
+                Delay(0.4),
+                (e.EV_KEY, e.KEY_A, 0),
+
+                # The hook activates before the timeout expires.
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_B, 1),
+                Delay(0.4),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-withhold-timeout-out": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_X, 1),
+                (e.EV_KEY, e.KEY_X, 0),
+            ],
+        },
+    )
+
+def unittest_withhold_abs():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-withhold-abs-in", "grab=force",
+        "--hook", "abs:x:2~", "key:a", "send-key=key:z",
+        "--withhold",
+        "--output", "create-link=/dev/input/by-id/unittest-withhold-abs-out"],
+        {
+            "/dev/input/by-id/unittest-withhold-abs-in": [
+                # The hook does not activate, so the axis gets released in its latest state.
+                (e.EV_ABS, e.ABS_X, 1),
+                (e.EV_ABS, e.ABS_X, 2),
+                (e.EV_ABS, e.ABS_X, 3),
+                (e.EV_ABS, e.ABS_X, 0),
+
+                # The hook activates, so all events that took part in activating it get dropped.
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_ABS, e.ABS_X, 2),
+                (e.EV_ABS, e.ABS_X, 3),
+                (e.EV_ABS, e.ABS_X, 0),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-withhold-abs-out": [
+                (e.EV_ABS, e.ABS_X, 1),
+                (e.EV_ABS, e.ABS_X, 3),
+                (e.EV_ABS, e.ABS_X, 0),
+                (e.EV_KEY, e.KEY_Z, 1),
+                (e.EV_KEY, e.KEY_Z, 0),
+            ],
+        },
+    )
+
+def unittest_withhold_order():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-withhold-order-in", "grab=force",
+        "--hook", "key:a", "key:b", "send-key=key:x",
+        "--withhold", "order=original",
+        "--output", "create-link=/dev/input/by-id/unittest-withhold-order-out"],
+        {
+            "/dev/input/by-id/unittest-withhold-order-in": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_S, 1),
+                (e.EV_KEY, e.KEY_S, 0),
+                (e.EV_KEY, e.KEY_A, 0),
+
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_S, 1),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_S, 0),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-withhold-order-out": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_S, 1),
+                (e.EV_KEY, e.KEY_S, 0),
+                (e.EV_KEY, e.KEY_A, 0),
+
+                (e.EV_KEY, e.KEY_S, 1),
+                (e.EV_KEY, e.KEY_X, 1),
+                (e.EV_KEY, e.KEY_S, 0),
+                (e.EV_KEY, e.KEY_X, 0),
+            ],
+        },
+    )
+
+def unittest_withhold_selective():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-withhold-selective-in", "grab=force",
+        "--hook", "key:leftctrl", "key:c", "send-key=key:copy",
+        "--withhold", "key:c",
+        "--output", "create-link=/dev/input/by-id/unittest-withhold-selective-out"],
+        {
+            "/dev/input/by-id/unittest-withhold-selective-in": [
+                # Only the letter gets withheld, the modifier passes immediately.
+                (e.EV_KEY, e.KEY_LEFTCTRL, 1),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 0),
+
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-withhold-selective-out": [
+                (e.EV_KEY, e.KEY_LEFTCTRL, 1),
+                (e.EV_KEY, e.KEY_COPY, 1),
+                (e.EV_KEY, e.KEY_COPY, 0),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 0),
+
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+            ],
+        },
+    )
+
+def unittest_debounce():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-debounce-in", "grab=force",
+        "--debounce", "key:a", "period=0.05",
+        "--output", "create-link=/dev/input/by-id/unittest-debounce-out"],
+        {
+            "/dev/input/by-id/unittest-debounce-in": [
+                # The key bounces: the release and the press that follows it should be dropped.
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                Delay(0.01),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+                Delay(0.1),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                Delay(0.1),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-debounce-out": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+    )
+
+def unittest_throttle():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-throttle-in", "grab=force",
+        "--throttle", "key:a", "period=0.1",
+        "--throttle", "rel:wheel", "period=0.05", "mode=coalesce",
+        "--output", "create-link=/dev/input/by-id/unittest-throttle-out"],
+        {
+            "/dev/input/by-id/unittest-throttle-in": [
+                # A press that comes too soon gets dropped along with its release.
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                Delay(0.15),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+
+                # Wheel events that come too soon get added up.
+                (e.EV_REL, e.REL_WHEEL, 1),
+                (e.EV_REL, e.REL_WHEEL, 1),
+                (e.EV_REL, e.REL_WHEEL, 2),
+                Delay(0.15),
+                (e.EV_REL, e.REL_WHEEL, -1),
+                Delay(0.01),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-throttle-out": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_REL, e.REL_WHEEL, 1),
+                (e.EV_REL, e.REL_WHEEL, 3),
+                (e.EV_REL, e.REL_WHEEL, -1),
+            ],
+        },
+    )
+
+def unittest_latch():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-latch-in", "grab=force",
+        "--latch", "key:leftctrl",
+        "--output", "create-link=/dev/input/by-id/unittest-latch-out"],
+        {
+            "/dev/input/by-id/unittest-latch-in": [
+                (e.EV_KEY, e.KEY_LEFTCTRL, 1),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 2),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 1),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-latch-out": [
+                (e.EV_KEY, e.KEY_LEFTCTRL, 1),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 0),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+            ],
+        },
+    )
+
+def unittest_sticky():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-sticky-in", "grab=force",
+        "--sticky", "key:leftshift", "timeout=0.05",
+        "--output", "create-link=/dev/input/by-id/unittest-sticky-out"],
+        {
+            "/dev/input/by-id/unittest-sticky-in": [
+                # Tapping shift keeps it pressed until the next key gets pressed.
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 1),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+
+                # A tapped shift gets released after the timeout.
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 1),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 0),
+                Delay(0.1),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+
+                # Holding shift while pressing another key works as usual.
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 1),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-sticky-out": [
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 1),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 0),
+                (e.EV_KEY, e.KEY_A, 0),
+
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 1),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 1),
+                (e.EV_KEY, e.KEY_C, 1),
+                (e.EV_KEY, e.KEY_C, 0),
+                (e.EV_KEY, e.KEY_LEFTSHIFT, 0),
+            ],
+        },
+    )
+
+def unittest_chord():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-chord-in", "grab=force",
+        "--chord", "key:j", "key:k", "send-key=key:esc", "window=0.05",
+        "--output", "create-link=/dev/input/by-id/unittest-chord-out"],
+        {
+            "/dev/input/by-id/unittest-chord-in": [
+                # Pressing both keys at once presses escape instead.
+                (e.EV_KEY, e.KEY_J, 1),
+                (e.EV_KEY, e.KEY_K, 1),
+                (e.EV_KEY, e.KEY_J, 0),
+                (e.EV_KEY, e.KEY_K, 0),
+
+                # Releasing a key before the chord is complete sends the withheld press.
+                (e.EV_KEY, e.KEY_J, 1),
+                (e.EV_KEY, e.KEY_J, 0),
+
+                # So does waiting longer than the window.
+                (e.EV_KEY, e.KEY_K, 1),
+                Delay(0.1),
+                (e.EV_KEY, e.KEY_K, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-chord-out": [
+                (e.EV_KEY, e.KEY_ESC, 1),
+                (e.EV_KEY, e.KEY_ESC, 0),
+
+                (e.EV_KEY, e.KEY_J, 1),
+                (e.EV_KEY, e.KEY_J, 0),
+
+                (e.EV_KEY, e.KEY_K, 1),
+                (e.EV_KEY, e.KEY_K, 0),
+            ],
+        },
+    )
+
+def unittest_counter():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-counter-in", "grab=force",
+        "--counter", "key:a:1", "output=abs:misc", "range=0~2", "reset=key:b:1",
+        "--output", "create-link=/dev/input/by-id/unittest-counter-out"],
+        {
+            "/dev/input/by-id/unittest-counter-in": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                # The count cannot exceed the range, so it does not change.
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-counter-out": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_ABS, e.ABS_MISC, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_ABS, e.ABS_MISC, 2),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_B, 1),
+                (e.EV_ABS, e.ABS_MISC, 0),
+                (e.EV_KEY, e.KEY_B, 0),
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_ABS, e.ABS_MISC, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+            ],
+        },
+    )
+
+def unittest_timer():
+    run_unittest(
+        ["--input", "/dev/input/by-id/unittest-timer-in", "grab=force",
+        "--timer", "period=0.5", "send-key=key:leftctrl", "send-key=key:f24",
+        "--output", "create-link=/dev/input/by-id/unittest-timer-out"],
+        {
+            "/dev/input/by-id/unittest-timer-in": [
+                # Other events do not affect the timer.
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                # The first tick happens half a second after evsieve started.
+                Delay(0.5),
+            ],
+        },
+        {
+            "/dev/input/by-id/unittest-timer-out": [
+                (e.EV_KEY, e.KEY_A, 1),
+                (e.EV_KEY, e.KEY_A, 0),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 1),
+                (e.EV_KEY, e.KEY_F24, 1),
+                (e.EV_KEY, e.KEY_F24, 0),
+                (e.EV_KEY, e.KEY_LEFTCTRL, 0),
+            ],
+        },
+    )
+
 
 unittest_mirror()
 unittest_syn()
@@ -1449,6 +2033,8 @@ unittest_kbmousemap()
 unittest_execshell()
 unittest_sequential_hook()
 unittest_toggle()
+unittest_toggle_names()
+unittest_toggle_timeout()
 unittest_yield()
 unittest_order()
 unittest_namespace()
@@ -1458,8 +2044,23 @@ unittest_bynumber()
 unittest_merge()
 unittest_relative()
 unittest_delay()
+unittest_repeat()
+unittest_unicode()
+unittest_accel()
+unittest_map_table()
 unittest_withhold()
 unittest_withhold_2()
 unittest_withhold_3()
 unittest_withhold_period()
 unittest_withhold_sequential()
+unittest_withhold_timeout()
+unittest_withhold_abs()
+unittest_withhold_order()
+unittest_withhold_selective()
+unittest_debounce()
+unittest_throttle()
+unittest_latch()
+unittest_sticky()
+unittest_chord()
+unittest_counter()
+unittest_timer()